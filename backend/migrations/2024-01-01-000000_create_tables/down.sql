@@ -0,0 +1,5 @@
+DROP TABLE IF EXISTS monitored_users;
+DROP TABLE IF EXISTS logs;
+DROP TABLE IF EXISTS products;
+DROP TABLE IF EXISTS categories;
+DROP TABLE IF EXISTS users;
@@ -0,0 +1 @@
+ALTER TABLE products ALTER COLUMN price TYPE FLOAT8 USING price::float8;
@@ -0,0 +1,12 @@
+-- Units of each currency worth one EUR; EUR itself is the reference and stays at 1
+CREATE TABLE exchange_rates (
+    currency VARCHAR(3) PRIMARY KEY CHECK (currency ~ '^[A-Z]{3}$'),
+    rate NUMERIC(18,6) NOT NULL CHECK (rate > 0),
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO exchange_rates (currency, rate) VALUES ('EUR', 1);
+
+-- Existing prices were entered in EUR
+ALTER TABLE products
+    ADD COLUMN currency VARCHAR(3) NOT NULL DEFAULT 'EUR' REFERENCES exchange_rates(currency);
@@ -0,0 +1,16 @@
+-- One row per price change; the row written when a product is created has no old price
+CREATE TABLE product_price_history (
+    id SERIAL PRIMARY KEY,
+    product_id INT NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    old_price NUMERIC(12,2),
+    old_currency VARCHAR(3),
+    new_price NUMERIC(12,2) NOT NULL,
+    new_currency VARCHAR(3) NOT NULL,
+    changed_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX product_price_history_product_id_changed_at_idx ON product_price_history (product_id, changed_at);
+
+-- Earlier prices are gone; start every existing product's history at its current price
+INSERT INTO product_price_history (product_id, new_price, new_currency)
+SELECT id, price, currency FROM products;
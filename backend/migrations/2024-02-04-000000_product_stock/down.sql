@@ -0,0 +1,2 @@
+ALTER TABLE logs DROP COLUMN reason;
+ALTER TABLE products DROP COLUMN low_stock_threshold, DROP COLUMN stock;
@@ -0,0 +1,7 @@
+-- Stock only moves through the increment/decrement endpoints, which never take it below zero
+ALTER TABLE products
+    ADD COLUMN stock INT NOT NULL DEFAULT 0 CHECK (stock >= 0),
+    ADD COLUMN low_stock_threshold INT CHECK (low_stock_threshold >= 0);
+
+-- Why an action was taken, e.g. the reason given for a stock movement
+ALTER TABLE logs ADD COLUMN reason TEXT;
@@ -0,0 +1,4 @@
+DROP TABLE product_variant_values;
+DROP TABLE product_variants;
+DROP TABLE product_option_values;
+DROP TABLE product_options;
@@ -0,0 +1,38 @@
+-- Options a product comes in (Size, Color) and the values they take (42, black)
+CREATE TABLE product_options (
+    id SERIAL PRIMARY KEY,
+    product_id INT NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    name VARCHAR(50) NOT NULL,
+    position INT NOT NULL,
+    UNIQUE (product_id, name)
+);
+
+CREATE TABLE product_option_values (
+    id SERIAL PRIMARY KEY,
+    option_id INT NOT NULL REFERENCES product_options(id) ON DELETE CASCADE,
+    value VARCHAR(100) NOT NULL,
+    UNIQUE (option_id, value)
+);
+
+-- One sellable combination of option values. A NULL price means the product's
+-- price applies; an override is in the product's currency.
+CREATE TABLE product_variants (
+    id SERIAL PRIMARY KEY,
+    product_id INT NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    sku VARCHAR(64) NOT NULL UNIQUE,
+    price NUMERIC(12,2) CHECK (price > 0),
+    stock INT NOT NULL DEFAULT 0 CHECK (stock >= 0),
+    image VARCHAR(2048),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX product_variants_product_id_idx ON product_variants (product_id);
+
+CREATE TABLE product_variant_values (
+    variant_id INT NOT NULL REFERENCES product_variants(id) ON DELETE CASCADE,
+    option_value_id INT NOT NULL REFERENCES product_option_values(id) ON DELETE CASCADE,
+    PRIMARY KEY (variant_id, option_value_id)
+);
+
+CREATE INDEX product_variant_values_option_value_id_idx ON product_variant_values (option_value_id);
@@ -0,0 +1,4 @@
+DROP TRIGGER categories_prevent_cycle ON categories;
+DROP FUNCTION categories_prevent_cycle();
+DROP VIEW category_ancestors;
+ALTER TABLE categories DROP COLUMN parent_id;
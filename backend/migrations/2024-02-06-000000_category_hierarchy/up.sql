@@ -0,0 +1,39 @@
+-- NULL for top-level categories. A parent with children cannot be deleted.
+ALTER TABLE categories ADD COLUMN parent_id INT REFERENCES categories(id);
+
+CREATE INDEX categories_parent_id_idx ON categories (parent_id);
+
+-- Every category paired with itself and with each of its ancestors, so
+-- "category X and everything below it" is a plain join on ancestor_id = X.
+-- UNION rather than UNION ALL stops the recursion even if a cycle slipped in.
+CREATE VIEW category_ancestors AS
+WITH RECURSIVE tree (category_id, ancestor_id) AS (
+    SELECT id, id FROM categories
+    UNION
+    SELECT tree.category_id, categories.parent_id
+    FROM tree
+    JOIN categories ON categories.id = tree.ancestor_id
+    WHERE categories.parent_id IS NOT NULL
+)
+SELECT category_id, ancestor_id FROM tree;
+
+-- Rejects moving a category under itself or one of its descendants. The
+-- advisory lock serializes hierarchy changes, so two concurrent moves cannot
+-- each pass the check and close a cycle between them.
+CREATE FUNCTION categories_prevent_cycle() RETURNS trigger AS $$
+BEGIN
+    IF NEW.parent_id IS NULL THEN
+        RETURN NEW;
+    END IF;
+    PERFORM pg_advisory_xact_lock(hashtext('categories_parent_id'));
+    IF EXISTS (SELECT 1 FROM category_ancestors WHERE category_id = NEW.parent_id AND ancestor_id = NEW.id) THEN
+        RAISE EXCEPTION 'category % cannot be placed under its own subcategory %', NEW.id, NEW.parent_id
+            USING ERRCODE = 'check_violation', CONSTRAINT = 'categories_parent_cycle';
+    END IF;
+    RETURN NEW;
+END
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER categories_prevent_cycle
+    BEFORE INSERT OR UPDATE OF parent_id ON categories
+    FOR EACH ROW EXECUTE FUNCTION categories_prevent_cycle();
@@ -0,0 +1,7 @@
+DROP INDEX categories_display_order_idx;
+DROP INDEX categories_name_lower_key;
+
+ALTER TABLE categories
+    DROP COLUMN display_order,
+    DROP COLUMN image,
+    DROP COLUMN slug;
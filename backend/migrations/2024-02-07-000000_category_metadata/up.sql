@@ -0,0 +1,23 @@
+-- URL-safe handle for a category, e.g. "running-shoes"; derived from the name
+-- unless given. Existing rows get the same derivation, with the id appended
+-- where two names collapse to one slug.
+ALTER TABLE categories
+    ADD COLUMN slug VARCHAR(100),
+    ADD COLUMN image VARCHAR(2048),
+    ADD COLUMN display_order INT NOT NULL DEFAULT 0;
+
+UPDATE categories SET slug = trim(both '-' from regexp_replace(lower(name), '[^a-z0-9]+', '-', 'g'));
+UPDATE categories SET slug = 'category' WHERE slug = '';
+UPDATE categories SET slug = slug || '-' || id
+WHERE EXISTS (SELECT 1 FROM categories earlier WHERE earlier.slug = categories.slug AND earlier.id < categories.id);
+
+ALTER TABLE categories
+    ALTER COLUMN slug SET NOT NULL,
+    ADD CONSTRAINT categories_slug_key UNIQUE (slug);
+
+-- "Shoes" and "shoes" are the same category. Fails if existing names already
+-- clash that way; rename one of them first.
+CREATE UNIQUE INDEX categories_name_lower_key ON categories (lower(name));
+
+-- Siblings are listed by display_order, then name
+CREATE INDEX categories_display_order_idx ON categories (display_order, name);
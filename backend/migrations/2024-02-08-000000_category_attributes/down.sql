@@ -0,0 +1,2 @@
+ALTER TABLE products DROP COLUMN attributes;
+DROP TABLE category_attributes;
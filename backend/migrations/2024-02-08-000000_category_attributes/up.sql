@@ -0,0 +1,24 @@
+-- Typed attributes a category asks of its products, e.g. "size" for shoes.
+-- Subcategories inherit them; a definition on a subcategory wins over an
+-- ancestor's of the same name. Products are checked on write, so making an
+-- attribute required does not touch products already stored.
+CREATE TABLE category_attributes (
+    id SERIAL PRIMARY KEY,
+    category_id INT NOT NULL REFERENCES categories(id) ON DELETE CASCADE,
+    name VARCHAR(50) NOT NULL,
+    kind VARCHAR(10) NOT NULL CHECK (kind IN ('string', 'number', 'enum', 'bool')),
+    required BOOLEAN NOT NULL DEFAULT FALSE,
+    -- What an enum attribute may take; empty for every other kind
+    allowed_values TEXT[] NOT NULL DEFAULT '{}',
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (category_id, name),
+    CONSTRAINT category_attributes_allowed_values_check CHECK ((kind = 'enum') = (cardinality(allowed_values) > 0))
+);
+
+CREATE INDEX category_attributes_category_id_idx ON category_attributes (category_id);
+
+-- Values keyed by attribute name, e.g. {"size": 42, "material": "leather"}
+ALTER TABLE products
+    ADD COLUMN attributes JSONB NOT NULL DEFAULT '{}'
+    CONSTRAINT products_attributes_object_check CHECK (jsonb_typeof(attributes) = 'object');
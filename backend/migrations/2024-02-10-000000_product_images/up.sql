@@ -0,0 +1,14 @@
+-- Images uploaded for a product. The bytes live under MEDIA_DIR at `key`, named by
+-- their SHA-256, so products uploading the same file share it.
+CREATE TABLE product_images (
+    id SERIAL PRIMARY KEY,
+    product_id INT NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    key VARCHAR(255) NOT NULL,
+    url VARCHAR(2048) NOT NULL,
+    content_type VARCHAR(50) NOT NULL,
+    size_bytes BIGINT NOT NULL CHECK (size_bytes > 0),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (product_id, key)
+);
+
+CREATE INDEX product_images_key_idx ON product_images (key);
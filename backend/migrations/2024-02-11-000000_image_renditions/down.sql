@@ -0,0 +1,10 @@
+DROP TRIGGER products_image_srcset ON products;
+DROP FUNCTION products_image_srcset();
+ALTER TABLE products DROP COLUMN image_srcset;
+DROP INDEX product_images_url_idx;
+DROP INDEX product_images_pending_idx;
+ALTER TABLE product_images
+    DROP COLUMN srcset,
+    DROP COLUMN renditions_status,
+    DROP COLUMN height,
+    DROP COLUMN width;
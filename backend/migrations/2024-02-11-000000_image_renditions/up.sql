@@ -0,0 +1,29 @@
+-- Resized copies of each upload, generated in the background. `srcset` holds one
+-- srcset string per format once they exist, e.g. {"webp": "... 160w, ... 640w", "jpeg": ...}.
+ALTER TABLE product_images
+    ADD COLUMN width INT,
+    ADD COLUMN height INT,
+    ADD COLUMN renditions_status VARCHAR(10) NOT NULL DEFAULT 'pending'
+        CONSTRAINT product_images_renditions_status_check CHECK (renditions_status IN ('pending', 'ready', 'failed')),
+    ADD COLUMN srcset JSONB;
+
+CREATE INDEX product_images_pending_idx ON product_images (id) WHERE renditions_status = 'pending';
+CREATE INDEX product_images_url_idx ON product_images (url);
+
+ALTER TABLE products ADD COLUMN image_srcset JSONB;
+
+-- Keeps products.image_srcset in step with products.image: the renditions of the
+-- uploaded image it points at once they exist, otherwise NULL. The rendition job
+-- fills it in for products already pointing at an image when it finishes.
+CREATE FUNCTION products_image_srcset() RETURNS trigger AS $$
+BEGIN
+    NEW.image_srcset := (
+        SELECT srcset FROM product_images WHERE url = NEW.image AND srcset IS NOT NULL LIMIT 1
+    );
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER products_image_srcset
+    BEFORE INSERT OR UPDATE OF image ON products
+    FOR EACH ROW EXECUTE FUNCTION products_image_srcset();
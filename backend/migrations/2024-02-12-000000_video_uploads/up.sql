@@ -0,0 +1,18 @@
+-- Resumable video uploads. The bytes received so far sit under MEDIA_DIR at
+-- .uploads/<id>.part; once all `size_bytes` are in and hash to `sha256` the file
+-- moves to videos/<sha256>.<ext> and `video` holds its URL, which the product
+-- now shows. Uploads untouched for MEDIA_UPLOAD_EXPIRY_HOURS are cleaned up.
+CREATE TABLE video_uploads (
+    id VARCHAR(36) PRIMARY KEY,
+    product_id INT NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    size_bytes BIGINT NOT NULL CHECK (size_bytes > 0),
+    received_bytes BIGINT NOT NULL DEFAULT 0,
+    sha256 VARCHAR(64) NOT NULL,
+    video VARCHAR(2048),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT video_uploads_received_bytes_check CHECK (received_bytes BETWEEN 0 AND size_bytes)
+);
+
+CREATE INDEX video_uploads_product_id_idx ON video_uploads (product_id);
+CREATE INDEX video_uploads_updated_at_idx ON video_uploads (updated_at);
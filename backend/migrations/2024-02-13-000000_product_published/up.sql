@@ -0,0 +1,3 @@
+-- Unpublished products are still being prepared; only their owner and admins
+-- may stream their videos
+ALTER TABLE products ADD COLUMN published BOOLEAN NOT NULL DEFAULT TRUE;
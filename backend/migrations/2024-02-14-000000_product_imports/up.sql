@@ -0,0 +1,29 @@
+-- Products may carry a stock keeping unit of their own, unique like the
+-- variants' ones; bulk imports update the product whose SKU a row names
+ALTER TABLE products ADD COLUMN sku VARCHAR(64) CONSTRAINT products_sku_key UNIQUE;
+
+-- Bulk imports of products from CSV or NDJSON. The uploaded file is kept in
+-- `source` until the job ends; rows are committed in batches, each together
+-- with the progress columns, so a job the server died in can resume after
+-- the last batch. `row_errors` is the per-row report, [{row, sku, message, details}].
+CREATE TABLE import_jobs (
+    id VARCHAR(36) PRIMARY KEY,
+    user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    format VARCHAR(10) NOT NULL CHECK (format IN ('csv', 'ndjson')),
+    dry_run BOOLEAN NOT NULL,
+    upsert BOOLEAN NOT NULL,
+    create_categories BOOLEAN NOT NULL,
+    status VARCHAR(10) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'running', 'finished', 'failed')),
+    total_rows INT NOT NULL CHECK (total_rows >= 0),
+    processed_rows INT NOT NULL DEFAULT 0,
+    created_rows INT NOT NULL DEFAULT 0,
+    updated_rows INT NOT NULL DEFAULT 0,
+    failed_rows INT NOT NULL DEFAULT 0,
+    row_errors JSONB NOT NULL DEFAULT '[]',
+    error TEXT,
+    source TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX import_jobs_status_created_at_idx ON import_jobs (status, created_at);
@@ -0,0 +1,90 @@
+use std::collections::{BTreeMap, HashSet};
+
+use serde_json::{Map, Value};
+
+use crate::db::models::CategoryAttribute;
+
+// What a product without attribute values stores
+pub fn no_attributes() -> Value {
+    Value::Object(Map::new())
+}
+
+// The attributes that apply to a category, by name: its own and its ancestors',
+// the nearest definition winning when names repeat. `lineage` is the category
+// followed by its ancestors, nearest first, as CategoryStore::ancestors gives it.
+pub fn effective(definitions: Vec<CategoryAttribute>, lineage: &[i32]) -> Vec<CategoryAttribute> {
+    let depth = |attribute: &CategoryAttribute| lineage.iter().position(|&id| id == attribute.category_id).unwrap_or(usize::MAX);
+    let mut definitions = definitions;
+    definitions.sort_by_key(|a| (a.name.clone(), depth(a)));
+    let mut seen = HashSet::new();
+    definitions.retain(|a| seen.insert(a.name.clone()));
+    definitions
+}
+
+// Complaints about `values` keyed as "attributes.<name>", or "attributes" when
+// it is not an object; empty if the values fit the schema
+pub fn check(schema: &[CategoryAttribute], values: &Value) -> BTreeMap<String, Vec<String>> {
+    let mut errors: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let Some(values) = values.as_object() else {
+        errors.insert("attributes".to_string(), vec!["must be an object".to_string()]);
+        return errors;
+    };
+    for (name, value) in values {
+        let message = match schema.iter().find(|a| &a.name == name) {
+            None => Some("is not an attribute of this category".to_string()),
+            Some(attribute) => mismatch(attribute, value),
+        };
+        if let Some(message) = message {
+            errors.entry(format!("attributes.{}", name)).or_default().push(message);
+        }
+    }
+    for attribute in schema.iter().filter(|a| a.required && !values.contains_key(&a.name)) {
+        errors.entry(format!("attributes.{}", attribute.name)).or_default().push("is required".to_string());
+    }
+    errors
+}
+
+fn mismatch(attribute: &CategoryAttribute, value: &Value) -> Option<String> {
+    let fits = match attribute.kind.as_str() {
+        "string" => value.as_str().is_some_and(|s| !s.trim().is_empty() && s.len() <= 500),
+        "number" => value.is_number(),
+        "bool" => value.is_boolean(),
+        "enum" => value.as_str().is_some_and(|s| attribute.allowed_values.iter().any(|allowed| allowed == s)),
+        _ => false,
+    };
+    if fits {
+        return None;
+    }
+    Some(match attribute.kind.as_str() {
+        "string" => "must be a non-blank string of at most 500 characters".to_string(),
+        "enum" => format!("must be one of {}", attribute.allowed_values.join(", ")),
+        kind => format!("must be a {}", if kind == "bool" { "boolean" } else { kind }),
+    })
+}
+
+// PATCH semantics for attribute values: keys in `changes` replace the stored
+// ones and a null removes the key. Anything but an object replaces them wholesale,
+// for check() to reject.
+pub fn merge(current: &Value, changes: Value) -> Value {
+    let (Some(current), Value::Object(changes)) = (current.as_object(), &changes) else {
+        return changes;
+    };
+    let mut merged = current.clone();
+    for (name, value) in changes {
+        match value {
+            Value::Null => merged.remove(name),
+            value => merged.insert(name.clone(), value.clone()),
+        };
+    }
+    Value::Object(merged)
+}
+
+// What attr.<name>=<value> filters compare against, lowercased; mirrors the
+// `attributes ->> name` text Postgres compares
+pub fn filter_text(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.to_lowercase()),
+        other => Some(other.to_string().to_lowercase()),
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::env;
+
+use chrono::Utc;
+
+use crate::db::models::NewLog;
+use crate::errors::ApiError;
+use crate::AppState;
+
+// More actions than this within a minute gets a user monitored
+const SUSPICIOUS_ACTIONS_PER_MINUTE: i64 = 10;
+
+// Recorded as the actor of writes that carry no user, e.g. category changes
+pub const ANONYMOUS_USER: i32 = 0;
+
+// What a write does when its audit entry cannot be recorded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AuditPolicy {
+    // The write is rolled back with the failed audit entry
+    #[default]
+    Required,
+    // The failure is reported and the write commits unaudited
+    BestEffort,
+}
+
+impl AuditPolicy {
+    // AUDIT_FAILURE_POLICY=required|best_effort; anything else keeps the default
+    pub fn from_env() -> Self {
+        match env::var("AUDIT_FAILURE_POLICY").as_deref() {
+            Ok("best_effort") => AuditPolicy::BestEffort,
+            Ok("required") | Err(_) => AuditPolicy::Required,
+            Ok(other) => {
+                eprintln!("Unknown AUDIT_FAILURE_POLICY {:?}, using required", other);
+                AuditPolicy::Required
+            }
+        }
+    }
+}
+
+// Records the action and runs the monitoring check. Call it inside the
+// transaction of the write it describes, so both commit or neither does.
+pub fn log_action(state: &AppState, user_id: i32, action: &str, entity: &str, entity_id: Option<i32>) -> Result<(), ApiError> {
+    log(state, user_id, action, entity, entity_id, None)
+}
+
+// log_action for writes that must say why they happened, such as stock movements
+pub fn log_action_with_reason(
+    state: &AppState,
+    user_id: i32,
+    action: &str,
+    entity: &str,
+    entity_id: Option<i32>,
+    reason: &str,
+) -> Result<(), ApiError> {
+    log(state, user_id, action, entity, entity_id, Some(reason))
+}
+
+fn log(state: &AppState, user_id: i32, action: &str, entity: &str, entity_id: Option<i32>, reason: Option<&str>) -> Result<(), ApiError> {
+    // A savepoint of its own, so under BestEffort a failed entry is dropped
+    // without aborting the write's transaction
+    let recorded = state.in_transaction(|tx| record(tx, user_id, action, entity, entity_id, reason));
+    match (recorded, state.audit_policy) {
+        (Ok(()), _) => Ok(()),
+        (Err(err), AuditPolicy::Required) => Err(err),
+        (Err(err), AuditPolicy::BestEffort) => {
+            eprintln!("Audit entry {} {} {:?} by user {} not recorded: {}", action, entity, entity_id, user_id, err);
+            Ok(())
+        }
+    }
+}
+
+fn record(state: &AppState, user_id: i32, action: &str, entity: &str, entity_id: Option<i32>, reason: Option<&str>) -> Result<(), ApiError> {
+    state.audit.log(NewLog {
+        user_id,
+        action: action.to_string(),
+        entity: entity.to_string(),
+        entity_id,
+        timestamp: Utc::now().naive_utc(),
+        reason: reason.map(str::to_string),
+    })?;
+    // Check for suspicious activity: more than 10 logs in the last minute
+    let one_min_ago = Utc::now().naive_utc() - chrono::Duration::minutes(1);
+    if state.audit.count_since(user_id, one_min_ago)? > SUSPICIOUS_ACTIONS_PER_MINUTE {
+        if let Some(user) = state.users.get(user_id)? {
+            state.audit.add_monitored_user(user_id, &user.username)?;
+        }
+    }
+    Ok(())
+}
+
+// One pass of the background monitor over the last minute of logs
+pub fn scan_recent_logs(state: &AppState) -> Result<(), ApiError> {
+    let one_min_ago = Utc::now().naive_utc() - chrono::Duration::minutes(1);
+    // Count actions per user
+    let mut user_counts: HashMap<i32, i64> = HashMap::new();
+    for log in state.audit.logs_since(one_min_ago)? {
+        *user_counts.entry(log.user_id).or_insert(0) += 1;
+    }
+    // For each suspicious user, add to monitored_users
+    for (user_id, count) in user_counts {
+        if count > SUSPICIOUS_ACTIONS_PER_MINUTE {
+            if let Some(user) = state.users.get(user_id)? {
+                state.audit.add_monitored_user(user_id, &user.username)?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,38 @@
+use actix_web::dev::Payload;
+use actix_web::{FromRequest, HttpRequest};
+use futures::future::{ready, Ready};
+
+use crate::errors::ApiError;
+use crate::AppState;
+
+// The caller named by `Authorization: Bearer <user_id>`. There are no issued
+// tokens yet, so this is who the client says it is, not who it proved to be.
+// Take it as Option<AuthUser> where anonymous callers are fine too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuthUser {
+    pub user_id: i32,
+}
+
+impl FromRequest for AuthUser {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let user_id = req
+            .headers()
+            .get("Authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .and_then(|token| token.trim().parse::<i32>().ok());
+        ready(match user_id {
+            Some(user_id) => Ok(AuthUser { user_id }),
+            None => Err(ApiError::Unauthorized("Missing or invalid Authorization header".to_string())),
+        })
+    }
+}
+
+// Whether the caller's account has the Admin role. Only seeded or appointed
+// accounts do: registration always creates plain users.
+pub fn is_admin(state: &AppState, user: AuthUser) -> Result<bool, ApiError> {
+    Ok(state.users.get(user.user_id)?.is_some_and(|u| u.role == "Admin"))
+}
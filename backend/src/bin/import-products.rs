@@ -0,0 +1,59 @@
+// Imports products from a file, as POST /api/v1/products/import does:
+//
+//     import-products <file> --user <id> [--format csv|ndjson] [--dry-run] [--upsert] [--create-categories]
+//
+// The products it creates are owned by --user. Without --format the file's
+// extension decides: .csv, or .ndjson or .jsonl. The whole file is imported
+// here, whatever its size, against the database in DATABASE_URL; progress goes
+// to stderr and the finished job, with its row errors, to stdout as JSON. Exits
+// with 1 if any row failed.
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::process;
+
+use backend::db::connection;
+use backend::imports::{self, ImportFormat, ImportOptions};
+use backend::AppState;
+
+const USAGE: &str = "usage: import-products <file> --user <id> [--format csv|ndjson] [--dry-run] [--upsert] [--create-categories]";
+
+fn fail(message: impl Display) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let (mut path, mut user_id, mut format) = (None, None, None);
+    let mut options = ImportOptions { format: ImportFormat::Csv, dry_run: false, upsert: false, create_categories: false };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--user" => user_id = Some(args.next().and_then(|id| id.parse::<i32>().ok()).unwrap_or_else(|| fail(USAGE))),
+            "--format" => format = Some(args.next().and_then(|name| ImportFormat::from_name(&name)).unwrap_or_else(|| fail(USAGE))),
+            "--dry-run" => options.dry_run = true,
+            "--upsert" => options.upsert = true,
+            "--create-categories" => options.create_categories = true,
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => fail(USAGE),
+        }
+    }
+    let (Some(path), Some(user_id)) = (path, user_id) else { fail(USAGE) };
+    options.format = format
+        .or_else(|| match path.rsplit_once('.').map(|(_, extension)| extension.to_ascii_lowercase()).as_deref() {
+            Some("csv") => Some(ImportFormat::Csv),
+            Some("ndjson" | "jsonl") => Some(ImportFormat::Ndjson),
+            _ => None,
+        })
+        .unwrap_or_else(|| fail("Cannot tell the format from the file name; give --format"));
+    let source = fs::read_to_string(&path).unwrap_or_else(|err| fail(format!("Cannot read {}: {}", path, err)));
+
+    let state = AppState::postgres(connection::init_pool());
+    let job = imports::create(&state, user_id, options, source, usize::MAX).unwrap_or_else(|err| fail(format!("{}: {}", err, err.details())));
+    let job = imports::run(&state, &job.id, &mut |job| eprintln!("{} of {} rows", job.processed_rows, job.total_rows))
+        .unwrap_or_else(|err| fail(format!("Import failed: {}", err)));
+    println!("{}", serde_json::to_string_pretty(&job).expect("jobs serialize"));
+    if job.failed_rows > 0 {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,52 @@
+// Copies uploaded media from one storage backend to another:
+//
+//     migrate-media <from> <to> [--rewrite-urls]
+//
+// <from> and <to> are `local` or `s3`, configured from the environment as for
+// the server (MEDIA_DIR, S3_*). Files the target has already are skipped, so an
+// interrupted run can simply be repeated. With --rewrite-urls the media URLs
+// stored in the database (DATABASE_URL) are then pointed at the target; do that
+// once the copy is complete, and switch MEDIA_STORAGE over at the same time.
+use std::env;
+use std::fmt::Display;
+use std::process;
+use std::sync::Arc;
+
+use backend::db::{connection, repository};
+use backend::media::MediaConfig;
+use backend::storage::{self, Backend, MediaStorage};
+
+const USAGE: &str = "usage: migrate-media <local|s3> <local|s3> [--rewrite-urls]";
+
+fn fail(message: impl Display) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+fn storage(media: &MediaConfig, name: &str) -> Arc<dyn MediaStorage> {
+    let backend = Backend::from_name(name).unwrap_or_else(|err| fail(err));
+    MediaConfig { backend, ..media.clone() }.storage()
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let rewrite_urls = args.iter().any(|arg| arg == "--rewrite-urls");
+    let names: Vec<&str> = args.iter().map(String::as_str).filter(|arg| *arg != "--rewrite-urls").collect();
+    let [from, to] = names[..] else { fail(USAGE) };
+    if from == to {
+        fail("<from> and <to> are the same storage");
+    }
+
+    let media = MediaConfig::from_env();
+    let (from, to) = (storage(&media, from), storage(&media, to));
+    let report = storage::copy_all(from.as_ref(), to.as_ref()).unwrap_or_else(|err| fail(format!("Copy failed: {}", err)));
+    println!("Copied {} files from {} to {}; {} were there already", report.copied, from.name(), to.name(), report.skipped);
+
+    if rewrite_urls {
+        let pool = connection::init_pool();
+        let mut conn = pool.get().unwrap_or_else(|err| fail(format!("No database connection: {}", err)));
+        let changed = repository::rewrite_media_urls(&mut conn, &from.url(""), &to.url(""))
+            .unwrap_or_else(|err| fail(format!("Rewriting URLs failed: {}", err)));
+        println!("Pointed {} database rows at {}", changed, to.url(""));
+    }
+}
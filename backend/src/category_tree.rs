@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::db::models::Category;
+
+// A category with everything below it
+#[derive(Serialize, ToSchema)]
+pub struct CategoryNode {
+    #[serde(flatten)]
+    pub category: Category,
+    #[schema(no_recursion)]
+    pub children: Vec<CategoryNode>,
+}
+
+// Nests the categories under their parents; siblings keep their order in `categories`
+pub fn build(categories: Vec<Category>) -> Vec<CategoryNode> {
+    let mut by_parent: HashMap<Option<i32>, Vec<Category>> = HashMap::new();
+    for category in categories {
+        by_parent.entry(category.parent_id).or_default().push(category);
+    }
+    attach(None, &mut by_parent)
+}
+
+fn attach(parent_id: Option<i32>, by_parent: &mut HashMap<Option<i32>, Vec<Category>>) -> Vec<CategoryNode> {
+    by_parent
+        .remove(&parent_id)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|category| {
+            let children = attach(Some(category.id), by_parent);
+            CategoryNode { category, children }
+        })
+        .collect()
+}
+
+// `id` followed by its ancestors, nearest first, like the category_ancestors
+// view. Stops at a repeat so a cycle cannot loop forever.
+pub fn ancestors(categories: &[Category], id: i32) -> Vec<i32> {
+    let mut chain = vec![id];
+    let mut current = id;
+    while let Some(parent_id) = categories.iter().find(|c| c.id == current).and_then(|c| c.parent_id) {
+        if chain.contains(&parent_id) {
+            break;
+        }
+        chain.push(parent_id);
+        current = parent_id;
+    }
+    chain
+}
@@ -0,0 +1,138 @@
+use std::fmt;
+use std::str::FromStr;
+
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::data_types::PgNumeric;
+use diesel::pg::{Pg, PgValue};
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Numeric;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use utoipa::openapi::schema::{ObjectBuilder, Schema, Type};
+use utoipa::openapi::RefOr;
+use utoipa::{PartialSchema, ToSchema};
+
+use crate::money::{self, Money, ParseDecimalError};
+
+// Every rate is quoted against this currency, whose own rate is fixed at 1
+pub const REFERENCE_CURRENCY: &str = "EUR";
+
+const RATE_SCALE: u32 = 6;
+
+// Serde default for products that do not say which currency they are priced in
+pub fn reference_currency() -> String {
+    REFERENCE_CURRENCY.to_string()
+}
+
+// Units of a currency worth one unit of the reference currency, to six
+// decimals; stored as NUMERIC(18,6)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Numeric)]
+pub struct Rate(i64);
+
+impl Rate {
+    pub const ONE: Rate = Rate(1_000_000);
+
+    pub const fn from_micros(micros: i64) -> Self {
+        Rate(micros)
+    }
+
+    pub const fn micros(self) -> i64 {
+        self.0
+    }
+}
+
+// `amount` in a currency quoted at `from`, re-expressed in one quoted at `to`.
+// Rounds half away from zero to whole cents, like round(price * to / from, 2) in SQL.
+pub fn convert(amount: Money, from: Rate, to: Rate) -> Money {
+    if from == to {
+        return amount;
+    }
+    let numerator = amount.cents() as i128 * to.0 as i128;
+    let denominator = from.0 as i128;
+    let rounded = (2 * numerator + numerator.signum() * denominator) / (2 * denominator);
+    Money::from_cents(rounded as i64)
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        money::format_fixed(f, self.0, RATE_SCALE as usize)
+    }
+}
+
+impl FromStr for Rate {
+    type Err = ParseDecimalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        money::parse_fixed(s, RATE_SCALE as usize).map(Rate)
+    }
+}
+
+impl Serialize for Rate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        money::serialize_fixed(serializer, self.0, RATE_SCALE as usize)
+    }
+}
+
+impl<'de> Deserialize<'de> for Rate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        money::deserialize_fixed(deserializer, RATE_SCALE as usize).map(Rate)
+    }
+}
+
+impl PartialSchema for Rate {
+    fn schema() -> RefOr<Schema> {
+        ObjectBuilder::new()
+            .schema_type(Type::Number)
+            .multiple_of(Some(0.000001))
+            .description(Some("Units of the currency worth one EUR, at most six decimal places"))
+            .into()
+    }
+}
+
+impl ToSchema for Rate {}
+
+impl ToSql<Numeric, Pg> for Rate {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        let numeric = money::to_numeric(self.0, RATE_SCALE);
+        ToSql::<Numeric, Pg>::to_sql(&numeric, &mut out.reborrow())
+    }
+}
+
+impl FromSql<Numeric, Pg> for Rate {
+    fn from_sql(value: PgValue<'_>) -> deserialize::Result<Self> {
+        money::from_numeric(PgNumeric::from_sql(value)?, RATE_SCALE).map(Rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_rounds_to_cents() {
+        let usd: Rate = "1.08".parse().unwrap();
+        let jpy: Rate = "161.5".parse().unwrap();
+        assert_eq!(convert(Money::from_cents(99_99), Rate::ONE, usd), Money::from_cents(107_99));
+        assert_eq!(convert(Money::from_cents(107_99), usd, Rate::ONE), Money::from_cents(99_99));
+        // 10.00 USD = 1495.370370... JPY
+        assert_eq!(convert(Money::from_cents(10_00), usd, jpy), Money::from_cents(1495_37));
+        // 0.05 * 1.5 = 0.075 rounds up, as Postgres does
+        let half_up: Rate = "1.5".parse().unwrap();
+        assert_eq!(convert(Money::from_cents(5), Rate::ONE, half_up), Money::from_cents(8));
+    }
+
+    #[test]
+    fn test_rate_text_json_and_numeric() {
+        let rate: Rate = serde_json::from_str("1.083251").unwrap();
+        assert_eq!(rate, Rate::from_micros(1_083_251));
+        assert_eq!(rate.to_string(), "1.083251");
+        assert_eq!(serde_json::to_string(&rate).unwrap(), "1.083251");
+        assert!("1.0000001".parse::<Rate>().is_err());
+        for micros in [1, 999_999, 1_000_000, 1_083_251, 100_000_000_000] {
+            let rate = Rate::from_micros(micros);
+            let numeric = money::to_numeric(rate.0, RATE_SCALE);
+            assert_eq!(money::from_numeric(numeric, RATE_SCALE).unwrap(), micros);
+        }
+    }
+}
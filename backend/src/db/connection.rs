@@ -1,14 +1,11 @@
 use diesel::pg::PgConnection;
 use diesel::r2d2::{ConnectionManager, Pool, PooledConnection};
 use config::Config;
-use std::sync::OnceLock;
 use std::env;
 
 pub type PgPool = Pool<ConnectionManager<PgConnection>>;
 pub type PgPooledConnection = PooledConnection<ConnectionManager<PgConnection>>;
 
-static POOL: OnceLock<PgPool> = OnceLock::new();
-
 pub fn init_pool() -> PgPool {
     // First try to get DATABASE_URL from environment
     let database_url = env::var("DATABASE_URL")
@@ -38,11 +35,3 @@ pub fn init_pool() -> PgPool {
         .build(manager)
         .expect("Failed to create pool")
 }
-
-pub fn get_pool() -> &'static PgPool {
-    POOL.get_or_init(init_pool)
-}
-
-pub fn get_conn() -> PgPooledConnection {
-    get_pool().get().expect("Failed to get connection from pool")
-} 
\ No newline at end of file
@@ -0,0 +1,12 @@
+use diesel::pg::PgConnection;
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+pub type MigrationError = Box<dyn std::error::Error + Send + Sync>;
+
+// Applies whatever in `migrations/` has not run against this connection's search_path yet
+pub fn run_pending(conn: &mut PgConnection) -> Result<(), MigrationError> {
+    conn.run_pending_migrations(MIGRATIONS)?;
+    Ok(())
+}
@@ -2,6 +2,7 @@ pub mod models;
 pub mod schema;
 pub mod repository;
 pub mod connection;
+pub mod migrations;
 
 pub use models::*;
 pub use repository::*;
@@ -1,69 +1,187 @@
-use chrono::NaiveDateTime;
+use chrono::{NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 use diesel::prelude::*;
-use crate::db::schema::{categories, products, users, logs, monitored_users};
+use validator::Validate;
+use utoipa::ToSchema;
+use crate::currency::Rate;
+use crate::db::schema::{categories, products, product_price_history, users, logs, monitored_users, video_uploads, import_jobs};
+use crate::money::Money;
 
-#[derive(Queryable, Serialize, Deserialize, Debug, Clone)]
+#[derive(Queryable, Serialize, Deserialize, Debug, Clone, ToSchema)]
 #[diesel(table_name = categories)]
 pub struct Category {
     pub id: i32,
     pub name: String,
+    // Unique, URL-safe handle such as "running-shoes"
+    pub slug: String,
     pub description: String,
+    // None for a top-level category
+    pub parent_id: Option<i32>,
+    pub image: Option<String>,
+    // Siblings are listed by display_order, then name
+    pub display_order: i32,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
 
-#[derive(Insertable, Deserialize)]
-#[diesel(table_name = categories)]
+// Also a changeset so PUT can replace every column; no parent moves it to the top level
+#[derive(Insertable, AsChangeset, Deserialize)]
+#[diesel(table_name = categories, treat_none_as_null = true)]
 pub struct NewCategory {
     pub name: String,
+    pub slug: String,
     pub description: String,
+    pub parent_id: Option<i32>,
+    pub image: Option<String>,
+    pub display_order: i32,
 }
 
-#[derive(Queryable, Serialize, Deserialize, Debug, Clone)]
+#[derive(Queryable, Serialize, Deserialize, Debug, Clone, ToSchema)]
 #[diesel(table_name = products)]
 pub struct Product {
     pub id: i32,
+    // Unique stock keeping unit; imports with upsert update the product it names
+    pub sku: Option<String>,
     pub name: String,
-    pub price: f64,
+    pub price: Money,
+    pub currency: String,
     pub description: String,
     pub image: String,
+    // Sizes of `image` for responsive markup, once generated; see media::Srcset
+    #[schema(value_type = Option<crate::media::Srcset>)]
+    pub image_srcset: Option<serde_json::Value>,
+    // Where the video is stored; uploaded ones are streamed from /products/{id}/video
     pub video: Option<String>,
+    // Unpublished products' videos are only streamed to their owner and admins
+    pub published: bool,
     pub category_id: i32,
     pub user_id: i32,
+    pub stock: i32,
+    // Stock at or below this raises a low-stock alert; None turns alerts off
+    pub low_stock_threshold: Option<i32>,
+    // Values for its category's attributes, e.g. {"size": 42}
+    #[schema(value_type = Object)]
+    pub attributes: serde_json::Value,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
 
-#[derive(Insertable, Deserialize)]
-#[diesel(table_name = products)]
+// Serde default for products created or replaced without saying
+fn published_by_default() -> bool {
+    true
+}
+
+// Also a changeset so PUT can replace every column; a missing video clears it.
+// Stock is left out: it only changes through the audited stock movements.
+#[derive(Insertable, AsChangeset, Deserialize, Validate, ToSchema)]
+#[diesel(table_name = products, treat_none_as_null = true)]
 pub struct NewProduct {
+    #[validate(length(max = 64), custom(function = "crate::validation::sku"))]
+    pub sku: Option<String>,
+    #[validate(length(max = 255), custom(function = "crate::validation::not_blank"))]
     pub name: String,
-    pub price: f64,
+    #[validate(custom(function = "crate::validation::price_range"))]
+    pub price: Money,
+    // ISO 4217 code; EUR when omitted
+    #[serde(default = "crate::currency::reference_currency")]
+    #[validate(custom(function = "crate::validation::currency_code"))]
+    pub currency: String,
+    #[validate(length(max = 5000), custom(function = "crate::validation::not_blank"))]
     pub description: String,
+    #[validate(length(max = 2048), custom(function = "crate::validation::media_url"))]
     pub image: String,
+    #[validate(length(max = 2048), custom(function = "crate::validation::media_url"))]
     pub video: Option<String>,
+    // Published unless said otherwise
+    #[serde(default = "published_by_default")]
+    pub published: bool,
+    #[validate(range(min = 1))]
     pub category_id: i32,
+    #[validate(range(min = 1))]
     pub user_id: i32,
+    #[validate(range(min = 0))]
+    pub low_stock_threshold: Option<i32>,
+    // Checked against the category's attributes; none when omitted
+    #[serde(default = "crate::attributes::no_attributes")]
+    #[schema(value_type = Object)]
+    pub attributes: serde_json::Value,
 }
 
-#[derive(AsChangeset, Deserialize)]
+#[derive(AsChangeset, Default, Deserialize, Validate, ToSchema)]
 #[diesel(table_name = products)]
 pub struct UpdateProduct {
+    // Use PUT to remove the SKU
+    #[validate(length(max = 64), custom(function = "crate::validation::sku"))]
+    pub sku: Option<String>,
+    #[validate(length(max = 255), custom(function = "crate::validation::not_blank"))]
     pub name: Option<String>,
-    pub price: Option<f64>,
+    #[validate(custom(function = "crate::validation::price_range"))]
+    pub price: Option<Money>,
+    #[validate(custom(function = "crate::validation::currency_code"))]
+    pub currency: Option<String>,
+    #[validate(length(max = 5000), custom(function = "crate::validation::not_blank"))]
     pub description: Option<String>,
+    #[validate(length(max = 2048), custom(function = "crate::validation::media_url"))]
     pub image: Option<String>,
+    #[validate(length(max = 2048), custom(function = "crate::validation::media_url"))]
     pub video: Option<String>,
+    pub published: Option<bool>,
+    #[validate(range(min = 1))]
     pub category_id: Option<i32>,
+    #[validate(range(min = 1))]
     pub user_id: Option<i32>,
+    #[validate(range(min = 0))]
+    pub low_stock_threshold: Option<i32>,
+    // Merged into the stored values; a null removes that attribute
+    #[schema(value_type = Option<Object>)]
+    pub attributes: Option<serde_json::Value>,
+}
+
+impl UpdateProduct {
+    pub fn is_empty(&self) -> bool {
+        self.sku.is_none()
+            && self.name.is_none()
+            && self.price.is_none()
+            && self.currency.is_none()
+            && self.description.is_none()
+            && self.image.is_none()
+            && self.video.is_none()
+            && self.published.is_none()
+            && self.category_id.is_none()
+            && self.user_id.is_none()
+            && self.low_stock_threshold.is_none()
+            && self.attributes.is_none()
+    }
 }
 
-#[derive(AsChangeset, Deserialize)]
+#[derive(AsChangeset, Deserialize, Validate, ToSchema)]
 #[diesel(table_name = categories)]
 pub struct UpdateCategory {
+    #[validate(length(max = 100), custom(function = "crate::validation::not_blank"))]
     pub name: Option<String>,
+    #[validate(length(max = 1000))]
     pub description: Option<String>,
+    // Moves the category under another one; use PUT to move it back to the top level
+    #[validate(range(min = 1))]
+    pub parent_id: Option<i32>,
+    // Kept when the name changes, so existing links keep working
+    #[validate(length(max = 100), custom(function = "crate::validation::slug"))]
+    pub slug: Option<String>,
+    // Use PUT to remove the image
+    #[validate(length(max = 2048), custom(function = "crate::validation::media_url"))]
+    pub image: Option<String>,
+    pub display_order: Option<i32>,
+}
+
+impl UpdateCategory {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+            && self.description.is_none()
+            && self.parent_id.is_none()
+            && self.slug.is_none()
+            && self.image.is_none()
+            && self.display_order.is_none()
+    }
 }
 
 #[derive(Queryable, Serialize, Deserialize, Debug, Clone)]
@@ -92,6 +210,7 @@ pub struct Log {
     pub entity: String,
     pub entity_id: Option<i32>,
     pub timestamp: NaiveDateTime,
+    pub reason: Option<String>,
 }
 
 #[derive(Insertable, Deserialize)]
@@ -102,11 +221,304 @@ pub struct NewLog {
     pub entity: String,
     pub entity_id: Option<i32>,
     pub timestamp: NaiveDateTime,
+    pub reason: Option<String>,
 }
 
-#[derive(Queryable, Serialize, Debug, Clone)]
+#[derive(Queryable, Serialize, Debug, Clone, ToSchema)]
 #[diesel(table_name = monitored_users)]
 pub struct MonitoredUser {
     pub user_id: i32,
     pub username: String,
-} 
\ No newline at end of file
+} 
+
+#[derive(Queryable, Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ExchangeRate {
+    pub currency: String,
+    pub rate: Rate,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct PriceChange {
+    pub id: i32,
+    pub product_id: i32,
+    // None for the entry written when the product was created
+    pub old_price: Option<Money>,
+    pub old_currency: Option<String>,
+    pub new_price: Money,
+    pub new_currency: String,
+    pub changed_at: NaiveDateTime,
+}
+
+impl PriceChange {
+    // The price before this change; a product's first entry only has its starting price
+    pub fn price_before(&self) -> (Money, &str) {
+        match (self.old_price, &self.old_currency) {
+            (Some(price), Some(currency)) => (price, currency),
+            _ => (self.new_price, &self.new_currency),
+        }
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = product_price_history)]
+pub struct NewPriceChange {
+    pub product_id: i32,
+    pub old_price: Option<Money>,
+    pub old_currency: Option<String>,
+    pub new_price: Money,
+    pub new_currency: String,
+    pub changed_at: NaiveDateTime,
+}
+
+impl NewPriceChange {
+    // The entry for `after`, or None when its price and currency match `before`
+    pub fn between(before: Option<&Product>, after: &Product) -> Option<NewPriceChange> {
+        if let Some(before) = before {
+            if before.price == after.price && before.currency == after.currency {
+                return None;
+            }
+        }
+        Some(NewPriceChange {
+            product_id: after.id,
+            old_price: before.map(|p| p.price),
+            old_currency: before.map(|p| p.currency.clone()),
+            new_price: after.price,
+            new_currency: after.currency.clone(),
+            changed_at: Utc::now().naive_utc(),
+        })
+    }
+}
+
+// One option of a variant and the value it takes, e.g. size 42
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Validate, ToSchema)]
+pub struct VariantOption {
+    #[validate(length(max = 50), custom(function = "crate::validation::not_blank"))]
+    pub name: String,
+    #[validate(length(max = 100), custom(function = "crate::validation::not_blank"))]
+    pub value: String,
+}
+
+// A sellable combination of option values; its options come in the product's option order
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ProductVariant {
+    pub id: i32,
+    pub product_id: i32,
+    pub sku: String,
+    // Replaces the product's price, in the product's currency; None keeps the product's price
+    pub price: Option<Money>,
+    pub stock: i32,
+    // None shows the product's image
+    pub image: Option<String>,
+    pub options: Vec<VariantOption>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl ProductVariant {
+    // Option names and values compare case-insensitively, like lower() in SQL
+    pub fn has_option(&self, name: &str, value: &str) -> bool {
+        let (name, value) = (name.to_lowercase(), value.to_lowercase());
+        self.options
+            .iter()
+            .any(|o| o.name.to_lowercase() == name && o.value.to_lowercase() == value)
+    }
+
+    pub fn has_options(&self, options: &[VariantOption]) -> bool {
+        self.options.len() == options.len() && options.iter().all(|o| self.has_option(&o.name, &o.value))
+    }
+}
+
+// Body of POST and PUT. Stock is left out: a variant starts with none and it
+// only changes through the audited stock movements, as for products.
+#[derive(Deserialize, Validate, ToSchema)]
+pub struct NewVariant {
+    #[validate(length(max = 64), custom(function = "crate::validation::sku"))]
+    pub sku: String,
+    #[validate(custom(function = "crate::validation::price_range"))]
+    pub price: Option<Money>,
+    #[validate(length(max = 2048), custom(function = "crate::validation::media_url"))]
+    pub image: Option<String>,
+    #[validate(length(min = 1, max = 5), custom(function = "crate::validation::distinct_option_names"), nested)]
+    pub options: Vec<VariantOption>,
+}
+
+#[derive(Default, Deserialize, Validate, ToSchema)]
+pub struct UpdateVariant {
+    #[validate(length(max = 64), custom(function = "crate::validation::sku"))]
+    pub sku: Option<String>,
+    #[validate(custom(function = "crate::validation::price_range"))]
+    pub price: Option<Money>,
+    #[validate(length(max = 2048), custom(function = "crate::validation::media_url"))]
+    pub image: Option<String>,
+    #[validate(length(min = 1, max = 5), custom(function = "crate::validation::distinct_option_names"), nested)]
+    pub options: Option<Vec<VariantOption>>,
+}
+
+impl UpdateVariant {
+    pub fn is_empty(&self) -> bool {
+        self.sku.is_none() && self.price.is_none() && self.image.is_none() && self.options.is_none()
+    }
+
+    // The complete variant after applying these changes to `current`
+    pub fn apply(self, current: ProductVariant) -> NewVariant {
+        NewVariant {
+            sku: self.sku.unwrap_or(current.sku),
+            price: self.price.or(current.price),
+            image: self.image.or(current.image),
+            options: self.options.unwrap_or(current.options),
+        }
+    }
+}
+
+// A typed attribute a category asks of its products; subcategories inherit it
+#[derive(Queryable, Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct CategoryAttribute {
+    pub id: i32,
+    // The category that defines it, which may be an ancestor of the one asked about
+    pub category_id: i32,
+    pub name: String,
+    // One of validation::ATTRIBUTE_KINDS
+    pub kind: String,
+    pub required: bool,
+    // What an enum attribute may take; empty for every other kind
+    pub allowed_values: Vec<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+// Body of POST and PUT; allowed_values is checked against the kind by
+// validation::ensure_allowed_values
+#[derive(Deserialize, Validate, ToSchema)]
+pub struct NewAttribute {
+    #[validate(length(max = 50), custom(function = "crate::validation::attribute_name"))]
+    pub name: String,
+    #[validate(custom(function = "crate::validation::attribute_kind"))]
+    pub kind: String,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    #[validate(length(max = 100))]
+    pub allowed_values: Vec<String>,
+}
+
+#[derive(Queryable, Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct Tag {
+    pub id: i32,
+    pub name: String,
+    pub created_at: NaiveDateTime,
+}
+
+// Body of POST and PUT /tags
+#[derive(Deserialize, Validate, ToSchema)]
+pub struct NewTag {
+    #[validate(length(max = 50), custom(function = "crate::validation::tag_name"))]
+    pub name: String,
+}
+
+#[derive(Queryable, Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ProductImage {
+    pub id: i32,
+    pub product_id: i32,
+    // Where the file sits under the media directory, e.g. images/<sha256>.jpg
+    pub key: String,
+    pub url: String,
+    // Sniffed from the bytes, not taken from the upload
+    pub content_type: String,
+    pub size_bytes: i64,
+    // Known once the renditions are generated
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    // pending until the rendition job has run, then ready or failed
+    pub renditions_status: String,
+    #[schema(value_type = Option<crate::media::Srcset>)]
+    pub srcset: Option<serde_json::Value>,
+    pub created_at: NaiveDateTime,
+}
+
+pub struct NewImage {
+    pub key: String,
+    pub url: String,
+    pub content_type: String,
+    pub size_bytes: i64,
+}
+
+// A resumable video upload; see video_uploads::append
+#[derive(Queryable, Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct VideoUpload {
+    pub id: String,
+    pub product_id: i32,
+    pub size_bytes: i64,
+    // The offset the next chunk has to start at
+    pub received_bytes: i64,
+    // Lowercase hex SHA-256 of the whole file, checked once every byte is in
+    pub sha256: String,
+    // URL of the finished video; null while the upload is in progress
+    pub video: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = video_uploads)]
+pub struct NewVideoUpload {
+    pub id: String,
+    pub product_id: i32,
+    pub size_bytes: i64,
+    pub sha256: String,
+}
+
+// A bulk import of products; see imports::run
+#[derive(Queryable, Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ImportJob {
+    pub id: String,
+    // Owner of the products it creates
+    pub user_id: i32,
+    // csv or ndjson
+    pub format: String,
+    // Rows are checked and counted as if imported, then rolled back
+    pub dry_run: bool,
+    // Rows whose SKU names an existing product update it
+    pub upsert: bool,
+    // Category names that match no category create one
+    pub create_categories: bool,
+    // pending until the import job takes it on, then running, then finished or failed
+    pub status: String,
+    pub total_rows: i32,
+    pub processed_rows: i32,
+    pub created_rows: i32,
+    pub updated_rows: i32,
+    pub failed_rows: i32,
+    // The first imports::MAX_REPORTED_ERRORS failed rows, in file order
+    #[schema(value_type = Vec<crate::imports::RowError>)]
+    pub row_errors: serde_json::Value,
+    // Why the job stopped short, if it failed
+    pub error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = import_jobs)]
+pub struct NewImportJob {
+    pub id: String,
+    pub user_id: i32,
+    pub format: String,
+    pub dry_run: bool,
+    pub upsert: bool,
+    pub create_categories: bool,
+    pub status: String,
+    pub total_rows: i32,
+    pub source: String,
+}
+
+// Where a job stands after a batch; saved with the batch it describes
+#[derive(AsChangeset, Debug, Clone)]
+#[diesel(table_name = import_jobs)]
+pub struct ImportProgress {
+    pub processed_rows: i32,
+    pub created_rows: i32,
+    pub updated_rows: i32,
+    pub failed_rows: i32,
+    pub row_errors: serde_json::Value,
+}
@@ -1,152 +1,641 @@
+use std::collections::HashMap;
+
 use diesel::prelude::*;
 use diesel::dsl::*;
 use chrono::Utc;
-use crate::db::connection::get_conn;
+use crate::currency::Rate;
 use crate::db::models::*;
 use crate::db::schema::*;
+use crate::models::{OptionFilter, ProductQuery, TagFilter, TagMatch};
+use crate::money::Money;
 use serde::Serialize;
+use utoipa::ToSchema;
 
-pub struct ProductRepository;
+// Every function takes the connection to run on, so callers can pass a pooled
+// connection or the one inside `conn.transaction(|conn| ...)`
 
-impl ProductRepository {
-    pub fn create(new_product: NewProduct) -> Result<Product, diesel::result::Error> {
-        let conn = &mut get_conn();
-        diesel::insert_into(products::table)
-            .values(&new_product)
-            .get_result(conn)
-    }
+// Product writes also append to product_price_history, in the same transaction
 
-    pub fn get_by_id(id: i32) -> Result<Product, diesel::result::Error> {
-        let conn = &mut get_conn();
-        products::table.find(id).first(conn)
-    }
+pub fn create_product(conn: &mut PgConnection, new_product: NewProduct) -> QueryResult<Product> {
+    conn.transaction(|conn| {
+        let product: Product = diesel::insert_into(products::table)
+            .values(new_product)
+            .get_result(conn)?;
+        record_price_change(conn, None, &product)?;
+        Ok(product)
+    })
+}
+
+pub fn get_product(conn: &mut PgConnection, id: i32) -> QueryResult<Product> {
+    products::table.find(id).first(conn)
+}
 
-    pub fn update(id: i32, update_data: UpdateProduct) -> Result<Product, diesel::result::Error> {
-        let conn = &mut get_conn();
-        diesel::update(products::table.find(id))
+pub fn get_product_by_sku(conn: &mut PgConnection, sku: &str) -> QueryResult<Product> {
+    products::table.filter(products::sku.eq(sku)).first(conn)
+}
+
+pub fn update_product(conn: &mut PgConnection, id: i32, product: UpdateProduct) -> QueryResult<Product> {
+    conn.transaction(|conn| {
+        let before = products::table.find(id).for_update().first::<Product>(conn)?;
+        let after = diesel::update(products::table.find(id))
             .set((
-                update_data,
+                product,
                 products::updated_at.eq(Utc::now().naive_utc()),
             ))
-            .get_result(conn)
+            .get_result(conn)?;
+        record_price_change(conn, Some(&before), &after)?;
+        Ok(after)
+    })
+}
+
+pub fn replace_product(conn: &mut PgConnection, id: i32, product: &NewProduct) -> QueryResult<Product> {
+    conn.transaction(|conn| {
+        let before = products::table.find(id).for_update().first::<Product>(conn)?;
+        let after = diesel::update(products::table.find(id))
+            .set((
+                product,
+                products::updated_at.eq(Utc::now().naive_utc()),
+            ))
+            .get_result(conn)?;
+        record_price_change(conn, Some(&before), &after)?;
+        Ok(after)
+    })
+}
+
+fn record_price_change(conn: &mut PgConnection, before: Option<&Product>, after: &Product) -> QueryResult<()> {
+    if let Some(change) = NewPriceChange::between(before, after) {
+        diesel::insert_into(product_price_history::table)
+            .values(change)
+            .execute(conn)?;
     }
+    Ok(())
+}
 
-    pub fn delete(id: i32) -> Result<usize, diesel::result::Error> {
-        let conn = &mut get_conn();
-        diesel::delete(products::table.find(id)).execute(conn)
+// Stock range `delta` can be applied to, as bounds so Postgres never sums past i32
+fn stock_bounds(delta: i32) -> (i32, i32) {
+    if delta < 0 {
+        (delta.saturating_neg(), i32::MAX)
+    } else {
+        (0, i32::MAX - delta)
     }
+}
 
-    pub fn get_all(
-        category_id: Option<i32>,
-        min_price: Option<f64>,
-        max_price: Option<f64>,
-        search_term: Option<String>,
-        sort_by: Option<String>,
-        sort_order: Option<String>,
-    ) -> Result<Vec<Product>, diesel::result::Error> {
-        let conn = &mut get_conn();
-        let mut query = products::table.into_boxed();
-
-        if let Some(cat_id) = category_id {
-            query = query.filter(products::category_id.eq(cat_id));
-        }
+// Adds `delta` (negative takes stock out) in a single guarded UPDATE, so
+// concurrent movements can never oversell. None if the product is missing or
+// the stock would go below zero or past the column's maximum.
+pub fn adjust_stock(conn: &mut PgConnection, id: i32, delta: i32) -> QueryResult<Option<Product>> {
+    let (min, max) = stock_bounds(delta);
+    diesel::update(products::table.find(id).filter(products::stock.between(min, max)))
+        .set((
+            products::stock.eq(products::stock + delta),
+            products::updated_at.eq(Utc::now().naive_utc()),
+        ))
+        .get_result(conn)
+        .optional()
+}
 
-        if let Some(min) = min_price {
-            query = query.filter(products::price.ge(min));
-        }
+// Products at or below their low-stock threshold, emptiest first
+pub fn get_low_stock_products(conn: &mut PgConnection) -> QueryResult<Vec<Product>> {
+    products::table
+        .filter(products::stock.nullable().le(products::low_stock_threshold))
+        .order((products::stock.asc(), products::id.asc()))
+        .load(conn)
+}
 
-        if let Some(max) = max_price {
-            query = query.filter(products::price.le(max));
-        }
+// Oldest first
+pub fn get_price_history(conn: &mut PgConnection, product_id: i32) -> QueryResult<Vec<PriceChange>> {
+    product_price_history::table
+        .filter(product_price_history::product_id.eq(product_id))
+        .order((product_price_history::changed_at.asc(), product_price_history::id.asc()))
+        .load(conn)
+}
 
-        if let Some(term) = search_term {
-            let search_pattern = format!("%{}%", term);
-            let pattern = search_pattern.clone();
-            query = query.filter(
-                products::name.ilike(pattern)
-                    .or(products::description.ilike(search_pattern))
-            );
-        }
+// Products now cheaper than they were at `since` (or when created, if later), in
+// the same currency. The first change after `since` carries that price: its old
+// price, or its new one if it is the product's first entry.
+fn products_with_price_drop_since(conn: &mut PgConnection, since: chrono::NaiveDateTime) -> QueryResult<Vec<i32>> {
+    let first_changes: Vec<(i32, Money, String, PriceChange)> = product_price_history::table
+        .inner_join(products::table)
+        .filter(product_price_history::changed_at.ge(since))
+        .distinct_on(product_price_history::product_id)
+        .order((
+            product_price_history::product_id,
+            product_price_history::changed_at,
+            product_price_history::id,
+        ))
+        .select((
+            products::id,
+            products::price,
+            products::currency,
+            product_price_history::all_columns,
+        ))
+        .load(conn)?;
+    Ok(first_changes
+        .into_iter()
+        .filter(|(_, price, currency, first_change)| {
+            let (earlier_price, earlier_currency) = first_change.price_before();
+            earlier_currency == currency && *price < earlier_price
+        })
+        .map(|(id, ..)| id)
+        .collect())
+}
 
-        if let Some(sort) = sort_by {
-            let order = sort_order.unwrap_or_else(|| "asc".to_string());
-            query = match (sort.as_str(), order.as_str()) {
-                ("name", "asc") => query.order(products::name.asc()),
-                ("name", "desc") => query.order(products::name.desc()),
-                ("price", "asc") => query.order(products::price.asc()),
-                ("price", "desc") => query.order(products::price.desc()),
-                ("created_at", "asc") => query.order(products::created_at.asc()),
-                ("created_at", "desc") => query.order(products::created_at.desc()),
-                _ => query.order(products::id.asc()),
-            };
-        } else {
-            query = query.order(products::id.asc());
-        }
+// Variant rows before their options are attached
+#[derive(Queryable)]
+struct VariantRow {
+    id: i32,
+    product_id: i32,
+    sku: String,
+    price: Option<Money>,
+    stock: i32,
+    image: Option<String>,
+    created_at: chrono::NaiveDateTime,
+    updated_at: chrono::NaiveDateTime,
+}
 
-        query.load(conn)
+// Loads the options of every row in one query, in the product's option order
+fn with_options(conn: &mut PgConnection, rows: Vec<VariantRow>) -> QueryResult<Vec<ProductVariant>> {
+    let variant_ids: Vec<i32> = rows.iter().map(|row| row.id).collect();
+    let pairs: Vec<(i32, String, String)> = product_variant_values::table
+        .inner_join(product_option_values::table.inner_join(product_options::table))
+        .filter(product_variant_values::variant_id.eq_any(variant_ids))
+        .order((product_options::position.asc(), product_options::id.asc()))
+        .select((product_variant_values::variant_id, product_options::name, product_option_values::value))
+        .load(conn)?;
+    let mut options: HashMap<i32, Vec<VariantOption>> = HashMap::new();
+    for (variant_id, name, value) in pairs {
+        options.entry(variant_id).or_default().push(VariantOption { name, value });
     }
+    Ok(rows
+        .into_iter()
+        .map(|row| ProductVariant {
+            options: options.remove(&row.id).unwrap_or_default(),
+            id: row.id,
+            product_id: row.product_id,
+            sku: row.sku,
+            price: row.price,
+            stock: row.stock,
+            image: row.image,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        })
+        .collect())
 }
 
-pub struct CategoryRepository;
+pub fn get_variants(conn: &mut PgConnection, product_id: i32) -> QueryResult<Vec<ProductVariant>> {
+    let rows = product_variants::table
+        .filter(product_variants::product_id.eq(product_id))
+        .order(product_variants::id.asc())
+        .load(conn)?;
+    with_options(conn, rows)
+}
 
-impl CategoryRepository {
-    pub fn create(new_category: NewCategory) -> Result<Category, diesel::result::Error> {
-        let conn = &mut get_conn();
-        diesel::insert_into(categories::table)
-            .values(&new_category)
-            .get_result(conn)
-    }
+pub fn get_variant(conn: &mut PgConnection, product_id: i32, id: i32) -> QueryResult<ProductVariant> {
+    let row = product_variants::table
+        .find(id)
+        .filter(product_variants::product_id.eq(product_id))
+        .first(conn)?;
+    Ok(with_options(conn, vec![row])?.remove(0))
+}
 
-    pub fn get_by_id(id: i32) -> Result<Category, diesel::result::Error> {
-        let conn = &mut get_conn();
-        categories::table.find(id).first(conn)
-    }
+pub fn create_variant(conn: &mut PgConnection, product_id: i32, variant: NewVariant) -> QueryResult<ProductVariant> {
+    conn.transaction(|conn| {
+        let id = diesel::insert_into(product_variants::table)
+            .values((
+                product_variants::product_id.eq(product_id),
+                product_variants::sku.eq(&variant.sku),
+                product_variants::price.eq(variant.price),
+                product_variants::image.eq(&variant.image),
+            ))
+            .returning(product_variants::id)
+            .get_result(conn)?;
+        link_option_values(conn, product_id, id, &variant.options)?;
+        get_variant(conn, product_id, id)
+    })
+}
 
-    pub fn update(id: i32, update_data: UpdateCategory) -> Result<Category, diesel::result::Error> {
-        let conn = &mut get_conn();
-        diesel::update(categories::table.find(id))
+pub fn replace_variant(conn: &mut PgConnection, product_id: i32, id: i32, variant: NewVariant) -> QueryResult<ProductVariant> {
+    conn.transaction(|conn| {
+        let updated = diesel::update(product_variants::table.find(id).filter(product_variants::product_id.eq(product_id)))
             .set((
-                update_data,
-                categories::updated_at.eq(Utc::now().naive_utc()),
+                product_variants::sku.eq(&variant.sku),
+                product_variants::price.eq(variant.price),
+                product_variants::image.eq(&variant.image),
+                product_variants::updated_at.eq(Utc::now().naive_utc()),
             ))
-            .get_result(conn)
+            .execute(conn)?;
+        if updated == 0 {
+            return Err(diesel::result::Error::NotFound);
+        }
+        diesel::delete(product_variant_values::table.filter(product_variant_values::variant_id.eq(id))).execute(conn)?;
+        link_option_values(conn, product_id, id, &variant.options)?;
+        prune_options(conn, product_id)?;
+        get_variant(conn, product_id, id)
+    })
+}
+
+pub fn delete_variant(conn: &mut PgConnection, product_id: i32, id: i32) -> QueryResult<usize> {
+    conn.transaction(|conn| {
+        let deleted = diesel::delete(product_variants::table.find(id).filter(product_variants::product_id.eq(product_id)))
+            .execute(conn)?;
+        prune_options(conn, product_id)?;
+        Ok(deleted)
+    })
+}
+
+// adjust_stock for one variant
+pub fn adjust_variant_stock(conn: &mut PgConnection, product_id: i32, id: i32, delta: i32) -> QueryResult<Option<ProductVariant>> {
+    let (min, max) = stock_bounds(delta);
+    let row = diesel::update(
+        product_variants::table
+            .find(id)
+            .filter(product_variants::product_id.eq(product_id))
+            .filter(product_variants::stock.between(min, max)),
+    )
+    .set((
+        product_variants::stock.eq(product_variants::stock + delta),
+        product_variants::updated_at.eq(Utc::now().naive_utc()),
+    ))
+    .get_result(conn)
+    .optional()?;
+    match row {
+        Some(row) => Ok(with_options(conn, vec![row])?.pop()),
+        None => Ok(None),
     }
+}
 
-    pub fn delete(id: i32) -> Result<usize, diesel::result::Error> {
-        let conn = &mut get_conn();
-        diesel::delete(categories::table.find(id)).execute(conn)
+// Points the variant at its option values, first adding the options and values
+// the product does not have yet. New options go after the existing ones.
+fn link_option_values(conn: &mut PgConnection, product_id: i32, variant_id: i32, options: &[VariantOption]) -> QueryResult<()> {
+    for option in options {
+        let existing_option = product_options::table
+            .filter(product_options::product_id.eq(product_id))
+            .filter(product_options::name.eq(&option.name))
+            .select(product_options::id)
+            .first::<i32>(conn)
+            .optional()?;
+        let option_id = match existing_option {
+            Some(option_id) => option_id,
+            None => {
+                let last_position: Option<i32> = product_options::table
+                    .filter(product_options::product_id.eq(product_id))
+                    .select(max(product_options::position))
+                    .first(conn)?;
+                diesel::insert_into(product_options::table)
+                    .values((
+                        product_options::product_id.eq(product_id),
+                        product_options::name.eq(&option.name),
+                        product_options::position.eq(last_position.map_or(0, |position| position + 1)),
+                    ))
+                    .returning(product_options::id)
+                    .get_result(conn)?
+            }
+        };
+        let existing_value = product_option_values::table
+            .filter(product_option_values::option_id.eq(option_id))
+            .filter(product_option_values::value.eq(&option.value))
+            .select(product_option_values::id)
+            .first::<i32>(conn)
+            .optional()?;
+        let option_value_id = match existing_value {
+            Some(option_value_id) => option_value_id,
+            None => diesel::insert_into(product_option_values::table)
+                .values((product_option_values::option_id.eq(option_id), product_option_values::value.eq(&option.value)))
+                .returning(product_option_values::id)
+                .get_result(conn)?,
+        };
+        diesel::insert_into(product_variant_values::table)
+            .values((
+                product_variant_values::variant_id.eq(variant_id),
+                product_variant_values::option_value_id.eq(option_value_id),
+            ))
+            .execute(conn)?;
     }
+    Ok(())
+}
+
+// Drops the values no variant uses any more, then the options left without values
+fn prune_options(conn: &mut PgConnection, product_id: i32) -> QueryResult<()> {
+    let option_ids: Vec<i32> = product_options::table
+        .filter(product_options::product_id.eq(product_id))
+        .select(product_options::id)
+        .load(conn)?;
+    let used_values = product_variant_values::table.select(product_variant_values::option_value_id);
+    diesel::delete(
+        product_option_values::table
+            .filter(product_option_values::option_id.eq_any(&option_ids))
+            .filter(product_option_values::id.ne_all(used_values)),
+    )
+    .execute(conn)?;
+    let used_options = product_option_values::table.select(product_option_values::option_id);
+    diesel::delete(
+        product_options::table
+            .filter(product_options::id.eq_any(&option_ids))
+            .filter(product_options::id.ne_all(used_options)),
+    )
+    .execute(conn)?;
+    Ok(())
+}
 
-    pub fn get_all() -> Result<Vec<Category>, diesel::result::Error> {
-        let conn = &mut get_conn();
-        categories::table.order(categories::name.asc()).load(conn)
+// Products with a variant that has every pair in `filter`. With `in_stock`,
+// only variants with stock left (true) or sold out (false) count.
+fn products_with_variant(conn: &mut PgConnection, filter: &OptionFilter, in_stock: Option<bool>) -> QueryResult<Vec<i32>> {
+    let mut variants = product_variants::table
+        .select(product_variants::product_id)
+        .distinct()
+        .into_boxed();
+    for (name, value) in &filter.0 {
+        let with_value = product_variant_values::table
+            .inner_join(product_option_values::table.inner_join(product_options::table))
+            .filter(lower(product_options::name).eq(name))
+            .filter(lower(product_option_values::value).eq(value))
+            .select(product_variant_values::variant_id);
+        variants = variants.filter(product_variants::id.eq_any(with_value));
     }
+    variants = match in_stock {
+        Some(true) => variants.filter(product_variants::stock.gt(0)),
+        Some(false) => variants.filter(product_variants::stock.eq(0)),
+        None => variants,
+    };
+    variants.load(conn)
+}
+
+// Oldest first
+pub fn get_product_images(conn: &mut PgConnection, product_id: i32) -> QueryResult<Vec<ProductImage>> {
+    product_images::table
+        .filter(product_images::product_id.eq(product_id))
+        .order(product_images::id.asc())
+        .load(conn)
+}
+
+pub fn get_product_image(conn: &mut PgConnection, product_id: i32, id: i32) -> QueryResult<ProductImage> {
+    product_images::table
+        .find(id)
+        .filter(product_images::product_id.eq(product_id))
+        .first(conn)
+}
+
+pub fn create_product_image(conn: &mut PgConnection, product_id: i32, image: &NewImage) -> QueryResult<ProductImage> {
+    diesel::insert_into(product_images::table)
+        .values((
+            product_images::product_id.eq(product_id),
+            product_images::key.eq(&image.key),
+            product_images::url.eq(&image.url),
+            product_images::content_type.eq(&image.content_type),
+            product_images::size_bytes.eq(image.size_bytes),
+        ))
+        .get_result(conn)
+}
+
+pub fn delete_product_image(conn: &mut PgConnection, product_id: i32, id: i32) -> QueryResult<usize> {
+    diesel::delete(product_images::table.find(id).filter(product_images::product_id.eq(product_id))).execute(conn)
+}
+
+pub fn image_key_in_use(conn: &mut PgConnection, key: &str) -> QueryResult<bool> {
+    select(exists(product_images::table.filter(product_images::key.eq(key)))).get_result(conn)
+}
+
+pub fn get_pending_renditions(conn: &mut PgConnection, limit: i64) -> QueryResult<Vec<ProductImage>> {
+    product_images::table
+        .filter(product_images::renditions_status.eq("pending"))
+        .order(product_images::id.asc())
+        .limit(limit)
+        .load(conn)
+}
+
+// Also points every product already showing this image at the new srcset; the
+// products_image_srcset trigger only covers later changes of products.image
+pub fn finish_renditions(
+    conn: &mut PgConnection,
+    id: i32,
+    width: i32,
+    height: i32,
+    srcset: &serde_json::Value,
+) -> QueryResult<usize> {
+    conn.transaction(|conn| {
+        let url: Option<String> = diesel::update(product_images::table.find(id))
+            .set((
+                product_images::width.eq(width),
+                product_images::height.eq(height),
+                product_images::renditions_status.eq("ready"),
+                product_images::srcset.eq(srcset),
+            ))
+            .returning(product_images::url)
+            .get_result(conn)
+            .optional()?;
+        let Some(url) = url else {
+            return Ok(0);
+        };
+        diesel::update(products::table.filter(products::image.eq(&url)))
+            .set(products::image_srcset.eq(srcset))
+            .execute(conn)?;
+        Ok(1)
+    })
+}
+
+pub fn fail_renditions(conn: &mut PgConnection, id: i32) -> QueryResult<usize> {
+    diesel::update(product_images::table.find(id))
+        .set(product_images::renditions_status.eq("failed"))
+        .execute(conn)
+}
+
+define_sql_function! {
+    fn left(value: diesel::sql_types::Nullable<diesel::sql_types::Varchar>, count: diesel::sql_types::Integer) -> diesel::sql_types::Nullable<diesel::sql_types::Varchar>;
+}
+
+define_sql_function! {
+    fn substr(value: diesel::sql_types::Nullable<diesel::sql_types::Varchar>, start: diesel::sql_types::Integer) -> diesel::sql_types::Nullable<diesel::sql_types::Varchar>;
+}
+
+define_sql_function! {
+    #[sql_name = "concat"]
+    fn concat_text(first: diesel::sql_types::Varchar, second: diesel::sql_types::Nullable<diesel::sql_types::Varchar>) -> diesel::sql_types::Varchar;
+}
+
+// `url` with its `from` prefix swapped for `to`, if it has it
+fn rebase_url(url: &str, from: &str, to: &str) -> Option<String> {
+    url.strip_prefix(from).map(|rest| format!("{}{}", to, rest))
+}
 
-    pub fn get_products_by_category(category_id: i32) -> Result<Vec<Product>, diesel::result::Error> {
-        let conn = &mut get_conn();
-        products::table
-            .filter(products::category_id.eq(category_id))
-            .order(products::name.asc())
-            .load(conn)
+// Swaps the prefix in each "url width" entry of every srcset string
+fn rebase_srcset(srcset: &serde_json::Value, from: &str, to: &str) -> serde_json::Value {
+    let mut srcset = srcset.clone();
+    if let Some(formats) = srcset.as_object_mut() {
+        for entries in formats.values_mut() {
+            if let Some(text) = entries.as_str() {
+                let rebased: Vec<String> =
+                    text.split(", ").map(|entry| rebase_url(entry, from, to).unwrap_or_else(|| entry.to_string())).collect();
+                *entries = serde_json::Value::String(rebased.join(", "));
+            }
+        }
     }
+    srcset
 }
 
-pub fn create_product(conn: &mut PgConnection, new_product: NewProduct) -> QueryResult<Product> {
-    diesel::insert_into(products::table)
-        .values(new_product)
+// Points every stored media URL that starts with `from` at `to` instead, for
+// when the media has moved to another storage backend. Products' srcsets follow
+// through the products_image_srcset trigger. Returns how many rows changed.
+pub fn rewrite_media_urls(conn: &mut PgConnection, from: &str, to: &str) -> QueryResult<usize> {
+    let length = from.chars().count() as i32;
+    conn.transaction(|conn| {
+        macro_rules! rewrite {
+            ($table:ident, $column:ident $(, $nullable:ident)?) => {
+                diesel::update($table::table.filter(left($table::$column.nullable(), length).eq(from)))
+                    .set($table::$column.eq(concat_text(to, substr($table::$column.nullable(), length + 1))$(.$nullable())?))
+                    .execute(conn)?
+            };
+        }
+        let images: Vec<(i32, Option<serde_json::Value>)> = product_images::table
+            .filter(left(product_images::url.nullable(), length).eq(from))
+            .select((product_images::id, product_images::srcset))
+            .load(conn)?;
+        for (id, srcset) in &images {
+            if let Some(srcset) = srcset {
+                diesel::update(product_images::table.find(id))
+                    .set(product_images::srcset.eq(rebase_srcset(srcset, from, to)))
+                    .execute(conn)?;
+            }
+        }
+        let mut changed = rewrite!(product_images, url);
+        changed += rewrite!(products, image);
+        changed += rewrite!(products, video, nullable);
+        changed += rewrite!(product_variants, image, nullable);
+        changed += rewrite!(categories, image, nullable);
+        changed += rewrite!(video_uploads, video, nullable);
+        Ok(changed)
+    })
+}
+
+pub fn get_video_upload(conn: &mut PgConnection, id: &str) -> QueryResult<VideoUpload> {
+    video_uploads::table.find(id).first(conn)
+}
+
+pub fn lock_video_upload(conn: &mut PgConnection, id: &str) -> QueryResult<VideoUpload> {
+    video_uploads::table.find(id).for_update().first(conn)
+}
+
+pub fn create_video_upload(conn: &mut PgConnection, upload: &NewVideoUpload) -> QueryResult<VideoUpload> {
+    diesel::insert_into(video_uploads::table).values(upload).get_result(conn)
+}
+
+pub fn set_video_upload_received(conn: &mut PgConnection, id: &str, received_bytes: i64) -> QueryResult<VideoUpload> {
+    diesel::update(video_uploads::table.find(id))
+        .set((
+            video_uploads::received_bytes.eq(received_bytes),
+            video_uploads::updated_at.eq(Utc::now().naive_utc()),
+        ))
         .get_result(conn)
 }
 
-pub fn get_product(conn: &mut PgConnection, id: i32) -> QueryResult<Product> {
-    products::table.find(id).first(conn)
+pub fn complete_video_upload(conn: &mut PgConnection, id: &str, video: &str) -> QueryResult<VideoUpload> {
+    diesel::update(video_uploads::table.find(id))
+        .set((
+            video_uploads::video.eq(video),
+            video_uploads::updated_at.eq(Utc::now().naive_utc()),
+        ))
+        .get_result(conn)
 }
 
-pub fn update_product(conn: &mut PgConnection, id: i32, product: UpdateProduct) -> QueryResult<Product> {
-    diesel::update(products::table.find(id))
+pub fn delete_video_upload(conn: &mut PgConnection, id: &str) -> QueryResult<usize> {
+    diesel::delete(video_uploads::table.find(id)).execute(conn)
+}
+
+pub fn delete_stale_video_uploads(conn: &mut PgConnection, cutoff: chrono::NaiveDateTime) -> QueryResult<Vec<VideoUpload>> {
+    diesel::delete(video_uploads::table.filter(video_uploads::updated_at.lt(cutoff))).get_results(conn)
+}
+
+// Every column but the uploaded file, which only the job running the import reads
+type ImportJobColumns = (
+    import_jobs::id,
+    import_jobs::user_id,
+    import_jobs::format,
+    import_jobs::dry_run,
+    import_jobs::upsert,
+    import_jobs::create_categories,
+    import_jobs::status,
+    import_jobs::total_rows,
+    import_jobs::processed_rows,
+    import_jobs::created_rows,
+    import_jobs::updated_rows,
+    import_jobs::failed_rows,
+    import_jobs::row_errors,
+    import_jobs::error,
+    import_jobs::created_at,
+    import_jobs::updated_at,
+);
+
+const IMPORT_JOB_COLUMNS: ImportJobColumns = (
+    import_jobs::id,
+    import_jobs::user_id,
+    import_jobs::format,
+    import_jobs::dry_run,
+    import_jobs::upsert,
+    import_jobs::create_categories,
+    import_jobs::status,
+    import_jobs::total_rows,
+    import_jobs::processed_rows,
+    import_jobs::created_rows,
+    import_jobs::updated_rows,
+    import_jobs::failed_rows,
+    import_jobs::row_errors,
+    import_jobs::error,
+    import_jobs::created_at,
+    import_jobs::updated_at,
+);
+
+pub fn get_import_job(conn: &mut PgConnection, id: &str) -> QueryResult<ImportJob> {
+    import_jobs::table.find(id).select(IMPORT_JOB_COLUMNS).first(conn)
+}
+
+pub fn get_import_source(conn: &mut PgConnection, id: &str) -> QueryResult<Option<String>> {
+    import_jobs::table.find(id).select(import_jobs::source).first(conn)
+}
+
+pub fn create_import_job(conn: &mut PgConnection, job: &NewImportJob) -> QueryResult<ImportJob> {
+    diesel::insert_into(import_jobs::table).values(job).returning(IMPORT_JOB_COLUMNS).get_result(conn)
+}
+
+// SKIP LOCKED, so concurrent workers each claim a different job
+pub fn claim_import_job(conn: &mut PgConnection, stale_before: chrono::NaiveDateTime) -> QueryResult<Option<ImportJob>> {
+    conn.transaction(|conn| {
+        let claimable = import_jobs::status
+            .eq("pending")
+            .or(import_jobs::status.eq("running").and(import_jobs::updated_at.lt(stale_before)));
+        let id: Option<String> = import_jobs::table
+            .filter(claimable)
+            .order(import_jobs::created_at.asc())
+            .select(import_jobs::id)
+            .for_update()
+            .skip_locked()
+            .first(conn)
+            .optional()?;
+        let Some(id) = id else {
+            return Ok(None);
+        };
+        diesel::update(import_jobs::table.find(id))
+            .set((import_jobs::status.eq("running"), import_jobs::updated_at.eq(Utc::now().naive_utc())))
+            .returning(IMPORT_JOB_COLUMNS)
+            .get_result(conn)
+            .map(Some)
+    })
+}
+
+pub fn save_import_progress(conn: &mut PgConnection, id: &str, progress: &ImportProgress) -> QueryResult<ImportJob> {
+    diesel::update(import_jobs::table.find(id))
+        .set((progress, import_jobs::updated_at.eq(Utc::now().naive_utc())))
+        .returning(IMPORT_JOB_COLUMNS)
+        .get_result(conn)
+}
+
+pub fn finish_import_job(conn: &mut PgConnection, id: &str, error: Option<&str>) -> QueryResult<ImportJob> {
+    diesel::update(import_jobs::table.find(id))
         .set((
-            product,
-            products::updated_at.eq(Utc::now().naive_utc()),
+            import_jobs::status.eq(if error.is_some() { "failed" } else { "finished" }),
+            import_jobs::error.eq(error),
+            import_jobs::source.eq(None::<String>),
+            import_jobs::updated_at.eq(Utc::now().naive_utc()),
         ))
+        .returning(IMPORT_JOB_COLUMNS)
         .get_result(conn)
 }
 
@@ -154,6 +643,30 @@ pub fn delete_product(conn: &mut PgConnection, id: i32) -> QueryResult<usize> {
     diesel::delete(products::table.find(id)).execute(conn)
 }
 
+pub fn delete_products(conn: &mut PgConnection, ids: &[i32]) -> QueryResult<usize> {
+    diesel::delete(products::table.filter(products::id.eq_any(ids))).execute(conn)
+}
+
+pub fn get_product_ids_in_categories(conn: &mut PgConnection, category_ids: &[i32]) -> QueryResult<Vec<i32>> {
+    products::table
+        .filter(products::category_id.eq_any(category_ids))
+        .select(products::id)
+        .order(products::id.asc())
+        .load(conn)
+}
+
+pub fn move_products_to_category(conn: &mut PgConnection, from: i32, to: i32) -> QueryResult<Vec<i32>> {
+    let mut moved: Vec<i32> = diesel::update(products::table.filter(products::category_id.eq(from)))
+        .set((
+            products::category_id.eq(to),
+            products::updated_at.eq(Utc::now().naive_utc()),
+        ))
+        .returning(products::id)
+        .get_results(conn)?;
+    moved.sort_unstable();
+    Ok(moved)
+}
+
 pub fn create_category(conn: &mut PgConnection, new_category: NewCategory) -> QueryResult<Category> {
     diesel::insert_into(categories::table)
         .values(new_category)
@@ -173,12 +686,228 @@ pub fn update_category(conn: &mut PgConnection, id: i32, category: UpdateCategor
         .get_result(conn)
 }
 
+pub fn replace_category(conn: &mut PgConnection, id: i32, category: &NewCategory) -> QueryResult<Category> {
+    diesel::update(categories::table.find(id))
+        .set((
+            category,
+            categories::updated_at.eq(Utc::now().naive_utc()),
+        ))
+        .get_result(conn)
+}
+
+// The category itself and every category above it, in no particular order
+pub fn get_category_ancestors(conn: &mut PgConnection, id: i32) -> QueryResult<Vec<i32>> {
+    category_ancestors::table
+        .filter(category_ancestors::category_id.eq(id))
+        .select(category_ancestors::ancestor_id)
+        .load(conn)
+}
+
 pub fn delete_category(conn: &mut PgConnection, id: i32) -> QueryResult<usize> {
     diesel::delete(categories::table.find(id)).execute(conn)
 }
 
-pub fn get_all_categories(conn: &mut PgConnection) -> QueryResult<Vec<Category>> {
-    categories::table.order(categories::name.asc()).load(conn)
+pub fn delete_categories(conn: &mut PgConnection, ids: &[i32]) -> QueryResult<usize> {
+    diesel::delete(categories::table.filter(categories::id.eq_any(ids))).execute(conn)
+}
+
+// The category itself and every category below it
+pub fn get_category_subtree(conn: &mut PgConnection, id: i32) -> QueryResult<Vec<i32>> {
+    category_ancestors::table
+        .filter(category_ancestors::ancestor_id.eq(id))
+        .select(category_ancestors::category_id)
+        .order(category_ancestors::category_id.asc())
+        .load(conn)
+}
+
+pub fn move_subcategories(conn: &mut PgConnection, from: i32, to: i32) -> QueryResult<Vec<i32>> {
+    let mut moved: Vec<i32> = diesel::update(categories::table.filter(categories::parent_id.eq(from)))
+        .set((
+            categories::parent_id.eq(to),
+            categories::updated_at.eq(Utc::now().naive_utc()),
+        ))
+        .returning(categories::id)
+        .get_results(conn)?;
+    moved.sort_unstable();
+    Ok(moved)
+}
+
+pub fn get_categories(conn: &mut PgConnection) -> QueryResult<Vec<Category>> {
+    categories::table
+        .order((categories::display_order.asc(), categories::name.asc()))
+        .load(conn)
+}
+
+pub fn get_category_by_slug(conn: &mut PgConnection, slug: &str) -> QueryResult<Category> {
+    categories::table.filter(categories::slug.eq(slug)).first(conn)
+}
+
+pub fn get_category_by_name(conn: &mut PgConnection, name: &str) -> QueryResult<Category> {
+    categories::table.filter(lower(categories::name).eq(name.to_lowercase())).first(conn)
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CategoryWithStats {
+    #[serde(flatten)]
+    pub category: Category,
+    // Products in the category and all of its subcategories
+    pub product_count: i64,
+    // Cheapest and dearest of those products in `currency`; null without products
+    pub min_price: Option<Money>,
+    pub max_price: Option<Money>,
+    pub currency: String,
+}
+
+// Every category with its product count and price range, in one grouped query.
+// Prices are converted to `currency` as in converted_price, the target rate
+// coming from a subselect; an unknown currency leaves the range null.
+pub fn get_all_categories(conn: &mut PgConnection, currency: &str) -> QueryResult<Vec<CategoryWithStats>> {
+    let target = diesel::alias!(exchange_rates as target);
+    let target_rate = target
+        .filter(target.field(exchange_rates::currency).eq(currency.to_string()))
+        .select(target.field(exchange_rates::rate))
+        .single_value();
+    let digits: i32 = 2;
+    let price = round(products::price.nullable() * target_rate / exchange_rates::rate.nullable(), digits);
+    let rows: Vec<(Category, i64, Option<Money>, Option<Money>)> = categories::table
+        .inner_join(category_ancestors::table.on(category_ancestors::ancestor_id.eq(categories::id)))
+        .left_join(products::table.on(products::category_id.eq(category_ancestors::category_id)))
+        .left_join(exchange_rates::table.on(products::currency.eq(exchange_rates::currency)))
+        .group_by(categories::id)
+        .select((categories::all_columns, count(products::id.nullable()), min_numeric(price.clone()), max_numeric(price)))
+        .order((categories::display_order.asc(), categories::name.asc()))
+        .load(conn)?;
+    Ok(rows
+        .into_iter()
+        .map(|(category, product_count, min_price, max_price)| CategoryWithStats {
+            category,
+            product_count,
+            min_price,
+            max_price,
+            currency: currency.to_string(),
+        })
+        .collect())
+}
+
+// By name, then category
+pub fn get_category_attributes(conn: &mut PgConnection, category_ids: &[i32]) -> QueryResult<Vec<CategoryAttribute>> {
+    category_attributes::table
+        .filter(category_attributes::category_id.eq_any(category_ids))
+        .order((category_attributes::name.asc(), category_attributes::category_id.asc()))
+        .load(conn)
+}
+
+pub fn get_category_attribute(conn: &mut PgConnection, category_id: i32, id: i32) -> QueryResult<CategoryAttribute> {
+    category_attributes::table
+        .find(id)
+        .filter(category_attributes::category_id.eq(category_id))
+        .first(conn)
+}
+
+pub fn create_category_attribute(conn: &mut PgConnection, category_id: i32, attribute: &NewAttribute) -> QueryResult<CategoryAttribute> {
+    diesel::insert_into(category_attributes::table)
+        .values((
+            category_attributes::category_id.eq(category_id),
+            category_attributes::name.eq(&attribute.name),
+            category_attributes::kind.eq(&attribute.kind),
+            category_attributes::required.eq(attribute.required),
+            category_attributes::allowed_values.eq(&attribute.allowed_values),
+        ))
+        .get_result(conn)
+}
+
+pub fn replace_category_attribute(conn: &mut PgConnection, category_id: i32, id: i32, attribute: &NewAttribute) -> QueryResult<CategoryAttribute> {
+    diesel::update(category_attributes::table.find(id).filter(category_attributes::category_id.eq(category_id)))
+        .set((
+            category_attributes::name.eq(&attribute.name),
+            category_attributes::kind.eq(&attribute.kind),
+            category_attributes::required.eq(attribute.required),
+            category_attributes::allowed_values.eq(&attribute.allowed_values),
+            category_attributes::updated_at.eq(Utc::now().naive_utc()),
+        ))
+        .get_result(conn)
+}
+
+pub fn delete_category_attribute(conn: &mut PgConnection, category_id: i32, id: i32) -> QueryResult<usize> {
+    diesel::delete(category_attributes::table.find(id).filter(category_attributes::category_id.eq(category_id))).execute(conn)
+}
+
+// Tag names are matched case-insensitively, as tags_name_lower_key enforces
+
+#[derive(Queryable, Serialize, ToSchema)]
+pub struct TagWithCount {
+    pub id: i32,
+    pub name: String,
+    // Products carrying the tag
+    pub product_count: i64,
+}
+
+// Most used first, then by name; unused tags are included with a count of 0
+pub fn get_tags_with_counts(conn: &mut PgConnection) -> QueryResult<Vec<TagWithCount>> {
+    let product_count = count(product_tags::product_id.nullable());
+    tags::table
+        .left_join(product_tags::table)
+        .group_by(tags::id)
+        .select((tags::id, tags::name, product_count))
+        .order((product_count.desc(), tags::name.asc()))
+        .load(conn)
+}
+
+pub fn get_tag(conn: &mut PgConnection, id: i32) -> QueryResult<Tag> {
+    tags::table.find(id).first(conn)
+}
+
+pub fn get_tag_by_name(conn: &mut PgConnection, name: &str) -> QueryResult<Tag> {
+    tags::table.filter(lower(tags::name).eq(name.to_lowercase())).first(conn)
+}
+
+pub fn create_tag(conn: &mut PgConnection, name: &str) -> QueryResult<Tag> {
+    diesel::insert_into(tags::table).values(tags::name.eq(name)).get_result(conn)
+}
+
+pub fn rename_tag(conn: &mut PgConnection, id: i32, name: &str) -> QueryResult<Tag> {
+    diesel::update(tags::table.find(id)).set(tags::name.eq(name)).get_result(conn)
+}
+
+pub fn delete_tag(conn: &mut PgConnection, id: i32) -> QueryResult<usize> {
+    diesel::delete(tags::table.find(id)).execute(conn)
+}
+
+// By name
+pub fn get_product_tags(conn: &mut PgConnection, product_id: i32) -> QueryResult<Vec<Tag>> {
+    tags::table
+        .inner_join(product_tags::table)
+        .filter(product_tags::product_id.eq(product_id))
+        .select(tags::all_columns)
+        .order(tags::name.asc())
+        .load(conn)
+}
+
+pub fn set_product_tags(conn: &mut PgConnection, product_id: i32, tag_ids: &[i32]) -> QueryResult<()> {
+    conn.transaction(|conn| {
+        diesel::delete(product_tags::table.filter(product_tags::product_id.eq(product_id))).execute(conn)?;
+        let rows: Vec<_> = tag_ids
+            .iter()
+            .map(|tag_id| (product_tags::product_id.eq(product_id), product_tags::tag_id.eq(tag_id)))
+            .collect();
+        if !rows.is_empty() {
+            diesel::insert_into(product_tags::table).values(rows).execute(conn)?;
+        }
+        Ok(())
+    })
+}
+
+// Products carrying any or all of the tags in `filter`
+fn products_with_tags(conn: &mut PgConnection, filter: &TagFilter, mode: TagMatch) -> QueryResult<Vec<i32>> {
+    let tagged = product_tags::table
+        .inner_join(tags::table)
+        .filter(lower(tags::name).eq_any(&filter.0))
+        .group_by(product_tags::product_id)
+        .select(product_tags::product_id);
+    match mode {
+        TagMatch::Any => tagged.load(conn),
+        TagMatch::All => tagged.having(count(product_tags::tag_id).eq(filter.0.len() as i64)).load(conn),
+    }
 }
 
 pub fn get_all_products(conn: &mut PgConnection, user_id: i32) -> QueryResult<Vec<Product>> {
@@ -194,63 +923,155 @@ pub fn get_products_by_category(conn: &mut PgConnection, category_id: i32, user_
         .load(conn)
 }
 
-#[derive(Queryable, Serialize)]
+#[derive(Queryable, Serialize, ToSchema)]
 pub struct ProductWithCategory {
     pub id: i32,
+    pub sku: Option<String>,
     pub name: String,
-    pub price: f64,
+    pub price: Money,
+    pub currency: String,
     pub description: String,
     pub image: String,
+    #[schema(value_type = Option<crate::media::Srcset>)]
+    pub image_srcset: Option<serde_json::Value>,
     pub video: Option<String>,
+    pub published: bool,
     pub category_id: i32,
     pub category_name: String,
     pub user_id: i32,
+    pub stock: i32,
+    #[schema(value_type = Object)]
+    pub attributes: serde_json::Value,
     pub created_at: chrono::NaiveDateTime,
     pub updated_at: chrono::NaiveDateTime,
 }
 
-pub fn get_all_products_with_category(conn: &mut PgConnection, user_id_val: i32) -> QueryResult<Vec<ProductWithCategory>> {
-    use crate::db::schema::products;
-    use crate::db::schema::categories::dsl::{categories, name as cat_name, id as cat_id};
-    
-    products::table
-        .inner_join(categories.on(products::category_id.eq(cat_id)))
-        .filter(products::user_id.eq(user_id_val))
-        .select((
-            products::id,
-            products::name,
-            products::price,
-            products::description,
-            products::image,
-            products::video,
-            products::category_id,
-            cat_name,
-            products::user_id,
-            products::created_at,
-            products::updated_at,
-        ))
-        .load::<ProductWithCategory>(conn)
+define_sql_function! {
+    #[sql_name = "coalesce"]
+    fn coalesce_rate(requested: diesel::sql_types::Nullable<diesel::sql_types::Numeric>, own: diesel::sql_types::Numeric) -> diesel::sql_types::Numeric;
 }
 
-pub fn get_all_products_with_category_all_users(conn: &mut PgConnection) -> QueryResult<Vec<ProductWithCategory>> {
-    use crate::db::schema::products;
-    use crate::db::schema::categories::dsl::{categories, name as cat_name, id as cat_id};
-    products::table
-        .inner_join(categories.on(products::category_id.eq(cat_id)))
+define_sql_function! {
+    #[sql_name = "coalesce"]
+    fn coalesce_currency(requested: diesel::sql_types::Nullable<diesel::sql_types::Varchar>, own: diesel::sql_types::Varchar) -> diesel::sql_types::Varchar;
+}
+
+// The product's price in the currency quoted at `target`, rounded to cents like
+// currency::convert. Needs exchange_rates joined on the product's currency;
+// without a target the product's own rate cancels out.
+#[diesel::dsl::auto_type]
+fn converted_price(target: Option<Rate>) -> _ {
+    let digits: i32 = 2;
+    round((products::price * coalesce_rate(target, exchange_rates::rate) / exchange_rates::rate).nullable(), digits).assume_not_null()
+}
+
+// LIKE reads %, _ and \ in the search term as wildcards; escaped, they
+// match only themselves
+fn escape_like(term: &str) -> String {
+    term.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+// Products joined with their category name. Filters and ordering run in SQL, on
+// prices converted to query.currency if given; an unknown sort field falls back
+// to id order, anything but "desc" sorts ascending.
+pub fn list_products(conn: &mut PgConnection, owner: Option<i32>, query: &ProductQuery) -> QueryResult<Vec<ProductWithCategory>> {
+    let target_rate = match &query.currency {
+        Some(currency) => Some(get_exchange_rate(conn, currency)?.rate),
+        None => None,
+    };
+    let price = converted_price(target_rate);
+    let mut select = products::table
+        .inner_join(categories::table.on(products::category_id.eq(categories::id)))
+        .inner_join(exchange_rates::table.on(products::currency.eq(exchange_rates::currency)))
         .select((
             products::id,
+            products::sku,
             products::name,
-            products::price,
+            price,
+            coalesce_currency(query.currency.clone(), products::currency),
             products::description,
             products::image,
+            products::image_srcset,
             products::video,
+            products::published,
             products::category_id,
-            cat_name,
+            categories::name,
             products::user_id,
+            products::stock,
+            products::attributes,
             products::created_at,
             products::updated_at,
         ))
-        .load::<ProductWithCategory>(conn)
+        .into_boxed();
+
+    if let Some(user_id) = owner {
+        select = select.filter(products::user_id.eq(user_id));
+    }
+
+    if let Some(category_id) = query.category_id {
+        if query.include_subcategories == Some(true) {
+            let subtree = category_ancestors::table
+                .filter(category_ancestors::ancestor_id.eq(category_id))
+                .select(category_ancestors::category_id);
+            select = select.filter(products::category_id.eq_any(subtree));
+        } else {
+            select = select.filter(products::category_id.eq(category_id));
+        }
+    }
+
+    if let Some(min) = query.min_price {
+        select = select.filter(price.ge(min));
+    }
+
+    if let Some(max) = query.max_price {
+        select = select.filter(price.le(max));
+    }
+
+    // With an option filter, in_stock is about the matching variants instead
+    if let Some(options) = &query.options {
+        select = select.filter(products::id.eq_any(products_with_variant(conn, options, query.in_stock)?));
+    } else {
+        select = match query.in_stock {
+            Some(true) => select.filter(products::stock.gt(0)),
+            Some(false) => select.filter(products::stock.eq(0)),
+            None => select,
+        };
+    }
+
+    if let Some(since) = query.price_drop_cutoff() {
+        select = select.filter(products::id.eq_any(products_with_price_drop_since(conn, since)?));
+    }
+
+    if let Some(tags) = &query.tags {
+        select = select.filter(products::id.eq_any(products_with_tags(conn, tags, query.tags_match.unwrap_or_default())?));
+    }
+
+    // Compared as text, so attr.size=42 matches both 42 and "42"
+    for (name, value) in &query.attributes.0 {
+        let stored = products::attributes.retrieve_as_text(name.clone()).assume_not_null();
+        select = select.filter(lower(stored).eq(value.clone()));
+    }
+
+    if let Some(term) = &query.search_term {
+        let pattern = format!("%{}%", escape_like(term));
+        select = select.filter(
+            products::name.ilike(pattern.clone()).escape('\\')
+                .or(products::description.ilike(pattern).escape('\\'))
+        );
+    }
+
+    let descending = query.sort_order.as_deref() == Some("desc");
+    select = match (query.sort_by.as_deref(), descending) {
+        (Some("name"), false) => select.order((products::name.asc(), products::id.asc())),
+        (Some("name"), true) => select.order((products::name.desc(), products::id.asc())),
+        (Some("price"), false) => select.order((price.asc(), products::id.asc())),
+        (Some("price"), true) => select.order((price.desc(), products::id.asc())),
+        (Some("created_at"), false) => select.order((products::created_at.asc(), products::id.asc())),
+        (Some("created_at"), true) => select.order((products::created_at.desc(), products::id.asc())),
+        _ => select.order(products::id.asc()),
+    };
+
+    select.load::<ProductWithCategory>(conn)
 }
 
 pub fn get_monitored_users(conn: &mut PgConnection) -> QueryResult<Vec<MonitoredUser>> {
@@ -270,4 +1091,102 @@ pub fn add_monitored_user(conn: &mut PgConnection, user_id_val: i32, username_va
 pub fn clear_monitored_users(conn: &mut PgConnection) -> QueryResult<usize> {
     use crate::db::schema::monitored_users::dsl::*;
     diesel::delete(monitored_users).execute(conn)
-} 
\ No newline at end of file
+} 
+pub fn get_user(conn: &mut PgConnection, id: i32) -> QueryResult<User> {
+    users::table.find(id).first(conn)
+}
+
+pub fn get_user_by_username(conn: &mut PgConnection, username: &str) -> QueryResult<User> {
+    users::table.filter(users::username.eq(username)).first(conn)
+}
+
+pub fn create_user(conn: &mut PgConnection, new_user: NewUser) -> QueryResult<User> {
+    diesel::insert_into(users::table)
+        .values(new_user)
+        .get_result(conn)
+}
+
+pub fn create_log(conn: &mut PgConnection, new_log: NewLog) -> QueryResult<Log> {
+    diesel::insert_into(logs::table)
+        .values(new_log)
+        .get_result(conn)
+}
+
+pub fn get_logs_since(conn: &mut PgConnection, since: chrono::NaiveDateTime) -> QueryResult<Vec<Log>> {
+    logs::table
+        .filter(logs::timestamp.ge(since))
+        .order(logs::timestamp.asc())
+        .load(conn)
+}
+
+pub fn count_logs_since(conn: &mut PgConnection, user_id: i32, since: chrono::NaiveDateTime) -> QueryResult<i64> {
+    logs::table
+        .filter(logs::user_id.eq(user_id))
+        .filter(logs::timestamp.ge(since))
+        .count()
+        .get_result(conn)
+}
+
+define_sql_function! {
+    fn lower(value: diesel::sql_types::Varchar) -> diesel::sql_types::Varchar;
+}
+
+// Diesel only orders numeric types it can decode natively, so min and max over
+// NUMERIC are declared by hand
+define_sql_function! {
+    #[aggregate]
+    #[sql_name = "min"]
+    fn min_numeric(value: diesel::sql_types::Nullable<diesel::sql_types::Numeric>) -> diesel::sql_types::Nullable<diesel::sql_types::Numeric>;
+}
+
+define_sql_function! {
+    #[aggregate]
+    #[sql_name = "max"]
+    fn max_numeric(value: diesel::sql_types::Nullable<diesel::sql_types::Numeric>) -> diesel::sql_types::Nullable<diesel::sql_types::Numeric>;
+}
+
+define_sql_function! {
+    fn round(value: diesel::sql_types::Nullable<diesel::sql_types::Numeric>, digits: diesel::sql_types::Integer) -> diesel::sql_types::Nullable<diesel::sql_types::Numeric>;
+}
+
+// Averages of the prices as listed in `currency`; they stay NUMERIC and are
+// rounded to whole cents in SQL. Each product counts towards its own category
+// and every category above it.
+pub fn get_avg_price_per_category(conn: &mut PgConnection, user_id: i32, currency: &str) -> QueryResult<Vec<(String, Option<Money>)>> {
+    let price = converted_price(Some(get_exchange_rate(conn, currency)?.rate));
+    products::table
+        .inner_join(category_ancestors::table.on(products::category_id.eq(category_ancestors::category_id)))
+        .inner_join(categories::table.on(category_ancestors::ancestor_id.eq(categories::id)))
+        .inner_join(exchange_rates::table.on(products::currency.eq(exchange_rates::currency)))
+        .filter(products::user_id.eq(user_id))
+        .group_by(categories::name)
+        .select((categories::name, round(avg(price), 2)))
+        .order_by((avg(price).desc(), categories::name.asc()))
+        .load(conn)
+}
+
+pub fn get_all_exchange_rates(conn: &mut PgConnection) -> QueryResult<Vec<ExchangeRate>> {
+    exchange_rates::table.order(exchange_rates::currency.asc()).load(conn)
+}
+
+pub fn get_exchange_rate(conn: &mut PgConnection, currency: &str) -> QueryResult<ExchangeRate> {
+    exchange_rates::table.find(currency).first(conn)
+}
+
+pub fn set_exchange_rate(conn: &mut PgConnection, currency: &str, rate: Rate) -> QueryResult<ExchangeRate> {
+    let updated_at = Utc::now().naive_utc();
+    diesel::insert_into(exchange_rates::table)
+        .values((
+            exchange_rates::currency.eq(currency),
+            exchange_rates::rate.eq(rate),
+            exchange_rates::updated_at.eq(updated_at),
+        ))
+        .on_conflict(exchange_rates::currency)
+        .do_update()
+        .set((exchange_rates::rate.eq(rate), exchange_rates::updated_at.eq(updated_at)))
+        .get_result(conn)
+}
+
+pub fn delete_exchange_rate(conn: &mut PgConnection, currency: &str) -> QueryResult<usize> {
+    diesel::delete(exchange_rates::table.find(currency)).execute(conn)
+}
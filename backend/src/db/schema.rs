@@ -4,22 +4,41 @@ diesel::table! {
     categories (id) {
         id -> Int4,
         name -> Varchar,
+        slug -> Varchar,
         description -> Text,
+        parent_id -> Nullable<Int4>,
+        image -> Nullable<Varchar>,
+        display_order -> Int4,
         created_at -> Timestamp,
         updated_at -> Timestamp,
     }
 }
 
+// A view: each category with itself and every ancestor
+diesel::table! {
+    category_ancestors (category_id, ancestor_id) {
+        category_id -> Int4,
+        ancestor_id -> Int4,
+    }
+}
+
 diesel::table! {
     products (id) {
         id -> Int4,
+        sku -> Nullable<Varchar>,
         name -> Varchar,
-        price -> Float8,
+        price -> Numeric,
+        currency -> Varchar,
         description -> Text,
         image -> Varchar,
+        image_srcset -> Nullable<Jsonb>,
         video -> Nullable<Varchar>,
+        published -> Bool,
         category_id -> Int4,
         user_id -> Int4,
+        stock -> Int4,
+        low_stock_threshold -> Nullable<Int4>,
+        attributes -> Jsonb,
         created_at -> Timestamp,
         updated_at -> Timestamp,
     }
@@ -27,6 +46,7 @@ diesel::table! {
 
 diesel::joinable!(products -> categories (category_id));
 diesel::joinable!(products -> users (user_id));
+diesel::joinable!(products -> exchange_rates (currency));
 
 diesel::table! {
     users (id) {
@@ -45,6 +65,7 @@ diesel::table! {
         entity -> Varchar,
         entity_id -> Nullable<Int4>,
         timestamp -> Timestamp,
+        reason -> Nullable<Text>,
     }
 }
 
@@ -55,10 +76,175 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    exchange_rates (currency) {
+        currency -> Varchar,
+        rate -> Numeric,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    product_price_history (id) {
+        id -> Int4,
+        product_id -> Int4,
+        old_price -> Nullable<Numeric>,
+        old_currency -> Nullable<Varchar>,
+        new_price -> Numeric,
+        new_currency -> Varchar,
+        changed_at -> Timestamp,
+    }
+}
+
+diesel::joinable!(product_price_history -> products (product_id));
+
+diesel::table! {
+    product_options (id) {
+        id -> Int4,
+        product_id -> Int4,
+        name -> Varchar,
+        position -> Int4,
+    }
+}
+
+diesel::table! {
+    product_option_values (id) {
+        id -> Int4,
+        option_id -> Int4,
+        value -> Varchar,
+    }
+}
+
+diesel::table! {
+    product_variants (id) {
+        id -> Int4,
+        product_id -> Int4,
+        sku -> Varchar,
+        price -> Nullable<Numeric>,
+        stock -> Int4,
+        image -> Nullable<Varchar>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    product_variant_values (variant_id, option_value_id) {
+        variant_id -> Int4,
+        option_value_id -> Int4,
+    }
+}
+
+diesel::joinable!(product_options -> products (product_id));
+diesel::joinable!(product_option_values -> product_options (option_id));
+diesel::joinable!(product_variants -> products (product_id));
+diesel::joinable!(product_variant_values -> product_variants (variant_id));
+diesel::joinable!(product_variant_values -> product_option_values (option_value_id));
+
+diesel::table! {
+    category_attributes (id) {
+        id -> Int4,
+        category_id -> Int4,
+        name -> Varchar,
+        kind -> Varchar,
+        required -> Bool,
+        allowed_values -> Array<Text>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::joinable!(category_attributes -> categories (category_id));
+
+diesel::table! {
+    tags (id) {
+        id -> Int4,
+        name -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    product_tags (product_id, tag_id) {
+        product_id -> Int4,
+        tag_id -> Int4,
+    }
+}
+
+diesel::table! {
+    product_images (id) {
+        id -> Int4,
+        product_id -> Int4,
+        key -> Varchar,
+        url -> Varchar,
+        content_type -> Varchar,
+        size_bytes -> Int8,
+        width -> Nullable<Int4>,
+        height -> Nullable<Int4>,
+        renditions_status -> Varchar,
+        srcset -> Nullable<Jsonb>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    video_uploads (id) {
+        id -> Varchar,
+        product_id -> Int4,
+        size_bytes -> Int8,
+        received_bytes -> Int8,
+        sha256 -> Varchar,
+        video -> Nullable<Varchar>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    import_jobs (id) {
+        id -> Varchar,
+        user_id -> Int4,
+        format -> Varchar,
+        dry_run -> Bool,
+        upsert -> Bool,
+        create_categories -> Bool,
+        status -> Varchar,
+        total_rows -> Int4,
+        processed_rows -> Int4,
+        created_rows -> Int4,
+        updated_rows -> Int4,
+        failed_rows -> Int4,
+        row_errors -> Jsonb,
+        error -> Nullable<Text>,
+        source -> Nullable<Text>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::joinable!(product_tags -> products (product_id));
+diesel::joinable!(product_tags -> tags (tag_id));
+diesel::joinable!(product_images -> products (product_id));
+diesel::joinable!(video_uploads -> products (product_id));
+diesel::joinable!(import_jobs -> users (user_id));
+
 diesel::allow_tables_to_appear_in_same_query!(
     categories,
+    category_ancestors,
     products,
     users,
     logs,
     monitored_users,
+    exchange_rates,
+    product_price_history,
+    product_options,
+    product_option_values,
+    product_variants,
+    product_variant_values,
+    category_attributes,
+    tags,
+    product_tags,
+    product_images,
+    video_uploads,
+    import_jobs,
 ); 
\ No newline at end of file
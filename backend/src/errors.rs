@@ -0,0 +1,212 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fmt;
+use utoipa::ToSchema;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+tokio::task_local! {
+    // Id of the request currently being handled, so error bodies can echo it back
+    static REQUEST_ID: String;
+}
+
+// Single error type returned by every handler
+#[derive(Debug)]
+pub enum ApiError {
+    // No connection could be checked out of the pool in time
+    PoolTimeout,
+    NotFound(String),
+    // Unique, foreign key or check constraint violated
+    Conflict { message: String, details: Value },
+    // Payload parsed but failed validation
+    Validation { message: String, details: Value },
+    // Payload, query string or path could not be parsed
+    BadRequest(String),
+    // Request body, or one part of it, over its size limit
+    PayloadTooLarge(String),
+    Unauthorized(String),
+    // Authenticated, but not allowed to see or do this
+    Forbidden(String),
+    Internal(String),
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ErrorBody {
+    pub code: &'static str,
+    pub message: String,
+    // Per-field messages for validation errors, constraint info for conflicts, otherwise null
+    #[schema(value_type = Object, nullable)]
+    pub details: Value,
+    pub request_id: Option<String>,
+}
+
+impl ApiError {
+    pub fn not_found(entity: &str, id: i32) -> Self {
+        ApiError::NotFound(format!("{} {} not found", entity, id))
+    }
+
+    pub fn insufficient_stock(available: i32, requested: i32) -> Self {
+        ApiError::Conflict {
+            message: "Not enough stock".to_string(),
+            details: json!({ "available": available, "requested": requested }),
+        }
+    }
+
+    pub fn stock_limit_exceeded(available: i32, added: i32) -> Self {
+        ApiError::Conflict {
+            message: "Stock would exceed the maximum".to_string(),
+            details: json!({ "available": available, "added": added, "maximum": i32::MAX }),
+        }
+    }
+
+    // Why a stock change of `delta` could not be applied to `available`
+    pub fn stock_rejected(available: i32, delta: i32) -> Self {
+        if delta < 0 {
+            ApiError::insufficient_stock(available, -delta)
+        } else {
+            ApiError::stock_limit_exceeded(available, delta)
+        }
+    }
+
+    pub fn validation(message: impl Into<String>, details: Value) -> Self {
+        ApiError::Validation { message: message.into(), details }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::PoolTimeout => "service_unavailable",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Conflict { .. } => "conflict",
+            ApiError::Validation { .. } => "validation_failed",
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::PayloadTooLarge(_) => "payload_too_large",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn details(&self) -> Value {
+        match self {
+            ApiError::Conflict { details, .. } | ApiError::Validation { details, .. } => details.clone(),
+            _ => Value::Null,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::PoolTimeout => write!(f, "Database is busy, please retry"),
+            ApiError::NotFound(message)
+            | ApiError::BadRequest(message)
+            | ApiError::PayloadTooLarge(message)
+            | ApiError::Unauthorized(message)
+            | ApiError::Forbidden(message) => write!(f, "{}", message),
+            ApiError::Conflict { message, .. } | ApiError::Validation { message, .. } => write!(f, "{}", message),
+            // Never leak database internals to the client
+            ApiError::Internal(_) => write!(f, "Internal server error"),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::PoolTimeout => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict { .. } => StatusCode::CONFLICT,
+            ApiError::Validation { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        if let ApiError::Internal(cause) = self {
+            eprintln!("Internal error: {}", cause);
+        }
+        HttpResponse::build(self.status_code()).json(ErrorBody {
+            code: self.code(),
+            message: self.to_string(),
+            details: self.details(),
+            request_id: REQUEST_ID.try_with(|id| id.clone()).ok(),
+        })
+    }
+}
+
+impl From<r2d2::Error> for ApiError {
+    fn from(_: r2d2::Error) -> Self {
+        ApiError::PoolTimeout
+    }
+}
+
+impl From<DieselError> for ApiError {
+    fn from(err: DieselError) -> Self {
+        match err {
+            DieselError::NotFound => ApiError::NotFound("Resource not found".to_string()),
+            DieselError::DatabaseError(
+                kind @ (DatabaseErrorKind::UniqueViolation | DatabaseErrorKind::ForeignKeyViolation | DatabaseErrorKind::CheckViolation),
+                info,
+            ) => {
+                let message = match kind {
+                    DatabaseErrorKind::UniqueViolation => "Resource already exists",
+                    DatabaseErrorKind::CheckViolation => "Operation violates a data constraint",
+                    _ => "Operation conflicts with a related resource",
+                };
+                ApiError::Conflict {
+                    message: message.to_string(),
+                    details: json!({
+                        "constraint": info.constraint_name(),
+                        "table": info.table_name(),
+                        "reason": info.message(),
+                    }),
+                }
+            }
+            other => ApiError::Internal(other.to_string()),
+        }
+    }
+}
+
+// Error handlers for the built-in extractors so malformed input gets the same envelope
+pub fn json_error_handler(err: actix_web::error::JsonPayloadError, _: &HttpRequest) -> actix_web::Error {
+    ApiError::BadRequest(err.to_string()).into()
+}
+
+pub fn query_error_handler(err: actix_web::error::QueryPayloadError, _: &HttpRequest) -> actix_web::Error {
+    ApiError::BadRequest(err.to_string()).into()
+}
+
+pub fn path_error_handler(err: actix_web::error::PathError, _: &HttpRequest) -> actix_web::Error {
+    ApiError::BadRequest(err.to_string()).into()
+}
+
+// Tags every request with an id (reusing the caller's X-Request-Id if sent) and echoes it back
+pub async fn request_id(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty() && value.len() <= 128)
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    let mut res = REQUEST_ID.scope(id.clone(), next.call(req)).await?;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        res.headers_mut().insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+    Ok(res)
+}
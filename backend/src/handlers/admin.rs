@@ -0,0 +1,51 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use actix_web::{web, HttpResponse, Responder};
+use serde_json::json;
+
+use crate::db::models::MonitoredUser;
+use crate::errors::ApiError;
+use crate::AppState;
+
+#[utoipa::path(
+    post,
+    path = "/api/toggle-generation",
+    tag = "admin",
+    responses((status = 200, description = "New generation status"))
+)]
+pub async fn toggle_generation(generation_status: web::Data<Arc<AtomicBool>>) -> impl Responder {
+    let current = generation_status.load(Ordering::SeqCst);
+    let new_status = !current;
+    generation_status.store(new_status, Ordering::SeqCst);
+    println!("Product generation toggled: {} -> {}", current, new_status);
+    
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .json(json!({
+            "generating": new_status,
+            "message": format!("Product generation {}", if new_status { "started" } else { "stopped" })
+        }))
+}
+
+pub async fn shutdown_server() -> impl Responder {
+    println!("Shutting down server via /api/shutdown endpoint");
+    let response = HttpResponse::Ok().json(serde_json::json!({"message": "Server is shutting down"}));
+    // Give the response before exiting
+    tokio::spawn(async {
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        std::process::exit(0);
+    });
+    response
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/monitored-users",
+    tag = "admin",
+    responses((status = 200, body = [MonitoredUser]))
+)]
+pub async fn get_monitored_users_handler(data: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let monitored = data.db(|state| state.audit.monitored_users()).await?;
+    Ok(HttpResponse::Ok().json(monitored))
+}
@@ -0,0 +1,163 @@
+use actix_web::http::header;
+use actix_web::{web, HttpResponse};
+use validator::Validate;
+
+use crate::attributes;
+use crate::audit::{log_action, ANONYMOUS_USER};
+use crate::db::models::{CategoryAttribute, NewAttribute};
+use crate::errors::{ApiError, ErrorBody};
+use crate::{validation, AppState, API_V1};
+
+fn ensure_category(state: &AppState, category_id: i32) -> Result<(), ApiError> {
+    match state.categories.get(category_id)? {
+        Some(_) => Ok(()),
+        None => Err(ApiError::not_found("Category", category_id)),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/categories/{id}/attributes",
+    tag = "attributes",
+    params(("id" = i32, Path, description = "Category id")),
+    responses(
+        (status = 200, description = "Attributes its products take, inherited ones included, by name", body = [CategoryAttribute]),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn get_attributes(data: web::Data<AppState>, id: web::Path<i32>) -> Result<HttpResponse, ApiError> {
+    let category_id = id.into_inner();
+    let schema = data
+        .db(move |state| {
+            let lineage = state.categories.ancestors(category_id)?;
+            if lineage.is_empty() {
+                return Err(ApiError::not_found("Category", category_id));
+            }
+            Ok(attributes::effective(state.attributes.list(&lineage)?, &lineage))
+        })
+        .await?;
+    Ok(HttpResponse::Ok().json(schema))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/categories/{id}/attributes/{attribute_id}",
+    tag = "attributes",
+    params(
+        ("id" = i32, Path, description = "Category id"),
+        ("attribute_id" = i32, Path, description = "Attribute id"),
+    ),
+    responses(
+        (status = 200, body = CategoryAttribute),
+        (status = 404, description = "No such attribute defined on this category itself", body = ErrorBody),
+    )
+)]
+pub async fn get_attribute(data: web::Data<AppState>, path: web::Path<(i32, i32)>) -> Result<HttpResponse, ApiError> {
+    let (category_id, attribute_id) = path.into_inner();
+    let attribute = data
+        .db(move |state| state.attributes.get(category_id, attribute_id))
+        .await?
+        .ok_or_else(|| ApiError::not_found("Attribute", attribute_id))?;
+    Ok(HttpResponse::Ok().json(attribute))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/categories/{id}/attributes",
+    tag = "attributes",
+    params(("id" = i32, Path, description = "Category id")),
+    request_body = NewAttribute,
+    responses(
+        (status = 201, body = CategoryAttribute, headers(("Location" = String, description = "URL of the new attribute"))),
+        (status = 404, body = ErrorBody),
+        (status = 409, description = "The category already defines an attribute of that name", body = ErrorBody),
+        (status = 422, body = ErrorBody),
+    )
+)]
+pub async fn create_attribute(
+    data: web::Data<AppState>,
+    id: web::Path<i32>,
+    attribute: web::Json<NewAttribute>,
+) -> Result<HttpResponse, ApiError> {
+    attribute.validate()?;
+    validation::ensure_allowed_values(&attribute)?;
+    let category_id = id.into_inner();
+    let new_attribute = attribute.into_inner();
+    let attribute = data
+        .transaction(move |state| {
+            ensure_category(state, category_id)?;
+            let attribute = state.attributes.create(category_id, new_attribute)?;
+            log_action(state, ANONYMOUS_USER, "CREATE", "category_attribute", Some(attribute.id))?;
+            Ok(attribute)
+        })
+        .await?;
+    Ok(HttpResponse::Created()
+        .insert_header((header::LOCATION, format!("{}/categories/{}/attributes/{}", API_V1, category_id, attribute.id)))
+        .json(attribute))
+}
+
+// PUT: the body is the complete definition. Products already stored are not
+// re-checked; they have to fit the new definition on their next write.
+#[utoipa::path(
+    put,
+    path = "/api/v1/categories/{id}/attributes/{attribute_id}",
+    tag = "attributes",
+    params(
+        ("id" = i32, Path, description = "Category id"),
+        ("attribute_id" = i32, Path, description = "Attribute id"),
+    ),
+    request_body = NewAttribute,
+    responses(
+        (status = 200, body = CategoryAttribute),
+        (status = 404, body = ErrorBody),
+        (status = 409, description = "The category already defines an attribute of that name", body = ErrorBody),
+        (status = 422, body = ErrorBody),
+    )
+)]
+pub async fn replace_attribute(
+    data: web::Data<AppState>,
+    path: web::Path<(i32, i32)>,
+    attribute: web::Json<NewAttribute>,
+) -> Result<HttpResponse, ApiError> {
+    attribute.validate()?;
+    validation::ensure_allowed_values(&attribute)?;
+    let (category_id, attribute_id) = path.into_inner();
+    let new_attribute = attribute.into_inner();
+    let attribute = data
+        .transaction(move |state| {
+            let attribute = state
+                .attributes
+                .replace(category_id, attribute_id, new_attribute)?
+                .ok_or_else(|| ApiError::not_found("Attribute", attribute_id))?;
+            log_action(state, ANONYMOUS_USER, "UPDATE", "category_attribute", Some(attribute.id))?;
+            Ok(attribute)
+        })
+        .await?;
+    Ok(HttpResponse::Ok().json(attribute))
+}
+
+// Values products stored for it stay until their next write, which rejects them
+#[utoipa::path(
+    delete,
+    path = "/api/v1/categories/{id}/attributes/{attribute_id}",
+    tag = "attributes",
+    params(
+        ("id" = i32, Path, description = "Category id"),
+        ("attribute_id" = i32, Path, description = "Attribute id"),
+    ),
+    responses(
+        (status = 204, description = "Attribute deleted"),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn delete_attribute(data: web::Data<AppState>, path: web::Path<(i32, i32)>) -> Result<HttpResponse, ApiError> {
+    let (category_id, attribute_id) = path.into_inner();
+    data.transaction(move |state| {
+        if !state.attributes.delete(category_id, attribute_id)? {
+            return Err(ApiError::not_found("Attribute", attribute_id));
+        }
+        log_action(state, ANONYMOUS_USER, "DELETE", "category_attribute", Some(attribute_id))
+    })
+    .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
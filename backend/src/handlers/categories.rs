@@ -0,0 +1,307 @@
+use actix_web::http::header;
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use utoipa::IntoParams;
+use validator::Validate;
+
+use crate::audit::{log_action, log_action_with_reason, ANONYMOUS_USER};
+use crate::category_tree::{self, CategoryNode};
+use crate::currency::REFERENCE_CURRENCY;
+use crate::db::models::{Category, UpdateCategory};
+use crate::db::repository::CategoryWithStats;
+use crate::errors::{ApiError, ErrorBody};
+use crate::models::{CreateCategoryRequest, DeleteCategoryQuery};
+use crate::{validation, AppState, API_V1};
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CategoryListQuery {
+    // Currency of the price ranges; EUR when omitted
+    pub currency: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/categories",
+    tag = "categories",
+    params(CategoryListQuery),
+    responses(
+        (status = 200, description = "Categories by display_order, then name, with product counts and price ranges including subcategories", body = [CategoryWithStats]),
+        (status = 422, description = "No exchange rate for the currency", body = ErrorBody),
+    )
+)]
+pub async fn get_categories(data: web::Data<AppState>, query: web::Query<CategoryListQuery>) -> Result<HttpResponse, ApiError> {
+    let currency = query.into_inner().currency.unwrap_or_else(|| REFERENCE_CURRENCY.to_string());
+    let categories = data
+        .db(move |state| {
+            validation::ensure_currency_known(state.exchange_rates.as_ref(), &currency)?;
+            state.categories.list_with_stats(&currency)
+        })
+        .await?;
+    Ok(HttpResponse::Ok().json(categories))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/categories/tree",
+    tag = "categories",
+    responses((status = 200, description = "Top-level categories with their subcategories nested, siblings by display_order, then name", body = [CategoryNode]))
+)]
+pub async fn get_category_tree(data: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let categories = data.db(|state| state.categories.list()).await?;
+    Ok(HttpResponse::Ok().json(category_tree::build(categories)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/categories/{id}",
+    tag = "categories",
+    params(("id" = i32, Path, description = "Category id")),
+    responses(
+        (status = 200, body = Category),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn get_category(data: web::Data<AppState>, id: web::Path<i32>) -> Result<HttpResponse, ApiError> {
+    let category_id = id.into_inner();
+    let category = data
+        .db(move |state| state.categories.get(category_id))
+        .await?
+        .ok_or_else(|| ApiError::not_found("Category", category_id))?;
+    Ok(HttpResponse::Ok().json(category))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/categories/by-slug/{slug}",
+    tag = "categories",
+    params(("slug" = String, Path, description = "Category slug")),
+    responses(
+        (status = 200, body = Category),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn get_category_by_slug(data: web::Data<AppState>, slug: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    let slug = slug.into_inner();
+    let lookup = slug.clone();
+    let category = data
+        .db(move |state| state.categories.get_by_slug(&lookup))
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Category '{}' not found", slug)))?;
+    Ok(HttpResponse::Ok().json(category))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/categories",
+    tag = "categories",
+    request_body = CreateCategoryRequest,
+    responses(
+        (status = 201, body = Category, headers(("Location" = String, description = "URL of the new category"))),
+        (status = 409, description = "Name (ignoring case) or slug already taken", body = ErrorBody),
+        (status = 422, body = ErrorBody),
+    )
+)]
+pub async fn create_category(data: web::Data<AppState>, category: web::Json<CreateCategoryRequest>) -> Result<HttpResponse, ApiError> {
+    category.validate()?;
+    let new_category = category.into_inner().into_new_category();
+
+    let category = data
+        .transaction(move |state| {
+            if let Some(parent_id) = new_category.parent_id {
+                validation::ensure_valid_parent(state.categories.as_ref(), None, parent_id)?;
+            }
+            let category = state.categories.create(new_category)?;
+            log_action(state, ANONYMOUS_USER, "CREATE", "category", Some(category.id))?;
+            Ok(category)
+        })
+        .await?;
+    Ok(HttpResponse::Created()
+        .insert_header((header::LOCATION, format!("{}/categories/{}", API_V1, category.id)))
+        .json(category))
+}
+
+// PUT: name and description are required and replace the stored values; no
+// parent_id moves the category to the top level, no slug derives it from the name
+#[utoipa::path(
+    put,
+    path = "/api/v1/categories/{id}",
+    tag = "categories",
+    params(("id" = i32, Path, description = "Category id")),
+    request_body = CreateCategoryRequest,
+    responses(
+        (status = 200, body = Category),
+        (status = 404, body = ErrorBody),
+        (status = 409, description = "Name (ignoring case) or slug already taken", body = ErrorBody),
+        (status = 422, body = ErrorBody),
+    )
+)]
+pub async fn update_category(
+    data: web::Data<AppState>,
+    id: web::Path<i32>,
+    category: web::Json<CreateCategoryRequest>,
+) -> Result<HttpResponse, ApiError> {
+    category.validate()?;
+    let category_id = id.into_inner();
+    let new_category = category.into_inner().into_new_category();
+
+    let category = data
+        .transaction(move |state| {
+            if let Some(parent_id) = new_category.parent_id {
+                validation::ensure_valid_parent(state.categories.as_ref(), Some(category_id), parent_id)?;
+            }
+            let category = state
+                .categories
+                .replace(category_id, new_category)?
+                .ok_or_else(|| ApiError::not_found("Category", category_id))?;
+            log_action(state, ANONYMOUS_USER, "UPDATE", "category", Some(category.id))?;
+            Ok(category)
+        })
+        .await?;
+    Ok(HttpResponse::Ok().json(category))
+}
+
+// PATCH: only the fields present in the body are changed
+#[utoipa::path(
+    patch,
+    path = "/api/v1/categories/{id}",
+    tag = "categories",
+    params(("id" = i32, Path, description = "Category id")),
+    request_body = UpdateCategory,
+    responses(
+        (status = 200, body = Category),
+        (status = 404, body = ErrorBody),
+        (status = 409, description = "Name (ignoring case) or slug already taken", body = ErrorBody),
+        (status = 422, body = ErrorBody),
+    )
+)]
+pub async fn patch_category(
+    data: web::Data<AppState>,
+    id: web::Path<i32>,
+    category: web::Json<UpdateCategory>,
+) -> Result<HttpResponse, ApiError> {
+    category.validate()?;
+    let category_id = id.into_inner();
+    let changes = category.into_inner();
+    let category = data
+        .transaction(move |state| {
+            if let Some(parent_id) = changes.parent_id {
+                validation::ensure_valid_parent(state.categories.as_ref(), Some(category_id), parent_id)?;
+            }
+            if changes.is_empty() {
+                return state
+                    .categories
+                    .get(category_id)?
+                    .ok_or_else(|| ApiError::not_found("Category", category_id));
+            }
+            let category = state
+                .categories
+                .update(category_id, changes)?
+                .ok_or_else(|| ApiError::not_found("Category", category_id))?;
+            log_action(state, ANONYMOUS_USER, "UPDATE", "category", Some(category.id))?;
+            Ok(category)
+        })
+        .await?;
+    Ok(HttpResponse::Ok().json(category))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/categories/{id}",
+    tag = "categories",
+    params(("id" = i32, Path, description = "Category id"), DeleteCategoryQuery),
+    responses(
+        (status = 204, description = "Category deleted, with its products and subcategories moved or deleted as asked"),
+        (status = 404, body = ErrorBody),
+        (status = 409, description = "Products or subcategories still belong to it; details count them", body = ErrorBody),
+        (status = 422, description = "reassign_to is missing, inside the deleted subtree or combined with cascade", body = ErrorBody),
+    )
+)]
+pub async fn delete_category(
+    data: web::Data<AppState>,
+    id: web::Path<i32>,
+    query: web::Query<DeleteCategoryQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let category_id = id.into_inner();
+    let DeleteCategoryQuery { reassign_to, cascade } = query.into_inner();
+    let cascade = cascade.unwrap_or(false);
+    if cascade && reassign_to.is_some() {
+        return Err(ApiError::validation(
+            "Request validation failed",
+            json!({ "cascade": ["cannot be combined with reassign_to"] }),
+        ));
+    }
+    data.transaction(move |state| {
+        let subtree = state.categories.subtree(category_id)?;
+        if subtree.is_empty() {
+            return Err(ApiError::not_found("Category", category_id));
+        }
+        match reassign_to {
+            Some(target) => reassign_and_delete(state, category_id, &subtree, target),
+            None if cascade => cascade_delete(state, category_id, &subtree),
+            None => {
+                let products = state.products.ids_in_categories(&subtree)?;
+                if !products.is_empty() || subtree.len() > 1 {
+                    return Err(ApiError::Conflict {
+                        message: "Category still has products or subcategories".to_string(),
+                        details: json!({ "products": products.len(), "subcategories": subtree.len() - 1 }),
+                    });
+                }
+                state.categories.delete(category_id)?;
+                log_action(state, ANONYMOUS_USER, "DELETE", "category", Some(category_id))
+            }
+        }
+    })
+    .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// Hands the category's products and direct subcategories to `target`, then deletes it
+fn reassign_and_delete(state: &AppState, category_id: i32, subtree: &[i32], target: i32) -> Result<(), ApiError> {
+    if subtree.contains(&target) {
+        return Err(ApiError::validation(
+            "Request validation failed",
+            json!({ "reassign_to": [format!("category {} is being deleted", target)] }),
+        ));
+    }
+    if state.categories.get(target)?.is_none() {
+        return Err(ApiError::validation(
+            "Request validation failed",
+            json!({ "reassign_to": [format!("category {} does not exist", target)] }),
+        ));
+    }
+    let reason = format!("category {} deleted, moved to category {}", category_id, target);
+    let products = state.products.move_category(category_id, target)?;
+    for &product_id in &products {
+        log_action_with_reason(state, ANONYMOUS_USER, "UPDATE", "product", Some(product_id), &reason)?;
+    }
+    let children = state.categories.move_children(category_id, target)?;
+    for &child_id in &children {
+        log_action_with_reason(state, ANONYMOUS_USER, "UPDATE", "category", Some(child_id), &reason)?;
+    }
+    state.categories.delete(category_id)?;
+    let summary = format!(
+        "reassigned {} products and {} subcategories to category {}",
+        products.len(),
+        children.len(),
+        target
+    );
+    log_action_with_reason(state, ANONYMOUS_USER, "DELETE", "category", Some(category_id), &summary)
+}
+
+// Deletes every product in the subtree, then the subtree itself
+fn cascade_delete(state: &AppState, category_id: i32, subtree: &[i32]) -> Result<(), ApiError> {
+    let reason = format!("category {} deleted with cascade", category_id);
+    let products = state.products.ids_in_categories(subtree)?;
+    state.products.delete_many(&products)?;
+    for &product_id in &products {
+        log_action_with_reason(state, ANONYMOUS_USER, "DELETE", "product", Some(product_id), &reason)?;
+    }
+    state.categories.delete_many(subtree)?;
+    for &subcategory_id in subtree.iter().filter(|&&id| id != category_id) {
+        log_action_with_reason(state, ANONYMOUS_USER, "DELETE", "category", Some(subcategory_id), &reason)?;
+    }
+    let summary = format!("deleted {} products and {} subcategories", products.len(), subtree.len() - 1);
+    log_action_with_reason(state, ANONYMOUS_USER, "DELETE", "category", Some(category_id), &summary)
+}
@@ -0,0 +1,121 @@
+use actix_web::{web, HttpResponse};
+use serde_json::json;
+use validator::{Validate, ValidationErrors};
+
+use crate::audit::log_action;
+use crate::auth::{self, AuthUser};
+use crate::currency::REFERENCE_CURRENCY;
+use crate::db::models::ExchangeRate;
+use crate::errors::{ApiError, ErrorBody};
+use crate::models::SetExchangeRateRequest;
+use crate::validation;
+use crate::AppState;
+
+// The reference currency anchors every other rate, so it cannot be changed or removed
+fn ensure_not_reference(currency: &str) -> Result<(), ApiError> {
+    if currency == REFERENCE_CURRENCY {
+        return Err(ApiError::validation(
+            "Request validation failed",
+            json!({ "currency": [format!("{} is the reference currency; its rate is always 1", REFERENCE_CURRENCY)] }),
+        ));
+    }
+    Ok(())
+}
+
+// Every converted price follows the rates, so only admins may change them
+fn ensure_admin(state: &AppState, user: AuthUser) -> Result<(), ApiError> {
+    if !auth::is_admin(state, user)? {
+        return Err(ApiError::Forbidden("Only admins may change exchange rates".to_string()));
+    }
+    Ok(())
+}
+
+// The code comes from the path, so it is checked here rather than by a derive
+fn parse_currency(currency: String) -> Result<String, ApiError> {
+    if let Err(err) = validation::currency_code(&currency) {
+        let mut errors = ValidationErrors::new();
+        errors.add("currency", err);
+        return Err(errors.into());
+    }
+    Ok(currency)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/exchange-rates",
+    tag = "admin",
+    responses((status = 200, description = "Units of each currency worth one EUR", body = [ExchangeRate]))
+)]
+pub async fn get_exchange_rates(data: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let exchange_rates = data.db(|state| state.exchange_rates.list()).await?;
+    Ok(HttpResponse::Ok().json(exchange_rates))
+}
+
+// PUT: creates the rate or replaces the current one
+#[utoipa::path(
+    put,
+    path = "/api/v1/exchange-rates/{currency}",
+    tag = "admin",
+    params(
+        ("currency" = String, Path, description = "ISO 4217 code"),
+        ("Authorization" = String, Header, description = "Bearer <user_id> of an admin"),
+    ),
+    request_body = SetExchangeRateRequest,
+    responses(
+        (status = 200, body = ExchangeRate),
+        (status = 401, body = ErrorBody),
+        (status = 403, description = "The user is not an admin", body = ErrorBody),
+        (status = 422, body = ErrorBody),
+    )
+)]
+pub async fn set_exchange_rate(
+    data: web::Data<AppState>,
+    currency: web::Path<String>,
+    body: web::Json<SetExchangeRateRequest>,
+    user: AuthUser,
+) -> Result<HttpResponse, ApiError> {
+    body.validate()?;
+    let currency = parse_currency(currency.into_inner())?;
+    ensure_not_reference(&currency)?;
+    let rate = body.rate;
+    let exchange_rate = data
+        .transaction(move |state| {
+            ensure_admin(state, user)?;
+            let exchange_rate = state.exchange_rates.set(&currency, rate)?;
+            log_action(state, user.user_id, "UPDATE", "exchange_rate", None)?;
+            Ok(exchange_rate)
+        })
+        .await?;
+    Ok(HttpResponse::Ok().json(exchange_rate))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/exchange-rates/{currency}",
+    tag = "admin",
+    params(
+        ("currency" = String, Path, description = "ISO 4217 code"),
+        ("Authorization" = String, Header, description = "Bearer <user_id> of an admin"),
+    ),
+    responses(
+        (status = 204, description = "Exchange rate deleted"),
+        (status = 401, body = ErrorBody),
+        (status = 403, description = "The user is not an admin", body = ErrorBody),
+        (status = 404, body = ErrorBody),
+        (status = 409, description = "Products are still priced in this currency", body = ErrorBody),
+        (status = 422, body = ErrorBody),
+    )
+)]
+pub async fn delete_exchange_rate(data: web::Data<AppState>, currency: web::Path<String>, user: AuthUser) -> Result<HttpResponse, ApiError> {
+    let currency = currency.into_inner();
+    ensure_not_reference(&currency)?;
+    data.transaction(move |state| {
+        ensure_admin(state, user)?;
+        if !state.exchange_rates.delete(&currency)? {
+            return Err(ApiError::NotFound(format!("Exchange rate for {} not found", currency)));
+        }
+        log_action(state, user.user_id, "DELETE", "exchange_rate", None)
+    })
+    .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
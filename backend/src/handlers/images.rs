@@ -0,0 +1,353 @@
+use std::io::{self, Read};
+
+use actix_multipart::Multipart;
+use actix_web::http::header;
+use actix_web::{web, HttpResponse};
+use chrono::{Duration, NaiveDateTime, Utc};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::audit::{log_action, log_action_with_reason};
+use crate::db::models::{NewImage, Product, ProductImage, UpdateProduct};
+use crate::errors::{ApiError, ErrorBody};
+use crate::media::{self, MediaFormat};
+use crate::renditions;
+use crate::{AppState, API_V1};
+
+// Parts of one upload request beyond this are refused
+const MAX_IMAGES_PER_UPLOAD: usize = 10;
+
+// How long a direct upload's URL accepts the file
+const DIRECT_UPLOAD_MINUTES: i64 = 15;
+// Direct uploads land here until they are checked and stored by content hash.
+// Ones never completed stay behind; a bucket lifecycle rule on the prefix
+// clears them out.
+const INCOMING_DIR: &str = "incoming";
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImageUploadQuery {
+    // Also make the first uploaded image the product's main image
+    pub primary: Option<bool>,
+}
+
+// The multipart body; parts under other names are ignored
+#[derive(ToSchema)]
+pub struct ImageUploadForm {
+    // JPEG, PNG, GIF or WebP, recognised by content; repeat the part to upload several
+    #[schema(value_type = Vec<String>, format = Binary)]
+    pub file: Vec<Vec<u8>>,
+}
+
+// Where to send an image straight to storage; see create_direct_upload
+#[derive(Serialize, ToSchema)]
+pub struct DirectUpload {
+    pub id: String,
+    // Takes the image as the raw request body
+    pub url: String,
+    // Always PUT
+    pub method: String,
+    pub expires_at: NaiveDateTime,
+}
+
+fn incoming_key(upload_id: &str) -> String {
+    format!("{}/{}", INCOMING_DIR, upload_id)
+}
+
+fn upload_not_found(upload_id: &str) -> ApiError {
+    ApiError::NotFound(format!("Upload {} not found; PUT the image to its URL first", upload_id))
+}
+
+fn find_product(state: &AppState, product_id: i32) -> Result<Product, ApiError> {
+    state.products.get(product_id)?.ok_or_else(|| ApiError::not_found("Product", product_id))
+}
+
+fn invalid_file(message: &str) -> ApiError {
+    ApiError::validation("Request validation failed", json!({ "file": [message] }))
+}
+
+// Buffers every `file` part, refusing one as soon as it outgrows `max_bytes`
+async fn read_images(mut payload: Multipart, max_bytes: usize) -> Result<Vec<(MediaFormat, Vec<u8>)>, ApiError> {
+    let mut images = Vec::new();
+    while let Some(field) = payload.next().await {
+        let mut field = field.map_err(|err| ApiError::BadRequest(err.to_string()))?;
+        if field.name() != Some("file") {
+            continue;
+        }
+        if images.len() == MAX_IMAGES_PER_UPLOAD {
+            return Err(invalid_file(&format!("at most {} images per upload", MAX_IMAGES_PER_UPLOAD)));
+        }
+        let mut bytes = Vec::new();
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk.map_err(|err| ApiError::BadRequest(err.to_string()))?;
+            if bytes.len() + chunk.len() > max_bytes {
+                return Err(ApiError::PayloadTooLarge(format!("Each image may be at most {} bytes", max_bytes)));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        let format = media::sniff_image(&bytes).ok_or_else(|| invalid_file("must be a JPEG, PNG, GIF or WebP image"))?;
+        images.push((format, bytes));
+    }
+    if images.is_empty() {
+        return Err(invalid_file("at least one image is required"));
+    }
+    Ok(images)
+}
+
+// Stores the files by content hash and adds them to the product, the first as
+// its main image if `primary`. Call inside a transaction.
+fn add_images(state: &AppState, product_id: i32, files: &[(MediaFormat, Vec<u8>)], primary: bool) -> Result<Vec<ProductImage>, ApiError> {
+    let product = find_product(state, product_id)?;
+    let mut images: Vec<ProductImage> = Vec::with_capacity(files.len());
+    for (format, bytes) in files {
+        let key = media::image_key(bytes, *format);
+        state.storage.put(&key, bytes).map_err(|err| ApiError::Internal(format!("Storing {}: {}", key, err)))?;
+        let image = NewImage {
+            url: state.storage.url(&key),
+            key,
+            content_type: format.content_type.to_string(),
+            size_bytes: bytes.len() as i64,
+        };
+        let image = state.images.create(product_id, image)?;
+        log_action(state, product.user_id, "CREATE", "product_image", Some(image.id))?;
+        images.push(image);
+    }
+    if primary {
+        let changes = UpdateProduct { image: Some(images[0].url.clone()), ..Default::default() };
+        state.products.update(product_id, changes)?;
+        log_action_with_reason(state, product.user_id, "UPDATE", "product", Some(product_id), "main image uploaded")?;
+    }
+    Ok(images)
+}
+
+// Removes the file under each key no image refers to, with its renditions.
+// Content-addressed files can be shared, so one still in use stays.
+fn remove_unused(state: &AppState, keys: &[String]) -> Result<(), ApiError> {
+    for key in keys {
+        if state.images.key_in_use(key)? {
+            continue;
+        }
+        for key in renditions::rendition_keys(key).iter().chain([key]) {
+            state.storage.remove(key).map_err(|err| ApiError::Internal(format!("Removing {}: {}", key, err)))?;
+        }
+    }
+    Ok(())
+}
+
+// Runs add_images in a transaction. Files are stored before their rows, so
+// when the transaction rolls back the ones it stored for nothing are removed.
+async fn add_images_or_clean_up(
+    data: &web::Data<AppState>,
+    product_id: i32,
+    files: Vec<(MediaFormat, Vec<u8>)>,
+    primary: bool,
+) -> Result<Vec<ProductImage>, ApiError> {
+    let keys: Vec<String> = files.iter().map(|(format, bytes)| media::image_key(bytes, *format)).collect();
+    let images = data.transaction(move |state| add_images(state, product_id, &files, primary)).await;
+    if images.is_err() {
+        data.db(move |state| remove_unused(state, &keys)).await?;
+    }
+    images
+}
+
+// The image PUT to a direct upload, read back and checked like a multipart
+// part. One that fails the checks is removed; the client starts over.
+fn take_incoming(state: &AppState, upload_id: &str, max_bytes: usize) -> Result<(MediaFormat, Vec<u8>), ApiError> {
+    let key = incoming_key(upload_id);
+    let storage_error = |err: io::Error| ApiError::Internal(format!("Reading {}: {}", key, err));
+    let (body, len) = match state.storage.open(&key) {
+        Ok(opened) => opened,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Err(upload_not_found(upload_id)),
+        Err(err) => return Err(storage_error(err)),
+    };
+    let mut bytes = Vec::new();
+    if len <= max_bytes as u64 {
+        body.take(max_bytes as u64 + 1).read_to_end(&mut bytes).map_err(storage_error)?;
+    }
+    let rejection = if len > max_bytes as u64 || bytes.len() > max_bytes {
+        ApiError::PayloadTooLarge(format!("Each image may be at most {} bytes", max_bytes))
+    } else if let Some(format) = media::sniff_image(&bytes) {
+        return Ok((format, bytes));
+    } else {
+        invalid_file("must be a JPEG, PNG, GIF or WebP image")
+    };
+    state.storage.remove(&key).map_err(storage_error)?;
+    Err(rejection)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/products/{id}/images",
+    tag = "images",
+    params(("id" = i32, Path, description = "Product id")),
+    responses(
+        (status = 200, description = "Uploaded images, oldest first", body = [ProductImage]),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn get_images(data: web::Data<AppState>, id: web::Path<i32>) -> Result<HttpResponse, ApiError> {
+    let product_id = id.into_inner();
+    let images = data
+        .db(move |state| {
+            find_product(state, product_id)?;
+            state.images.list(product_id)
+        })
+        .await?;
+    Ok(HttpResponse::Ok().json(images))
+}
+
+// Files are stored by content hash and served from the returned URLs. Resized WebP and JPEG copies follow in the background:
+// the images come back with renditions_status "pending" and no srcset yet.
+#[utoipa::path(
+    post,
+    path = "/api/v1/products/{id}/images",
+    tag = "images",
+    params(("id" = i32, Path, description = "Product id"), ImageUploadQuery),
+    request_body(content = ImageUploadForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 201, description = "The uploaded images", body = [ProductImage],
+            headers(("Location" = String, description = "URL of the product's image list"))),
+        (status = 400, description = "Not a readable multipart body", body = ErrorBody),
+        (status = 404, body = ErrorBody),
+        (status = 409, description = "The product has this image already", body = ErrorBody),
+        (status = 413, description = "An image is over MEDIA_MAX_IMAGE_BYTES", body = ErrorBody),
+        (status = 422, description = "No image, too many, or one that is not JPEG, PNG, GIF or WebP", body = ErrorBody),
+    )
+)]
+pub async fn upload_images(
+    data: web::Data<AppState>,
+    id: web::Path<i32>,
+    query: web::Query<ImageUploadQuery>,
+    payload: Multipart,
+) -> Result<HttpResponse, ApiError> {
+    let product_id = id.into_inner();
+    let primary = query.primary == Some(true);
+    let files = read_images(payload, data.media.max_image_bytes).await?;
+    let images = add_images_or_clean_up(&data, product_id, files, primary).await?;
+    Ok(HttpResponse::Created()
+        .insert_header((header::LOCATION, format!("{}/products/{}/images", API_V1, product_id)))
+        .json(images))
+}
+
+// Deleting the main image makes the newest remaining one the main image. The
+// file and its renditions go once no product has an image stored under its key.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/products/{id}/images/{image_id}",
+    tag = "images",
+    params(
+        ("id" = i32, Path, description = "Product id"),
+        ("image_id" = i32, Path, description = "Image id"),
+    ),
+    responses(
+        (status = 204, description = "Image deleted"),
+        (status = 404, body = ErrorBody),
+        (status = 409, description = "It is the main image and the product has no other", body = ErrorBody),
+    )
+)]
+pub async fn delete_image(data: web::Data<AppState>, path: web::Path<(i32, i32)>) -> Result<HttpResponse, ApiError> {
+    let (product_id, image_id) = path.into_inner();
+    let key = data
+        .transaction(move |state| {
+            let image = state.images.get(product_id, image_id)?.ok_or_else(|| ApiError::not_found("Image", image_id))?;
+            let product = find_product(state, product_id)?;
+            state.images.delete(product_id, image_id)?;
+            if product.image == image.url {
+                let next = state.images.list(product_id)?.pop().ok_or_else(|| ApiError::Conflict {
+                    message: "Image is the product's main image and it has no other".to_string(),
+                    details: json!({ "image_id": image_id }),
+                })?;
+                let changes = UpdateProduct { image: Some(next.url), ..Default::default() };
+                state.products.update(product_id, changes)?;
+                log_action_with_reason(state, product.user_id, "UPDATE", "product", Some(product_id), "main image deleted")?;
+            }
+            log_action(state, product.user_id, "DELETE", "product_image", Some(image_id))?;
+            Ok(image.key)
+        })
+        .await?;
+    // After the commit, so a rolled-back delete never loses its file
+    data.db(move |state| remove_unused(state, &[key])).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// Lets the client send an image straight to object storage instead of through
+// this API: PUT the file to the returned URL before it expires, then complete
+// the upload. Only object storage backends hand out such URLs.
+#[utoipa::path(
+    post,
+    path = "/api/v1/products/{id}/images/direct-uploads",
+    tag = "images",
+    params(("id" = i32, Path, description = "Product id")),
+    responses(
+        (status = 200, description = "Where to PUT the image", body = DirectUpload),
+        (status = 404, body = ErrorBody),
+        (status = 409, description = "Media is stored locally; upload through POST /api/v1/products/{id}/images", body = ErrorBody),
+    )
+)]
+pub async fn create_direct_upload(data: web::Data<AppState>, id: web::Path<i32>) -> Result<HttpResponse, ApiError> {
+    let product_id = id.into_inner();
+    let upload = data
+        .db(move |state| {
+            find_product(state, product_id)?;
+            let id = uuid::Uuid::new_v4().to_string();
+            let expiry = Duration::minutes(DIRECT_UPLOAD_MINUTES);
+            let url = state.storage.presigned_url("PUT", &incoming_key(&id), expiry).ok_or_else(|| ApiError::Conflict {
+                message: "Direct uploads need object storage; upload the image through this API instead".to_string(),
+                details: json!({ "storage": state.storage.name() }),
+            })?;
+            Ok(DirectUpload { id, url, method: "PUT".to_string(), expires_at: Utc::now().naive_utc() + expiry })
+        })
+        .await?;
+    Ok(HttpResponse::Ok().json(upload))
+}
+
+// Adds the image PUT to a direct upload to the product, like a multipart upload
+// of that one file. The uploaded object is removed either way.
+#[utoipa::path(
+    post,
+    path = "/api/v1/products/{id}/images/direct-uploads/{upload_id}",
+    tag = "images",
+    params(
+        ("id" = i32, Path, description = "Product id"),
+        ("upload_id" = String, Path, description = "Id of the direct upload"),
+        ImageUploadQuery,
+    ),
+    responses(
+        (status = 201, description = "The uploaded image", body = [ProductImage],
+            headers(("Location" = String, description = "URL of the product's image list"))),
+        (status = 404, description = "No such product, or nothing was PUT to the upload's URL", body = ErrorBody),
+        (status = 409, description = "The product has this image already", body = ErrorBody),
+        (status = 413, description = "The image is over MEDIA_MAX_IMAGE_BYTES", body = ErrorBody),
+        (status = 422, description = "Not a JPEG, PNG, GIF or WebP image", body = ErrorBody),
+    )
+)]
+pub async fn complete_direct_upload(
+    data: web::Data<AppState>,
+    path: web::Path<(i32, String)>,
+    query: web::Query<ImageUploadQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let (product_id, upload_id) = path.into_inner();
+    let primary = query.primary == Some(true);
+    if uuid::Uuid::parse_str(&upload_id).is_err() {
+        return Err(upload_not_found(&upload_id));
+    }
+    let max_bytes = data.media.max_image_bytes;
+    let incoming = upload_id.clone();
+    let file = data
+        .db(move |state| {
+            find_product(state, product_id)?;
+            take_incoming(state, &incoming, max_bytes)
+        })
+        .await?;
+    let images = add_images_or_clean_up(&data, product_id, vec![file], primary).await;
+    data.db(move |state| {
+        let key = incoming_key(&upload_id);
+        state.storage.remove(&key).map_err(|err| ApiError::Internal(format!("Removing {}: {}", key, err)))
+    })
+    .await?;
+    Ok(HttpResponse::Created()
+        .insert_header((header::LOCATION, format!("{}/products/{}/images", API_V1, product_id)))
+        .json(images?))
+}
@@ -0,0 +1,109 @@
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures::StreamExt;
+
+use crate::auth::{self, AuthUser};
+use crate::db::models::ImportJob;
+use crate::errors::{ApiError, ErrorBody};
+use crate::imports::{self, ImportFormat, ImportOptions, INLINE_ROWS, MAX_IMPORT_BYTES};
+use crate::models::ImportQuery;
+use crate::{AppState, API_V1};
+
+// Imports products from a spreadsheet export, one product per row. A row whose
+// SKU no product has creates one, owned by the caller; with upsert, one whose
+// SKU a product has updates it with the columns the row fills. Rows stand
+// alone: one that fails is reported and the others go ahead. Small files are
+// imported before the response; larger ones by the import job, which the
+// response's Location reports on.
+#[utoipa::path(
+    post,
+    path = "/api/v1/products/import",
+    tag = "products",
+    params(
+        ImportQuery,
+        ("Authorization" = String, Header, description = "Bearer <user_id>; the owner of the products the import creates"),
+    ),
+    request_body(
+        description = "CSV with a header row, or one JSON object per line. Columns: sku, name, price, currency, description, image, video, \
+                       published, category (a name) or category_id, low_stock_threshold, and attributes (in CSV, one attributes.<name> \
+                       column per attribute).",
+        content(("text/csv"), ("application/x-ndjson")),
+    ),
+    responses(
+        (status = 200, description = "Imported, or checked for a dry run; failed rows are in row_errors", body = ImportJob),
+        (status = 202, description = "Queued for the import job", body = ImportJob,
+            headers(("Location" = String, description = "Where the job's progress is reported"))),
+        (status = 400, description = "No format given and the Content-Type names none", body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 413, description = "The file is over 50 MiB", body = ErrorBody),
+        (status = 422, description = "The file cannot be read at all, e.g. for unknown columns or no rows", body = ErrorBody),
+    )
+)]
+pub async fn import_products(
+    data: web::Data<AppState>,
+    query: web::Query<ImportQuery>,
+    req: HttpRequest,
+    user: AuthUser,
+    mut payload: web::Payload,
+) -> Result<HttpResponse, ApiError> {
+    let query = query.into_inner();
+    let content_type = req.headers().get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok());
+    let format = query
+        .format
+        .or_else(|| content_type.and_then(ImportFormat::from_content_type))
+        .ok_or_else(|| ApiError::BadRequest("Give format=csv or format=ndjson, or a Content-Type of text/csv or application/x-ndjson".to_string()))?;
+    let options = ImportOptions {
+        format,
+        dry_run: query.dry_run.unwrap_or(false),
+        upsert: query.upsert.unwrap_or(false),
+        create_categories: query.create_categories.unwrap_or(false),
+    };
+    let mut body = Vec::new();
+    while let Some(bytes) = payload.next().await {
+        let bytes = bytes.map_err(|err| ApiError::BadRequest(err.to_string()))?;
+        if body.len() + bytes.len() > MAX_IMPORT_BYTES {
+            return Err(ApiError::PayloadTooLarge(format!("Import files may be at most {} bytes", MAX_IMPORT_BYTES)));
+        }
+        body.extend_from_slice(&bytes);
+    }
+    let source = String::from_utf8(body)
+        .map_err(|_| ApiError::validation("Import file rejected", serde_json::json!({ "file": ["must be UTF-8 text"] })))?;
+
+    let job = data.db(move |state| imports::create(state, user.user_id, options, source, INLINE_ROWS)).await?;
+    if job.status == "pending" {
+        let location = format!("{}/products/import/{}", API_V1, job.id);
+        return Ok(HttpResponse::Accepted().insert_header((header::LOCATION, location)).json(job));
+    }
+    let job = data.db(move |state| imports::run(state, &job.id, &mut |_| {})).await?;
+    Ok(HttpResponse::Ok().json(job))
+}
+
+// How far an import got and, once it ended, which rows failed
+#[utoipa::path(
+    get,
+    path = "/api/v1/products/import/{id}",
+    tag = "products",
+    params(
+        ("id" = String, Path, description = "Import id"),
+        ("Authorization" = String, Header, description = "Bearer <user_id>; the user who started the import, or an admin"),
+    ),
+    responses(
+        (status = 200, body = ImportJob),
+        (status = 401, body = ErrorBody),
+        (status = 403, description = "The import is another user's", body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn get_import(data: web::Data<AppState>, id: web::Path<String>, user: AuthUser) -> Result<HttpResponse, ApiError> {
+    let id = id.into_inner();
+    let job = data
+        .db(move |state| {
+            let job = state.import_jobs.get(&id)?.ok_or_else(|| imports::not_found(&id))?;
+            if job.user_id != user.user_id && !auth::is_admin(state, user)? {
+                return Err(ApiError::Forbidden("Only the user who started an import and admins may see it".to_string()));
+            }
+            Ok(job)
+        })
+        .await?;
+    Ok(HttpResponse::Ok().json(job))
+}
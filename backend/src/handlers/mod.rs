@@ -0,0 +1,13 @@
+pub mod admin;
+pub mod attributes;
+pub mod categories;
+pub mod exchange_rates;
+pub mod images;
+pub mod imports;
+pub mod products;
+pub mod stats;
+pub mod stock;
+pub mod tags;
+pub mod users;
+pub mod variants;
+pub mod videos;
@@ -0,0 +1,293 @@
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use validator::Validate;
+
+use crate::attributes;
+use crate::audit::log_action;
+use crate::db::models::{NewProduct, PriceChange, UpdateProduct};
+use crate::db::repository::ProductWithCategory;
+use crate::errors::{ApiError, ErrorBody};
+use crate::models::{AttributeFilter, ProductQuery};
+use crate::validation;
+use crate::{AppState, API_V1};
+
+// serde cannot collect the attr.* parameters next to ProductQuery's typed fields
+fn with_attribute_filter(req: &HttpRequest, query: ProductQuery) -> Result<ProductQuery, ApiError> {
+    let attributes = AttributeFilter::from_query_string(req.query_string()).map_err(ApiError::BadRequest)?;
+    Ok(ProductQuery { attributes, ..query })
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/products",
+    tag = "products",
+    params(
+        ProductQuery,
+        ("attr.{name}" = Option<String>, Query, description = "Only products whose attribute `name` has this value, ignoring case; repeatable, e.g. attr.size=42"),
+    ),
+    responses(
+        (status = 200, description = "Products of all users matching the filters", body = [ProductWithCategory]),
+        (status = 400, description = "Malformed filter", body = ErrorBody),
+        (status = 422, description = "No exchange rate for the requested currency, or a filter out of range", body = ErrorBody),
+        (status = 503, description = "Database unavailable", body = ErrorBody),
+    )
+)]
+pub async fn get_products(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<ProductQuery>,
+) -> Result<HttpResponse, ApiError> {
+    query.validate()?;
+    let query = with_attribute_filter(&req, query.into_inner())?;
+    let products = data
+        .db(move |state| {
+            if let Some(currency) = &query.currency {
+                validation::ensure_currency_known(state.exchange_rates.as_ref(), currency)?;
+            }
+            state.products.list(None, &query)
+        })
+        .await?;
+    Ok(HttpResponse::Ok().json(products))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/users/{user_id}/products",
+    tag = "products",
+    params(
+        ("user_id" = i32, Path, description = "Owner id"),
+        ProductQuery,
+        ("attr.{name}" = Option<String>, Query, description = "Only products whose attribute `name` has this value, ignoring case; repeatable"),
+    ),
+    responses(
+        (status = 200, body = [ProductWithCategory]),
+        (status = 422, description = "A filter is out of range", body = ErrorBody),
+    )
+)]
+pub async fn get_products_by_user_id(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    user_id: web::Path<i32>,
+    query: web::Query<ProductQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = user_id.into_inner();
+    query.validate()?;
+    let query = with_attribute_filter(&req, query.into_inner())?;
+    let products = data
+        .db(move |state| {
+            if let Some(currency) = &query.currency {
+                validation::ensure_currency_known(state.exchange_rates.as_ref(), currency)?;
+            }
+            state.products.list(Some(user_id), &query)
+        })
+        .await?;
+    Ok(HttpResponse::Ok().json(products))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/products/{id}",
+    tag = "products",
+    params(("id" = i32, Path, description = "Product id")),
+    responses(
+        (status = 200, body = crate::db::models::Product),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn get_product(data: web::Data<AppState>, id: web::Path<i32>) -> Result<HttpResponse, ApiError> {
+    let product_id = id.into_inner();
+    let product = data
+        .db(move |state| state.products.get(product_id))
+        .await?
+        .ok_or_else(|| ApiError::not_found("Product", product_id))?;
+    Ok(HttpResponse::Ok().json(product))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/products/{id}/price-history",
+    tag = "products",
+    params(("id" = i32, Path, description = "Product id")),
+    responses(
+        (status = 200, description = "Every price the product has had, oldest first", body = [PriceChange]),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn get_price_history(data: web::Data<AppState>, id: web::Path<i32>) -> Result<HttpResponse, ApiError> {
+    let product_id = id.into_inner();
+    let history = data
+        .db(move |state| {
+            if state.products.get(product_id)?.is_none() {
+                return Err(ApiError::not_found("Product", product_id));
+            }
+            state.products.price_history(product_id)
+        })
+        .await?;
+    Ok(HttpResponse::Ok().json(history))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/products",
+    tag = "products",
+    request_body = NewProduct,
+    responses(
+        (status = 201, body = crate::db::models::Product, headers(("Location" = String, description = "URL of the new product"))),
+        (status = 409, body = ErrorBody),
+        (status = 422, body = ErrorBody),
+    )
+)]
+pub async fn create_product(
+    data: web::Data<AppState>,
+    product: web::Json<NewProduct>,
+) -> Result<HttpResponse, ApiError> {
+    product.validate()?;
+    let new_product = product.into_inner();
+    let product = data
+        .transaction(move |state| {
+            validation::ensure_category_exists(state.categories.as_ref(), new_product.category_id)?;
+            validation::ensure_currency_known(state.exchange_rates.as_ref(), &new_product.currency)?;
+            validation::ensure_valid_attributes(
+                state.categories.as_ref(),
+                state.attributes.as_ref(),
+                new_product.category_id,
+                &new_product.attributes,
+            )?;
+            let product = state.products.create(new_product)?;
+            log_action(state, product.user_id, "CREATE", "product", Some(product.id))?;
+            Ok(product)
+        })
+        .await?;
+    Ok(HttpResponse::Created()
+        .insert_header((header::LOCATION, format!("{}/products/{}", API_V1, product.id)))
+        .json(product))
+}
+
+// PUT: the body is the complete product and replaces the stored one
+#[utoipa::path(
+    put,
+    path = "/api/v1/products/{id}",
+    tag = "products",
+    params(("id" = i32, Path, description = "Product id")),
+    request_body = NewProduct,
+    responses(
+        (status = 200, body = crate::db::models::Product),
+        (status = 404, body = ErrorBody),
+        (status = 422, body = ErrorBody),
+    )
+)]
+pub async fn replace_product(
+    data: web::Data<AppState>,
+    id: web::Path<i32>,
+    product: web::Json<NewProduct>,
+) -> Result<HttpResponse, ApiError> {
+    product.validate()?;
+    let product_id = id.into_inner();
+    let new_product = product.into_inner();
+    let product = data
+        .transaction(move |state| {
+            validation::ensure_category_exists(state.categories.as_ref(), new_product.category_id)?;
+            validation::ensure_currency_known(state.exchange_rates.as_ref(), &new_product.currency)?;
+            validation::ensure_valid_attributes(
+                state.categories.as_ref(),
+                state.attributes.as_ref(),
+                new_product.category_id,
+                &new_product.attributes,
+            )?;
+            let product = state
+                .products
+                .replace(product_id, new_product)?
+                .ok_or_else(|| ApiError::not_found("Product", product_id))?;
+            log_action(state, product.user_id, "UPDATE", "product", Some(product.id))?;
+            Ok(product)
+        })
+        .await?;
+    Ok(HttpResponse::Ok().json(product))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/products/{id}",
+    tag = "products",
+    params(("id" = i32, Path, description = "Product id")),
+    request_body = UpdateProduct,
+    responses(
+        (status = 200, body = crate::db::models::Product),
+        (status = 404, body = ErrorBody),
+        (status = 422, body = ErrorBody),
+    )
+)]
+pub async fn update_product(
+    data: web::Data<AppState>,
+    id: web::Path<i32>,
+    product: web::Json<UpdateProduct>,
+) -> Result<HttpResponse, ApiError> {
+    product.validate()?;
+    let product_id = id.into_inner();
+    let mut changes = product.into_inner();
+    let product = data
+        .transaction(move |state| {
+            if let Some(category_id) = changes.category_id {
+                validation::ensure_category_exists(state.categories.as_ref(), category_id)?;
+            }
+            if let Some(currency) = &changes.currency {
+                validation::ensure_currency_known(state.exchange_rates.as_ref(), currency)?;
+            }
+            // Moving to another category re-checks the stored values against its attributes
+            if changes.attributes.is_some() || changes.category_id.is_some() {
+                let current = state.products.get(product_id)?.ok_or_else(|| ApiError::not_found("Product", product_id))?;
+                let values = match changes.attributes.take() {
+                    Some(patch) => attributes::merge(&current.attributes, patch),
+                    None => current.attributes.clone(),
+                };
+                let category_id = changes.category_id.unwrap_or(current.category_id);
+                validation::ensure_valid_attributes(state.categories.as_ref(), state.attributes.as_ref(), category_id, &values)?;
+                changes.attributes = Some(values);
+            }
+            if changes.is_empty() {
+                return state
+                    .products
+                    .get(product_id)?
+                    .ok_or_else(|| ApiError::not_found("Product", product_id));
+            }
+            let user_id = changes.user_id.unwrap_or(0); // fallback if not provided
+            let product = state
+                .products
+                .update(product_id, changes)?
+                .ok_or_else(|| ApiError::not_found("Product", product_id))?;
+            log_action(state, user_id, "UPDATE", "product", Some(product.id))?;
+            Ok(product)
+        })
+        .await?;
+    Ok(HttpResponse::Ok().json(product))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/products/{id}",
+    tag = "products",
+    params(("id" = i32, Path, description = "Product id")),
+    responses(
+        (status = 204, description = "Product deleted"),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn delete_product(
+    data: web::Data<AppState>,
+    id: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let product_id = id.into_inner();
+    data.transaction(move |state| {
+        // Get the product before deleting to get user_id
+        let user_id = state
+            .products
+            .get(product_id)?
+            .ok_or_else(|| ApiError::not_found("Product", product_id))?
+            .user_id;
+        state.products.delete(product_id)?;
+        log_action(state, user_id, "DELETE", "product", Some(product_id))?;
+        Ok(())
+    })
+    .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
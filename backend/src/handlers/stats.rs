@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+use crate::category_tree;
+use crate::currency::REFERENCE_CURRENCY;
+use crate::errors::ApiError;
+use crate::models::ProductQuery;
+use crate::money::Money;
+use crate::{validation, AppState};
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StatsQuery {
+    pub user_id: i32,
+    // Currency the averages are computed in; EUR when omitted
+    pub currency: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AvgPriceQuery {
+    pub user_id: i32,
+    // Currency the average is computed in; EUR when omitted
+    pub currency: Option<String>,
+}
+
+// Averaging only makes sense over one currency, so stats always convert
+fn stats_currency(state: &AppState, requested: Option<String>) -> Result<String, ApiError> {
+    let currency = requested.unwrap_or_else(|| REFERENCE_CURRENCY.to_string());
+    validation::ensure_currency_known(state.exchange_rates.as_ref(), &currency)?;
+    Ok(currency)
+}
+
+// Every product of one owner, priced in the stats currency
+fn owner_products_query(currency: String) -> ProductQuery {
+    ProductQuery { currency: Some(currency), ..Default::default() }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/stats/avg-price-per-category",
+    tag = "stats",
+    params(StatsQuery),
+    responses((status = 200, description = "Average price per category including its subcategories, highest first"))
+)]
+pub async fn avg_price_per_category_handler(data: web::Data<AppState>, query: web::Query<StatsQuery>) -> Result<HttpResponse, ApiError> {
+    let user_id_val = query.user_id;
+    let currency = query.into_inner().currency;
+    let results = data
+        .db(move |state| {
+            let currency = stats_currency(state, currency)?;
+            state.products.avg_price_per_category(user_id_val, &currency)
+        })
+        .await?;
+    let response: Vec<_> = results.into_iter().map(|(category, avg_price)| serde_json::json!({"category": category, "avg_price": avg_price})).collect();
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/stats/avg-price-inefficient",
+    tag = "stats",
+    params(AvgPriceQuery),
+    responses((status = 200, description = "Average price and product count"))
+)]
+pub async fn avg_price_inefficient_handler(data: web::Data<AppState>, query: web::Query<AvgPriceQuery>) -> Result<HttpResponse, ApiError> {
+    let user_id_val = query.user_id;
+    let currency = query.into_inner().currency;
+    // Load all products for the user
+    let products = data
+        .db(move |state| {
+            let query = owner_products_query(stats_currency(state, currency)?);
+            state.products.list(Some(user_id_val), &query)
+        })
+        .await?;
+    if products.is_empty() {
+        return Ok(HttpResponse::Ok().json(serde_json::json!({"average_price": null, "count": 0})));
+    }
+    // Calculate average in Rust (inefficient)
+    let avg = Money::average(products.iter().map(|p| p.price));
+    Ok(HttpResponse::Ok().json(serde_json::json!({"average_price": avg, "count": products.len()})))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/stats/avg-price-per-category-inefficient",
+    tag = "stats",
+    params(StatsQuery),
+    responses((status = 200, description = "Average price per category including its subcategories, highest first"))
+)]
+pub async fn avg_price_per_category_inefficient_handler(data: web::Data<AppState>, query: web::Query<StatsQuery>) -> Result<HttpResponse, ApiError> {
+    let user_id_val = query.user_id;
+    let currency = query.into_inner().currency;
+    
+    // Load all products with their categories
+    let (products, categories) = data
+        .db(move |state| {
+            let query = owner_products_query(stats_currency(state, currency)?);
+            Ok((state.products.list(Some(user_id_val), &query)?, state.categories.list()?))
+        })
+        .await?;
+    
+    // Group products by category and calculate averages in memory; a product
+    // counts towards its category and every category above it
+    let mut category_prices: HashMap<String, Vec<Money>> = HashMap::new();
+    
+    for product in products {
+        for category_id in category_tree::ancestors(&categories, product.category_id) {
+            if let Some(category) = categories.iter().find(|c| c.id == category_id) {
+                category_prices.entry(category.name.clone())
+                    .or_default()
+                    .push(product.price);
+            }
+        }
+    }
+    
+    // Sort by average price in descending order
+    let mut averages: Vec<(String, Option<Money>)> = category_prices.into_iter()
+        .map(|(category, prices)| (category, Money::average(prices)))
+        .collect();
+    averages.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    
+    let results: Vec<_> = averages.into_iter()
+        .map(|(category, avg_price)| serde_json::json!({
+            "category": category,
+            "avg_price": avg_price
+        }))
+        .collect();
+    
+    Ok(HttpResponse::Ok().json(results))
+}
@@ -0,0 +1,180 @@
+use actix_web::{web, HttpResponse};
+use validator::Validate;
+
+use crate::audit::log_action_with_reason;
+use crate::db::models::{Product, ProductVariant};
+use crate::errors::{ApiError, ErrorBody};
+use crate::models::StockMovementRequest;
+use crate::AppState;
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/products/{id}/stock/increment",
+    tag = "stock",
+    params(("id" = i32, Path, description = "Product id")),
+    request_body = StockMovementRequest,
+    responses(
+        (status = 200, description = "Product with its new stock", body = Product),
+        (status = 404, body = ErrorBody),
+        (status = 409, description = "Stock would exceed the maximum; nothing was added", body = ErrorBody),
+        (status = 422, body = ErrorBody),
+    )
+)]
+pub async fn increment_stock(
+    data: web::Data<AppState>,
+    id: web::Path<i32>,
+    movement: web::Json<StockMovementRequest>,
+) -> Result<HttpResponse, ApiError> {
+    move_stock(data, id.into_inner(), movement.into_inner(), 1).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/products/{id}/stock/decrement",
+    tag = "stock",
+    params(("id" = i32, Path, description = "Product id")),
+    request_body = StockMovementRequest,
+    responses(
+        (status = 200, description = "Product with its new stock", body = Product),
+        (status = 404, body = ErrorBody),
+        (status = 409, description = "Not enough stock; nothing was taken out", body = ErrorBody),
+        (status = 422, body = ErrorBody),
+    )
+)]
+pub async fn decrement_stock(
+    data: web::Data<AppState>,
+    id: web::Path<i32>,
+    movement: web::Json<StockMovementRequest>,
+) -> Result<HttpResponse, ApiError> {
+    move_stock(data, id.into_inner(), movement.into_inner(), -1).await
+}
+
+// Moves the stock and audits the movement, plus a LOW_STOCK entry when it
+// crosses the product's threshold, all in one transaction
+async fn move_stock(
+    data: web::Data<AppState>,
+    product_id: i32,
+    movement: StockMovementRequest,
+    direction: i32,
+) -> Result<HttpResponse, ApiError> {
+    movement.validate()?;
+    let delta = movement.quantity * direction;
+    let product = data
+        .transaction(move |state| {
+            let product = state
+                .products
+                .adjust_stock(product_id, delta)?
+                .ok_or_else(|| ApiError::not_found("Product", product_id))?;
+            let reason = movement_reason(&movement, delta, product.stock);
+            log_action_with_reason(state, product.user_id, movement_action(delta), "product", Some(product.id), &reason)?;
+
+            if let Some(threshold) = product.low_stock_threshold {
+                let previous = product.stock - delta;
+                if product.stock <= threshold && previous > threshold {
+                    let reason = format!("stock {} is at or below the threshold of {}", product.stock, threshold);
+                    log_action_with_reason(state, product.user_id, "LOW_STOCK", "product", Some(product.id), &reason)?;
+                }
+            }
+            Ok(product)
+        })
+        .await?;
+    Ok(HttpResponse::Ok().json(product))
+}
+
+fn movement_action(delta: i32) -> &'static str {
+    if delta > 0 { "STOCK_IN" } else { "STOCK_OUT" }
+}
+
+fn movement_reason(movement: &StockMovementRequest, delta: i32, stock: i32) -> String {
+    format!("{} ({:+}, stock now {})", movement.reason.trim(), delta, stock)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/products/{id}/variants/{variant_id}/stock/increment",
+    tag = "stock",
+    params(
+        ("id" = i32, Path, description = "Product id"),
+        ("variant_id" = i32, Path, description = "Variant id"),
+    ),
+    request_body = StockMovementRequest,
+    responses(
+        (status = 200, description = "Variant with its new stock", body = ProductVariant),
+        (status = 404, body = ErrorBody),
+        (status = 409, description = "Stock would exceed the maximum; nothing was added", body = ErrorBody),
+        (status = 422, body = ErrorBody),
+    )
+)]
+pub async fn increment_variant_stock(
+    data: web::Data<AppState>,
+    path: web::Path<(i32, i32)>,
+    movement: web::Json<StockMovementRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let (product_id, variant_id) = path.into_inner();
+    move_variant_stock(data, product_id, variant_id, movement.into_inner(), 1).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/products/{id}/variants/{variant_id}/stock/decrement",
+    tag = "stock",
+    params(
+        ("id" = i32, Path, description = "Product id"),
+        ("variant_id" = i32, Path, description = "Variant id"),
+    ),
+    request_body = StockMovementRequest,
+    responses(
+        (status = 200, description = "Variant with its new stock", body = ProductVariant),
+        (status = 404, body = ErrorBody),
+        (status = 409, description = "Not enough stock; nothing was taken out", body = ErrorBody),
+        (status = 422, body = ErrorBody),
+    )
+)]
+pub async fn decrement_variant_stock(
+    data: web::Data<AppState>,
+    path: web::Path<(i32, i32)>,
+    movement: web::Json<StockMovementRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let (product_id, variant_id) = path.into_inner();
+    move_variant_stock(data, product_id, variant_id, movement.into_inner(), -1).await
+}
+
+// move_stock for one variant. Low-stock thresholds are per product, so
+// variant movements raise no alerts.
+async fn move_variant_stock(
+    data: web::Data<AppState>,
+    product_id: i32,
+    variant_id: i32,
+    movement: StockMovementRequest,
+    direction: i32,
+) -> Result<HttpResponse, ApiError> {
+    movement.validate()?;
+    let delta = movement.quantity * direction;
+    let variant = data
+        .transaction(move |state| {
+            let product = state
+                .products
+                .get(product_id)?
+                .ok_or_else(|| ApiError::not_found("Product", product_id))?;
+            let variant = state
+                .variants
+                .adjust_stock(product_id, variant_id, delta)?
+                .ok_or_else(|| ApiError::not_found("Variant", variant_id))?;
+            let reason = movement_reason(&movement, delta, variant.stock);
+            log_action_with_reason(state, product.user_id, movement_action(delta), "product_variant", Some(variant.id), &reason)?;
+            Ok(variant)
+        })
+        .await?;
+    Ok(HttpResponse::Ok().json(variant))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/stock-alerts",
+    tag = "stock",
+    responses((status = 200, description = "Products at or below their low-stock threshold, emptiest first", body = [Product]))
+)]
+pub async fn get_stock_alerts(data: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let products = data.db(|state| state.products.low_stock()).await?;
+    Ok(HttpResponse::Ok().json(products))
+}
@@ -0,0 +1,191 @@
+use actix_web::http::header;
+use actix_web::{web, HttpResponse};
+use validator::Validate;
+
+use crate::audit::{log_action, log_action_with_reason, ANONYMOUS_USER};
+use crate::db::models::{NewTag, Tag};
+use crate::db::repository::TagWithCount;
+use crate::errors::{ApiError, ErrorBody};
+use crate::models::SetProductTagsRequest;
+use crate::{AppState, API_V1};
+
+// Doubles as the tag cloud: each tag with how many products carry it
+#[utoipa::path(
+    get,
+    path = "/api/v1/tags",
+    tag = "tags",
+    responses((status = 200, description = "Every tag with its product count, most used first, then by name", body = [TagWithCount]))
+)]
+pub async fn get_tags(data: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let tags = data.db(|state| state.tags.list()).await?;
+    Ok(HttpResponse::Ok().json(tags))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/tags/{id}",
+    tag = "tags",
+    params(("id" = i32, Path, description = "Tag id")),
+    responses(
+        (status = 200, body = Tag),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn get_tag(data: web::Data<AppState>, id: web::Path<i32>) -> Result<HttpResponse, ApiError> {
+    let tag_id = id.into_inner();
+    let tag = data
+        .db(move |state| state.tags.get(tag_id))
+        .await?
+        .ok_or_else(|| ApiError::not_found("Tag", tag_id))?;
+    Ok(HttpResponse::Ok().json(tag))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/tags",
+    tag = "tags",
+    request_body = NewTag,
+    responses(
+        (status = 201, body = Tag, headers(("Location" = String, description = "URL of the new tag"))),
+        (status = 409, description = "A tag of that name exists, in any case", body = ErrorBody),
+        (status = 422, body = ErrorBody),
+    )
+)]
+pub async fn create_tag(data: web::Data<AppState>, tag: web::Json<NewTag>) -> Result<HttpResponse, ApiError> {
+    tag.validate()?;
+    let name = tag.into_inner().name;
+    let tag = data
+        .transaction(move |state| {
+            let tag = state.tags.create(&name)?;
+            log_action(state, ANONYMOUS_USER, "CREATE", "tag", Some(tag.id))?;
+            Ok(tag)
+        })
+        .await?;
+    Ok(HttpResponse::Created()
+        .insert_header((header::LOCATION, format!("{}/tags/{}", API_V1, tag.id)))
+        .json(tag))
+}
+
+// Renames the tag on every product carrying it
+#[utoipa::path(
+    put,
+    path = "/api/v1/tags/{id}",
+    tag = "tags",
+    params(("id" = i32, Path, description = "Tag id")),
+    request_body = NewTag,
+    responses(
+        (status = 200, body = Tag),
+        (status = 404, body = ErrorBody),
+        (status = 409, description = "Another tag has that name, in any case", body = ErrorBody),
+        (status = 422, body = ErrorBody),
+    )
+)]
+pub async fn rename_tag(data: web::Data<AppState>, id: web::Path<i32>, tag: web::Json<NewTag>) -> Result<HttpResponse, ApiError> {
+    tag.validate()?;
+    let tag_id = id.into_inner();
+    let name = tag.into_inner().name;
+    let tag = data
+        .transaction(move |state| {
+            let tag = state.tags.rename(tag_id, &name)?.ok_or_else(|| ApiError::not_found("Tag", tag_id))?;
+            log_action(state, ANONYMOUS_USER, "UPDATE", "tag", Some(tag.id))?;
+            Ok(tag)
+        })
+        .await?;
+    Ok(HttpResponse::Ok().json(tag))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/tags/{id}",
+    tag = "tags",
+    params(("id" = i32, Path, description = "Tag id")),
+    responses(
+        (status = 204, description = "Tag deleted and taken off every product"),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn delete_tag(data: web::Data<AppState>, id: web::Path<i32>) -> Result<HttpResponse, ApiError> {
+    let tag_id = id.into_inner();
+    data.transaction(move |state| {
+        if !state.tags.delete(tag_id)? {
+            return Err(ApiError::not_found("Tag", tag_id));
+        }
+        log_action(state, ANONYMOUS_USER, "DELETE", "tag", Some(tag_id))
+    })
+    .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/products/{id}/tags",
+    tag = "tags",
+    params(("id" = i32, Path, description = "Product id")),
+    responses(
+        (status = 200, description = "The product's tags, by name", body = [Tag]),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn get_product_tags(data: web::Data<AppState>, id: web::Path<i32>) -> Result<HttpResponse, ApiError> {
+    let product_id = id.into_inner();
+    let tags = data
+        .db(move |state| {
+            if state.products.get(product_id)?.is_none() {
+                return Err(ApiError::not_found("Product", product_id));
+            }
+            state.tags.for_product(product_id)
+        })
+        .await?;
+    Ok(HttpResponse::Ok().json(tags))
+}
+
+// Replaces the product's tags. Unknown names are created as tags on the way,
+// each logged as created by the product's owner.
+#[utoipa::path(
+    put,
+    path = "/api/v1/products/{id}/tags",
+    tag = "tags",
+    params(("id" = i32, Path, description = "Product id")),
+    request_body = SetProductTagsRequest,
+    responses(
+        (status = 200, description = "The product's tags now, by name", body = [Tag]),
+        (status = 404, body = ErrorBody),
+        (status = 422, body = ErrorBody),
+    )
+)]
+pub async fn set_product_tags(
+    data: web::Data<AppState>,
+    id: web::Path<i32>,
+    request: web::Json<SetProductTagsRequest>,
+) -> Result<HttpResponse, ApiError> {
+    request.validate()?;
+    let product_id = id.into_inner();
+    let names = request.into_inner().tags;
+    let tags = data
+        .transaction(move |state| {
+            let product = state.products.get(product_id)?.ok_or_else(|| ApiError::not_found("Product", product_id))?;
+            let mut tag_ids = Vec::with_capacity(names.len());
+            for name in &names {
+                let tag = match state.tags.find_by_name(name)? {
+                    Some(tag) => tag,
+                    None => {
+                        let tag = state.tags.create(name)?;
+                        log_action(state, product.user_id, "CREATE", "tag", Some(tag.id))?;
+                        tag
+                    }
+                };
+                tag_ids.push(tag.id);
+            }
+            state.tags.set_for_product(product_id, &tag_ids)?;
+            let tags = state.tags.for_product(product_id)?;
+            let reason = if tags.is_empty() {
+                "tags cleared".to_string()
+            } else {
+                format!("tags set to {}", tags.iter().map(|t| t.name.as_str()).collect::<Vec<_>>().join(", "))
+            };
+            log_action_with_reason(state, product.user_id, "UPDATE", "product", Some(product_id), &reason)?;
+            Ok(tags)
+        })
+        .await?;
+    Ok(HttpResponse::Ok().json(tags))
+}
@@ -0,0 +1,89 @@
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::db::models::NewUser;
+use crate::errors::{ApiError, ErrorBody};
+use crate::validation;
+use crate::AppState;
+
+#[derive(Deserialize, Validate, ToSchema)]
+pub struct RegisterRequest {
+    #[validate(length(min = 3, max = 32), custom(function = "validation::username_policy"))]
+    pub username: String,
+    #[validate(length(min = 8, max = 128), custom(function = "validation::password_policy"))]
+    pub password: String,
+    #[validate(custom(function = "validation::registered_role"))]
+    pub role: Option<String>, // Optional, only User is accepted
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct LoginRequest {
+    pub username: String,
+    pub password: String,
+}
+
+// Registration endpoint
+#[utoipa::path(
+    post,
+    path = "/api/register",
+    tag = "users",
+    request_body = RegisterRequest,
+    responses(
+        (status = 200, description = "Registered user id, username and role"),
+        (status = 409, description = "Username already taken", body = ErrorBody),
+        (status = 422, body = ErrorBody),
+    )
+)]
+pub async fn register(
+    data: web::Data<AppState>,
+    req: web::Json<RegisterRequest>,
+) -> Result<HttpResponse, ApiError> {
+    req.validate()?;
+    let req = req.into_inner();
+    let user = data
+        .db(move |state| {
+            // Check if username exists
+            if state.users.find_by_username(&req.username)?.is_some() {
+                return Err(ApiError::Conflict {
+                    message: "Username already exists".to_string(),
+                    details: json!({"field": "username"}),
+                });
+            }
+            state.users.create(NewUser {
+                username: req.username,
+                password: req.password, // plain text for demo
+                role: "User".to_string(),
+            })
+        })
+        .await?;
+    Ok(HttpResponse::Ok().json(json!({"id": user.id, "username": user.username, "role": user.role})))
+}
+
+// Login endpoint
+#[utoipa::path(
+    post,
+    path = "/api/login",
+    tag = "users",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "Logged in user id, username and role"),
+        (status = 401, body = ErrorBody),
+    )
+)]
+pub async fn login(
+    data: web::Data<AppState>,
+    req: web::Json<LoginRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let name = req.username.clone();
+    let user = data.db(move |state| state.users.find_by_username(&name)).await?;
+    match user {
+        Some(user) if user.password == req.password => {
+            Ok(HttpResponse::Ok().json(json!({"id": user.id, "username": user.username, "role": user.role})))
+        }
+        Some(_) => Err(ApiError::Unauthorized("Invalid password".to_string())),
+        None => Err(ApiError::Unauthorized("User not found".to_string())),
+    }
+}
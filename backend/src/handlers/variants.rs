@@ -0,0 +1,285 @@
+use actix_web::http::header;
+use actix_web::{web, HttpResponse};
+use serde_json::json;
+use validator::Validate;
+
+use crate::audit::log_action;
+use crate::db::models::{NewVariant, Product, ProductVariant, UpdateVariant, VariantOption};
+use crate::errors::{ApiError, ErrorBody};
+use crate::models::ProductOption;
+use crate::{AppState, API_V1};
+
+fn find_product(state: &AppState, product_id: i32) -> Result<Product, ApiError> {
+    state.products.get(product_id)?.ok_or_else(|| ApiError::not_found("Product", product_id))
+}
+
+// Every variant of a product sets the same options. The first variant defines
+// them; later ones are trimmed and then spelled and ordered like the existing
+// ones, so "SIZE: 42" joins an existing "Size: 42".
+fn conform_options(options: Vec<VariantOption>, others: &[ProductVariant]) -> Result<Vec<VariantOption>, ApiError> {
+    let options: Vec<VariantOption> = options
+        .into_iter()
+        .map(|o| VariantOption { name: o.name.trim().to_string(), value: o.value.trim().to_string() })
+        .collect();
+    let Some(existing) = others.first() else {
+        return Ok(options);
+    };
+
+    let same_names = options.len() == existing.options.len()
+        && existing.options.iter().all(|e| options.iter().any(|o| o.name.to_lowercase() == e.name.to_lowercase()));
+    if !same_names {
+        let names: Vec<&str> = existing.options.iter().map(|o| o.name.as_str()).collect();
+        return Err(ApiError::validation(
+            "Request validation failed",
+            json!({ "options": [format!("must give one value for each of the product's options: {}", names.join(", "))] }),
+        ));
+    }
+
+    let conformed: Vec<VariantOption> = existing
+        .options
+        .iter()
+        .map(|e| {
+            let given = options.iter().find(|o| o.name.to_lowercase() == e.name.to_lowercase()).expect("names checked above");
+            let known_value = others
+                .iter()
+                .flat_map(|v| &v.options)
+                .find(|o| o.name == e.name && o.value.to_lowercase() == given.value.to_lowercase());
+            VariantOption {
+                name: e.name.clone(),
+                value: known_value.map_or_else(|| given.value.clone(), |o| o.value.clone()),
+            }
+        })
+        .collect();
+
+    if let Some(duplicate) = others.iter().find(|v| v.has_options(&conformed)) {
+        return Err(ApiError::Conflict {
+            message: "Another variant of this product has the same options".to_string(),
+            details: json!({ "variant_id": duplicate.id }),
+        });
+    }
+    Ok(conformed)
+}
+
+// Shared by PUT and PATCH once the complete variant is known
+fn save_variant(state: &AppState, product: &Product, variant_id: i32, mut variant: NewVariant) -> Result<ProductVariant, ApiError> {
+    let others: Vec<ProductVariant> = state.variants.list(product.id)?.into_iter().filter(|v| v.id != variant_id).collect();
+    variant.options = conform_options(variant.options, &others)?;
+    let variant = state
+        .variants
+        .replace(product.id, variant_id, variant)?
+        .ok_or_else(|| ApiError::not_found("Variant", variant_id))?;
+    log_action(state, product.user_id, "UPDATE", "product_variant", Some(variant.id))?;
+    Ok(variant)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/products/{id}/variants",
+    tag = "variants",
+    params(("id" = i32, Path, description = "Product id")),
+    responses(
+        (status = 200, description = "The product's variants, oldest first", body = [ProductVariant]),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn get_variants(data: web::Data<AppState>, id: web::Path<i32>) -> Result<HttpResponse, ApiError> {
+    let product_id = id.into_inner();
+    let variants = data
+        .db(move |state| {
+            find_product(state, product_id)?;
+            state.variants.list(product_id)
+        })
+        .await?;
+    Ok(HttpResponse::Ok().json(variants))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/products/{id}/variants/options",
+    tag = "variants",
+    params(("id" = i32, Path, description = "Product id")),
+    responses(
+        (status = 200, description = "The product's options with the values its variants use, in option order", body = [ProductOption]),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn get_variant_options(data: web::Data<AppState>, id: web::Path<i32>) -> Result<HttpResponse, ApiError> {
+    let product_id = id.into_inner();
+    let variants = data
+        .db(move |state| {
+            find_product(state, product_id)?;
+            state.variants.list(product_id)
+        })
+        .await?;
+    let mut options: Vec<ProductOption> = Vec::new();
+    for option in variants.iter().flat_map(|v| &v.options) {
+        let index = match options.iter().position(|o| o.name == option.name) {
+            Some(index) => index,
+            None => {
+                options.push(ProductOption { name: option.name.clone(), values: Vec::new() });
+                options.len() - 1
+            }
+        };
+        if !options[index].values.contains(&option.value) {
+            options[index].values.push(option.value.clone());
+        }
+    }
+    Ok(HttpResponse::Ok().json(options))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/products/{id}/variants/{variant_id}",
+    tag = "variants",
+    params(
+        ("id" = i32, Path, description = "Product id"),
+        ("variant_id" = i32, Path, description = "Variant id"),
+    ),
+    responses(
+        (status = 200, body = ProductVariant),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn get_variant(data: web::Data<AppState>, path: web::Path<(i32, i32)>) -> Result<HttpResponse, ApiError> {
+    let (product_id, variant_id) = path.into_inner();
+    let variant = data
+        .db(move |state| state.variants.get(product_id, variant_id))
+        .await?
+        .ok_or_else(|| ApiError::not_found("Variant", variant_id))?;
+    Ok(HttpResponse::Ok().json(variant))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/products/{id}/variants",
+    tag = "variants",
+    params(("id" = i32, Path, description = "Product id")),
+    request_body = NewVariant,
+    responses(
+        (status = 201, body = ProductVariant, headers(("Location" = String, description = "URL of the new variant"))),
+        (status = 404, body = ErrorBody),
+        (status = 409, description = "SKU taken, or another variant has the same options", body = ErrorBody),
+        (status = 422, body = ErrorBody),
+    )
+)]
+pub async fn create_variant(
+    data: web::Data<AppState>,
+    id: web::Path<i32>,
+    variant: web::Json<NewVariant>,
+) -> Result<HttpResponse, ApiError> {
+    variant.validate()?;
+    let product_id = id.into_inner();
+    let mut new_variant = variant.into_inner();
+    let variant = data
+        .transaction(move |state| {
+            let product = find_product(state, product_id)?;
+            new_variant.options = conform_options(new_variant.options, &state.variants.list(product_id)?)?;
+            let variant = state.variants.create(product_id, new_variant)?;
+            log_action(state, product.user_id, "CREATE", "product_variant", Some(variant.id))?;
+            Ok(variant)
+        })
+        .await?;
+    Ok(HttpResponse::Created()
+        .insert_header((header::LOCATION, format!("{}/products/{}/variants/{}", API_V1, product_id, variant.id)))
+        .json(variant))
+}
+
+// PUT: the body is the complete variant; a missing price or image clears the override
+#[utoipa::path(
+    put,
+    path = "/api/v1/products/{id}/variants/{variant_id}",
+    tag = "variants",
+    params(
+        ("id" = i32, Path, description = "Product id"),
+        ("variant_id" = i32, Path, description = "Variant id"),
+    ),
+    request_body = NewVariant,
+    responses(
+        (status = 200, body = ProductVariant),
+        (status = 404, body = ErrorBody),
+        (status = 409, description = "SKU taken, or another variant has the same options", body = ErrorBody),
+        (status = 422, body = ErrorBody),
+    )
+)]
+pub async fn replace_variant(
+    data: web::Data<AppState>,
+    path: web::Path<(i32, i32)>,
+    variant: web::Json<NewVariant>,
+) -> Result<HttpResponse, ApiError> {
+    variant.validate()?;
+    let (product_id, variant_id) = path.into_inner();
+    let new_variant = variant.into_inner();
+    let variant = data
+        .transaction(move |state| {
+            let product = find_product(state, product_id)?;
+            save_variant(state, &product, variant_id, new_variant)
+        })
+        .await?;
+    Ok(HttpResponse::Ok().json(variant))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/products/{id}/variants/{variant_id}",
+    tag = "variants",
+    params(
+        ("id" = i32, Path, description = "Product id"),
+        ("variant_id" = i32, Path, description = "Variant id"),
+    ),
+    request_body = UpdateVariant,
+    responses(
+        (status = 200, body = ProductVariant),
+        (status = 404, body = ErrorBody),
+        (status = 409, description = "SKU taken, or another variant has the same options", body = ErrorBody),
+        (status = 422, body = ErrorBody),
+    )
+)]
+pub async fn update_variant(
+    data: web::Data<AppState>,
+    path: web::Path<(i32, i32)>,
+    variant: web::Json<UpdateVariant>,
+) -> Result<HttpResponse, ApiError> {
+    variant.validate()?;
+    let (product_id, variant_id) = path.into_inner();
+    let changes = variant.into_inner();
+    let variant = data
+        .transaction(move |state| {
+            let product = find_product(state, product_id)?;
+            let current = state
+                .variants
+                .get(product_id, variant_id)?
+                .ok_or_else(|| ApiError::not_found("Variant", variant_id))?;
+            if changes.is_empty() {
+                return Ok(current);
+            }
+            save_variant(state, &product, variant_id, changes.apply(current))
+        })
+        .await?;
+    Ok(HttpResponse::Ok().json(variant))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/products/{id}/variants/{variant_id}",
+    tag = "variants",
+    params(
+        ("id" = i32, Path, description = "Product id"),
+        ("variant_id" = i32, Path, description = "Variant id"),
+    ),
+    responses(
+        (status = 204, description = "Variant deleted"),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn delete_variant(data: web::Data<AppState>, path: web::Path<(i32, i32)>) -> Result<HttpResponse, ApiError> {
+    let (product_id, variant_id) = path.into_inner();
+    data.transaction(move |state| {
+        let product = find_product(state, product_id)?;
+        if !state.variants.delete(product_id, variant_id)? {
+            return Err(ApiError::not_found("Variant", variant_id));
+        }
+        log_action(state, product.user_id, "DELETE", "product_variant", Some(variant_id))
+    })
+    .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,272 @@
+use actix_files::NamedFile;
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::{web, HttpRequest, HttpResponse, HttpResponseBuilder};
+use chrono::Duration;
+use futures::StreamExt;
+use validator::Validate;
+
+use crate::auth::{self, AuthUser};
+use crate::db::models::{NewVideoUpload, Product, VideoUpload};
+use crate::errors::{ApiError, ErrorBody};
+use crate::media;
+use crate::models::CreateVideoUploadRequest;
+use crate::video_uploads::{self, MAX_CHUNK_BYTES};
+use crate::{AppState, API_V1};
+
+// As in tus: where the upload stands, and the offset a chunk is meant for
+const UPLOAD_OFFSET: &str = "Upload-Offset";
+// Optional per chunk: "sha256 <lowercase hex digest of the chunk>"
+const UPLOAD_CHECKSUM: &str = "Upload-Checksum";
+// Long enough to watch a film on one redirect; players seek with further
+// range requests against the same URL
+const PRESIGNED_VIDEO_HOURS: i64 = 4;
+
+fn upload_response(mut response: HttpResponseBuilder, upload: &VideoUpload) -> HttpResponse {
+    response.insert_header((UPLOAD_OFFSET, upload.received_bytes.to_string())).json(upload)
+}
+
+fn header_value<'a>(req: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    req.headers().get(name).and_then(|value| value.to_str().ok())
+}
+
+// Anyone may watch a published product's video; an unpublished one's only
+// its owner and admins
+fn check_can_watch(state: &AppState, product: &Product, user: Option<AuthUser>) -> Result<(), ApiError> {
+    if product.published {
+        return Ok(());
+    }
+    let user = user.ok_or_else(|| ApiError::Unauthorized("Product is not published; sign in as its owner or an admin".to_string()))?;
+    if user.user_id != product.user_id && !auth::is_admin(state, user)? {
+        return Err(ApiError::Forbidden("Only the owner and admins may watch unpublished products' videos".to_string()));
+    }
+    Ok(())
+}
+
+// Streams an uploaded video with Range and conditional request support, so
+// players can seek and revalidate. Object storage streams it itself: the
+// response is a redirect to a pre-signed URL, as for a video hosted elsewhere.
+// Legacy /videos/<file> videos are streamed from their old directory.
+#[utoipa::path(
+    get,
+    path = "/api/v1/products/{id}/video",
+    tag = "videos",
+    params(
+        ("id" = i32, Path, description = "Product id"),
+        ("Range" = Option<String>, Header, description = "e.g. bytes=0-1048575"),
+        ("If-None-Match" = Option<String>, Header, description = "ETag of a copy the client holds"),
+        ("Authorization" = Option<String>, Header, description = "Bearer <user_id>; needed for unpublished products"),
+    ),
+    responses(
+        (status = 200, description = "The whole video", content_type = "video/mp4",
+            headers(("ETag" = String), ("Accept-Ranges" = String, description = "bytes"))),
+        (status = 206, description = "The requested range", headers(("Content-Range" = String))),
+        (status = 302, description = "The video is hosted elsewhere or in object storage", headers(("Location" = String))),
+        (status = 304, description = "The client's copy is current"),
+        (status = 401, description = "The product is not published and no user is given", body = ErrorBody),
+        (status = 403, description = "The product is not published and the user is neither its owner nor an admin", body = ErrorBody),
+        (status = 404, description = "No such product, or it has no video", body = ErrorBody),
+        (status = 416, description = "The range is outside the video"),
+    )
+)]
+pub async fn get_video(
+    data: web::Data<AppState>,
+    id: web::Path<i32>,
+    req: HttpRequest,
+    user: Option<AuthUser>,
+) -> Result<HttpResponse, ApiError> {
+    let product_id = id.into_inner();
+    let product = data
+        .db(move |state| {
+            let product = state.products.get(product_id)?.ok_or_else(|| ApiError::not_found("Product", product_id))?;
+            check_can_watch(state, &product, user)?;
+            Ok(product)
+        })
+        .await?;
+    let no_video = || ApiError::NotFound(format!("Product {} has no video", product_id));
+    let video = product.video.ok_or_else(no_video)?;
+    let (path, format) = if let Some(path) = data.media.legacy_video_path(&video) {
+        // Typed by extension as the old static route did; these were never sniffed
+        (path, None)
+    } else {
+        let Some(key) = data.storage.key(&video) else {
+            return Ok(HttpResponse::Found().insert_header((header::LOCATION, video)).finish());
+        };
+        let format = media::video_format(&key).ok_or_else(no_video)?;
+        let Some(path) = data.storage.local_path(&key) else {
+            let url = data.storage.presigned_url("GET", &key, Duration::hours(PRESIGNED_VIDEO_HOURS)).ok_or_else(no_video)?;
+            // The signature expires, so nobody may keep the redirect
+            return Ok(HttpResponse::Found()
+                .insert_header((header::LOCATION, url))
+                .insert_header((header::CACHE_CONTROL, "private, no-store"))
+                .finish());
+        };
+        (path, Some(format))
+    };
+    let mut file = NamedFile::open_async(path).await.map_err(|_| no_video())?;
+    if let Some(format) = format {
+        file = file.set_content_type(format.content_type.parse().expect("known video content types parse"));
+    }
+    let mut response = file.into_response(&req);
+    // The URL stays while the video behind it may change, so caches revalidate;
+    // unpublished videos stay out of shared caches altogether
+    let cache_control = if product.published { "public, no-cache" } else { "private, no-cache" };
+    response.headers_mut().insert(header::CACHE_CONTROL, HeaderValue::from_static(cache_control));
+    Ok(response)
+}
+
+// Uploads of a large video start here: the response's Location is where its
+// chunks go, in order, with PATCH
+#[utoipa::path(
+    post,
+    path = "/api/v1/products/{id}/video/uploads",
+    tag = "videos",
+    params(("id" = i32, Path, description = "Product id")),
+    request_body = CreateVideoUploadRequest,
+    responses(
+        (status = 201, body = VideoUpload, headers(
+            ("Location" = String, description = "URL of the new upload"),
+            ("Upload-Offset" = i64, description = "Always 0"),
+        )),
+        (status = 404, body = ErrorBody),
+        (status = 413, description = "The video is over MEDIA_MAX_VIDEO_BYTES", body = ErrorBody),
+        (status = 422, body = ErrorBody),
+    )
+)]
+pub async fn create_video_upload(
+    data: web::Data<AppState>,
+    id: web::Path<i32>,
+    request: web::Json<CreateVideoUploadRequest>,
+) -> Result<HttpResponse, ApiError> {
+    request.validate()?;
+    let product_id = id.into_inner();
+    let request = request.into_inner();
+    if request.size_bytes as u64 > data.media.max_video_bytes {
+        return Err(ApiError::PayloadTooLarge(format!("Videos may be at most {} bytes", data.media.max_video_bytes)));
+    }
+    let upload = data
+        .db(move |state| {
+            if state.products.get(product_id)?.is_none() {
+                return Err(ApiError::not_found("Product", product_id));
+            }
+            state.video_uploads.create(NewVideoUpload {
+                id: uuid::Uuid::new_v4().to_string(),
+                product_id,
+                size_bytes: request.size_bytes,
+                sha256: request.sha256,
+            })
+        })
+        .await?;
+    let location = format!("{}/products/{}/video/uploads/{}", API_V1, product_id, upload.id);
+    let mut response = HttpResponse::Created();
+    response.insert_header((header::LOCATION, location));
+    Ok(upload_response(response, &upload))
+}
+
+// How far the upload got, so an interrupted client knows where to resume
+#[utoipa::path(
+    get,
+    path = "/api/v1/products/{id}/video/uploads/{upload_id}",
+    tag = "videos",
+    params(
+        ("id" = i32, Path, description = "Product id"),
+        ("upload_id" = String, Path, description = "Upload id"),
+    ),
+    responses(
+        (status = 200, body = VideoUpload, headers(("Upload-Offset" = i64, description = "Bytes received so far"))),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn get_video_upload(data: web::Data<AppState>, path: web::Path<(i32, String)>) -> Result<HttpResponse, ApiError> {
+    let (product_id, upload_id) = path.into_inner();
+    let upload = data
+        .db(move |state| {
+            state
+                .video_uploads
+                .get(&upload_id)?
+                .filter(|u| u.product_id == product_id)
+                .ok_or_else(|| video_uploads::not_found(&upload_id))
+        })
+        .await?;
+    Ok(upload_response(HttpResponse::Ok(), &upload))
+}
+
+// The chunk is the raw body. With the last byte in, the whole file is checked
+// against the upload's SHA-256 and becomes the product's video.
+#[utoipa::path(
+    patch,
+    path = "/api/v1/products/{id}/video/uploads/{upload_id}",
+    tag = "videos",
+    params(
+        ("id" = i32, Path, description = "Product id"),
+        ("upload_id" = String, Path, description = "Upload id"),
+        ("Upload-Offset" = i64, Header, description = "Where this chunk starts; must equal the bytes received so far"),
+        ("Upload-Checksum" = Option<String>, Header, description = "sha256 <lowercase hex digest of this chunk>"),
+    ),
+    request_body(content = String, description = "Up to 16 MiB of the file", content_type = "application/offset+octet-stream"),
+    responses(
+        (status = 200, description = "Chunk stored; `video` is set once the upload is complete", body = VideoUpload,
+            headers(("Upload-Offset" = i64, description = "Bytes received so far"))),
+        (status = 400, description = "Missing or malformed Upload-Offset or Upload-Checksum", body = ErrorBody),
+        (status = 404, body = ErrorBody),
+        (status = 409, description = "Upload-Offset is stale, or the upload is complete; details.received_bytes says where it stands", body = ErrorBody),
+        (status = 413, description = "The chunk is over 16 MiB or runs past the declared size", body = ErrorBody),
+        (status = 422, description = "The chunk or, at the end, the whole file does not match its checksum, or it is not a video", body = ErrorBody),
+    )
+)]
+pub async fn append_video_chunk(
+    data: web::Data<AppState>,
+    path: web::Path<(i32, String)>,
+    req: HttpRequest,
+    mut payload: web::Payload,
+) -> Result<HttpResponse, ApiError> {
+    let (product_id, upload_id) = path.into_inner();
+    let offset = header_value(&req, UPLOAD_OFFSET)
+        .and_then(|value| value.parse::<i64>().ok())
+        .filter(|offset| *offset >= 0)
+        .ok_or_else(|| ApiError::BadRequest(format!("{} must be a non-negative integer", UPLOAD_OFFSET)))?;
+    let checksum = match header_value(&req, UPLOAD_CHECKSUM) {
+        None => None,
+        Some(value) => match value.split_once(' ') {
+            Some(("sha256", digest)) if crate::validation::sha256_hex(digest).is_ok() => Some(digest.to_string()),
+            _ => return Err(ApiError::BadRequest(format!("{} must be \"sha256 <lowercase hex digest>\"", UPLOAD_CHECKSUM))),
+        },
+    };
+    let mut chunk = Vec::new();
+    while let Some(bytes) = payload.next().await {
+        let bytes = bytes.map_err(|err| ApiError::BadRequest(err.to_string()))?;
+        if chunk.len() + bytes.len() > MAX_CHUNK_BYTES {
+            return Err(ApiError::PayloadTooLarge(format!("Each chunk may be at most {} bytes", MAX_CHUNK_BYTES)));
+        }
+        chunk.extend_from_slice(&bytes);
+    }
+    let id = upload_id.clone();
+    let upload = data
+        .transaction(move |state| video_uploads::append(state, product_id, &id, offset, &chunk, checksum.as_deref()))
+        .await?;
+    let upload = if upload.received_bytes == upload.size_bytes {
+        data.db(move |state| video_uploads::complete(state, &upload_id)).await?
+    } else {
+        upload
+    };
+    Ok(upload_response(HttpResponse::Ok(), &upload))
+}
+
+// Abandons the upload; a finished one is just forgotten, its video stays
+#[utoipa::path(
+    delete,
+    path = "/api/v1/products/{id}/video/uploads/{upload_id}",
+    tag = "videos",
+    params(
+        ("id" = i32, Path, description = "Product id"),
+        ("upload_id" = String, Path, description = "Upload id"),
+    ),
+    responses(
+        (status = 204, description = "Upload and its partial file deleted"),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn cancel_video_upload(data: web::Data<AppState>, path: web::Path<(i32, String)>) -> Result<HttpResponse, ApiError> {
+    let (product_id, upload_id) = path.into_inner();
+    data.db(move |state| video_uploads::cancel(state, product_id, &upload_id)).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
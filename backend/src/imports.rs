@@ -0,0 +1,621 @@
+use std::collections::BTreeMap;
+
+use chrono::{Duration, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::attributes;
+use crate::audit::log_action_with_reason;
+use crate::currency;
+use crate::db::models::{ImportJob, ImportProgress, NewImportJob, NewProduct, Product, UpdateProduct};
+use crate::errors::ApiError;
+use crate::models::CreateCategoryRequest;
+use crate::money::Money;
+use crate::validation;
+use crate::AppState;
+
+// Files with more rows than this go to the import job; smaller ones are
+// imported while the client waits
+pub const INLINE_ROWS: usize = 500;
+
+// Larger files are refused
+pub const MAX_IMPORT_BYTES: usize = 50 * 1024 * 1024;
+
+// The report lists this many failed rows; failed_rows counts them all
+pub const MAX_REPORTED_ERRORS: usize = 1000;
+
+// Rows committed together, along with the progress that counts them
+const BATCH_ROWS: usize = 100;
+
+// A running job not heard from for this long died with its server and is
+// taken on again
+const STALE_MINUTES: i64 = 10;
+
+// What a file may have besides attributes: an object in NDJSON, one
+// attributes.<name> column per attribute in CSV
+const COLUMNS: [&str; 11] = [
+    "sku",
+    "name",
+    "price",
+    "currency",
+    "description",
+    "image",
+    "video",
+    "published",
+    "category",
+    "category_id",
+    "low_stock_threshold",
+];
+
+const ATTRIBUTE_PREFIX: &str = "attributes.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    // A header row naming the columns, then one product per row
+    Csv,
+    // One JSON object per line
+    Ndjson,
+}
+
+impl ImportFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "csv" => Some(ImportFormat::Csv),
+            "ndjson" => Some(ImportFormat::Ndjson),
+            _ => None,
+        }
+    }
+
+    // text/csv, or application/x-ndjson and its unprefixed spelling
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let essence = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        match essence.as_str() {
+            "text/csv" => Some(ImportFormat::Csv),
+            "application/x-ndjson" | "application/ndjson" => Some(ImportFormat::Ndjson),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ImportFormat::Csv => "csv",
+            ImportFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ImportOptions {
+    pub format: ImportFormat,
+    pub dry_run: bool,
+    pub upsert: bool,
+    pub create_categories: bool,
+}
+
+// One failed row of a job's report
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct RowError {
+    // Line of the file the row starts on; a CSV header is line 1
+    pub row: u64,
+    pub sku: Option<String>,
+    // As in an error response, e.g. validation_failed or conflict
+    pub code: String,
+    pub message: String,
+    // e.g. {"price": ["must be greater than 0 and at most 1000000.00"]}
+    #[schema(value_type = Object, nullable)]
+    pub details: Value,
+}
+
+// A row as read from the file: its fields, or why they could not be read
+struct SourceRow {
+    line: u64,
+    fields: Result<Map<String, Value>, String>,
+}
+
+impl SourceRow {
+    fn sku(&self) -> Option<String> {
+        let fields = self.fields.as_ref().ok()?;
+        fields.get("sku").and_then(Value::as_str).map(str::to_string)
+    }
+}
+
+fn file_error(message: impl Into<String>) -> ApiError {
+    ApiError::validation("Import file rejected", json!({ "file": [message.into()] }))
+}
+
+// Every row of the file, blank lines left out. Only a file that cannot be read
+// at all fails, e.g. for a CSV header naming unknown columns; a malformed row
+// is reported with that row.
+fn parse(format: ImportFormat, source: &str) -> Result<Vec<SourceRow>, ApiError> {
+    // Spreadsheets like to start their exports with a byte order mark
+    let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+    let rows = match format {
+        ImportFormat::Csv => parse_csv(source)?,
+        ImportFormat::Ndjson => parse_ndjson(source),
+    };
+    if rows.is_empty() {
+        return Err(file_error("has no rows"));
+    }
+    Ok(rows)
+}
+
+fn parse_ndjson(source: &str) -> Vec<SourceRow> {
+    source
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let fields = match serde_json::from_str(line) {
+                Ok(Value::Object(fields)) => Ok(fields),
+                Ok(_) => Err("must be a JSON object".to_string()),
+                Err(err) => Err(format!("is not valid JSON: {}", err)),
+            };
+            SourceRow { line: index as u64 + 1, fields }
+        })
+        .collect()
+}
+
+fn is_csv_column(column: &str) -> bool {
+    COLUMNS.contains(&column) || column.strip_prefix(ATTRIBUTE_PREFIX).is_some_and(|name| !name.is_empty())
+}
+
+fn parse_csv(source: &str) -> Result<Vec<SourceRow>, ApiError> {
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(source.as_bytes());
+    let headers = reader.headers().map_err(|err| file_error(format!("has an unreadable header: {}", err)))?.clone();
+    let unknown: Vec<&str> = headers.iter().filter(|column| !is_csv_column(column)).collect();
+    if !unknown.is_empty() {
+        return Err(file_error(format!("has columns products cannot be imported with: {}", unknown.join(", "))));
+    }
+    if let Some(twice) = headers.iter().enumerate().find_map(|(i, column)| headers.iter().skip(i + 1).find(|other| *other == column)) {
+        return Err(file_error(format!("has the column {} twice", twice)));
+    }
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let row = match record {
+            Ok(record) => SourceRow {
+                line: record.position().map_or(0, |position| position.line()),
+                fields: Ok(csv_fields(&headers, &record)),
+            },
+            Err(err) => SourceRow {
+                line: err.position().map_or(0, |position| position.line()),
+                fields: Err(match err.kind() {
+                    csv::ErrorKind::UnequalLengths { expected_len, len, .. } => {
+                        format!("has {} cells where the header has {}", len, expected_len)
+                    }
+                    _ => err.to_string(),
+                }),
+            },
+        };
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+// Cells stay text, see read_field; empty ones are left out, and
+// attributes.<name> ones are gathered under "attributes"
+fn csv_fields(headers: &csv::StringRecord, record: &csv::StringRecord) -> Map<String, Value> {
+    let mut fields = Map::new();
+    let mut values = Map::new();
+    for (column, cell) in headers.iter().zip(record.iter()).filter(|(_, cell)| !cell.is_empty()) {
+        match column.strip_prefix(ATTRIBUTE_PREFIX) {
+            Some(name) => values.insert(name.to_string(), Value::String(cell.to_string())),
+            None => fields.insert(column.to_string(), Value::String(cell.to_string())),
+        };
+    }
+    if !values.is_empty() {
+        fields.insert("attributes".to_string(), Value::Object(values));
+    }
+    fields
+}
+
+// Takes `name` out of the row as a T. Text is read as JSON too, so CSV cells
+// such as 42 or true fill number and boolean fields.
+fn read_field<T: DeserializeOwned>(fields: &mut Map<String, Value>, name: &str, errors: &mut BTreeMap<String, Vec<String>>) -> Option<T> {
+    let value = fields.remove(name).filter(|value| !value.is_null())?;
+    let read = match &value {
+        Value::String(text) => serde_json::from_value(value.clone()).or_else(|err| serde_json::from_str(text).map_err(|_| err)),
+        _ => serde_json::from_value(value),
+    };
+    read.map_err(|err| errors.entry(name.to_string()).or_default().push(err.to_string())).ok()
+}
+
+// A row's fields in the types products take; only the ones it has are Some
+struct ImportRow {
+    sku: Option<String>,
+    name: Option<String>,
+    price: Option<Money>,
+    currency: Option<String>,
+    description: Option<String>,
+    image: Option<String>,
+    video: Option<String>,
+    published: Option<bool>,
+    // The category's name, ignoring case; category_id names it by id instead
+    category: Option<String>,
+    category_id: Option<i32>,
+    low_stock_threshold: Option<i32>,
+    attributes: Option<Value>,
+}
+
+impl ImportRow {
+    fn read(mut fields: Map<String, Value>) -> Result<ImportRow, ApiError> {
+        let mut errors = BTreeMap::new();
+        let row = ImportRow {
+            sku: read_field(&mut fields, "sku", &mut errors),
+            name: read_field(&mut fields, "name", &mut errors),
+            price: read_field(&mut fields, "price", &mut errors),
+            currency: read_field(&mut fields, "currency", &mut errors),
+            description: read_field(&mut fields, "description", &mut errors),
+            image: read_field(&mut fields, "image", &mut errors),
+            video: read_field(&mut fields, "video", &mut errors),
+            published: read_field(&mut fields, "published", &mut errors),
+            category: read_field(&mut fields, "category", &mut errors),
+            category_id: read_field(&mut fields, "category_id", &mut errors),
+            low_stock_threshold: read_field(&mut fields, "low_stock_threshold", &mut errors),
+            attributes: read_field(&mut fields, "attributes", &mut errors),
+        };
+        for name in fields.keys() {
+            errors.entry(name.clone()).or_default().push("is not a field products can be imported with".to_string());
+        }
+        if !errors.is_empty() {
+            return Err(ApiError::validation("Request validation failed", json!(errors)));
+        }
+        Ok(row)
+    }
+}
+
+enum Outcome {
+    Created,
+    Updated,
+}
+
+// What the audit log says about the writes of an import
+fn reason(job: &ImportJob) -> String {
+    format!("import {}", job.id)
+}
+
+// The category a row names by id or by name, None if it names none. A name no
+// category has creates a top-level one when the job allows it.
+fn category_id(state: &AppState, job: &ImportJob, row: &ImportRow) -> Result<Option<i32>, ApiError> {
+    let name = match (&row.category, row.category_id) {
+        (None, id) => return Ok(id),
+        (Some(name), None) => name,
+        (Some(_), Some(_)) => {
+            return Err(ApiError::validation("Request validation failed", json!({ "category": ["give category or category_id, not both"] })));
+        }
+    };
+    if let Some(category) = state.categories.find_by_name(name)? {
+        return Ok(Some(category.id));
+    }
+    let problem = if !job.create_categories {
+        format!("no category is named {}; import with create_categories to create it", name)
+    } else if name.trim().is_empty() || name.chars().count() > 100 {
+        "must be a category name of at most 100 characters".to_string()
+    } else {
+        let request = CreateCategoryRequest {
+            name: name.trim().to_string(),
+            description: String::new(),
+            parent_id: None,
+            slug: None,
+            image: None,
+            display_order: None,
+        };
+        let category = state.categories.create(request.into_new_category())?;
+        log_action_with_reason(state, job.user_id, "CREATE", "category", Some(category.id), &reason(job))?;
+        return Ok(Some(category.id));
+    };
+    Err(ApiError::validation("Request validation failed", json!({ "category": [problem] })))
+}
+
+// CSV cells are text; numbers and booleans are read as the category's
+// attributes take them. Text that does not parse stays text, for
+// ensure_valid_attributes to reject.
+fn typed_attributes(state: &AppState, job: &ImportJob, category_id: i32, values: Value) -> Result<Value, ApiError> {
+    let (true, Value::Object(values)) = (job.format == ImportFormat::Csv.name(), &values) else {
+        return Ok(values);
+    };
+    let lineage = state.categories.ancestors(category_id)?;
+    let schema = attributes::effective(state.attributes.list(&lineage)?, &lineage);
+    let typed = values.iter().map(|(name, value)| {
+        let kind = schema.iter().find(|a| &a.name == name).map(|a| a.kind.as_str());
+        let typed = match (kind, value) {
+            (Some("number" | "bool"), Value::String(text)) => serde_json::from_str(text)
+                .ok()
+                .filter(|typed: &Value| typed.is_number() || typed.is_boolean())
+                .unwrap_or_else(|| value.clone()),
+            _ => value.clone(),
+        };
+        (name.clone(), typed)
+    });
+    Ok(Value::Object(typed.collect()))
+}
+
+// A new product from the row, checked as create_product checks one
+fn create_product(state: &AppState, job: &ImportJob, row: ImportRow, category_id: Option<i32>) -> Result<Product, ApiError> {
+    let required = [
+        ("name", row.name.is_none()),
+        ("price", row.price.is_none()),
+        ("description", row.description.is_none()),
+        ("image", row.image.is_none()),
+        ("category", category_id.is_none()),
+    ];
+    let missing: BTreeMap<&str, [&str; 1]> = required.iter().filter(|(_, missing)| *missing).map(|(name, _)| (*name, ["is required"])).collect();
+    let (Some(name), Some(price), Some(description), Some(image), Some(category_id)) = (row.name, row.price, row.description, row.image, category_id)
+    else {
+        return Err(ApiError::validation("Request validation failed", json!(missing)));
+    };
+    let values = match row.attributes {
+        Some(values) => typed_attributes(state, job, category_id, values)?,
+        None => attributes::no_attributes(),
+    };
+    let new_product = NewProduct {
+        sku: row.sku,
+        name,
+        price,
+        currency: row.currency.unwrap_or_else(currency::reference_currency),
+        description,
+        image,
+        video: row.video,
+        // Published unless said otherwise, as through the API
+        published: row.published.unwrap_or(true),
+        category_id,
+        user_id: job.user_id,
+        low_stock_threshold: row.low_stock_threshold,
+        attributes: values,
+    };
+    new_product.validate()?;
+    validation::ensure_category_exists(state.categories.as_ref(), new_product.category_id)?;
+    validation::ensure_currency_known(state.exchange_rates.as_ref(), &new_product.currency)?;
+    validation::ensure_valid_attributes(state.categories.as_ref(), state.attributes.as_ref(), new_product.category_id, &new_product.attributes)?;
+    let product = state.products.create(new_product)?;
+    log_action_with_reason(state, product.user_id, "CREATE", "product", Some(product.id), &reason(job))?;
+    Ok(product)
+}
+
+// Changes `current` as update_product would: only the columns the row has,
+// attribute values merged into the stored ones. The owner stays.
+fn update_product(state: &AppState, job: &ImportJob, current: Product, row: ImportRow, category_id: Option<i32>) -> Result<Product, ApiError> {
+    let mut changes = UpdateProduct {
+        sku: None,
+        name: row.name,
+        price: row.price,
+        currency: row.currency,
+        description: row.description,
+        image: row.image,
+        video: row.video,
+        published: row.published,
+        category_id,
+        user_id: None,
+        low_stock_threshold: row.low_stock_threshold,
+        attributes: None,
+    };
+    changes.validate()?;
+    if let Some(category_id) = changes.category_id {
+        validation::ensure_category_exists(state.categories.as_ref(), category_id)?;
+    }
+    if let Some(currency) = &changes.currency {
+        validation::ensure_currency_known(state.exchange_rates.as_ref(), currency)?;
+    }
+    if row.attributes.is_some() || changes.category_id.is_some() {
+        let category_id = changes.category_id.unwrap_or(current.category_id);
+        let values = match row.attributes {
+            Some(patch) => attributes::merge(&current.attributes, typed_attributes(state, job, category_id, patch)?),
+            None => current.attributes.clone(),
+        };
+        validation::ensure_valid_attributes(state.categories.as_ref(), state.attributes.as_ref(), category_id, &values)?;
+        changes.attributes = Some(values);
+    }
+    if changes.is_empty() {
+        return Ok(current);
+    }
+    let product = state.products.update(current.id, changes)?.ok_or_else(|| ApiError::not_found("Product", current.id))?;
+    log_action_with_reason(state, product.user_id, "UPDATE", "product", Some(product.id), &reason(job))?;
+    Ok(product)
+}
+
+fn import_row(state: &AppState, job: &ImportJob, fields: Map<String, Value>) -> Result<Outcome, ApiError> {
+    let row = ImportRow::read(fields)?;
+    let existing = match &row.sku {
+        Some(sku) => state.products.find_by_sku(sku)?,
+        None => None,
+    };
+    if let (Some(product), false) = (&existing, job.upsert) {
+        let taken = format!("is taken by product {}; import with upsert to update it", product.id);
+        return Err(ApiError::validation("Request validation failed", json!({ "sku": [taken] })));
+    }
+    let category_id = category_id(state, job, &row)?;
+    match existing {
+        Some(product) => update_product(state, job, product, row, category_id).map(|_| Outcome::Updated),
+        None => create_product(state, job, row, category_id).map(|_| Outcome::Created),
+    }
+}
+
+// One row in a savepoint of its own, so a row that fails leaves nothing
+// behind, not even a category it created
+fn import_one(state: &AppState, job: &ImportJob, row: &SourceRow) -> Result<Outcome, ApiError> {
+    let fields = row
+        .fields
+        .clone()
+        .map_err(|problem| ApiError::validation("Row could not be read", json!({ "row": [problem] })))?;
+    state.in_transaction(|tx| import_row(tx, job, fields))
+}
+
+// What is wrong with the row rather than with the database or the server;
+// those stop the job instead
+fn is_row_problem(err: &ApiError) -> bool {
+    matches!(err, ApiError::Validation { .. } | ApiError::Conflict { .. } | ApiError::NotFound(_) | ApiError::BadRequest(_))
+}
+
+// The counts and the report as the job goes along
+#[derive(Clone, Default)]
+struct Tally {
+    processed: i32,
+    created: i32,
+    updated: i32,
+    failed: i32,
+    errors: Vec<RowError>,
+}
+
+impl Tally {
+    // Where an interrupted job got to; a dry run starts over
+    fn of(job: &ImportJob) -> Tally {
+        if job.dry_run {
+            return Tally::default();
+        }
+        Tally {
+            processed: job.processed_rows,
+            created: job.created_rows,
+            updated: job.updated_rows,
+            failed: job.failed_rows,
+            errors: serde_json::from_value(job.row_errors.clone()).unwrap_or_default(),
+        }
+    }
+
+    fn record(&mut self, row: &SourceRow, outcome: Result<Outcome, ApiError>) -> Result<(), ApiError> {
+        match outcome {
+            Ok(Outcome::Created) => self.created += 1,
+            Ok(Outcome::Updated) => self.updated += 1,
+            Err(err) if is_row_problem(&err) => {
+                self.failed += 1;
+                if self.errors.len() < MAX_REPORTED_ERRORS {
+                    self.errors.push(RowError {
+                        row: row.line,
+                        sku: row.sku(),
+                        code: err.code().to_string(),
+                        message: err.to_string(),
+                        details: err.details(),
+                    });
+                }
+            }
+            Err(err) => return Err(err),
+        }
+        self.processed += 1;
+        Ok(())
+    }
+
+    fn progress(&self) -> ImportProgress {
+        ImportProgress {
+            processed_rows: self.processed,
+            created_rows: self.created,
+            updated_rows: self.updated,
+            failed_rows: self.failed,
+            row_errors: json!(self.errors),
+        }
+    }
+}
+
+fn save(state: &AppState, job: &ImportJob, tally: &Tally) -> Result<ImportJob, ApiError> {
+    state.import_jobs.save_progress(&job.id, tally.progress())?.ok_or_else(|| not_found(&job.id))
+}
+
+// Each batch commits together with the progress that counts it
+fn import_rows(state: &AppState, job: &ImportJob, rows: &[SourceRow], on_progress: &mut dyn FnMut(&ImportJob)) -> Result<(), ApiError> {
+    let mut tally = Tally::of(job);
+    let remaining = rows.get(tally.processed as usize..).unwrap_or_default();
+    for batch in remaining.chunks(BATCH_ROWS) {
+        let (next, saved) = state.in_transaction(|tx| {
+            let mut tally = tally.clone();
+            for row in batch {
+                tally.record(row, import_one(tx, job, row))?;
+            }
+            let saved = save(tx, job, &tally)?;
+            Ok((tally, saved))
+        })?;
+        tally = next;
+        on_progress(&saved);
+    }
+    Ok(())
+}
+
+// Like import_rows, but in one transaction that is rolled back at the end, so
+// later rows see what earlier ones would have done, such as a category they
+// would have created. Progress is saved outside it.
+fn check_rows(state: &AppState, job: &ImportJob, rows: &[SourceRow], on_progress: &mut dyn FnMut(&ImportJob)) -> Result<(), ApiError> {
+    let mut tally = Tally::of(job);
+    let mut checked = false;
+    let rolled_back = state.in_transaction(|tx| {
+        for batch in rows.chunks(BATCH_ROWS) {
+            for row in batch {
+                tally.record(row, import_one(tx, job, row))?;
+            }
+            on_progress(&save(state, job, &tally)?);
+        }
+        checked = true;
+        Err::<(), _>(ApiError::Internal("dry run rolled back".to_string()))
+    });
+    if !checked {
+        return rolled_back;
+    }
+    // Again, for stores whose rollback also undid the progress
+    save(state, job, &tally)?;
+    Ok(())
+}
+
+pub fn not_found(id: &str) -> ApiError {
+    ApiError::NotFound(format!("Import {} not found", id))
+}
+
+// Reads the file and records the job for `user_id`, who will own the products
+// it creates. With at most `max_inline_rows` rows the job starts out running,
+// for the caller to run right away; otherwise it waits for the import job.
+// Fails, recording nothing, if the file cannot be read at all.
+pub fn create(state: &AppState, user_id: i32, options: ImportOptions, source: String, max_inline_rows: usize) -> Result<ImportJob, ApiError> {
+    let total_rows = parse(options.format, &source)?.len();
+    if state.users.get(user_id)?.is_none() {
+        return Err(ApiError::Unauthorized(format!("User {} does not exist", user_id)));
+    }
+    state.import_jobs.create(NewImportJob {
+        id: uuid::Uuid::new_v4().to_string(),
+        user_id,
+        format: options.format.name().to_string(),
+        dry_run: options.dry_run,
+        upsert: options.upsert,
+        create_categories: options.create_categories,
+        status: if total_rows <= max_inline_rows { "running" } else { "pending" }.to_string(),
+        total_rows: total_rows as i32,
+        source,
+    })
+}
+
+// Imports the rows of a running job, or checks them for a dry run, saving
+// progress after every batch; `on_progress` is shown the job each time. Rows
+// that fail go to the report. Anything else stops the job and marks it failed;
+// if even that cannot be saved it stays running, to be taken on again once
+// stale and resume after its last committed batch.
+pub fn run(state: &AppState, id: &str, on_progress: &mut dyn FnMut(&ImportJob)) -> Result<ImportJob, ApiError> {
+    let job = state.import_jobs.get(id)?.ok_or_else(|| not_found(id))?;
+    // Ended already
+    let Some(source) = state.import_jobs.source(id)? else {
+        return Ok(job);
+    };
+    let format = ImportFormat::from_name(&job.format).ok_or_else(|| ApiError::Internal(format!("Unknown import format {}", job.format)))?;
+    let outcome = parse(format, &source).and_then(|rows| {
+        if job.dry_run {
+            check_rows(state, &job, &rows, on_progress)
+        } else {
+            import_rows(state, &job, &rows, on_progress)
+        }
+    });
+    match outcome {
+        Ok(()) => state.import_jobs.finish(id, None)?.ok_or_else(|| not_found(id)),
+        Err(err) => {
+            eprintln!("Import {} failed: {:?}", id, err);
+            state.import_jobs.finish(id, Some(&err.to_string()))?;
+            Err(err)
+        }
+    }
+}
+
+// One pass of the import job: runs the oldest pending import, if there is one,
+// to the end. Returns how many it ran.
+pub fn process_pending(state: &AppState) -> Result<usize, ApiError> {
+    let stale_before = Utc::now().naive_utc() - Duration::minutes(STALE_MINUTES);
+    let Some(job) = state.import_jobs.claim(stale_before)? else {
+        return Ok(0);
+    };
+    run(state, &job.id, &mut |_| {})?;
+    Ok(1)
+}
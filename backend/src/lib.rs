@@ -0,0 +1,149 @@
+// Amounts in cents are grouped as euros_cents, e.g. Money::from_cents(99_99)
+#![allow(clippy::inconsistent_digit_grouping)]
+
+use std::sync::Arc;
+
+use actix_web::web;
+
+pub mod attributes;
+pub mod audit;
+pub mod auth;
+pub mod category_tree;
+pub mod currency;
+pub mod db;
+pub mod errors;
+pub mod handlers;
+pub mod imports;
+pub mod media;
+pub mod mock_data;
+pub mod models;
+pub mod money;
+pub mod openapi;
+pub mod renditions;
+pub mod routes;
+pub mod storage;
+pub mod store;
+pub mod validation;
+pub mod video_uploads;
+
+use audit::AuditPolicy;
+use errors::ApiError;
+use media::MediaConfig;
+use storage::MediaStorage;
+use store::{
+    AttributeStore, AuditStore, CategoryStore, ExchangeRateStore, ImageStore, ImportJobStore, MemoryStore, PgStore, ProductStore, Store, TagStore,
+    Transactional, UserStore, VariantStore, VideoUploadStore,
+};
+
+pub const API_V1: &str = "/api/v1";
+
+pub type DbPool = db::connection::PgPool;
+
+// Shared state: the stores every handler reads and writes through
+#[derive(Clone)]
+pub struct AppState {
+    pub products: Arc<dyn ProductStore>,
+    pub variants: Arc<dyn VariantStore>,
+    pub images: Arc<dyn ImageStore>,
+    pub video_uploads: Arc<dyn VideoUploadStore>,
+    pub import_jobs: Arc<dyn ImportJobStore>,
+    pub categories: Arc<dyn CategoryStore>,
+    pub attributes: Arc<dyn AttributeStore>,
+    pub tags: Arc<dyn TagStore>,
+    pub exchange_rates: Arc<dyn ExchangeRateStore>,
+    pub users: Arc<dyn UserStore>,
+    pub audit: Arc<dyn AuditStore>,
+    pub audit_policy: AuditPolicy,
+    pub media: MediaConfig,
+    // Built from `media`; see with_media
+    pub storage: Arc<dyn MediaStorage>,
+    transactions: Arc<dyn Transactional>,
+}
+
+impl AppState {
+    pub fn postgres(pool: DbPool) -> Self {
+        Self::from_store(Arc::new(PgStore::new(pool)))
+    }
+
+    // Seeded from mock_data; used by the HTTP tests
+    pub fn in_memory() -> Self {
+        Self::from_store(Arc::new(MemoryStore::seeded()))
+    }
+
+    fn from_store<S: Store + 'static>(store: Arc<S>) -> Self {
+        let media = MediaConfig::default();
+        AppState {
+            products: store.clone(),
+            variants: store.clone(),
+            images: store.clone(),
+            video_uploads: store.clone(),
+            import_jobs: store.clone(),
+            categories: store.clone(),
+            attributes: store.clone(),
+            tags: store.clone(),
+            exchange_rates: store.clone(),
+            users: store.clone(),
+            audit: store.clone(),
+            audit_policy: AuditPolicy::default(),
+            storage: media.storage(),
+            media,
+            transactions: store,
+        }
+    }
+
+    // The same state with every store swapped for `store`; how a transaction
+    // hands its connection-bound stores to the code running inside it
+    pub fn with_store<S: Store + 'static>(&self, store: Arc<S>) -> Self {
+        AppState {
+            audit_policy: self.audit_policy,
+            media: self.media.clone(),
+            storage: self.storage.clone(),
+            ..Self::from_store(store)
+        }
+    }
+
+    pub fn with_audit_policy(self, audit_policy: AuditPolicy) -> Self {
+        AppState { audit_policy, ..self }
+    }
+
+    pub fn with_media(self, media: MediaConfig) -> Self {
+        AppState { storage: media.storage(), media, ..self }
+    }
+
+    // Store calls block (Diesel is synchronous), so run them on actix's
+    // blocking thread pool instead of stalling the async worker
+    pub async fn db<F, T>(&self, f: F) -> Result<T, ApiError>
+    where
+        F: FnOnce(&AppState) -> Result<T, ApiError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let state = self.clone();
+        web::block(move || f(&state))
+            .await
+            .map_err(|err| ApiError::Internal(err.to_string()))?
+    }
+
+    // Like `db`, but everything `f` does commits or rolls back together
+    pub async fn transaction<F, T>(&self, f: F) -> Result<T, ApiError>
+    where
+        F: FnOnce(&AppState) -> Result<T, ApiError> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.db(move |state| state.in_transaction(f)).await
+    }
+
+    // Blocking form for code already on the blocking pool; nested calls become savepoints
+    pub fn in_transaction<F, T>(&self, f: F) -> Result<T, ApiError>
+    where
+        F: FnOnce(&AppState) -> Result<T, ApiError>,
+    {
+        let mut f = Some(f);
+        let mut output = None;
+        self.transactions.transaction(self, &mut |tx| {
+            let f = f.take().expect("transaction body runs once");
+            output = Some(f(tx)?);
+            Ok(())
+        })?;
+        Ok(output.expect("committed transaction has a result"))
+    }
+}
@@ -0,0 +1,226 @@
+use std::env;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use actix_files::Files;
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+
+use crate::storage::{Backend, LocalStorage, MediaStorage, S3Storage};
+
+// Not served under the URL prefix; see MediaConfig::files
+const VIDEOS_DIR: &str = "videos";
+
+// Product videos from before uploads went to media storage have URLs like
+// /videos/<file>, the file sitting in MediaConfig::legacy_videos_dir
+const LEGACY_VIDEOS_PREFIX: &str = "/videos/";
+
+const DEFAULT_MAX_IMAGE_BYTES: usize = 10 * 1024 * 1024;
+const DEFAULT_MAX_VIDEO_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+const DEFAULT_UPLOAD_EXPIRY_HOURS: i64 = 24;
+
+// Where uploaded files are kept and the URL prefix they are served under
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaConfig {
+    // Holds resumable uploads in progress whatever the backend, and with the
+    // local backend the media itself
+    pub dir: PathBuf,
+    pub url_prefix: String,
+    pub backend: Backend,
+    pub max_image_bytes: usize,
+    pub max_video_bytes: u64,
+    // Resumable uploads left alone this long are abandoned and cleaned up
+    pub upload_expiry: Duration,
+    // Holds the videos behind /videos/<file> URLs; see legacy_video_path
+    pub legacy_videos_dir: PathBuf,
+}
+
+impl Default for MediaConfig {
+    fn default() -> Self {
+        MediaConfig {
+            dir: PathBuf::from("media"),
+            url_prefix: "/media".to_string(),
+            backend: Backend::Local,
+            max_image_bytes: DEFAULT_MAX_IMAGE_BYTES,
+            max_video_bytes: DEFAULT_MAX_VIDEO_BYTES,
+            upload_expiry: Duration::hours(DEFAULT_UPLOAD_EXPIRY_HOURS),
+            legacy_videos_dir: PathBuf::from(VIDEOS_DIR),
+        }
+    }
+}
+
+impl MediaConfig {
+    // MEDIA_DIR, MEDIA_STORAGE (local or s3, see S3Config::from_env),
+    // MEDIA_MAX_IMAGE_BYTES, MEDIA_MAX_VIDEO_BYTES, MEDIA_UPLOAD_EXPIRY_HOURS and
+    // MEDIA_LEGACY_VIDEOS_DIR; unset or unparsable ones keep the defaults. Panics on an incomplete s3
+    // configuration rather than starting without storage.
+    pub fn from_env() -> Self {
+        let defaults = MediaConfig::default();
+        fn parsed<T: FromStr>(name: &str) -> Option<T> {
+            env::var(name).ok().and_then(|s| s.parse().ok())
+        }
+        let backend = match env::var("MEDIA_STORAGE") {
+            Ok(name) => Backend::from_name(&name).unwrap_or_else(|err| panic!("{}", err)),
+            Err(_) => defaults.backend.clone(),
+        };
+        MediaConfig {
+            dir: env::var("MEDIA_DIR").map(PathBuf::from).unwrap_or(defaults.dir),
+            backend,
+            max_image_bytes: parsed("MEDIA_MAX_IMAGE_BYTES").unwrap_or(defaults.max_image_bytes),
+            max_video_bytes: parsed("MEDIA_MAX_VIDEO_BYTES").unwrap_or(defaults.max_video_bytes),
+            upload_expiry: parsed("MEDIA_UPLOAD_EXPIRY_HOURS").map(Duration::hours).unwrap_or(defaults.upload_expiry),
+            legacy_videos_dir: env::var("MEDIA_LEGACY_VIDEOS_DIR").map(PathBuf::from).unwrap_or(defaults.legacy_videos_dir),
+            ..defaults
+        }
+    }
+
+    pub fn storage(&self) -> Arc<dyn MediaStorage> {
+        match &self.backend {
+            Backend::Local => Arc::new(LocalStorage::new(&self.dir, &self.url_prefix)),
+            Backend::S3(config) => Arc::new(S3Storage::new(config.clone())),
+        }
+    }
+
+    // With the local backend, serves the media directory under the URL prefix,
+    // videos excepted: those go through /products/{id}/video, which checks who
+    // is asking. Other backends serve their files themselves.
+    pub fn files(&self) -> Option<Files> {
+        (self.backend == Backend::Local)
+            .then(|| Files::new(&self.url_prefix, &self.dir).path_filter(|path, _| !path.starts_with(VIDEOS_DIR)))
+    }
+
+    // Where the file behind a legacy /videos/<file> URL is; None for any other URL
+    pub fn legacy_video_path(&self, url: &str) -> Option<PathBuf> {
+        let file = url.strip_prefix(LEGACY_VIDEOS_PREFIX)?;
+        (!file.is_empty() && !file.split('/').any(|part| part.is_empty() || part == "..")).then(|| self.legacy_videos_dir.join(file))
+    }
+}
+
+// One srcset attribute value per format, e.g. "/media/images/<sha256>/thumbnail.webp 160w, ..."
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Srcset {
+    pub webp: String,
+    pub jpeg: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MediaFormat {
+    pub extension: &'static str,
+    pub content_type: &'static str,
+}
+
+pub const JPEG: MediaFormat = MediaFormat { extension: "jpg", content_type: "image/jpeg" };
+pub const PNG: MediaFormat = MediaFormat { extension: "png", content_type: "image/png" };
+pub const GIF: MediaFormat = MediaFormat { extension: "gif", content_type: "image/gif" };
+pub const WEBP: MediaFormat = MediaFormat { extension: "webp", content_type: "image/webp" };
+pub const MP4: MediaFormat = MediaFormat { extension: "mp4", content_type: "video/mp4" };
+pub const MOV: MediaFormat = MediaFormat { extension: "mov", content_type: "video/quicktime" };
+pub const WEBM: MediaFormat = MediaFormat { extension: "webm", content_type: "video/webm" };
+pub const OGV: MediaFormat = MediaFormat { extension: "ogv", content_type: "video/ogg" };
+
+// The format the bytes themselves announce; whatever the client claimed is ignored
+pub fn sniff_image(bytes: &[u8]) -> Option<MediaFormat> {
+    match bytes {
+        [0xFF, 0xD8, 0xFF, ..] => Some(JPEG),
+        [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some(PNG),
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some(GIF),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some(WEBP),
+        _ => None,
+    }
+}
+
+// The format a stored key was saved as, going by its extension
+pub fn format_of(key: &str) -> Option<MediaFormat> {
+    let extension = key.rsplit_once('.')?.1;
+    [JPEG, PNG, GIF, WEBP, MP4, MOV, WEBM, OGV].into_iter().find(|format| format.extension == extension)
+}
+
+pub fn video_format(key: &str) -> Option<MediaFormat> {
+    format_of(key).filter(|format| format.content_type.starts_with("video/"))
+}
+
+// Sniffed from the first few bytes of the file, like sniff_image
+pub fn sniff_video(bytes: &[u8]) -> Option<MediaFormat> {
+    match bytes {
+        [_, _, _, _, b'f', b't', b'y', b'p', b'q', b't', b' ', b' ', ..] => Some(MOV),
+        [_, _, _, _, b'f', b't', b'y', b'p', ..] => Some(MP4),
+        [0x1A, 0x45, 0xDF, 0xA3, ..] => Some(WEBM),
+        [b'O', b'g', b'g', b'S', ..] => Some(OGV),
+        _ => None,
+    }
+}
+
+// images/<sha256 of the bytes>.<extension>
+pub fn image_key(bytes: &[u8], format: MediaFormat) -> String {
+    format!("images/{:x}.{}", Sha256::digest(bytes), format.extension)
+}
+
+// videos/<sha256 of the bytes>.<extension>; the digest is already known, the
+// bytes being too many to hold in memory
+pub fn video_key(sha256: &str, format: MediaFormat) -> String {
+    format!("{}/{}.{}", VIDEOS_DIR, sha256, format.extension)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff_image() {
+        assert_eq!(sniff_image(&[0xFF, 0xD8, 0xFF, 0xE0, 0, 0x10]), Some(JPEG));
+        assert_eq!(sniff_image(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), Some(PNG));
+        assert_eq!(sniff_image(b"GIF89a\x01\0"), Some(GIF));
+        assert_eq!(sniff_image(b"RIFF\x24\0\0\0WEBPVP8 "), Some(WEBP));
+        assert_eq!(sniff_image(b"RIFF\x24\0\0\0WAVEfmt "), None);
+        assert_eq!(sniff_image(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>"), None);
+        assert_eq!(sniff_image(&[0xFF, 0xD8]), None);
+    }
+
+    #[test]
+    fn test_sniff_video() {
+        assert_eq!(sniff_video(b"\0\0\0\x20ftypisom\0\0\x02\0"), Some(MP4));
+        assert_eq!(sniff_video(b"\0\0\0\x14ftypqt  \0\0\x02\0"), Some(MOV));
+        assert_eq!(sniff_video(&[0x1A, 0x45, 0xDF, 0xA3, 0x9F, 0x42]), Some(WEBM));
+        assert_eq!(sniff_video(b"OggS\0\x02"), Some(OGV));
+        assert_eq!(sniff_video(b"\0\0\0\x20ftyp"), Some(MP4));
+        assert_eq!(sniff_video(b"\0\0\0\x20moov"), None);
+        assert_eq!(sniff_video(b"GIF89a"), None);
+    }
+
+    #[test]
+    fn test_video_key_round_trip() {
+        let storage = MediaConfig::default().storage();
+        let key = video_key(&"ab".repeat(32), WEBM);
+        assert_eq!(storage.key(&storage.url(&key)).as_deref(), Some(key.as_str()));
+        assert_eq!(video_format(&key), Some(WEBM));
+        assert_eq!(video_format("images/x.png"), None);
+        assert_eq!(format_of("images/x.png"), Some(PNG));
+        assert_eq!(storage.key("https://cdn.example.com/media/videos/a.mp4"), None);
+        assert_eq!(storage.key("/media-other/videos/a.mp4"), None);
+        assert_eq!(storage.key("/media/videos/../secret"), None);
+    }
+
+    #[test]
+    fn test_legacy_video_path() {
+        let media = MediaConfig::default();
+        assert_eq!(media.legacy_video_path("/videos/demo.mp4"), Some(PathBuf::from("videos/demo.mp4")));
+        assert_eq!(media.legacy_video_path("/videos/2023/demo.mp4"), Some(PathBuf::from("videos/2023/demo.mp4")));
+        assert_eq!(media.legacy_video_path("/videos/"), None);
+        assert_eq!(media.legacy_video_path("/videos/../secret"), None);
+        assert_eq!(media.legacy_video_path("/videos//etc/passwd"), None);
+        assert_eq!(media.legacy_video_path("/media/videos/demo.mp4"), None);
+        assert_eq!(media.legacy_video_path("https://cdn.example.com/videos/demo.mp4"), None);
+    }
+
+    #[test]
+    fn test_image_key_is_content_hash() {
+        let key = image_key(b"GIF89a", GIF);
+        assert_eq!(key, image_key(b"GIF89a", GIF));
+        assert_ne!(key, image_key(b"GIF87a", GIF));
+        assert!(key.starts_with("images/") && key.ends_with(".gif"));
+        assert_eq!(key.len(), "images/".len() + 64 + ".gif".len());
+    }
+}
@@ -1,29 +1,106 @@
-use crate::models::Product;
+use crate::currency::{Rate, REFERENCE_CURRENCY};
+use crate::db::models::{Category, ExchangeRate, Product, User};
+use crate::money::Money;
 use chrono::Utc;
 
+pub fn init_mock_users() -> Vec<User> {
+    vec![
+        User {
+            id: 1,
+            username: "admin".to_string(),
+            password: "admin123".to_string(),
+            role: "Admin".to_string(),
+        },
+        User {
+            id: 2,
+            username: "shopper".to_string(),
+            password: "shopper123".to_string(),
+            role: "User".to_string(),
+        },
+    ]
+}
+
+pub fn init_mock_categories() -> Vec<Category> {
+    vec![
+        Category {
+            id: 1,
+            name: "Clothes".to_string(),
+            slug: "clothes".to_string(),
+            description: "Clothing and apparel items".to_string(),
+            parent_id: None,
+            image: None,
+            display_order: 0,
+            created_at: Utc::now().naive_utc(),
+            updated_at: Utc::now().naive_utc(),
+        },
+        Category {
+            id: 2,
+            name: "Shoes".to_string(),
+            slug: "shoes".to_string(),
+            description: "Footwear and shoes".to_string(),
+            parent_id: None,
+            image: None,
+            display_order: 0,
+            created_at: Utc::now().naive_utc(),
+            updated_at: Utc::now().naive_utc(),
+        },
+    ]
+}
+
 pub fn init_mock_data() -> Vec<Product> {
     vec![
         Product {
             id: 1,
+            sku: None,
             name: "Sample Product 1".to_string(),
-            price: 99.99,
+            price: Money::from_cents(99_99),
+            currency: REFERENCE_CURRENCY.to_string(),
             description: "This is a sample product".to_string(),
             image: "/assets/images/placeholder.jpg".to_string(),
+            image_srcset: None,
             video: None,
+            published: true,
             category_id: 1,
+            user_id: 1,
+            stock: 10,
+            low_stock_threshold: Some(3),
+            attributes: crate::attributes::no_attributes(),
             created_at: Utc::now().naive_utc(),
             updated_at: Utc::now().naive_utc(),
         },
         Product {
             id: 2,
+            sku: None,
             name: "Sample Product 2".to_string(),
-            price: 149.99,
+            price: Money::from_cents(149_99),
+            currency: REFERENCE_CURRENCY.to_string(),
             description: "Another sample product".to_string(),
             image: "/assets/images/placeholder.jpg".to_string(),
+            image_srcset: None,
             video: None,
+            published: true,
             category_id: 1,
+            user_id: 1,
+            stock: 0,
+            low_stock_threshold: None,
+            attributes: crate::attributes::no_attributes(),
             created_at: Utc::now().naive_utc(),
             updated_at: Utc::now().naive_utc(),
         },
     ]
-} 
\ No newline at end of file
+}
+
+pub fn init_mock_exchange_rates() -> Vec<ExchangeRate> {
+    vec![
+        ExchangeRate {
+            currency: REFERENCE_CURRENCY.to_string(),
+            rate: Rate::ONE,
+            updated_at: Utc::now().naive_utc(),
+        },
+        ExchangeRate {
+            currency: "USD".to_string(),
+            rate: Rate::from_micros(1_080_000),
+            updated_at: Utc::now().naive_utc(),
+        },
+    ]
+}
@@ -1,11 +1,21 @@
-use serde::{Deserialize, Serialize};
-use chrono::NaiveDateTime;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use chrono::{Duration, NaiveDateTime, Utc};
+use validator::Validate;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::currency::Rate;
+use crate::db::models::NewCategory;
+use crate::money::Money;
+use crate::validation;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Product {
     pub id: i32,
     pub name: String,
-    pub price: f64,
+    pub price: Money,
     pub description: String,
     pub image: String,
     pub video: Option<String>,
@@ -17,27 +27,265 @@ pub struct Product {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateProductRequest {
     pub name: String,
-    pub price: f64,
+    pub price: Money,
     pub image: String,
     pub description: String,
     pub category_id: i32,
     pub video: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize, Validate, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct ProductQuery {
     pub category_id: Option<i32>,
-    pub min_price: Option<f64>,
-    pub max_price: Option<f64>,
+    // With category_id, also products in its subcategories at any depth
+    pub include_subcategories: Option<bool>,
+    // Prices are converted to this currency before filtering and sorting;
+    // without it each product keeps its own currency
+    pub currency: Option<String>,
+    pub min_price: Option<Money>,
+    pub max_price: Option<Money>,
     pub search_term: Option<String>,
+    // true: only products with stock left; false: only sold-out ones. With
+    // `options` it applies to the matching variants' stock instead.
+    pub in_stock: Option<bool>,
+    // Only products with a variant that has all of these option values
+    #[param(value_type = Option<String>, example = "size:42,color:black")]
+    pub options: Option<OptionFilter>,
+    // Only products cheaper now than this many days ago (or than at creation, if newer)
+    #[validate(range(min = 1, max = 3650))]
+    #[param(minimum = 1, maximum = 3650)]
+    pub price_dropped_within_days: Option<u32>,
+    // Only tagged products, compared case-insensitively
+    #[param(value_type = Option<String>, example = "summer,sale")]
+    pub tags: Option<TagFilter>,
+    // any (the default): products with at least one of `tags`; all: with every one
+    pub tags_match: Option<TagMatch>,
     pub sort_by: Option<String>,
     pub sort_order: Option<String>,
+    // The attr.<name>=<value> parameters, which serde cannot collect next to the
+    // typed fields; handlers fill it in with AttributeFilter::from_query_string
+    #[serde(skip)]
+    #[param(ignore)]
+    pub attributes: AttributeFilter,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl ProductQuery {
+    // validate() bounds the days; past the earliest representable time the
+    // cutoff stays there, which still means every price change counts
+    pub fn price_drop_cutoff(&self) -> Option<NaiveDateTime> {
+        self.price_dropped_within_days.map(|days| {
+            Utc::now().naive_utc().checked_sub_signed(Duration::days(days.into())).unwrap_or(NaiveDateTime::MIN)
+        })
+    }
+}
+
+// `size:42,color:black` as (option, value) pairs, lowercased since variants
+// match them case-insensitively
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OptionFilter(pub Vec<(String, String)>);
+
+impl FromStr for OptionFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(|pair| match pair.split_once(':') {
+                Some((name, value)) if !name.trim().is_empty() && !value.trim().is_empty() => {
+                    Ok((name.trim().to_lowercase(), value.trim().to_lowercase()))
+                }
+                _ => Err(format!("expected option:value pairs separated by commas, got {:?}", pair)),
+            })
+            .collect::<Result<_, _>>()
+            .map(OptionFilter)
+    }
+}
+
+impl fmt::Display for OptionFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, (name, value)) in self.0.iter().enumerate() {
+            if index > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}:{}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for OptionFilter {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for OptionFilter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
+
+// `summer,sale` as lowercased tag names, without duplicates
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TagFilter(pub Vec<String>);
+
+impl FromStr for TagFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut names: Vec<String> = Vec::new();
+        for name in s.split(',').map(|name| name.trim().to_lowercase()) {
+            if name.is_empty() {
+                return Err(format!("expected tag names separated by commas, got {:?}", s));
+            }
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        Ok(TagFilter(names))
+    }
+}
+
+impl fmt::Display for TagFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.join(","))
+    }
+}
+
+impl Serialize for TagFilter {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for TagFilter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TagMatch {
+    #[default]
+    Any,
+    All,
+}
+
+// `attr.size=42&attr.material=leather` as (attribute, value) pairs, the values
+// lowercased since they match case-insensitively
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AttributeFilter(pub Vec<(String, String)>);
+
+impl AttributeFilter {
+    pub fn from_query_string(query: &str) -> Result<Self, String> {
+        url::form_urlencoded::parse(query.as_bytes())
+            .filter_map(|(key, value)| key.strip_prefix("attr.").map(|name| (name.to_string(), value.trim().to_lowercase())))
+            .map(|(name, value)| {
+                if validation::attribute_name(&name).is_err() || value.is_empty() {
+                    return Err(format!("expected attr.<name>=<value>, got attr.{}={:?}", name, value));
+                }
+                Ok((name, value))
+            })
+            .collect::<Result<_, _>>()
+            .map(AttributeFilter)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
 pub struct CreateCategoryRequest {
+    #[validate(length(max = 100), custom(function = "crate::validation::not_blank"))]
     pub name: String,
+    #[validate(length(max = 1000))]
     pub description: String,
+    // Top level when omitted
+    #[validate(range(min = 1))]
+    pub parent_id: Option<i32>,
+    // Derived from the name when omitted
+    #[validate(length(max = 100), custom(function = "crate::validation::slug"))]
+    pub slug: Option<String>,
+    #[validate(length(max = 2048), custom(function = "crate::validation::media_url"))]
+    pub image: Option<String>,
+    // 0 when omitted
+    pub display_order: Option<i32>,
+}
+
+impl CreateCategoryRequest {
+    pub fn into_new_category(self) -> NewCategory {
+        NewCategory {
+            slug: self.slug.unwrap_or_else(|| validation::slugify(&self.name)),
+            name: self.name,
+            description: self.description,
+            parent_id: self.parent_id,
+            image: self.image,
+            display_order: self.display_order.unwrap_or(0),
+        }
+    }
+}
+
+// What happens to a category's products and subcategories when it is deleted.
+// Without either option the delete is refused while it still has any.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeleteCategoryQuery {
+    // Move its products and direct subcategories to this category first
+    pub reassign_to: Option<i32>,
+    // Delete its subcategories and every product in them along with it
+    pub cascade: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
+pub struct SetExchangeRateRequest {
+    #[validate(custom(function = "crate::validation::rate_range"))]
+    pub rate: Rate,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
+pub struct StockMovementRequest {
+    #[validate(range(min = 1, max = 1_000_000))]
+    pub quantity: i32,
+    // Recorded with the movement in the audit log, e.g. "sold in store"
+    #[validate(length(max = 500), custom(function = "crate::validation::not_blank"))]
+    pub reason: String,
+}
+
+// Body of PUT /products/{id}/tags. Names that are not tags yet become new tags;
+// existing ones are matched case-insensitively.
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
+pub struct SetProductTagsRequest {
+    #[validate(length(max = 20), custom(function = "crate::validation::tag_names"))]
+    pub tags: Vec<String>,
+}
+
+// Body of POST /products/{id}/video/uploads: what the finished file will be
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
+pub struct CreateVideoUploadRequest {
+    #[validate(range(min = 1))]
+    pub size_bytes: i64,
+    // Lowercase hex SHA-256 of the whole file
+    #[validate(custom(function = "crate::validation::sha256_hex"))]
+    pub sha256: String,
+}
+
+// How /products/import reads the file and what it does with the rows
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportQuery {
+    // Without it, taken from the Content-Type: text/csv or application/x-ndjson
+    pub format: Option<crate::imports::ImportFormat>,
+    // Check every row and report, but change nothing
+    pub dry_run: Option<bool>,
+    // Rows whose SKU a product has update that product instead of failing
+    pub upsert: Option<bool>,
+    // Category names no category has create top-level categories instead of failing
+    pub create_categories: Option<bool>,
+}
+
+// One option of a product with every value its variants use, e.g. Size: 41, 42
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ProductOption {
+    pub name: String,
+    pub values: Vec<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -0,0 +1,322 @@
+use std::fmt;
+use std::iter::Sum;
+use std::ops::Add;
+use std::str::FromStr;
+
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::data_types::PgNumeric;
+use diesel::pg::{Pg, PgValue};
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Numeric;
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use utoipa::openapi::schema::{ObjectBuilder, Schema, Type};
+use utoipa::openapi::RefOr;
+use utoipa::{PartialSchema, ToSchema};
+
+// An exact amount in cents, stored as NUMERIC(12,2)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Numeric)]
+pub struct Money(i64);
+
+// Postgres numerics carry their digits in base 10000
+const NBASE: i128 = 10_000;
+
+impl Money {
+    pub const fn from_cents(cents: i64) -> Self {
+        Money(cents)
+    }
+
+    pub const fn cents(self) -> i64 {
+        self.0
+    }
+
+    // Mean rounded half away from zero to whole cents; None for no amounts
+    pub fn average(amounts: impl IntoIterator<Item = Money>) -> Option<Money> {
+        let (sum, count) = amounts
+            .into_iter()
+            .fold((0i128, 0i128), |(sum, count), amount| (sum + amount.0 as i128, count + 1));
+        if count == 0 {
+            return None;
+        }
+        let rounded = (2 * sum + sum.signum() * count) / (2 * count);
+        Some(Money(rounded as i64))
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+
+    fn add(self, other: Money) -> Money {
+        Money(self.0 + other.0)
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+        iter.fold(Money::default(), Add::add)
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        format_fixed(f, self.0, 2)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDecimalError(String);
+
+impl fmt::Display for ParseDecimalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ParseDecimalError {}
+
+// Fixed-point helpers shared with currency::Rate; `units` counts steps of 10^-scale
+
+// "12", "12.3", "-12.34"; more than `scale` decimals is an error, not a rounding
+pub(crate) fn parse_fixed(s: &str, scale: usize) -> Result<i64, ParseDecimalError> {
+    let invalid = || ParseDecimalError("expected a decimal number like 12.34".to_string());
+    let too_large = || ParseDecimalError("amount is too large".to_string());
+    let (negative, unsigned) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let (whole, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    if whole.is_empty() || !whole.bytes().all(|b| b.is_ascii_digit()) || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    if fraction.len() > scale {
+        return Err(ParseDecimalError(format!("at most {} decimal places are allowed", scale)));
+    }
+    let whole: i64 = whole.parse().map_err(|_| too_large())?;
+    let fraction: i64 = format!("{:0<width$}", fraction, width = scale).parse().unwrap_or(0);
+    let units = whole
+        .checked_mul(10i64.pow(scale as u32))
+        .and_then(|u| u.checked_add(fraction))
+        .ok_or_else(too_large)?;
+    Ok(if negative { -units } else { units })
+}
+
+pub(crate) fn format_fixed(f: &mut fmt::Formatter<'_>, units: i64, scale: usize) -> fmt::Result {
+    let sign = if units < 0 { "-" } else { "" };
+    let factor = 10u64.pow(scale as u32);
+    let units = units.unsigned_abs();
+    write!(f, "{}{}.{:0width$}", sign, units / factor, units % factor, width = scale)
+}
+
+// A JSON number. units / 10^scale is the double nearest the decimal, and
+// serde_json prints the shortest text that round-trips, so the output is the
+// exact decimal ("19.99", never "19.990000000000002").
+pub(crate) fn serialize_fixed<S: Serializer>(serializer: S, units: i64, scale: usize) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(units as f64 / 10f64.powi(scale as i32))
+}
+
+// Accepts numbers and strings; query strings always arrive as strings
+pub(crate) fn deserialize_fixed<'de, D: Deserializer<'de>>(deserializer: D, scale: usize) -> Result<i64, D::Error> {
+    deserializer.deserialize_any(FixedVisitor { scale })
+}
+
+struct FixedVisitor {
+    scale: usize,
+}
+
+impl FixedVisitor {
+    fn whole<E: de::Error>(&self, value: Option<i64>) -> Result<i64, E> {
+        value
+            .and_then(|v| v.checked_mul(10i64.pow(self.scale as u32)))
+            .ok_or_else(|| E::custom("amount is too large"))
+    }
+}
+
+impl<'de> Visitor<'de> for FixedVisitor {
+    type Value = i64;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a number with at most {} decimal places", self.scale)
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<i64, E> {
+        parse_fixed(value.trim(), self.scale).map_err(E::custom)
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<i64, E> {
+        self.whole(Some(value))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<i64, E> {
+        self.whole(i64::try_from(value).ok())
+    }
+
+    // Go through the shortest decimal text of the double, the same digits the client sent
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<i64, E> {
+        if !value.is_finite() {
+            return Err(E::custom("amount must be a finite number"));
+        }
+        parse_fixed(&value.to_string(), self.scale).map_err(E::custom)
+    }
+}
+
+pub(crate) fn to_numeric(units: i64, scale: u32) -> PgNumeric {
+    let factor = 10i128.pow(scale);
+    let magnitude = units.unsigned_abs() as i128;
+    let (whole, fraction) = (magnitude / factor, magnitude % factor);
+    let mut digits = Vec::new();
+    let mut rest = whole;
+    while rest > 0 {
+        digits.insert(0, (rest % NBASE) as i16);
+        rest /= NBASE;
+    }
+    let weight = digits.len() as i16 - 1;
+    // Pad the decimals out to whole base-10000 digits after the point
+    let fraction_digits = scale.div_ceil(4);
+    let mut fraction = fraction * 10i128.pow(fraction_digits * 4 - scale);
+    let mut tail = Vec::new();
+    for _ in 0..fraction_digits {
+        tail.insert(0, (fraction % NBASE) as i16);
+        fraction /= NBASE;
+    }
+    digits.extend(tail);
+    let scale = scale as u16;
+    if units < 0 {
+        PgNumeric::Negative { weight, scale, digits }
+    } else {
+        PgNumeric::Positive { weight, scale, digits }
+    }
+}
+
+pub(crate) fn from_numeric(numeric: PgNumeric, scale: u32) -> Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+    let (negative, weight, digits) = match numeric {
+        PgNumeric::Positive { weight, digits, .. } => (false, weight, digits),
+        PgNumeric::Negative { weight, digits, .. } => (true, weight, digits),
+        PgNumeric::NaN => return Err("NaN is not a number".into()),
+    };
+    let mut units: i128 = 0;
+    for (i, digit) in digits.iter().enumerate() {
+        // This digit is worth 10^power units
+        let power = 4 * (weight as i32 - i as i32) + scale as i32;
+        let digit = *digit as i128;
+        let value = match power {
+            p if p >= 0 => 10i128.checked_pow(p as u32).and_then(|f| digit.checked_mul(f)),
+            p if p > -4 && digit % 10i128.pow(-p as u32) == 0 => Some(digit / 10i128.pow(-p as u32)),
+            _ if digit == 0 => Some(0),
+            _ => return Err(format!("number has more than {} decimal places", scale).into()),
+        };
+        units = value.and_then(|v| units.checked_add(v)).ok_or("number is too large")?;
+    }
+    let units = i64::try_from(units).map_err(|_| "number is too large")?;
+    Ok(if negative { -units } else { units })
+}
+
+impl FromStr for Money {
+    type Err = ParseDecimalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_fixed(s, 2).map(Money)
+    }
+}
+
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_fixed(serializer, self.0, 2)
+    }
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_fixed(deserializer, 2).map(Money)
+    }
+}
+
+impl PartialSchema for Money {
+    fn schema() -> RefOr<Schema> {
+        ObjectBuilder::new()
+            .schema_type(Type::Number)
+            .multiple_of(Some(0.01))
+            .description(Some("Exact amount with at most two decimal places"))
+            .into()
+    }
+}
+
+impl ToSchema for Money {}
+
+impl From<Money> for PgNumeric {
+    fn from(money: Money) -> Self {
+        to_numeric(money.0, 2)
+    }
+}
+
+impl TryFrom<PgNumeric> for Money {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    fn try_from(numeric: PgNumeric) -> Result<Self, Self::Error> {
+        from_numeric(numeric, 2).map(Money)
+    }
+}
+
+impl ToSql<Numeric, Pg> for Money {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        let numeric = PgNumeric::from(*self);
+        ToSql::<Numeric, Pg>::to_sql(&numeric, &mut out.reborrow())
+    }
+}
+
+impl FromSql<Numeric, Pg> for Money {
+    fn from_sql(value: PgValue<'_>) -> deserialize::Result<Self> {
+        Money::try_from(PgNumeric::from_sql(value)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_display_round_trip() {
+        for (text, cents) in [("0", 0), ("12", 1200), ("12.3", 1230), ("12.34", 1234), ("-0.05", -5), ("1000000.00", 100_000_000)] {
+            let money: Money = text.parse().unwrap();
+            assert_eq!(money.cents(), cents, "{}", text);
+        }
+        assert_eq!(Money::from_cents(-1205).to_string(), "-12.05");
+        assert_eq!(Money::from_cents(7).to_string(), "0.07");
+        assert!("12.345".parse::<Money>().is_err());
+        assert!("12.".parse::<Money>().is_ok());
+        assert!(".5".parse::<Money>().is_err());
+        assert!("1e3".parse::<Money>().is_err());
+    }
+
+    #[test]
+    fn test_json_is_exact() {
+        let money: Money = serde_json::from_str("0.1").unwrap();
+        assert_eq!(money, Money::from_cents(10));
+        let sum = Money::from_cents(10) + Money::from_cents(20);
+        assert_eq!(serde_json::to_string(&sum).unwrap(), "0.3");
+        assert_eq!(serde_json::to_string(&Money::from_cents(1999)).unwrap(), "19.99");
+        assert_eq!(serde_json::from_str::<Money>("\"149.99\"").unwrap(), Money::from_cents(14999));
+        assert_eq!(serde_json::from_str::<Money>("42").unwrap(), Money::from_cents(4200));
+        assert!(serde_json::from_str::<Money>("10.005").is_err());
+    }
+
+    #[test]
+    fn test_average_rounds_half_away_from_zero() {
+        let amounts = [Money::from_cents(1), Money::from_cents(2)];
+        assert_eq!(Money::average(amounts), Some(Money::from_cents(2)));
+        let amounts = [Money::from_cents(-1), Money::from_cents(-2)];
+        assert_eq!(Money::average(amounts), Some(Money::from_cents(-2)));
+        assert_eq!(Money::average([]), None);
+    }
+
+    #[test]
+    fn test_numeric_round_trip() {
+        for cents in [0, 5, 99, 100, 9999, 10_000, 1_234_567_89, -4_200_01] {
+            let money = Money::from_cents(cents);
+            assert_eq!(Money::try_from(PgNumeric::from(money)).unwrap(), money);
+        }
+        let sub_cent = PgNumeric::Positive { weight: -1, scale: 4, digits: vec![1234] };
+        assert!(Money::try_from(sub_cent).is_err());
+    }
+}
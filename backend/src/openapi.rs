@@ -0,0 +1,297 @@
+use actix_web::http::header;
+use actix_web::HttpResponse;
+use utoipa::OpenApi;
+
+use crate::category_tree::CategoryNode;
+use crate::currency::Rate;
+use crate::db::models::{
+    Category, CategoryAttribute, ExchangeRate, ImportJob, MonitoredUser, NewAttribute, NewProduct, NewTag, NewVariant, PriceChange, Product, ProductImage,
+    ProductVariant, Tag, UpdateCategory, UpdateProduct, UpdateVariant, VariantOption, VideoUpload,
+};
+use crate::db::repository::{CategoryWithStats, ProductWithCategory, TagWithCount};
+use crate::errors::ErrorBody;
+use crate::handlers::{
+    admin, attributes, categories, exchange_rates, images, imports, products, stats, stock, tags, users, variants, videos,
+};
+use crate::imports::{ImportFormat, RowError};
+use crate::media::Srcset;
+use crate::models::{
+    CreateCategoryRequest, CreateVideoUploadRequest, ProductOption, SetExchangeRateRequest, SetProductTagsRequest, StockMovementRequest, TagMatch,
+};
+use crate::money::Money;
+
+// Only the /api/v1 layout is documented; the verb-prefixed aliases are deprecated
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "MPP backend", description = "Product catalogue API"),
+    paths(
+        products::get_products,
+        products::create_product,
+        products::get_product,
+        products::replace_product,
+        products::update_product,
+        products::delete_product,
+        products::get_products_by_user_id,
+        products::get_price_history,
+        imports::import_products,
+        imports::get_import,
+        images::get_images,
+        images::upload_images,
+        images::delete_image,
+        images::create_direct_upload,
+        images::complete_direct_upload,
+        videos::get_video,
+        videos::create_video_upload,
+        videos::get_video_upload,
+        videos::append_video_chunk,
+        videos::cancel_video_upload,
+        variants::get_variants,
+        variants::create_variant,
+        variants::get_variant_options,
+        variants::get_variant,
+        variants::replace_variant,
+        variants::update_variant,
+        variants::delete_variant,
+        stock::increment_stock,
+        stock::decrement_stock,
+        stock::increment_variant_stock,
+        stock::decrement_variant_stock,
+        stock::get_stock_alerts,
+        categories::get_categories,
+        categories::get_category_tree,
+        categories::create_category,
+        categories::get_category,
+        categories::get_category_by_slug,
+        categories::update_category,
+        categories::patch_category,
+        categories::delete_category,
+        attributes::get_attributes,
+        attributes::create_attribute,
+        attributes::get_attribute,
+        attributes::replace_attribute,
+        attributes::delete_attribute,
+        tags::get_tags,
+        tags::create_tag,
+        tags::get_tag,
+        tags::rename_tag,
+        tags::delete_tag,
+        tags::get_product_tags,
+        tags::set_product_tags,
+        users::register,
+        users::login,
+        admin::toggle_generation,
+        admin::get_monitored_users_handler,
+        exchange_rates::get_exchange_rates,
+        exchange_rates::set_exchange_rate,
+        exchange_rates::delete_exchange_rate,
+        stats::avg_price_per_category_handler,
+        stats::avg_price_inefficient_handler,
+        stats::avg_price_per_category_inefficient_handler,
+    ),
+    components(schemas(
+        Product,
+        NewProduct,
+        UpdateProduct,
+        ProductWithCategory,
+        PriceChange,
+        ImportJob,
+        RowError,
+        ImportFormat,
+        ProductImage,
+        Srcset,
+        images::ImageUploadForm,
+        images::DirectUpload,
+        VideoUpload,
+        CreateVideoUploadRequest,
+        ProductVariant,
+        NewVariant,
+        UpdateVariant,
+        VariantOption,
+        ProductOption,
+        Category,
+        CategoryWithStats,
+        CategoryNode,
+        CategoryAttribute,
+        NewAttribute,
+        Tag,
+        TagWithCount,
+        NewTag,
+        SetProductTagsRequest,
+        TagMatch,
+        CreateCategoryRequest,
+        UpdateCategory,
+        MonitoredUser,
+        ExchangeRate,
+        SetExchangeRateRequest,
+        StockMovementRequest,
+        ErrorBody,
+        Money,
+        Rate,
+        users::RegisterRequest,
+        users::LoginRequest,
+    )),
+    tags(
+        (name = "products"),
+        (name = "images"),
+        (name = "videos"),
+        (name = "variants"),
+        (name = "categories"),
+        (name = "attributes"),
+        (name = "tags"),
+        (name = "stock"),
+        (name = "users"),
+        (name = "stats"),
+        (name = "admin"),
+    )
+)]
+pub struct ApiDoc;
+
+pub async fn openapi_json() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+pub async fn docs_page() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(include_str!("../static/docs.html"))
+}
+
+// Swagger UI 5.17.14 (static/swagger-ui, Apache-2.0), built into the binary so
+// the docs work offline and under a strict CSP
+pub async fn swagger_ui_css() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/css; charset=utf-8")
+        .insert_header((header::CACHE_CONTROL, "public, max-age=86400"))
+        .body(include_str!("../static/swagger-ui/swagger-ui.css"))
+}
+
+pub async fn swagger_ui_bundle() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/javascript; charset=utf-8")
+        .insert_header((header::CACHE_CONTROL, "public, max-age=86400"))
+        .body(include_str!("../static/swagger-ui/swagger-ui-bundle.js"))
+}
+
+// Starts Swagger UI on the spec; a file rather than an inline script, which a
+// CSP would refuse
+pub async fn docs_script() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/javascript; charset=utf-8")
+        .body(include_str!("../static/docs.js"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::Method, test, web, App};
+    use diesel::r2d2::{ConnectionManager, Pool};
+    use std::time::Duration;
+
+    // Every documented operation must be served; a 404/405 means the spec and the routes drifted apart
+    #[actix_web::test]
+    async fn test_spec_matches_routes() {
+        // Nothing listens here, so handlers fail fast with 503 instead of touching a database
+        let pool = Pool::builder()
+            .connection_timeout(Duration::from_millis(50))
+            .build_unchecked(ConnectionManager::new("postgres://127.0.0.1:1/unreachable"));
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(crate::AppState::postgres(pool)))
+                .configure(crate::routes::configure_routes),
+        )
+        .await;
+
+        let spec = ApiDoc::openapi();
+        assert!(!spec.paths.paths.is_empty());
+        for (path, item) in &spec.paths.paths {
+            let uri = path
+                .replace("{id}", "1")
+                .replace("{user_id}", "1")
+                .replace("{variant_id}", "1")
+                .replace("{attribute_id}", "1")
+                .replace("{image_id}", "1")
+                .replace("{upload_id}", "00000000-0000-0000-0000-000000000000")
+                .replace("{currency}", "USD")
+                .replace("{slug}", "shoes");
+            let operations = [
+                (Method::GET, item.get.is_some()),
+                (Method::POST, item.post.is_some()),
+                (Method::PUT, item.put.is_some()),
+                (Method::PATCH, item.patch.is_some()),
+                (Method::DELETE, item.delete.is_some()),
+            ];
+            for (method, _) in operations.into_iter().filter(|(_, documented)| *documented) {
+                let req = test::TestRequest::default()
+                    .method(method.clone())
+                    .uri(&format!("{}?user_id=1", uri))
+                    .insert_header(("content-type", "application/json"))
+                    .set_payload("{}")
+                    .to_request();
+                let resp = test::call_service(&app, req).await;
+                assert!(
+                    resp.status() != 404 && resp.status() != 405,
+                    "{} {} is documented but not routed (got {})",
+                    method,
+                    path,
+                    resp.status()
+                );
+            }
+        }
+    }
+
+    // The other direction: every /api/v1 route must be documented. actix-web
+    // cannot list an app's resources, so they are read from configure_v1_routes.
+    #[actix_web::test]
+    async fn test_routes_are_documented() {
+        let source = include_str!("routes.rs");
+        let start = source.find("pub fn configure_v1_routes").expect("configure_v1_routes");
+        let body = &source[start..start + source[start..].find("\n}\n").expect("end of configure_v1_routes")];
+        let spec = ApiDoc::openapi();
+        let mut resources = 0;
+        for service in body.split(".service(").skip(1) {
+            let path = service.split("web::resource(\"").nth(1).and_then(|rest| rest.split('"').next()).expect("a resource path");
+            let path = format!("{}{}", crate::API_V1, path);
+            let item = spec.paths.paths.get(&path).unwrap_or_else(|| panic!("{} is routed but not documented", path));
+            let documented = [
+                ("get", item.get.is_some()),
+                ("post", item.post.is_some()),
+                ("put", item.put.is_some()),
+                ("patch", item.patch.is_some()),
+                ("delete", item.delete.is_some()),
+            ];
+            for (method, is_documented) in documented {
+                if service.contains(&format!("web::{}()", method)) {
+                    assert!(is_documented, "{} {} is routed but not documented", method.to_uppercase(), path);
+                }
+            }
+            resources += 1;
+        }
+        let documented = spec.paths.paths.keys().filter(|path| path.starts_with(crate::API_V1)).count();
+        assert_eq!(resources, documented, "{} /api/v1 resources routed, {} documented", resources, documented);
+    }
+
+    #[actix_web::test]
+    async fn test_spec_is_served() {
+        let app = test::init_service(App::new().route("/api/openapi.json", web::get().to(openapi_json))).await;
+        let req = test::TestRequest::get().uri("/api/openapi.json").to_request();
+        let spec: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert!(spec["openapi"].as_str().unwrap().starts_with("3."));
+        assert!(spec["paths"]["/api/v1/products"]["get"].is_object());
+        assert!(spec["components"]["schemas"]["NewProduct"].is_object());
+    }
+
+    // The docs page loads nothing from other hosts
+    #[actix_web::test]
+    async fn test_docs_are_self_contained() {
+        let app = test::init_service(App::new().configure(crate::routes::configure_routes)).await;
+        let req = test::TestRequest::get().uri("/api/docs").to_request();
+        let page = String::from_utf8(test::call_and_read_body(&app, req).await.to_vec()).unwrap();
+        assert!(!page.contains("http://") && !page.contains("https://"), "{}", page);
+        for asset in ["/api/docs/swagger-ui.css", "/api/docs/swagger-ui-bundle.js", "/api/docs/docs.js"] {
+            assert!(page.contains(asset));
+            let req = test::TestRequest::get().uri(asset).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200, "{}", asset);
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use std::io::Cursor;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder, ImageReader, ImageResult, Limits};
+
+use crate::db::models::ProductImage;
+use crate::errors::ApiError;
+use crate::media::Srcset;
+use crate::storage::MediaStorage;
+use crate::AppState;
+
+// Widths every upload is rendered at; narrower images are not scaled up
+pub const SIZES: [(&str, u32); 3] = [("thumbnail", 160), ("medium", 640), ("large", 1280)];
+
+// What each size is encoded as, by file extension
+const EXTENSIONS: [&str; 2] = ["webp", "jpg"];
+
+const JPEG_QUALITY: u8 = 82;
+// Decoding anything larger on either side is refused rather than attempted
+const MAX_SIDE: u32 = 12_000;
+
+struct Rendition {
+    key: String,
+    extension: &'static str,
+    width: u32,
+    bytes: Vec<u8>,
+}
+
+// images/<sha256>.png renders to images/<sha256>/thumbnail.webp and so on
+fn rendition_key(key: &str, size: &str, extension: &str) -> String {
+    let stem = key.rsplit_once('.').map_or(key, |(stem, _)| stem);
+    format!("{}/{}.{}", stem, size, extension)
+}
+
+// Every key the renditions of the original under `key` are stored at
+pub fn rendition_keys(key: &str) -> Vec<String> {
+    SIZES
+        .iter()
+        .flat_map(|(size, _)| EXTENSIONS.iter().map(move |extension| rendition_key(key, size, extension)))
+        .collect()
+}
+
+// Upright, decoded pixels; only these are encoded again, so no metadata of the
+// upload (EXIF, XMP, colour profiles) reaches a rendition
+fn decode(bytes: &[u8]) -> ImageResult<DynamicImage> {
+    let mut reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?;
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_SIDE);
+    limits.max_image_height = Some(MAX_SIDE);
+    reader.limits(limits);
+    let mut decoder = reader.into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+fn render(key: &str, original: &[u8]) -> ImageResult<(DynamicImage, Vec<Rendition>)> {
+    let image = decode(original)?;
+    let mut renditions = Vec::new();
+    for (size, max_width) in SIZES {
+        let width = max_width.min(image.width());
+        let height = ((u64::from(image.height()) * u64::from(width)) / u64::from(image.width())).max(1) as u32;
+        let resized = image.resize_exact(width, height, FilterType::Lanczos3);
+
+        let mut webp = Vec::new();
+        resized.write_with_encoder(WebPEncoder::new_lossless(&mut webp))?;
+        let mut jpeg = Vec::new();
+        // JPEG has no alpha channel; transparent areas come out black without this
+        DynamicImage::ImageRgb8(resized.to_rgb8()).write_with_encoder(JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY))?;
+
+        for (extension, bytes) in EXTENSIONS.into_iter().zip([webp, jpeg]) {
+            renditions.push(Rendition { key: rendition_key(key, size, extension), extension, width, bytes });
+        }
+    }
+    Ok((image, renditions))
+}
+
+// "url 160w, url 640w, ..." over the renditions in one format, skipping sizes
+// that came out no wider than the one before
+fn srcset(storage: &dyn MediaStorage, renditions: &[Rendition], extension: &str) -> String {
+    let mut entries: Vec<String> = Vec::new();
+    let mut widest = 0;
+    for rendition in renditions.iter().filter(|r| r.extension == extension) {
+        if rendition.width <= widest {
+            continue;
+        }
+        widest = rendition.width;
+        entries.push(format!("{} {}w", storage.url(&rendition.key), rendition.width));
+    }
+    entries.join(", ")
+}
+
+fn generate(storage: &dyn MediaStorage, image: &ProductImage) -> Result<(i32, i32, Srcset), String> {
+    let original = storage.get(&image.key).map_err(|err| format!("reading {}: {}", image.key, err))?;
+    let (decoded, renditions) = render(&image.key, &original).map_err(|err| err.to_string())?;
+    for rendition in &renditions {
+        storage.put(&rendition.key, &rendition.bytes).map_err(|err| format!("storing {}: {}", rendition.key, err))?;
+    }
+    let srcset = Srcset { webp: srcset(storage, &renditions, "webp"), jpeg: srcset(storage, &renditions, "jpg") };
+    Ok((decoded.width() as i32, decoded.height() as i32, srcset))
+}
+
+// One pass of the rendition job: renders up to `limit` pending uploads, oldest
+// first, and returns how many it took on. An upload that cannot be rendered is
+// marked failed and not retried.
+pub fn process_pending(state: &AppState, limit: i64) -> Result<usize, ApiError> {
+    let pending = state.images.pending_renditions(limit)?;
+    for image in &pending {
+        match generate(state.storage.as_ref(), image) {
+            Ok((width, height, srcset)) => {
+                let srcset = serde_json::to_value(srcset).map_err(|err| ApiError::Internal(err.to_string()))?;
+                state.images.finish_renditions(image.id, width, height, srcset)?;
+            }
+            Err(reason) => {
+                eprintln!("Renditions of image {} failed: {}", image.id, reason);
+                state.images.fail_renditions(image.id)?;
+            }
+        }
+    }
+    Ok(pending.len())
+}
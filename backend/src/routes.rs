@@ -0,0 +1,182 @@
+use actix_web::http::header;
+use actix_web::middleware::DefaultHeaders;
+use actix_web::web;
+
+use crate::handlers::admin::{get_monitored_users_handler, shutdown_server, toggle_generation};
+use crate::handlers::attributes::{create_attribute, delete_attribute, get_attribute, get_attributes, replace_attribute};
+use crate::handlers::categories::{
+    create_category, delete_category, get_categories, get_category, get_category_by_slug, get_category_tree, patch_category, update_category,
+};
+use crate::handlers::exchange_rates::{delete_exchange_rate, get_exchange_rates, set_exchange_rate};
+use crate::handlers::images::{complete_direct_upload, create_direct_upload, delete_image, get_images, upload_images};
+use crate::handlers::imports::{get_import, import_products};
+use crate::handlers::products::{
+    create_product, delete_product, get_price_history, get_product, get_products, get_products_by_user_id, replace_product, update_product,
+};
+use crate::handlers::stats::{avg_price_inefficient_handler, avg_price_per_category_handler, avg_price_per_category_inefficient_handler};
+use crate::handlers::stock::{decrement_stock, decrement_variant_stock, get_stock_alerts, increment_stock, increment_variant_stock};
+use crate::handlers::tags::{create_tag, delete_tag, get_product_tags, get_tag, get_tags, rename_tag, set_product_tags};
+use crate::handlers::users::{login, register};
+use crate::handlers::variants::{create_variant, delete_variant, get_variant, get_variant_options, get_variants, replace_variant, update_variant};
+use crate::handlers::videos::{append_video_chunk, cancel_video_upload, create_video_upload, get_video, get_video_upload};
+use crate::openapi;
+use crate::API_V1;
+
+// Every API route; shared by the server and the OpenAPI drift test
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope(API_V1).configure(configure_v1_routes))
+        .configure(configure_legacy_routes)
+        .route("/api/openapi.json", web::get().to(openapi::openapi_json))
+        .route("/api/docs", web::get().to(openapi::docs_page))
+        .route("/api/docs/swagger-ui.css", web::get().to(openapi::swagger_ui_css))
+        .route("/api/docs/swagger-ui-bundle.js", web::get().to(openapi::swagger_ui_bundle))
+        .route("/api/docs/docs.js", web::get().to(openapi::docs_script))
+        .route("/api/toggle-generation", web::post().to(toggle_generation))
+        .route("/api/shutdown", web::get().to(shutdown_server))
+        .route("/api/register", web::post().to(register))
+        .route("/api/login", web::post().to(login))
+        .route("/api/monitored-users", web::get().to(get_monitored_users_handler))
+        .route("/api/stats/avg-price-per-category", web::get().to(avg_price_per_category_handler))
+        .route("/api/stats/avg-price-inefficient", web::get().to(avg_price_inefficient_handler))
+        .route("/api/stats/avg-price-per-category-inefficient", web::get().to(avg_price_per_category_inefficient_handler));
+}
+
+pub fn configure_v1_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/products")
+            .route(web::get().to(get_products))
+            .route(web::post().to(create_product)),
+    )
+    // Before /products/{id}, which would otherwise claim "import"
+    .service(web::resource("/products/import").route(web::post().to(import_products)))
+    .service(web::resource("/products/import/{id}").route(web::get().to(get_import)))
+    .service(
+        web::resource("/products/{id}")
+            .route(web::get().to(get_product))
+            .route(web::put().to(replace_product))
+            .route(web::patch().to(update_product))
+            .route(web::delete().to(delete_product)),
+    )
+    .service(web::resource("/products/{id}/price-history").route(web::get().to(get_price_history)))
+    .service(web::resource("/products/{id}/stock/increment").route(web::post().to(increment_stock)))
+    .service(web::resource("/products/{id}/stock/decrement").route(web::post().to(decrement_stock)))
+    .service(
+        web::resource("/products/{id}/variants")
+            .route(web::get().to(get_variants))
+            .route(web::post().to(create_variant)),
+    )
+    // Before /variants/{variant_id}, which would otherwise claim "options"
+    .service(web::resource("/products/{id}/variants/options").route(web::get().to(get_variant_options)))
+    .service(
+        web::resource("/products/{id}/variants/{variant_id}")
+            .route(web::get().to(get_variant))
+            .route(web::put().to(replace_variant))
+            .route(web::patch().to(update_variant))
+            .route(web::delete().to(delete_variant)),
+    )
+    .service(web::resource("/products/{id}/variants/{variant_id}/stock/increment").route(web::post().to(increment_variant_stock)))
+    .service(web::resource("/products/{id}/variants/{variant_id}/stock/decrement").route(web::post().to(decrement_variant_stock)))
+    .service(
+        web::resource("/products/{id}/images")
+            .route(web::get().to(get_images))
+            .route(web::post().to(upload_images)),
+    )
+    .service(web::resource("/products/{id}/images/direct-uploads").route(web::post().to(create_direct_upload)))
+    .service(web::resource("/products/{id}/images/direct-uploads/{upload_id}").route(web::post().to(complete_direct_upload)))
+    .service(web::resource("/products/{id}/images/{image_id}").route(web::delete().to(delete_image)))
+    .service(
+        web::resource("/products/{id}/video")
+            .route(web::get().to(get_video))
+            .route(web::head().to(get_video)),
+    )
+    .service(web::resource("/products/{id}/video/uploads").route(web::post().to(create_video_upload)))
+    .service(
+        web::resource("/products/{id}/video/uploads/{upload_id}")
+            .route(web::get().to(get_video_upload))
+            .route(web::patch().to(append_video_chunk))
+            .route(web::delete().to(cancel_video_upload)),
+    )
+    .service(
+        web::resource("/products/{id}/tags")
+            .route(web::get().to(get_product_tags))
+            .route(web::put().to(set_product_tags)),
+    )
+    .service(web::resource("/stock-alerts").route(web::get().to(get_stock_alerts)))
+    .service(web::resource("/users/{user_id}/products").route(web::get().to(get_products_by_user_id)))
+    .service(
+        web::resource("/categories")
+            .route(web::get().to(get_categories))
+            .route(web::post().to(create_category)),
+    )
+    // Before /categories/{id}, which would otherwise claim "tree"
+    .service(web::resource("/categories/tree").route(web::get().to(get_category_tree)))
+    .service(web::resource("/categories/by-slug/{slug}").route(web::get().to(get_category_by_slug)))
+    .service(
+        web::resource("/categories/{id}")
+            .route(web::get().to(get_category))
+            .route(web::put().to(update_category))
+            .route(web::patch().to(patch_category))
+            .route(web::delete().to(delete_category)),
+    )
+    .service(
+        web::resource("/categories/{id}/attributes")
+            .route(web::get().to(get_attributes))
+            .route(web::post().to(create_attribute)),
+    )
+    .service(
+        web::resource("/categories/{id}/attributes/{attribute_id}")
+            .route(web::get().to(get_attribute))
+            .route(web::put().to(replace_attribute))
+            .route(web::delete().to(delete_attribute)),
+    )
+    .service(
+        web::resource("/tags")
+            .route(web::get().to(get_tags))
+            .route(web::post().to(create_tag)),
+    )
+    .service(
+        web::resource("/tags/{id}")
+            .route(web::get().to(get_tag))
+            .route(web::put().to(rename_tag))
+            .route(web::delete().to(delete_tag)),
+    )
+    .service(web::resource("/exchange-rates").route(web::get().to(get_exchange_rates)))
+    .service(
+        web::resource("/exchange-rates/{currency}")
+            .route(web::put().to(set_exchange_rate))
+            .route(web::delete().to(delete_exchange_rate)),
+    );
+}
+
+// Marks a legacy response as deprecated and points clients at the v1 replacement
+fn deprecated(successor: &str) -> DefaultHeaders {
+    DefaultHeaders::new()
+        .add(("Deprecation", "true"))
+        .add((header::LINK, format!("<{}{}>; rel=\"successor-version\"", API_V1, successor)))
+}
+
+// Verb-prefixed paths kept as aliases for clients that have not moved to /api/v1 yet
+pub fn configure_legacy_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/api/get/products").wrap(deprecated("/products")).route(web::get().to(get_products)))
+        .service(web::resource("/api/post/products").wrap(deprecated("/products")).route(web::post().to(create_product)))
+        .service(web::resource("/api/get/products/{id}").wrap(deprecated("/products")).route(web::get().to(get_product)))
+        .service(web::resource("/api/patch/products/{id}").wrap(deprecated("/products")).route(web::patch().to(update_product)))
+        .service(web::resource("/api/delete/products/{id}").wrap(deprecated("/products")).route(web::delete().to(delete_product)))
+        .service(
+            web::resource("/api/get/products/user/{user_id}")
+                .wrap(deprecated("/products"))
+                .route(web::get().to(get_products_by_user_id)),
+        )
+        .service(web::resource("/api/get/categories").wrap(deprecated("/categories")).route(web::get().to(get_categories)))
+        .service(web::resource("/api/post/categories").wrap(deprecated("/categories")).route(web::post().to(create_category)))
+        .service(web::resource("/api/get/categories/{id}").wrap(deprecated("/categories")).route(web::get().to(get_category)))
+        // Historically registered as PUT despite the name; PATCH is accepted too.
+        // Both update in place, so old clients that know nothing of parents keep them.
+        .service(
+            web::resource("/api/patch/categories/{id}")
+                .wrap(deprecated("/categories"))
+                .route(web::put().to(patch_category))
+                .route(web::patch().to(patch_category)),
+        )
+        .service(web::resource("/api/delete/categories/{id}").wrap(deprecated("/categories")).route(web::delete().to(delete_category)));
+}
@@ -0,0 +1,112 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use super::MediaStorage;
+
+// Extension of a file still being written, followed by a uuid; see `write`
+const PARTIAL_EXTENSION: &str = "part-";
+
+// Files in a directory on this machine, served by the app under `url_prefix`
+#[derive(Debug, Clone)]
+pub struct LocalStorage {
+    dir: PathBuf,
+    url_prefix: String,
+}
+
+impl LocalStorage {
+    pub fn new(dir: impl Into<PathBuf>, url_prefix: &str) -> Self {
+        LocalStorage { dir: dir.into(), url_prefix: url_prefix.to_string() }
+    }
+
+    // Hidden entries (partial video uploads) and files still being written are skipped
+    fn collect(&self, dir: &Path, keys: &mut Vec<String>) -> io::Result<()> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') || name.rsplit_once('.').is_some_and(|(_, ext)| ext.starts_with(PARTIAL_EXTENSION)) {
+                continue;
+            }
+            if entry.file_type()?.is_dir() {
+                self.collect(&entry.path(), keys)?;
+            } else if let Ok(key) = entry.path().strip_prefix(&self.dir) {
+                keys.push(key.to_string_lossy().replace('\\', "/"));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl MediaStorage for LocalStorage {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn url(&self, key: &str) -> String {
+        format!("{}/{}", self.url_prefix, key)
+    }
+
+    // The file appears whole or not at all, so a concurrent reader never sees half of it
+    fn write(&self, key: &str, body: &mut dyn Read, len: u64) -> io::Result<()> {
+        let path = self.dir.join(key);
+        if path.exists() {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let partial = path.with_extension(format!("{}{}", PARTIAL_EXTENSION, uuid::Uuid::new_v4()));
+        let written = File::create(&partial).and_then(|mut file| {
+            io::copy(&mut body.take(len), &mut file)?;
+            file.sync_data()
+        });
+        written.and_then(|_| fs::rename(&partial, &path)).inspect_err(|_| {
+            let _ = fs::remove_file(&partial);
+        })
+    }
+
+    fn open(&self, key: &str) -> io::Result<(Box<dyn Read + Send>, u64)> {
+        let file = File::open(self.dir.join(key))?;
+        let len = file.metadata()?.len();
+        Ok((Box::new(file), len))
+    }
+
+    fn exists(&self, key: &str) -> io::Result<bool> {
+        self.dir.join(key).try_exists()
+    }
+
+    fn remove(&self, key: &str) -> io::Result<()> {
+        match fs::remove_file(self.dir.join(key)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    fn list(&self) -> io::Result<Vec<String>> {
+        let mut keys = Vec::new();
+        self.collect(&self.dir, &mut keys)?;
+        Ok(keys)
+    }
+
+    // A rename rather than a copy; when `key` is taken already the file at
+    // `path` is simply dropped
+    fn put_file(&self, key: &str, path: &Path) -> io::Result<()> {
+        let target = self.dir.join(key);
+        if target.exists() {
+            return fs::remove_file(path);
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(path, target)
+    }
+
+    fn local_path(&self, key: &str) -> Option<PathBuf> {
+        Some(self.dir.join(key))
+    }
+}
@@ -0,0 +1,116 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use chrono::Duration;
+
+pub mod local;
+pub mod s3;
+
+pub use local::LocalStorage;
+pub use s3::{S3Config, S3Storage};
+
+// Where uploaded media lives. Keys are relative paths such as
+// images/<sha256>.png and carry their format's extension; URLs are where
+// clients fetch them. Methods block, like the stores; call them through
+// AppState::db.
+pub trait MediaStorage: Send + Sync {
+    // "local" or "s3", for logs and error messages
+    fn name(&self) -> &'static str;
+
+    fn url(&self, key: &str) -> String;
+
+    // Writes `len` bytes from `body` under `key`. Keys are content-hashed, so
+    // one that is taken already holds the same bytes and may be left alone.
+    fn write(&self, key: &str, body: &mut dyn Read, len: u64) -> io::Result<()>;
+
+    // The stored bytes and how many there are; NotFound for a missing key
+    fn open(&self, key: &str) -> io::Result<(Box<dyn Read + Send>, u64)>;
+
+    fn exists(&self, key: &str) -> io::Result<bool>;
+
+    // Missing keys are fine; there is nothing left to remove
+    fn remove(&self, key: &str) -> io::Result<()>;
+
+    // Every stored key, in no particular order
+    fn list(&self) -> io::Result<Vec<String>>;
+
+    fn put(&self, key: &str, bytes: &[u8]) -> io::Result<()> {
+        self.write(key, &mut &bytes[..], bytes.len() as u64)
+    }
+
+    // Stores the local file at `path` under `key` and removes it
+    fn put_file(&self, key: &str, path: &Path) -> io::Result<()> {
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+        self.write(key, &mut file, len)?;
+        fs::remove_file(path)
+    }
+
+    fn get(&self, key: &str) -> io::Result<Vec<u8>> {
+        let (mut body, len) = self.open(key)?;
+        let mut bytes = Vec::with_capacity(len as usize);
+        body.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    // The reverse of `url`; None for URLs of files stored elsewhere
+    fn key(&self, url: &str) -> Option<String> {
+        let key = url.strip_prefix(&self.url(""))?;
+        (!key.is_empty() && !key.contains("..")).then(|| key.to_string())
+    }
+
+    // Where the file would be on this machine, for backends that keep it here;
+    // the app serves those files itself
+    fn local_path(&self, _key: &str) -> Option<PathBuf> {
+        None
+    }
+
+    // A URL that lets whoever holds it GET or PUT `key` directly for the next
+    // `expires`; None if the backend cannot hand those out
+    fn presigned_url(&self, _method: &str, _key: &str, _expires: Duration) -> Option<String> {
+        None
+    }
+}
+
+// Which backend MediaConfig::storage builds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Backend {
+    // Files under MediaConfig::dir, served by the app under MediaConfig::url_prefix
+    Local,
+    S3(S3Config),
+}
+
+impl Backend {
+    // "local", or "s3" configured from the S3_* variables
+    pub fn from_name(name: &str) -> Result<Backend, String> {
+        match name {
+            "local" => Ok(Backend::Local),
+            "s3" => S3Config::from_env().map(Backend::S3),
+            other => Err(format!("Unknown media storage {:?}; expected local or s3", other)),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CopyReport {
+    pub copied: usize,
+    // Already in the target
+    pub skipped: usize,
+}
+
+// Copies every key of `from` that `to` lacks. Safe to run again after an
+// interruption: whatever made it across is skipped.
+pub fn copy_all(from: &dyn MediaStorage, to: &dyn MediaStorage) -> io::Result<CopyReport> {
+    let mut report = CopyReport::default();
+    for key in from.list()? {
+        if to.exists(&key)? {
+            report.skipped += 1;
+            continue;
+        }
+        let (mut body, len) = from.open(&key)?;
+        to.write(&key, &mut body, len)?;
+        report.copied += 1;
+    }
+    Ok(report)
+}
@@ -0,0 +1,1282 @@
+use std::cmp::Ordering;
+use std::sync::{Mutex, MutexGuard};
+
+use chrono::{NaiveDateTime, Utc};
+use serde_json::json;
+
+use crate::attributes;
+use crate::category_tree;
+use crate::currency::{self, Rate, REFERENCE_CURRENCY};
+use crate::db::models::{
+    Category, CategoryAttribute, ExchangeRate, ImportJob, ImportProgress, Log, MonitoredUser, NewAttribute, NewCategory, NewImage, NewImportJob,
+    NewLog, NewPriceChange, NewProduct, NewUser, NewVariant, NewVideoUpload, PriceChange, Product, ProductImage, ProductVariant, Tag,
+    UpdateCategory, UpdateProduct, User, VideoUpload,
+};
+use crate::db::repository::{CategoryWithStats, ProductWithCategory, TagWithCount};
+use crate::errors::ApiError;
+use crate::mock_data;
+use crate::models::{OptionFilter, ProductQuery, TagFilter, TagMatch};
+use crate::money::Money;
+use crate::store::{
+    AttributeStore, AuditStore, CategoryStore, ExchangeRateStore, ImageStore, ImportJobStore, ProductStore, TagStore, Transactional, UserStore,
+    VariantStore, VideoUploadStore,
+};
+use crate::AppState;
+
+#[derive(Clone, Default)]
+struct Tables {
+    products: Vec<Product>,
+    categories: Vec<Category>,
+    exchange_rates: Vec<ExchangeRate>,
+    price_history: Vec<PriceChange>,
+    // Options live on the variants here; the product_options tables are derived in SQL
+    variants: Vec<ProductVariant>,
+    images: Vec<ProductImage>,
+    video_uploads: Vec<VideoUpload>,
+    // Each job with its uploaded file, the source column
+    import_jobs: Vec<(ImportJob, Option<String>)>,
+    attributes: Vec<CategoryAttribute>,
+    tags: Vec<Tag>,
+    // (product_id, tag_id)
+    product_tags: Vec<(i32, i32)>,
+    users: Vec<User>,
+    logs: Vec<Log>,
+    monitored_users: Vec<MonitoredUser>,
+}
+
+impl Tables {
+    fn next_id<T>(rows: &[T], id: impl Fn(&T) -> i32) -> i32 {
+        rows.iter().map(id).max().unwrap_or(0) + 1
+    }
+
+    // Mirrors the foreign keys on products so tests see the same conflicts as Postgres
+    fn check_product_refs(&self, category_id: i32, user_id: i32, currency: &str) -> Result<(), ApiError> {
+        if !self.categories.iter().any(|c| c.id == category_id) {
+            return Err(conflict("products_category_id_fkey"));
+        }
+        if self.rate(currency).is_none() {
+            return Err(conflict("products_currency_fkey"));
+        }
+        if !self.users.iter().any(|u| u.id == user_id) {
+            return Err(conflict("products_user_id_fkey"));
+        }
+        Ok(())
+    }
+
+    // Mirrors products_sku_key
+    fn check_product_sku(&self, id: Option<i32>, sku: Option<&str>) -> Result<(), ApiError> {
+        if sku.is_some_and(|sku| self.products.iter().any(|p| p.sku.as_deref() == Some(sku) && Some(p.id) != id)) {
+            return Err(ApiError::Conflict {
+                message: "Resource already exists".to_string(),
+                details: json!({ "constraint": "products_sku_key" }),
+            });
+        }
+        Ok(())
+    }
+
+    fn import_job(&mut self, id: &str) -> Option<&mut (ImportJob, Option<String>)> {
+        self.import_jobs.iter_mut().find(|(job, _)| job.id == id)
+    }
+
+    fn rate(&self, currency: &str) -> Option<Rate> {
+        self.exchange_rates.iter().find(|r| r.currency == currency).map(|r| r.rate)
+    }
+
+    // Mirrors repository::record_price_change
+    fn record_price_change(&mut self, before: Option<&Product>, after: &Product) {
+        if let Some(change) = NewPriceChange::between(before, after) {
+            let id = Tables::next_id(&self.price_history, |c| c.id);
+            self.price_history.push(PriceChange {
+                id,
+                product_id: change.product_id,
+                old_price: change.old_price,
+                old_currency: change.old_currency,
+                new_price: change.new_price,
+                new_currency: change.new_currency,
+                changed_at: change.changed_at,
+            });
+        }
+    }
+
+    // Mirrors repository::products_with_price_drop_since
+    fn price_dropped_since(&self, product: &Product, since: NaiveDateTime) -> bool {
+        let first_change = self
+            .price_history
+            .iter()
+            .filter(|c| c.product_id == product.id && c.changed_at >= since)
+            .min_by_key(|c| (c.changed_at, c.id));
+        first_change.is_some_and(|c| {
+            let (earlier_price, earlier_currency) = c.price_before();
+            earlier_currency == product.currency && product.price < earlier_price
+        })
+    }
+
+    // Mirrors the foreign key and the unique (category_id, name) on category_attributes
+    fn check_attribute_refs(&self, category_id: i32, id: Option<i32>, name: &str) -> Result<(), ApiError> {
+        if !self.categories.iter().any(|c| c.id == category_id) {
+            return Err(conflict("category_attributes_category_id_fkey"));
+        }
+        if self.attributes.iter().any(|a| a.category_id == category_id && a.name == name && Some(a.id) != id) {
+            return Err(ApiError::Conflict {
+                message: "Resource already exists".to_string(),
+                details: json!({ "constraint": "category_attributes_category_id_name_key" }),
+            });
+        }
+        Ok(())
+    }
+
+    // Mirrors tags_name_lower_key
+    fn check_tag_unique(&self, id: Option<i32>, name: &str) -> Result<(), ApiError> {
+        if self.tags.iter().any(|t| t.name.to_lowercase() == name.to_lowercase() && Some(t.id) != id) {
+            return Err(ApiError::Conflict {
+                message: "Resource already exists".to_string(),
+                details: json!({ "constraint": "tags_name_lower_key" }),
+            });
+        }
+        Ok(())
+    }
+
+    // Mirrors repository::products_with_tags
+    fn has_tags(&self, product: &Product, filter: &TagFilter, mode: TagMatch) -> bool {
+        let carries = |name: &String| {
+            self.tags
+                .iter()
+                .filter(|t| &t.name.to_lowercase() == name)
+                .any(|t| self.product_tags.contains(&(product.id, t.id)))
+        };
+        match mode {
+            TagMatch::Any => filter.0.iter().any(carries),
+            TagMatch::All => filter.0.iter().all(carries),
+        }
+    }
+
+    // Mirrors the products_image_srcset trigger
+    fn image_srcset(&self, image: &str) -> Option<serde_json::Value> {
+        self.images.iter().filter(|i| i.url == image).find_map(|i| i.srcset.clone())
+    }
+
+    // Mirrors categories_slug_key and categories_name_lower_key
+    fn check_category_unique(&self, id: Option<i32>, name: &str, slug: &str) -> Result<(), ApiError> {
+        let others = || self.categories.iter().filter(|c| Some(c.id) != id);
+        if others().any(|c| c.slug == slug) {
+            return Err(conflict("categories_slug_key"));
+        }
+        if others().any(|c| c.name.to_lowercase() == name.to_lowercase()) {
+            return Err(conflict("categories_name_lower_key"));
+        }
+        Ok(())
+    }
+
+    // Mirrors the parent foreign key and the categories_prevent_cycle trigger
+    fn check_category_parent(&self, id: Option<i32>, parent_id: Option<i32>) -> Result<(), ApiError> {
+        let Some(parent_id) = parent_id else {
+            return Ok(());
+        };
+        if !self.categories.iter().any(|c| c.id == parent_id) {
+            return Err(conflict("categories_parent_id_fkey"));
+        }
+        if id.is_some_and(|id| category_tree::ancestors(&self.categories, parent_id).contains(&id)) {
+            return Err(ApiError::Conflict {
+                message: "Operation violates a data constraint".to_string(),
+                details: json!({ "constraint": "categories_parent_cycle" }),
+            });
+        }
+        Ok(())
+    }
+
+    // Mirrors repository::products_with_variant
+    fn has_variant(&self, product: &Product, filter: &OptionFilter, in_stock: Option<bool>) -> bool {
+        self.variants.iter().any(|v| {
+            v.product_id == product.id
+                && filter.0.iter().all(|(name, value)| v.has_option(name, value))
+                && in_stock.is_none_or(|in_stock| (v.stock > 0) == in_stock)
+        })
+    }
+
+    // Mirrors the foreign key and the unique SKU on product_variants
+    fn check_variant_refs(&self, product_id: i32, id: Option<i32>, sku: &str) -> Result<(), ApiError> {
+        if !self.products.iter().any(|p| p.id == product_id) {
+            return Err(conflict("product_variants_product_id_fkey"));
+        }
+        if self.variants.iter().any(|v| v.sku == sku && Some(v.id) != id) {
+            return Err(ApiError::Conflict {
+                message: "Resource already exists".to_string(),
+                details: json!({ "constraint": "product_variants_sku_key" }),
+            });
+        }
+        Ok(())
+    }
+
+    // Missing rates fail like the lookup in the SQL version
+    fn target_rate(&self, currency: &str) -> Result<Rate, ApiError> {
+        self.rate(currency).ok_or_else(|| ApiError::NotFound("Resource not found".to_string()))
+    }
+}
+
+// Mirrors repository::list_products so both stores answer a query alike
+fn apply_product_query(products: &mut Vec<ProductWithCategory>, query: &ProductQuery, categories: &[Category]) {
+    if let Some(category_id) = query.category_id {
+        if query.include_subcategories == Some(true) {
+            products.retain(|p| category_tree::ancestors(categories, p.category_id).contains(&category_id));
+        } else {
+            products.retain(|p| p.category_id == category_id);
+        }
+    }
+    if let Some(min_price) = query.min_price {
+        products.retain(|p| p.price >= min_price);
+    }
+    if let Some(max_price) = query.max_price {
+        products.retain(|p| p.price <= max_price);
+    }
+    // With an option filter, in_stock was applied to the variants already
+    if let (Some(in_stock), None) = (query.in_stock, &query.options) {
+        products.retain(|p| (p.stock > 0) == in_stock);
+    }
+    for (name, value) in &query.attributes.0 {
+        products.retain(|p| p.attributes.get(name).and_then(attributes::filter_text).as_ref() == Some(value));
+    }
+    if let Some(search_term) = &query.search_term {
+        let search_term = search_term.to_lowercase();
+        products.retain(|p| {
+            p.name.to_lowercase().contains(&search_term) ||
+            p.description.to_lowercase().contains(&search_term)
+        });
+    }
+
+    // Stable sorts on top of id order, so ties stay in id order as in SQL
+    products.sort_by_key(|p| p.id);
+    let descending = query.sort_order.as_deref() == Some("desc");
+    let directed = |ordering: Ordering| if descending { ordering.reverse() } else { ordering };
+    match query.sort_by.as_deref() {
+        Some("name") => products.sort_by(|a, b| directed(a.name.cmp(&b.name))),
+        Some("price") => products.sort_by(|a, b| directed(a.price.cmp(&b.price))),
+        Some("created_at") => products.sort_by(|a, b| directed(a.created_at.cmp(&b.created_at))),
+        _ => {}
+    }
+}
+
+fn conflict(constraint: &str) -> ApiError {
+    ApiError::Conflict {
+        message: "Operation conflicts with a related resource".to_string(),
+        details: json!({ "constraint": constraint }),
+    }
+}
+
+// In-process store for tests and local runs without a database
+#[derive(Default)]
+pub struct MemoryStore {
+    tables: Mutex<Tables>,
+}
+
+impl MemoryStore {
+    // Empty apart from the reference currency's rate, like a freshly migrated database
+    pub fn new() -> Self {
+        let store = Self::default();
+        store.lock().exchange_rates.push(ExchangeRate {
+            currency: REFERENCE_CURRENCY.to_string(),
+            rate: Rate::ONE,
+            updated_at: Utc::now().naive_utc(),
+        });
+        store
+    }
+
+    // Starts with the same users, categories and products as mock_data
+    pub fn seeded() -> Self {
+        let store = Self::new();
+        {
+            let mut tables = store.lock();
+            tables.users = mock_data::init_mock_users();
+            tables.categories = mock_data::init_mock_categories();
+            tables.exchange_rates = mock_data::init_mock_exchange_rates();
+            tables.products = mock_data::init_mock_data();
+            for product in tables.products.clone() {
+                tables.record_price_change(None, &product);
+            }
+        }
+        store
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Tables> {
+        // A panicking test must not poison the store for the others sharing it
+        self.tables.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl ProductStore for MemoryStore {
+    fn list(&self, owner: Option<i32>, query: &ProductQuery) -> Result<Vec<ProductWithCategory>, ApiError> {
+        let tables = self.lock();
+        let target_rate = match &query.currency {
+            Some(currency) => Some(tables.target_rate(currency)?),
+            None => None,
+        };
+        let price_drop_cutoff = query.price_drop_cutoff();
+        let mut products: Vec<ProductWithCategory> = tables
+            .products
+            .iter()
+            .filter(|p| owner.is_none_or(|user_id| p.user_id == user_id))
+            .filter(|p| price_drop_cutoff.is_none_or(|since| tables.price_dropped_since(p, since)))
+            .filter(|p| query.options.as_ref().is_none_or(|filter| tables.has_variant(p, filter, query.in_stock)))
+            .filter(|p| query.tags.as_ref().is_none_or(|filter| tables.has_tags(p, filter, query.tags_match.unwrap_or_default())))
+            .filter_map(|p| {
+                // Inner join semantics: products without a category are skipped
+                let category = tables.categories.iter().find(|c| c.id == p.category_id)?;
+                let own_rate = tables.rate(&p.currency)?;
+                Some(ProductWithCategory {
+                    id: p.id,
+                    sku: p.sku.clone(),
+                    name: p.name.clone(),
+                    price: target_rate.map_or(p.price, |to| currency::convert(p.price, own_rate, to)),
+                    currency: query.currency.clone().unwrap_or_else(|| p.currency.clone()),
+                    description: p.description.clone(),
+                    image: p.image.clone(),
+                    image_srcset: p.image_srcset.clone(),
+                    video: p.video.clone(),
+                    published: p.published,
+                    category_id: p.category_id,
+                    category_name: category.name.clone(),
+                    user_id: p.user_id,
+                    stock: p.stock,
+                    attributes: p.attributes.clone(),
+                    created_at: p.created_at,
+                    updated_at: p.updated_at,
+                })
+            })
+            .collect();
+        apply_product_query(&mut products, query, &tables.categories);
+        Ok(products)
+    }
+
+    fn list_by_owner(&self, owner: i32) -> Result<Vec<Product>, ApiError> {
+        Ok(self.lock().products.iter().filter(|p| p.user_id == owner).cloned().collect())
+    }
+
+    fn get(&self, id: i32) -> Result<Option<Product>, ApiError> {
+        Ok(self.lock().products.iter().find(|p| p.id == id).cloned())
+    }
+
+    fn find_by_sku(&self, sku: &str) -> Result<Option<Product>, ApiError> {
+        Ok(self.lock().products.iter().find(|p| p.sku.as_deref() == Some(sku)).cloned())
+    }
+
+    fn create(&self, product: NewProduct) -> Result<Product, ApiError> {
+        let mut tables = self.lock();
+        tables.check_product_refs(product.category_id, product.user_id, &product.currency)?;
+        tables.check_product_sku(None, product.sku.as_deref())?;
+        let now = Utc::now().naive_utc();
+        let product = Product {
+            id: Tables::next_id(&tables.products, |p| p.id),
+            sku: product.sku,
+            name: product.name,
+            price: product.price,
+            currency: product.currency,
+            description: product.description,
+            image_srcset: tables.image_srcset(&product.image),
+            image: product.image,
+            video: product.video,
+            published: product.published,
+            category_id: product.category_id,
+            user_id: product.user_id,
+            stock: 0,
+            low_stock_threshold: product.low_stock_threshold,
+            attributes: product.attributes,
+            created_at: now,
+            updated_at: now,
+        };
+        tables.products.push(product.clone());
+        tables.record_price_change(None, &product);
+        Ok(product)
+    }
+
+    fn replace(&self, id: i32, product: NewProduct) -> Result<Option<Product>, ApiError> {
+        let mut tables = self.lock();
+        let Some(before) = tables.products.iter().find(|p| p.id == id).cloned() else {
+            return Ok(None);
+        };
+        tables.check_product_refs(product.category_id, product.user_id, &product.currency)?;
+        tables.check_product_sku(Some(id), product.sku.as_deref())?;
+        let image_srcset = tables.image_srcset(&product.image);
+        let existing = tables.products.iter_mut().find(|p| p.id == id).expect("checked above");
+        existing.sku = product.sku;
+        existing.name = product.name;
+        existing.price = product.price;
+        existing.currency = product.currency;
+        existing.description = product.description;
+        existing.image = product.image;
+        existing.image_srcset = image_srcset;
+        existing.video = product.video;
+        existing.published = product.published;
+        existing.category_id = product.category_id;
+        existing.user_id = product.user_id;
+        existing.low_stock_threshold = product.low_stock_threshold;
+        existing.attributes = product.attributes;
+        existing.updated_at = Utc::now().naive_utc();
+        let after = existing.clone();
+        tables.record_price_change(Some(&before), &after);
+        Ok(Some(after))
+    }
+
+    fn update(&self, id: i32, changes: UpdateProduct) -> Result<Option<Product>, ApiError> {
+        let mut tables = self.lock();
+        let Some(current) = tables.products.iter().find(|p| p.id == id).cloned() else {
+            return Ok(None);
+        };
+        tables.check_product_refs(
+            changes.category_id.unwrap_or(current.category_id),
+            changes.user_id.unwrap_or(current.user_id),
+            changes.currency.as_deref().unwrap_or(&current.currency),
+        )?;
+        tables.check_product_sku(Some(id), changes.sku.as_deref())?;
+        let image_srcset = changes.image.as_ref().map(|image| tables.image_srcset(image));
+        let existing = tables.products.iter_mut().find(|p| p.id == id).expect("checked above");
+        if let Some(sku) = changes.sku {
+            existing.sku = Some(sku);
+        }
+        if let Some(name) = changes.name {
+            existing.name = name;
+        }
+        if let Some(price) = changes.price {
+            existing.price = price;
+        }
+        if let Some(currency) = changes.currency {
+            existing.currency = currency;
+        }
+        if let Some(description) = changes.description {
+            existing.description = description;
+        }
+        if let Some(image) = changes.image {
+            existing.image = image;
+        }
+        if let Some(image_srcset) = image_srcset {
+            existing.image_srcset = image_srcset;
+        }
+        if let Some(video) = changes.video {
+            existing.video = Some(video);
+        }
+        if let Some(published) = changes.published {
+            existing.published = published;
+        }
+        if let Some(category_id) = changes.category_id {
+            existing.category_id = category_id;
+        }
+        if let Some(user_id) = changes.user_id {
+            existing.user_id = user_id;
+        }
+        if let Some(low_stock_threshold) = changes.low_stock_threshold {
+            existing.low_stock_threshold = Some(low_stock_threshold);
+        }
+        if let Some(attributes) = changes.attributes {
+            existing.attributes = attributes;
+        }
+        existing.updated_at = Utc::now().naive_utc();
+        let after = existing.clone();
+        tables.record_price_change(Some(&current), &after);
+        Ok(Some(after))
+    }
+
+    fn delete(&self, id: i32) -> Result<bool, ApiError> {
+        Ok(ProductStore::delete_many(self, &[id])? > 0)
+    }
+
+    fn delete_many(&self, ids: &[i32]) -> Result<usize, ApiError> {
+        let mut tables = self.lock();
+        let before = tables.products.len();
+        tables.products.retain(|p| !ids.contains(&p.id));
+        // ON DELETE CASCADE
+        tables.price_history.retain(|c| !ids.contains(&c.product_id));
+        tables.variants.retain(|v| !ids.contains(&v.product_id));
+        tables.product_tags.retain(|(product_id, _)| !ids.contains(product_id));
+        tables.images.retain(|i| !ids.contains(&i.product_id));
+        tables.video_uploads.retain(|u| !ids.contains(&u.product_id));
+        Ok(before - tables.products.len())
+    }
+
+    fn ids_in_categories(&self, category_ids: &[i32]) -> Result<Vec<i32>, ApiError> {
+        let mut ids: Vec<i32> = self
+            .lock()
+            .products
+            .iter()
+            .filter(|p| category_ids.contains(&p.category_id))
+            .map(|p| p.id)
+            .collect();
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    fn move_category(&self, from: i32, to: i32) -> Result<Vec<i32>, ApiError> {
+        let mut tables = self.lock();
+        if !tables.categories.iter().any(|c| c.id == to) {
+            return Err(conflict("products_category_id_fkey"));
+        }
+        let now = Utc::now().naive_utc();
+        let mut moved = Vec::new();
+        for product in tables.products.iter_mut().filter(|p| p.category_id == from) {
+            product.category_id = to;
+            product.updated_at = now;
+            moved.push(product.id);
+        }
+        moved.sort_unstable();
+        Ok(moved)
+    }
+
+    fn adjust_stock(&self, id: i32, delta: i32) -> Result<Option<Product>, ApiError> {
+        let mut tables = self.lock();
+        let Some(existing) = tables.products.iter_mut().find(|p| p.id == id) else {
+            return Ok(None);
+        };
+        existing.stock = match existing.stock.checked_add(delta) {
+            Some(stock) if stock >= 0 => stock,
+            _ => return Err(ApiError::stock_rejected(existing.stock, delta)),
+        };
+        existing.updated_at = Utc::now().naive_utc();
+        Ok(Some(existing.clone()))
+    }
+
+    fn low_stock(&self) -> Result<Vec<Product>, ApiError> {
+        let mut products: Vec<Product> = self
+            .lock()
+            .products
+            .iter()
+            .filter(|p| p.low_stock_threshold.is_some_and(|threshold| p.stock <= threshold))
+            .cloned()
+            .collect();
+        products.sort_by_key(|p| (p.stock, p.id));
+        Ok(products)
+    }
+
+    fn price_history(&self, id: i32) -> Result<Vec<PriceChange>, ApiError> {
+        let mut history: Vec<PriceChange> = self.lock().price_history.iter().filter(|c| c.product_id == id).cloned().collect();
+        history.sort_by_key(|c| (c.changed_at, c.id));
+        Ok(history)
+    }
+
+    fn avg_price_per_category(&self, owner: i32, currency: &str) -> Result<Vec<(String, Option<Money>)>, ApiError> {
+        let tables = self.lock();
+        let target_rate = tables.target_rate(currency)?;
+        let mut results: Vec<(String, Option<Money>)> = tables
+            .categories
+            .iter()
+            .filter_map(|category| {
+                let prices = tables
+                    .products
+                    .iter()
+                    .filter(|p| p.user_id == owner)
+                    .filter(|p| category_tree::ancestors(&tables.categories, p.category_id).contains(&category.id))
+                    .filter_map(|p| Some(currency::convert(p.price, tables.rate(&p.currency)?, target_rate)));
+                Money::average(prices).map(|avg| (category.name.clone(), Some(avg)))
+            })
+            .collect();
+        results.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        Ok(results)
+    }
+}
+
+impl VariantStore for MemoryStore {
+    fn list(&self, product_id: i32) -> Result<Vec<ProductVariant>, ApiError> {
+        Ok(self.lock().variants.iter().filter(|v| v.product_id == product_id).cloned().collect())
+    }
+
+    fn get(&self, product_id: i32, id: i32) -> Result<Option<ProductVariant>, ApiError> {
+        Ok(self.lock().variants.iter().find(|v| v.product_id == product_id && v.id == id).cloned())
+    }
+
+    fn create(&self, product_id: i32, variant: NewVariant) -> Result<ProductVariant, ApiError> {
+        let mut tables = self.lock();
+        tables.check_variant_refs(product_id, None, &variant.sku)?;
+        let now = Utc::now().naive_utc();
+        let variant = ProductVariant {
+            id: Tables::next_id(&tables.variants, |v| v.id),
+            product_id,
+            sku: variant.sku,
+            price: variant.price,
+            stock: 0,
+            image: variant.image,
+            options: variant.options,
+            created_at: now,
+            updated_at: now,
+        };
+        tables.variants.push(variant.clone());
+        Ok(variant)
+    }
+
+    fn replace(&self, product_id: i32, id: i32, variant: NewVariant) -> Result<Option<ProductVariant>, ApiError> {
+        let mut tables = self.lock();
+        if !tables.variants.iter().any(|v| v.product_id == product_id && v.id == id) {
+            return Ok(None);
+        }
+        tables.check_variant_refs(product_id, Some(id), &variant.sku)?;
+        let existing = tables.variants.iter_mut().find(|v| v.id == id).expect("checked above");
+        existing.sku = variant.sku;
+        existing.price = variant.price;
+        existing.image = variant.image;
+        existing.options = variant.options;
+        existing.updated_at = Utc::now().naive_utc();
+        Ok(Some(existing.clone()))
+    }
+
+    fn delete(&self, product_id: i32, id: i32) -> Result<bool, ApiError> {
+        let mut tables = self.lock();
+        let before = tables.variants.len();
+        tables.variants.retain(|v| !(v.product_id == product_id && v.id == id));
+        Ok(tables.variants.len() < before)
+    }
+
+    fn adjust_stock(&self, product_id: i32, id: i32, delta: i32) -> Result<Option<ProductVariant>, ApiError> {
+        let mut tables = self.lock();
+        let Some(existing) = tables.variants.iter_mut().find(|v| v.product_id == product_id && v.id == id) else {
+            return Ok(None);
+        };
+        existing.stock = match existing.stock.checked_add(delta) {
+            Some(stock) if stock >= 0 => stock,
+            _ => return Err(ApiError::stock_rejected(existing.stock, delta)),
+        };
+        existing.updated_at = Utc::now().naive_utc();
+        Ok(Some(existing.clone()))
+    }
+}
+
+impl ImageStore for MemoryStore {
+    fn list(&self, product_id: i32) -> Result<Vec<ProductImage>, ApiError> {
+        Ok(self.lock().images.iter().filter(|i| i.product_id == product_id).cloned().collect())
+    }
+
+    fn get(&self, product_id: i32, id: i32) -> Result<Option<ProductImage>, ApiError> {
+        Ok(self.lock().images.iter().find(|i| i.product_id == product_id && i.id == id).cloned())
+    }
+
+    fn create(&self, product_id: i32, image: NewImage) -> Result<ProductImage, ApiError> {
+        let mut tables = self.lock();
+        // Mirrors the foreign key and the unique (product_id, key) on product_images
+        if !tables.products.iter().any(|p| p.id == product_id) {
+            return Err(conflict("product_images_product_id_fkey"));
+        }
+        if tables.images.iter().any(|i| i.product_id == product_id && i.key == image.key) {
+            return Err(ApiError::Conflict {
+                message: "Resource already exists".to_string(),
+                details: json!({ "constraint": "product_images_product_id_key_key" }),
+            });
+        }
+        let image = ProductImage {
+            id: Tables::next_id(&tables.images, |i| i.id),
+            product_id,
+            key: image.key,
+            url: image.url,
+            content_type: image.content_type,
+            size_bytes: image.size_bytes,
+            width: None,
+            height: None,
+            renditions_status: "pending".to_string(),
+            srcset: None,
+            created_at: Utc::now().naive_utc(),
+        };
+        tables.images.push(image.clone());
+        Ok(image)
+    }
+
+    fn delete(&self, product_id: i32, id: i32) -> Result<bool, ApiError> {
+        let mut tables = self.lock();
+        let before = tables.images.len();
+        tables.images.retain(|i| !(i.product_id == product_id && i.id == id));
+        Ok(tables.images.len() < before)
+    }
+
+    fn key_in_use(&self, key: &str) -> Result<bool, ApiError> {
+        Ok(self.lock().images.iter().any(|i| i.key == key))
+    }
+
+    fn pending_renditions(&self, limit: i64) -> Result<Vec<ProductImage>, ApiError> {
+        let tables = self.lock();
+        let pending = tables.images.iter().filter(|i| i.renditions_status == "pending");
+        Ok(pending.take(limit.max(0) as usize).cloned().collect())
+    }
+
+    fn finish_renditions(&self, id: i32, width: i32, height: i32, srcset: serde_json::Value) -> Result<bool, ApiError> {
+        let mut tables = self.lock();
+        let Some(image) = tables.images.iter_mut().find(|i| i.id == id) else {
+            return Ok(false);
+        };
+        image.width = Some(width);
+        image.height = Some(height);
+        image.renditions_status = "ready".to_string();
+        image.srcset = Some(srcset.clone());
+        let url = image.url.clone();
+        for product in tables.products.iter_mut().filter(|p| p.image == url) {
+            product.image_srcset = Some(srcset.clone());
+        }
+        Ok(true)
+    }
+
+    fn fail_renditions(&self, id: i32) -> Result<bool, ApiError> {
+        let mut tables = self.lock();
+        let Some(image) = tables.images.iter_mut().find(|i| i.id == id) else {
+            return Ok(false);
+        };
+        image.renditions_status = "failed".to_string();
+        Ok(true)
+    }
+}
+
+impl VideoUploadStore for MemoryStore {
+    fn get(&self, id: &str) -> Result<Option<VideoUpload>, ApiError> {
+        Ok(self.lock().video_uploads.iter().find(|u| u.id == id).cloned())
+    }
+
+    // Every call holds the one lock anyway
+    fn lock(&self, id: &str) -> Result<Option<VideoUpload>, ApiError> {
+        VideoUploadStore::get(self, id)
+    }
+
+    fn create(&self, upload: NewVideoUpload) -> Result<VideoUpload, ApiError> {
+        let mut tables = self.lock();
+        // Mirrors the foreign key on video_uploads
+        if !tables.products.iter().any(|p| p.id == upload.product_id) {
+            return Err(conflict("video_uploads_product_id_fkey"));
+        }
+        let now = Utc::now().naive_utc();
+        let upload = VideoUpload {
+            id: upload.id,
+            product_id: upload.product_id,
+            size_bytes: upload.size_bytes,
+            received_bytes: 0,
+            sha256: upload.sha256,
+            video: None,
+            created_at: now,
+            updated_at: now,
+        };
+        tables.video_uploads.push(upload.clone());
+        Ok(upload)
+    }
+
+    fn set_received(&self, id: &str, received_bytes: i64) -> Result<Option<VideoUpload>, ApiError> {
+        let mut tables = self.lock();
+        let Some(upload) = tables.video_uploads.iter_mut().find(|u| u.id == id) else {
+            return Ok(None);
+        };
+        // Mirrors video_uploads_received_bytes_check
+        if !(0..=upload.size_bytes).contains(&received_bytes) {
+            return Err(ApiError::Conflict {
+                message: "Operation violates a data constraint".to_string(),
+                details: json!({ "constraint": "video_uploads_received_bytes_check" }),
+            });
+        }
+        upload.received_bytes = received_bytes;
+        upload.updated_at = Utc::now().naive_utc();
+        Ok(Some(upload.clone()))
+    }
+
+    fn complete(&self, id: &str, video: &str) -> Result<Option<VideoUpload>, ApiError> {
+        let mut tables = self.lock();
+        let Some(upload) = tables.video_uploads.iter_mut().find(|u| u.id == id) else {
+            return Ok(None);
+        };
+        upload.video = Some(video.to_string());
+        upload.updated_at = Utc::now().naive_utc();
+        Ok(Some(upload.clone()))
+    }
+
+    fn delete(&self, id: &str) -> Result<bool, ApiError> {
+        let mut tables = self.lock();
+        let before = tables.video_uploads.len();
+        tables.video_uploads.retain(|u| u.id != id);
+        Ok(tables.video_uploads.len() < before)
+    }
+
+    fn delete_stale(&self, cutoff: NaiveDateTime) -> Result<Vec<VideoUpload>, ApiError> {
+        let mut tables = self.lock();
+        let (stale, kept) = tables.video_uploads.drain(..).partition(|u| u.updated_at < cutoff);
+        tables.video_uploads = kept;
+        Ok(stale)
+    }
+}
+
+impl ImportJobStore for MemoryStore {
+    fn get(&self, id: &str) -> Result<Option<ImportJob>, ApiError> {
+        Ok(self.lock().import_job(id).map(|(job, _)| job.clone()))
+    }
+
+    fn source(&self, id: &str) -> Result<Option<String>, ApiError> {
+        Ok(self.lock().import_job(id).and_then(|(_, source)| source.clone()))
+    }
+
+    fn create(&self, job: NewImportJob) -> Result<ImportJob, ApiError> {
+        let mut tables = self.lock();
+        // Mirrors the foreign key on import_jobs
+        if !tables.users.iter().any(|u| u.id == job.user_id) {
+            return Err(conflict("import_jobs_user_id_fkey"));
+        }
+        let now = Utc::now().naive_utc();
+        let created = ImportJob {
+            id: job.id,
+            user_id: job.user_id,
+            format: job.format,
+            dry_run: job.dry_run,
+            upsert: job.upsert,
+            create_categories: job.create_categories,
+            status: job.status,
+            total_rows: job.total_rows,
+            processed_rows: 0,
+            created_rows: 0,
+            updated_rows: 0,
+            failed_rows: 0,
+            row_errors: json!([]),
+            error: None,
+            created_at: now,
+            updated_at: now,
+        };
+        tables.import_jobs.push((created.clone(), Some(job.source)));
+        Ok(created)
+    }
+
+    fn claim(&self, stale_before: NaiveDateTime) -> Result<Option<ImportJob>, ApiError> {
+        let mut tables = self.lock();
+        let claimable = |job: &ImportJob| job.status == "pending" || (job.status == "running" && job.updated_at < stale_before);
+        let Some((job, _)) = tables.import_jobs.iter_mut().filter(|(job, _)| claimable(job)).min_by_key(|(job, _)| job.created_at) else {
+            return Ok(None);
+        };
+        job.status = "running".to_string();
+        job.updated_at = Utc::now().naive_utc();
+        Ok(Some(job.clone()))
+    }
+
+    fn save_progress(&self, id: &str, progress: ImportProgress) -> Result<Option<ImportJob>, ApiError> {
+        let mut tables = self.lock();
+        let Some((job, _)) = tables.import_job(id) else {
+            return Ok(None);
+        };
+        job.processed_rows = progress.processed_rows;
+        job.created_rows = progress.created_rows;
+        job.updated_rows = progress.updated_rows;
+        job.failed_rows = progress.failed_rows;
+        job.row_errors = progress.row_errors;
+        job.updated_at = Utc::now().naive_utc();
+        Ok(Some(job.clone()))
+    }
+
+    fn finish(&self, id: &str, error: Option<&str>) -> Result<Option<ImportJob>, ApiError> {
+        let mut tables = self.lock();
+        let Some((job, source)) = tables.import_job(id) else {
+            return Ok(None);
+        };
+        job.status = if error.is_some() { "failed" } else { "finished" }.to_string();
+        job.error = error.map(str::to_string);
+        job.updated_at = Utc::now().naive_utc();
+        *source = None;
+        Ok(Some(job.clone()))
+    }
+}
+
+impl AttributeStore for MemoryStore {
+    fn list(&self, category_ids: &[i32]) -> Result<Vec<CategoryAttribute>, ApiError> {
+        let mut attributes: Vec<CategoryAttribute> =
+            self.lock().attributes.iter().filter(|a| category_ids.contains(&a.category_id)).cloned().collect();
+        attributes.sort_by(|a, b| a.name.cmp(&b.name).then(a.category_id.cmp(&b.category_id)));
+        Ok(attributes)
+    }
+
+    fn get(&self, category_id: i32, id: i32) -> Result<Option<CategoryAttribute>, ApiError> {
+        Ok(self.lock().attributes.iter().find(|a| a.category_id == category_id && a.id == id).cloned())
+    }
+
+    fn create(&self, category_id: i32, attribute: NewAttribute) -> Result<CategoryAttribute, ApiError> {
+        let mut tables = self.lock();
+        tables.check_attribute_refs(category_id, None, &attribute.name)?;
+        let now = Utc::now().naive_utc();
+        let attribute = CategoryAttribute {
+            id: Tables::next_id(&tables.attributes, |a| a.id),
+            category_id,
+            name: attribute.name,
+            kind: attribute.kind,
+            required: attribute.required,
+            allowed_values: attribute.allowed_values,
+            created_at: now,
+            updated_at: now,
+        };
+        tables.attributes.push(attribute.clone());
+        Ok(attribute)
+    }
+
+    fn replace(&self, category_id: i32, id: i32, attribute: NewAttribute) -> Result<Option<CategoryAttribute>, ApiError> {
+        let mut tables = self.lock();
+        if !tables.attributes.iter().any(|a| a.category_id == category_id && a.id == id) {
+            return Ok(None);
+        }
+        tables.check_attribute_refs(category_id, Some(id), &attribute.name)?;
+        let existing = tables.attributes.iter_mut().find(|a| a.id == id).expect("checked above");
+        existing.name = attribute.name;
+        existing.kind = attribute.kind;
+        existing.required = attribute.required;
+        existing.allowed_values = attribute.allowed_values;
+        existing.updated_at = Utc::now().naive_utc();
+        Ok(Some(existing.clone()))
+    }
+
+    fn delete(&self, category_id: i32, id: i32) -> Result<bool, ApiError> {
+        let mut tables = self.lock();
+        let before = tables.attributes.len();
+        tables.attributes.retain(|a| !(a.category_id == category_id && a.id == id));
+        Ok(tables.attributes.len() < before)
+    }
+}
+
+impl TagStore for MemoryStore {
+    fn list(&self) -> Result<Vec<TagWithCount>, ApiError> {
+        let tables = self.lock();
+        let mut tags: Vec<TagWithCount> = tables
+            .tags
+            .iter()
+            .map(|t| TagWithCount {
+                id: t.id,
+                name: t.name.clone(),
+                product_count: tables.product_tags.iter().filter(|(_, tag_id)| *tag_id == t.id).count() as i64,
+            })
+            .collect();
+        tags.sort_by(|a, b| b.product_count.cmp(&a.product_count).then(a.name.cmp(&b.name)));
+        Ok(tags)
+    }
+
+    fn get(&self, id: i32) -> Result<Option<Tag>, ApiError> {
+        Ok(self.lock().tags.iter().find(|t| t.id == id).cloned())
+    }
+
+    fn find_by_name(&self, name: &str) -> Result<Option<Tag>, ApiError> {
+        Ok(self.lock().tags.iter().find(|t| t.name.to_lowercase() == name.to_lowercase()).cloned())
+    }
+
+    fn create(&self, name: &str) -> Result<Tag, ApiError> {
+        let mut tables = self.lock();
+        tables.check_tag_unique(None, name)?;
+        let tag = Tag {
+            id: Tables::next_id(&tables.tags, |t| t.id),
+            name: name.to_string(),
+            created_at: Utc::now().naive_utc(),
+        };
+        tables.tags.push(tag.clone());
+        Ok(tag)
+    }
+
+    fn rename(&self, id: i32, name: &str) -> Result<Option<Tag>, ApiError> {
+        let mut tables = self.lock();
+        if !tables.tags.iter().any(|t| t.id == id) {
+            return Ok(None);
+        }
+        tables.check_tag_unique(Some(id), name)?;
+        let existing = tables.tags.iter_mut().find(|t| t.id == id).expect("checked above");
+        existing.name = name.to_string();
+        Ok(Some(existing.clone()))
+    }
+
+    fn delete(&self, id: i32) -> Result<bool, ApiError> {
+        let mut tables = self.lock();
+        let before = tables.tags.len();
+        tables.tags.retain(|t| t.id != id);
+        // ON DELETE CASCADE
+        tables.product_tags.retain(|(_, tag_id)| *tag_id != id);
+        Ok(tables.tags.len() < before)
+    }
+
+    fn for_product(&self, product_id: i32) -> Result<Vec<Tag>, ApiError> {
+        let tables = self.lock();
+        let mut tags: Vec<Tag> =
+            tables.tags.iter().filter(|t| tables.product_tags.contains(&(product_id, t.id))).cloned().collect();
+        tags.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(tags)
+    }
+
+    fn set_for_product(&self, product_id: i32, tag_ids: &[i32]) -> Result<(), ApiError> {
+        let mut tables = self.lock();
+        // Mirrors the foreign keys on product_tags
+        if !tables.products.iter().any(|p| p.id == product_id) {
+            return Err(conflict("product_tags_product_id_fkey"));
+        }
+        if !tag_ids.iter().all(|id| tables.tags.iter().any(|t| t.id == *id)) {
+            return Err(conflict("product_tags_tag_id_fkey"));
+        }
+        tables.product_tags.retain(|(id, _)| *id != product_id);
+        tables.product_tags.extend(tag_ids.iter().map(|tag_id| (product_id, *tag_id)));
+        Ok(())
+    }
+}
+
+impl CategoryStore for MemoryStore {
+    fn list(&self) -> Result<Vec<Category>, ApiError> {
+        let mut categories = self.lock().categories.clone();
+        categories.sort_by(|a, b| a.display_order.cmp(&b.display_order).then_with(|| a.name.cmp(&b.name)));
+        Ok(categories)
+    }
+
+    fn list_with_stats(&self, currency: &str) -> Result<Vec<CategoryWithStats>, ApiError> {
+        let categories = CategoryStore::list(self)?;
+        let tables = self.lock();
+        let target_rate = tables.rate(currency);
+        Ok(categories
+            .into_iter()
+            .map(|category| {
+                let products: Vec<&Product> = tables
+                    .products
+                    .iter()
+                    .filter(|p| category_tree::ancestors(&tables.categories, p.category_id).contains(&category.id))
+                    .collect();
+                let prices: Vec<Money> = products
+                    .iter()
+                    .filter_map(|p| Some(currency::convert(p.price, tables.rate(&p.currency)?, target_rate?)))
+                    .collect();
+                CategoryWithStats {
+                    category,
+                    product_count: products.len() as i64,
+                    min_price: prices.iter().min().copied(),
+                    max_price: prices.iter().max().copied(),
+                    currency: currency.to_string(),
+                }
+            })
+            .collect())
+    }
+
+    fn get(&self, id: i32) -> Result<Option<Category>, ApiError> {
+        Ok(self.lock().categories.iter().find(|c| c.id == id).cloned())
+    }
+
+    fn get_by_slug(&self, slug: &str) -> Result<Option<Category>, ApiError> {
+        Ok(self.lock().categories.iter().find(|c| c.slug == slug).cloned())
+    }
+
+    fn find_by_name(&self, name: &str) -> Result<Option<Category>, ApiError> {
+        Ok(self.lock().categories.iter().find(|c| c.name.to_lowercase() == name.to_lowercase()).cloned())
+    }
+
+    fn create(&self, category: NewCategory) -> Result<Category, ApiError> {
+        let mut tables = self.lock();
+        tables.check_category_unique(None, &category.name, &category.slug)?;
+        tables.check_category_parent(None, category.parent_id)?;
+        let now = Utc::now().naive_utc();
+        let category = Category {
+            id: Tables::next_id(&tables.categories, |c| c.id),
+            name: category.name,
+            slug: category.slug,
+            description: category.description,
+            parent_id: category.parent_id,
+            image: category.image,
+            display_order: category.display_order,
+            created_at: now,
+            updated_at: now,
+        };
+        tables.categories.push(category.clone());
+        Ok(category)
+    }
+
+    fn replace(&self, id: i32, category: NewCategory) -> Result<Option<Category>, ApiError> {
+        let mut tables = self.lock();
+        if !tables.categories.iter().any(|c| c.id == id) {
+            return Ok(None);
+        }
+        tables.check_category_unique(Some(id), &category.name, &category.slug)?;
+        tables.check_category_parent(Some(id), category.parent_id)?;
+        let existing = tables.categories.iter_mut().find(|c| c.id == id).expect("checked above");
+        existing.name = category.name;
+        existing.slug = category.slug;
+        existing.description = category.description;
+        existing.parent_id = category.parent_id;
+        existing.image = category.image;
+        existing.display_order = category.display_order;
+        existing.updated_at = Utc::now().naive_utc();
+        Ok(Some(existing.clone()))
+    }
+
+    fn update(&self, id: i32, changes: UpdateCategory) -> Result<Option<Category>, ApiError> {
+        let mut tables = self.lock();
+        if !tables.categories.iter().any(|c| c.id == id) {
+            return Ok(None);
+        }
+        let current = tables.categories.iter().find(|c| c.id == id).expect("checked above");
+        let name = changes.name.as_deref().unwrap_or(&current.name).to_string();
+        let slug = changes.slug.as_deref().unwrap_or(&current.slug).to_string();
+        tables.check_category_unique(Some(id), &name, &slug)?;
+        tables.check_category_parent(Some(id), changes.parent_id)?;
+        let existing = tables.categories.iter_mut().find(|c| c.id == id).expect("checked above");
+        if let Some(name) = changes.name {
+            existing.name = name;
+        }
+        if let Some(description) = changes.description {
+            existing.description = description;
+        }
+        if let Some(parent_id) = changes.parent_id {
+            existing.parent_id = Some(parent_id);
+        }
+        if let Some(slug) = changes.slug {
+            existing.slug = slug;
+        }
+        if let Some(image) = changes.image {
+            existing.image = Some(image);
+        }
+        if let Some(display_order) = changes.display_order {
+            existing.display_order = display_order;
+        }
+        existing.updated_at = Utc::now().naive_utc();
+        Ok(Some(existing.clone()))
+    }
+
+    fn delete(&self, id: i32) -> Result<bool, ApiError> {
+        Ok(CategoryStore::delete_many(self, &[id])? > 0)
+    }
+
+    fn delete_many(&self, ids: &[i32]) -> Result<usize, ApiError> {
+        let mut tables = self.lock();
+        if tables.products.iter().any(|p| ids.contains(&p.category_id)) {
+            return Err(conflict("products_category_id_fkey"));
+        }
+        let orphaned = |c: &Category| !ids.contains(&c.id) && c.parent_id.is_some_and(|p| ids.contains(&p));
+        if tables.categories.iter().any(orphaned) {
+            return Err(conflict("categories_parent_id_fkey"));
+        }
+        let before = tables.categories.len();
+        tables.categories.retain(|c| !ids.contains(&c.id));
+        // ON DELETE CASCADE
+        tables.attributes.retain(|a| !ids.contains(&a.category_id));
+        Ok(before - tables.categories.len())
+    }
+
+    fn ancestors(&self, id: i32) -> Result<Vec<i32>, ApiError> {
+        let tables = self.lock();
+        if !tables.categories.iter().any(|c| c.id == id) {
+            return Ok(Vec::new());
+        }
+        Ok(category_tree::ancestors(&tables.categories, id))
+    }
+
+    fn subtree(&self, id: i32) -> Result<Vec<i32>, ApiError> {
+        let tables = self.lock();
+        let mut ids: Vec<i32> = tables
+            .categories
+            .iter()
+            .filter(|c| category_tree::ancestors(&tables.categories, c.id).contains(&id))
+            .map(|c| c.id)
+            .collect();
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    fn move_children(&self, from: i32, to: i32) -> Result<Vec<i32>, ApiError> {
+        let mut tables = self.lock();
+        let children: Vec<i32> = tables.categories.iter().filter(|c| c.parent_id == Some(from)).map(|c| c.id).collect();
+        for &child in &children {
+            tables.check_category_parent(Some(child), Some(to))?;
+        }
+        let now = Utc::now().naive_utc();
+        for category in tables.categories.iter_mut().filter(|c| c.parent_id == Some(from)) {
+            category.parent_id = Some(to);
+            category.updated_at = now;
+        }
+        let mut moved = children;
+        moved.sort_unstable();
+        Ok(moved)
+    }
+}
+
+impl ExchangeRateStore for MemoryStore {
+    fn list(&self) -> Result<Vec<ExchangeRate>, ApiError> {
+        let mut exchange_rates = self.lock().exchange_rates.clone();
+        exchange_rates.sort_by(|a, b| a.currency.cmp(&b.currency));
+        Ok(exchange_rates)
+    }
+
+    fn get(&self, currency: &str) -> Result<Option<ExchangeRate>, ApiError> {
+        Ok(self.lock().exchange_rates.iter().find(|r| r.currency == currency).cloned())
+    }
+
+    fn set(&self, currency: &str, rate: Rate) -> Result<ExchangeRate, ApiError> {
+        let mut tables = self.lock();
+        let exchange_rate = ExchangeRate { currency: currency.to_string(), rate, updated_at: Utc::now().naive_utc() };
+        match tables.exchange_rates.iter_mut().find(|r| r.currency == currency) {
+            Some(existing) => *existing = exchange_rate.clone(),
+            None => tables.exchange_rates.push(exchange_rate.clone()),
+        }
+        Ok(exchange_rate)
+    }
+
+    fn delete(&self, currency: &str) -> Result<bool, ApiError> {
+        let mut tables = self.lock();
+        if tables.products.iter().any(|p| p.currency == currency) {
+            return Err(conflict("products_currency_fkey"));
+        }
+        let before = tables.exchange_rates.len();
+        tables.exchange_rates.retain(|r| r.currency != currency);
+        Ok(tables.exchange_rates.len() < before)
+    }
+}
+
+impl UserStore for MemoryStore {
+    fn get(&self, id: i32) -> Result<Option<User>, ApiError> {
+        Ok(self.lock().users.iter().find(|u| u.id == id).cloned())
+    }
+
+    fn find_by_username(&self, username: &str) -> Result<Option<User>, ApiError> {
+        Ok(self.lock().users.iter().find(|u| u.username == username).cloned())
+    }
+
+    fn create(&self, user: NewUser) -> Result<User, ApiError> {
+        let mut tables = self.lock();
+        if tables.users.iter().any(|u| u.username == user.username) {
+            return Err(ApiError::Conflict {
+                message: "Resource already exists".to_string(),
+                details: json!({ "constraint": "users_username_key" }),
+            });
+        }
+        let user = User {
+            id: Tables::next_id(&tables.users, |u| u.id),
+            username: user.username,
+            password: user.password,
+            role: user.role,
+        };
+        tables.users.push(user.clone());
+        Ok(user)
+    }
+}
+
+impl AuditStore for MemoryStore {
+    fn log(&self, entry: NewLog) -> Result<Log, ApiError> {
+        let mut tables = self.lock();
+        let log = Log {
+            id: Tables::next_id(&tables.logs, |l| l.id),
+            user_id: entry.user_id,
+            action: entry.action,
+            entity: entry.entity,
+            entity_id: entry.entity_id,
+            timestamp: entry.timestamp,
+            reason: entry.reason,
+        };
+        tables.logs.push(log.clone());
+        Ok(log)
+    }
+
+    fn logs_since(&self, since: NaiveDateTime) -> Result<Vec<Log>, ApiError> {
+        Ok(self.lock().logs.iter().filter(|l| l.timestamp >= since).cloned().collect())
+    }
+
+    fn count_since(&self, user_id: i32, since: NaiveDateTime) -> Result<i64, ApiError> {
+        let tables = self.lock();
+        Ok(tables.logs.iter().filter(|l| l.user_id == user_id && l.timestamp >= since).count() as i64)
+    }
+
+    fn monitored_users(&self) -> Result<Vec<MonitoredUser>, ApiError> {
+        Ok(self.lock().monitored_users.clone())
+    }
+
+    fn add_monitored_user(&self, user_id: i32, username: &str) -> Result<(), ApiError> {
+        let mut tables = self.lock();
+        if !tables.monitored_users.iter().any(|m| m.user_id == user_id) {
+            tables.monitored_users.push(MonitoredUser { user_id, username: username.to_string() });
+        }
+        Ok(())
+    }
+}
+
+impl Transactional for MemoryStore {
+    // Rollback restores a snapshot, so it also undoes writes that other
+    // requests made meanwhile; good enough for tests, not for serving traffic
+    fn transaction(&self, state: &AppState, body: &mut dyn FnMut(&AppState) -> Result<(), ApiError>) -> Result<(), ApiError> {
+        let snapshot = self.lock().clone();
+        let result = body(state);
+        if result.is_err() {
+            *self.lock() = snapshot;
+        }
+        result
+    }
+}
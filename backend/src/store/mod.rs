@@ -0,0 +1,217 @@
+use chrono::NaiveDateTime;
+
+use crate::currency::Rate;
+use crate::db::models::{
+    Category, CategoryAttribute, ExchangeRate, ImportJob, ImportProgress, Log, MonitoredUser, NewAttribute, NewCategory, NewImage, NewImportJob,
+    NewLog, NewProduct, NewUser, NewVariant, NewVideoUpload, PriceChange, Product, ProductImage, ProductVariant, Tag, UpdateCategory,
+    UpdateProduct, User, VideoUpload,
+};
+use crate::db::repository::{CategoryWithStats, ProductWithCategory, TagWithCount};
+use crate::errors::ApiError;
+use crate::models::ProductQuery;
+use crate::money::Money;
+use crate::AppState;
+
+pub mod memory;
+pub mod postgres;
+
+pub use memory::MemoryStore;
+pub use postgres::PgStore;
+
+// Storage behind the handlers. Methods are blocking; handlers call them through AppState::db.
+// Lookups return Ok(None) for a missing row so callers decide how to report it.
+
+pub trait ProductStore: Send + Sync {
+    // Products joined with their category name, optionally restricted to one owner.
+    // A currency in the query must have a rate; prices come back converted to it.
+    fn list(&self, owner: Option<i32>, query: &ProductQuery) -> Result<Vec<ProductWithCategory>, ApiError>;
+    fn list_by_owner(&self, owner: i32) -> Result<Vec<Product>, ApiError>;
+    fn get(&self, id: i32) -> Result<Option<Product>, ApiError>;
+    fn find_by_sku(&self, sku: &str) -> Result<Option<Product>, ApiError>;
+    fn create(&self, product: NewProduct) -> Result<Product, ApiError>;
+    fn replace(&self, id: i32, product: NewProduct) -> Result<Option<Product>, ApiError>;
+    fn update(&self, id: i32, changes: UpdateProduct) -> Result<Option<Product>, ApiError>;
+    fn delete(&self, id: i32) -> Result<bool, ApiError>;
+    fn delete_many(&self, ids: &[i32]) -> Result<usize, ApiError>;
+    // Ids of the products in any of these categories, ascending
+    fn ids_in_categories(&self, category_ids: &[i32]) -> Result<Vec<i32>, ApiError>;
+    // Moves every product of category `from` to `to`; returns the moved ids, ascending
+    fn move_category(&self, from: i32, to: i32) -> Result<Vec<i32>, ApiError>;
+    // Adds `delta` to the stock atomically; a conflict if it would drop below zero
+    fn adjust_stock(&self, id: i32, delta: i32) -> Result<Option<Product>, ApiError>;
+    // Products at or below their low-stock threshold, emptiest first
+    fn low_stock(&self) -> Result<Vec<Product>, ApiError>;
+    // Every recorded price of one product, oldest first. Writes above record
+    // their own entries, so callers never add to it directly.
+    fn price_history(&self, id: i32) -> Result<Vec<PriceChange>, ApiError>;
+    // (category name, average price in `currency`) for one owner, highest average
+    // first. A category's average includes the products of its subcategories.
+    fn avg_price_per_category(&self, owner: i32, currency: &str) -> Result<Vec<(String, Option<Money>)>, ApiError>;
+}
+
+// Variants are always addressed through their product; one under another
+// product is as missing as one that does not exist
+pub trait VariantStore: Send + Sync {
+    // Oldest first
+    fn list(&self, product_id: i32) -> Result<Vec<ProductVariant>, ApiError>;
+    fn get(&self, product_id: i32, id: i32) -> Result<Option<ProductVariant>, ApiError>;
+    // Options and values the product does not have yet are added to it
+    fn create(&self, product_id: i32, variant: NewVariant) -> Result<ProductVariant, ApiError>;
+    fn replace(&self, product_id: i32, id: i32, variant: NewVariant) -> Result<Option<ProductVariant>, ApiError>;
+    fn delete(&self, product_id: i32, id: i32) -> Result<bool, ApiError>;
+    // Same contract as ProductStore::adjust_stock
+    fn adjust_stock(&self, product_id: i32, id: i32, delta: i32) -> Result<Option<ProductVariant>, ApiError>;
+}
+
+// Like variants, images are addressed through their product
+pub trait ImageStore: Send + Sync {
+    // Oldest first
+    fn list(&self, product_id: i32) -> Result<Vec<ProductImage>, ApiError>;
+    fn get(&self, product_id: i32, id: i32) -> Result<Option<ProductImage>, ApiError>;
+    fn create(&self, product_id: i32, image: NewImage) -> Result<ProductImage, ApiError>;
+    fn delete(&self, product_id: i32, id: i32) -> Result<bool, ApiError>;
+    // Whether any product still has an image stored under `key`
+    fn key_in_use(&self, key: &str) -> Result<bool, ApiError>;
+    // Images the rendition job has not got to yet, oldest first
+    fn pending_renditions(&self, limit: i64) -> Result<Vec<ProductImage>, ApiError>;
+    // Marks the renditions ready; products whose main image this is show the srcset from now on
+    fn finish_renditions(&self, id: i32, width: i32, height: i32, srcset: serde_json::Value) -> Result<bool, ApiError>;
+    fn fail_renditions(&self, id: i32) -> Result<bool, ApiError>;
+}
+
+// Resumable video uploads, addressed by their random id alone
+pub trait VideoUploadStore: Send + Sync {
+    fn get(&self, id: &str) -> Result<Option<VideoUpload>, ApiError>;
+    // Like get, but inside a transaction the row stays locked until it ends, so
+    // the chunks of one upload are written one at a time
+    fn lock(&self, id: &str) -> Result<Option<VideoUpload>, ApiError>;
+    fn create(&self, upload: NewVideoUpload) -> Result<VideoUpload, ApiError>;
+    fn set_received(&self, id: &str, received_bytes: i64) -> Result<Option<VideoUpload>, ApiError>;
+    fn complete(&self, id: &str, video: &str) -> Result<Option<VideoUpload>, ApiError>;
+    fn delete(&self, id: &str) -> Result<bool, ApiError>;
+    // Removes the uploads last touched before `cutoff`, finished or not, and returns them
+    fn delete_stale(&self, cutoff: NaiveDateTime) -> Result<Vec<VideoUpload>, ApiError>;
+}
+
+// Bulk product imports, addressed by their random id alone
+pub trait ImportJobStore: Send + Sync {
+    fn get(&self, id: &str) -> Result<Option<ImportJob>, ApiError>;
+    // The uploaded file; None once the job has ended
+    fn source(&self, id: &str) -> Result<Option<String>, ApiError>;
+    fn create(&self, job: NewImportJob) -> Result<ImportJob, ApiError>;
+    // Marks the oldest pending job running and returns it. A running job last
+    // touched before `stale_before` counts as pending: whoever ran it has died.
+    fn claim(&self, stale_before: NaiveDateTime) -> Result<Option<ImportJob>, ApiError>;
+    fn save_progress(&self, id: &str, progress: ImportProgress) -> Result<Option<ImportJob>, ApiError>;
+    // Finished without `error`, failed with it; either way the file is dropped
+    fn finish(&self, id: &str, error: Option<&str>) -> Result<Option<ImportJob>, ApiError>;
+}
+
+pub trait AttributeStore: Send + Sync {
+    // Definitions of all these categories, by name; see attributes::effective
+    fn list(&self, category_ids: &[i32]) -> Result<Vec<CategoryAttribute>, ApiError>;
+    fn get(&self, category_id: i32, id: i32) -> Result<Option<CategoryAttribute>, ApiError>;
+    fn create(&self, category_id: i32, attribute: NewAttribute) -> Result<CategoryAttribute, ApiError>;
+    fn replace(&self, category_id: i32, id: i32, attribute: NewAttribute) -> Result<Option<CategoryAttribute>, ApiError>;
+    fn delete(&self, category_id: i32, id: i32) -> Result<bool, ApiError>;
+}
+
+// Names are unique case-insensitively, and looked up that way
+pub trait TagStore: Send + Sync {
+    // Every tag with the number of products carrying it, most used first, then by name
+    fn list(&self) -> Result<Vec<TagWithCount>, ApiError>;
+    fn get(&self, id: i32) -> Result<Option<Tag>, ApiError>;
+    fn find_by_name(&self, name: &str) -> Result<Option<Tag>, ApiError>;
+    fn create(&self, name: &str) -> Result<Tag, ApiError>;
+    fn rename(&self, id: i32, name: &str) -> Result<Option<Tag>, ApiError>;
+    // Also takes it off every product
+    fn delete(&self, id: i32) -> Result<bool, ApiError>;
+    // The product's tags, by name
+    fn for_product(&self, product_id: i32) -> Result<Vec<Tag>, ApiError>;
+    // Replaces the product's tags with exactly these
+    fn set_for_product(&self, product_id: i32, tag_ids: &[i32]) -> Result<(), ApiError>;
+}
+
+pub trait CategoryStore: Send + Sync {
+    // By display_order, then name
+    fn list(&self) -> Result<Vec<Category>, ApiError>;
+    // list() with product counts and price ranges in `currency`, subcategories included
+    fn list_with_stats(&self, currency: &str) -> Result<Vec<CategoryWithStats>, ApiError>;
+    fn get(&self, id: i32) -> Result<Option<Category>, ApiError>;
+    fn get_by_slug(&self, slug: &str) -> Result<Option<Category>, ApiError>;
+    // Names are unique ignoring case, and looked up that way
+    fn find_by_name(&self, name: &str) -> Result<Option<Category>, ApiError>;
+    fn create(&self, category: NewCategory) -> Result<Category, ApiError>;
+    fn replace(&self, id: i32, category: NewCategory) -> Result<Option<Category>, ApiError>;
+    fn update(&self, id: i32, changes: UpdateCategory) -> Result<Option<Category>, ApiError>;
+    fn delete(&self, id: i32) -> Result<bool, ApiError>;
+    // In one statement, so a parent can go together with its subcategories
+    fn delete_many(&self, ids: &[i32]) -> Result<usize, ApiError>;
+    // `id` and every category above it; empty if there is no such category
+    fn ancestors(&self, id: i32) -> Result<Vec<i32>, ApiError>;
+    // `id` and every category below it, ascending; empty if there is no such category
+    fn subtree(&self, id: i32) -> Result<Vec<i32>, ApiError>;
+    // Moves the direct subcategories of `from` under `to`; returns their ids, ascending
+    fn move_children(&self, from: i32, to: i32) -> Result<Vec<i32>, ApiError>;
+}
+
+pub trait ExchangeRateStore: Send + Sync {
+    fn list(&self) -> Result<Vec<ExchangeRate>, ApiError>;
+    fn get(&self, currency: &str) -> Result<Option<ExchangeRate>, ApiError>;
+    // Inserts the rate or replaces the current one
+    fn set(&self, currency: &str, rate: Rate) -> Result<ExchangeRate, ApiError>;
+    fn delete(&self, currency: &str) -> Result<bool, ApiError>;
+}
+
+pub trait UserStore: Send + Sync {
+    fn get(&self, id: i32) -> Result<Option<User>, ApiError>;
+    fn find_by_username(&self, username: &str) -> Result<Option<User>, ApiError>;
+    fn create(&self, user: NewUser) -> Result<User, ApiError>;
+}
+
+pub trait AuditStore: Send + Sync {
+    fn log(&self, entry: NewLog) -> Result<Log, ApiError>;
+    fn logs_since(&self, since: NaiveDateTime) -> Result<Vec<Log>, ApiError>;
+    fn count_since(&self, user_id: i32, since: NaiveDateTime) -> Result<i64, ApiError>;
+    fn monitored_users(&self) -> Result<Vec<MonitoredUser>, ApiError>;
+    fn add_monitored_user(&self, user_id: i32, username: &str) -> Result<(), ApiError>;
+}
+
+// Every store AppState holds, implemented by one backend
+pub trait Store:
+    ProductStore
+    + VariantStore
+    + ImageStore
+    + VideoUploadStore
+    + ImportJobStore
+    + CategoryStore
+    + AttributeStore
+    + TagStore
+    + ExchangeRateStore
+    + UserStore
+    + AuditStore
+    + Transactional
+{
+}
+
+impl<S> Store for S where
+    S: ProductStore
+        + VariantStore
+        + ImageStore
+        + VideoUploadStore
+        + ImportJobStore
+        + CategoryStore
+        + AttributeStore
+        + TagStore
+        + ExchangeRateStore
+        + UserStore
+        + AuditStore
+        + Transactional
+{
+}
+
+pub trait Transactional: Send + Sync {
+    // Runs `body` against stores that share one transaction: committed when it
+    // returns Ok, rolled back on Err. Called again from inside `body` it nests.
+    fn transaction(&self, state: &AppState, body: &mut dyn FnMut(&AppState) -> Result<(), ApiError>) -> Result<(), ApiError>;
+}
@@ -0,0 +1,428 @@
+use std::sync::{Arc, Mutex};
+
+use chrono::NaiveDateTime;
+use diesel::connection::{AnsiTransactionManager, TransactionManager};
+use diesel::prelude::*;
+
+use crate::currency::Rate;
+use crate::db::connection::{PgPool, PgPooledConnection};
+use crate::db::models::{
+    Category, CategoryAttribute, ExchangeRate, ImportJob, ImportProgress, Log, MonitoredUser, NewAttribute, NewCategory, NewImage, NewImportJob,
+    NewLog, NewProduct, NewUser, NewVariant, NewVideoUpload, PriceChange, Product, ProductImage, ProductVariant, Tag, UpdateCategory,
+    UpdateProduct, User, VideoUpload,
+};
+use crate::db::repository::{self, CategoryWithStats, ProductWithCategory, TagWithCount};
+use crate::errors::ApiError;
+use crate::models::ProductQuery;
+use crate::money::Money;
+use crate::store::{
+    AttributeStore, AuditStore, CategoryStore, ExchangeRateStore, ImageStore, ImportJobStore, ProductStore, TagStore, Transactional, UserStore,
+    VariantStore, VideoUploadStore,
+};
+use crate::AppState;
+
+enum Source {
+    // Each call checks a connection out of the pool
+    Pool(PgPool),
+    // Every call runs on this one connection, inside an open transaction
+    Pinned(Mutex<PgPooledConnection>),
+}
+
+// Diesel-backed store
+pub struct PgStore {
+    source: Source,
+}
+
+impl PgStore {
+    pub fn new(pool: PgPool) -> Self {
+        PgStore { source: Source::Pool(pool) }
+    }
+
+    fn run<T>(&self, query: impl FnOnce(&mut PgConnection) -> QueryResult<T>) -> Result<T, ApiError> {
+        match &self.source {
+            Source::Pool(pool) => Ok(query(&mut *pool.get()?)?),
+            Source::Pinned(conn) => {
+                let mut conn = conn.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                Ok(query(&mut conn)?)
+            }
+        }
+    }
+}
+
+impl Transactional for PgStore {
+    fn transaction(&self, state: &AppState, body: &mut dyn FnMut(&AppState) -> Result<(), ApiError>) -> Result<(), ApiError> {
+        if let Source::Pool(pool) = &self.source {
+            // Pin one connection so every store call in `body` sees the same transaction
+            let pinned = Arc::new(PgStore { source: Source::Pinned(Mutex::new(pool.get()?)) });
+            return pinned.transaction(&state.with_store(pinned.clone()), body);
+        }
+        // Already pinned: Diesel turns a nested begin into a savepoint
+        self.run(AnsiTransactionManager::begin_transaction)?;
+        match body(state) {
+            Ok(()) => self.run(AnsiTransactionManager::commit_transaction),
+            Err(err) => {
+                if let Err(rollback_err) = self.run(AnsiTransactionManager::rollback_transaction) {
+                    eprintln!("Rollback failed: {}", rollback_err);
+                }
+                Err(err)
+            }
+        }
+    }
+}
+
+impl ProductStore for PgStore {
+    fn list(&self, owner: Option<i32>, query: &ProductQuery) -> Result<Vec<ProductWithCategory>, ApiError> {
+        self.run(|conn| repository::list_products(conn, owner, query))
+    }
+
+    fn list_by_owner(&self, owner: i32) -> Result<Vec<Product>, ApiError> {
+        self.run(|conn| repository::get_all_products(conn, owner))
+    }
+
+    fn get(&self, id: i32) -> Result<Option<Product>, ApiError> {
+        self.run(|conn| repository::get_product(conn, id).optional())
+    }
+
+    fn find_by_sku(&self, sku: &str) -> Result<Option<Product>, ApiError> {
+        self.run(|conn| repository::get_product_by_sku(conn, sku).optional())
+    }
+
+    fn create(&self, product: NewProduct) -> Result<Product, ApiError> {
+        self.run(|conn| repository::create_product(conn, product))
+    }
+
+    fn replace(&self, id: i32, product: NewProduct) -> Result<Option<Product>, ApiError> {
+        self.run(|conn| repository::replace_product(conn, id, &product).optional())
+    }
+
+    fn update(&self, id: i32, changes: UpdateProduct) -> Result<Option<Product>, ApiError> {
+        self.run(|conn| repository::update_product(conn, id, changes).optional())
+    }
+
+    fn delete(&self, id: i32) -> Result<bool, ApiError> {
+        Ok(self.run(|conn| repository::delete_product(conn, id))? > 0)
+    }
+
+    fn delete_many(&self, ids: &[i32]) -> Result<usize, ApiError> {
+        self.run(|conn| repository::delete_products(conn, ids))
+    }
+
+    fn ids_in_categories(&self, category_ids: &[i32]) -> Result<Vec<i32>, ApiError> {
+        self.run(|conn| repository::get_product_ids_in_categories(conn, category_ids))
+    }
+
+    fn move_category(&self, from: i32, to: i32) -> Result<Vec<i32>, ApiError> {
+        self.run(|conn| repository::move_products_to_category(conn, from, to))
+    }
+
+    fn adjust_stock(&self, id: i32, delta: i32) -> Result<Option<Product>, ApiError> {
+        if let Some(product) = self.run(|conn| repository::adjust_stock(conn, id, delta))? {
+            return Ok(Some(product));
+        }
+        // The guarded update touched nothing: either no such product or the stock would
+        // leave its range
+        match self.run(|conn| repository::get_product(conn, id).optional())? {
+            Some(product) => Err(ApiError::stock_rejected(product.stock, delta)),
+            None => Ok(None),
+        }
+    }
+
+    fn low_stock(&self) -> Result<Vec<Product>, ApiError> {
+        self.run(repository::get_low_stock_products)
+    }
+
+    fn price_history(&self, id: i32) -> Result<Vec<PriceChange>, ApiError> {
+        self.run(|conn| repository::get_price_history(conn, id))
+    }
+
+    fn avg_price_per_category(&self, owner: i32, currency: &str) -> Result<Vec<(String, Option<Money>)>, ApiError> {
+        self.run(|conn| repository::get_avg_price_per_category(conn, owner, currency))
+    }
+}
+
+impl VariantStore for PgStore {
+    fn list(&self, product_id: i32) -> Result<Vec<ProductVariant>, ApiError> {
+        self.run(|conn| repository::get_variants(conn, product_id))
+    }
+
+    fn get(&self, product_id: i32, id: i32) -> Result<Option<ProductVariant>, ApiError> {
+        self.run(|conn| repository::get_variant(conn, product_id, id).optional())
+    }
+
+    fn create(&self, product_id: i32, variant: NewVariant) -> Result<ProductVariant, ApiError> {
+        self.run(|conn| repository::create_variant(conn, product_id, variant))
+    }
+
+    fn replace(&self, product_id: i32, id: i32, variant: NewVariant) -> Result<Option<ProductVariant>, ApiError> {
+        self.run(|conn| repository::replace_variant(conn, product_id, id, variant).optional())
+    }
+
+    fn delete(&self, product_id: i32, id: i32) -> Result<bool, ApiError> {
+        Ok(self.run(|conn| repository::delete_variant(conn, product_id, id))? > 0)
+    }
+
+    fn adjust_stock(&self, product_id: i32, id: i32, delta: i32) -> Result<Option<ProductVariant>, ApiError> {
+        if let Some(variant) = self.run(|conn| repository::adjust_variant_stock(conn, product_id, id, delta))? {
+            return Ok(Some(variant));
+        }
+        match self.run(|conn| repository::get_variant(conn, product_id, id).optional())? {
+            Some(variant) => Err(ApiError::stock_rejected(variant.stock, delta)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl ImageStore for PgStore {
+    fn list(&self, product_id: i32) -> Result<Vec<ProductImage>, ApiError> {
+        self.run(|conn| repository::get_product_images(conn, product_id))
+    }
+
+    fn get(&self, product_id: i32, id: i32) -> Result<Option<ProductImage>, ApiError> {
+        self.run(|conn| repository::get_product_image(conn, product_id, id).optional())
+    }
+
+    fn create(&self, product_id: i32, image: NewImage) -> Result<ProductImage, ApiError> {
+        self.run(|conn| repository::create_product_image(conn, product_id, &image))
+    }
+
+    fn delete(&self, product_id: i32, id: i32) -> Result<bool, ApiError> {
+        Ok(self.run(|conn| repository::delete_product_image(conn, product_id, id))? > 0)
+    }
+
+    fn key_in_use(&self, key: &str) -> Result<bool, ApiError> {
+        self.run(|conn| repository::image_key_in_use(conn, key))
+    }
+
+    fn pending_renditions(&self, limit: i64) -> Result<Vec<ProductImage>, ApiError> {
+        self.run(|conn| repository::get_pending_renditions(conn, limit))
+    }
+
+    fn finish_renditions(&self, id: i32, width: i32, height: i32, srcset: serde_json::Value) -> Result<bool, ApiError> {
+        Ok(self.run(|conn| repository::finish_renditions(conn, id, width, height, &srcset))? > 0)
+    }
+
+    fn fail_renditions(&self, id: i32) -> Result<bool, ApiError> {
+        Ok(self.run(|conn| repository::fail_renditions(conn, id))? > 0)
+    }
+}
+
+impl VideoUploadStore for PgStore {
+    fn get(&self, id: &str) -> Result<Option<VideoUpload>, ApiError> {
+        self.run(|conn| repository::get_video_upload(conn, id).optional())
+    }
+
+    fn lock(&self, id: &str) -> Result<Option<VideoUpload>, ApiError> {
+        self.run(|conn| repository::lock_video_upload(conn, id).optional())
+    }
+
+    fn create(&self, upload: NewVideoUpload) -> Result<VideoUpload, ApiError> {
+        self.run(|conn| repository::create_video_upload(conn, &upload))
+    }
+
+    fn set_received(&self, id: &str, received_bytes: i64) -> Result<Option<VideoUpload>, ApiError> {
+        self.run(|conn| repository::set_video_upload_received(conn, id, received_bytes).optional())
+    }
+
+    fn complete(&self, id: &str, video: &str) -> Result<Option<VideoUpload>, ApiError> {
+        self.run(|conn| repository::complete_video_upload(conn, id, video).optional())
+    }
+
+    fn delete(&self, id: &str) -> Result<bool, ApiError> {
+        Ok(self.run(|conn| repository::delete_video_upload(conn, id))? > 0)
+    }
+
+    fn delete_stale(&self, cutoff: NaiveDateTime) -> Result<Vec<VideoUpload>, ApiError> {
+        self.run(|conn| repository::delete_stale_video_uploads(conn, cutoff))
+    }
+}
+
+impl ImportJobStore for PgStore {
+    fn get(&self, id: &str) -> Result<Option<ImportJob>, ApiError> {
+        self.run(|conn| repository::get_import_job(conn, id).optional())
+    }
+
+    fn source(&self, id: &str) -> Result<Option<String>, ApiError> {
+        Ok(self.run(|conn| repository::get_import_source(conn, id).optional())?.flatten())
+    }
+
+    fn create(&self, job: NewImportJob) -> Result<ImportJob, ApiError> {
+        self.run(|conn| repository::create_import_job(conn, &job))
+    }
+
+    fn claim(&self, stale_before: NaiveDateTime) -> Result<Option<ImportJob>, ApiError> {
+        self.run(|conn| repository::claim_import_job(conn, stale_before))
+    }
+
+    fn save_progress(&self, id: &str, progress: ImportProgress) -> Result<Option<ImportJob>, ApiError> {
+        self.run(|conn| repository::save_import_progress(conn, id, &progress).optional())
+    }
+
+    fn finish(&self, id: &str, error: Option<&str>) -> Result<Option<ImportJob>, ApiError> {
+        self.run(|conn| repository::finish_import_job(conn, id, error).optional())
+    }
+}
+
+impl AttributeStore for PgStore {
+    fn list(&self, category_ids: &[i32]) -> Result<Vec<CategoryAttribute>, ApiError> {
+        self.run(|conn| repository::get_category_attributes(conn, category_ids))
+    }
+
+    fn get(&self, category_id: i32, id: i32) -> Result<Option<CategoryAttribute>, ApiError> {
+        self.run(|conn| repository::get_category_attribute(conn, category_id, id).optional())
+    }
+
+    fn create(&self, category_id: i32, attribute: NewAttribute) -> Result<CategoryAttribute, ApiError> {
+        self.run(|conn| repository::create_category_attribute(conn, category_id, &attribute))
+    }
+
+    fn replace(&self, category_id: i32, id: i32, attribute: NewAttribute) -> Result<Option<CategoryAttribute>, ApiError> {
+        self.run(|conn| repository::replace_category_attribute(conn, category_id, id, &attribute).optional())
+    }
+
+    fn delete(&self, category_id: i32, id: i32) -> Result<bool, ApiError> {
+        Ok(self.run(|conn| repository::delete_category_attribute(conn, category_id, id))? > 0)
+    }
+}
+
+impl TagStore for PgStore {
+    fn list(&self) -> Result<Vec<TagWithCount>, ApiError> {
+        self.run(repository::get_tags_with_counts)
+    }
+
+    fn get(&self, id: i32) -> Result<Option<Tag>, ApiError> {
+        self.run(|conn| repository::get_tag(conn, id).optional())
+    }
+
+    fn find_by_name(&self, name: &str) -> Result<Option<Tag>, ApiError> {
+        self.run(|conn| repository::get_tag_by_name(conn, name).optional())
+    }
+
+    fn create(&self, name: &str) -> Result<Tag, ApiError> {
+        self.run(|conn| repository::create_tag(conn, name))
+    }
+
+    fn rename(&self, id: i32, name: &str) -> Result<Option<Tag>, ApiError> {
+        self.run(|conn| repository::rename_tag(conn, id, name).optional())
+    }
+
+    fn delete(&self, id: i32) -> Result<bool, ApiError> {
+        Ok(self.run(|conn| repository::delete_tag(conn, id))? > 0)
+    }
+
+    fn for_product(&self, product_id: i32) -> Result<Vec<Tag>, ApiError> {
+        self.run(|conn| repository::get_product_tags(conn, product_id))
+    }
+
+    fn set_for_product(&self, product_id: i32, tag_ids: &[i32]) -> Result<(), ApiError> {
+        self.run(|conn| repository::set_product_tags(conn, product_id, tag_ids))
+    }
+}
+
+impl CategoryStore for PgStore {
+    fn list(&self) -> Result<Vec<Category>, ApiError> {
+        self.run(repository::get_categories)
+    }
+
+    fn list_with_stats(&self, currency: &str) -> Result<Vec<CategoryWithStats>, ApiError> {
+        self.run(|conn| repository::get_all_categories(conn, currency))
+    }
+
+    fn get(&self, id: i32) -> Result<Option<Category>, ApiError> {
+        self.run(|conn| repository::get_category(conn, id).optional())
+    }
+
+    fn get_by_slug(&self, slug: &str) -> Result<Option<Category>, ApiError> {
+        self.run(|conn| repository::get_category_by_slug(conn, slug).optional())
+    }
+
+    fn find_by_name(&self, name: &str) -> Result<Option<Category>, ApiError> {
+        self.run(|conn| repository::get_category_by_name(conn, name).optional())
+    }
+
+    fn create(&self, category: NewCategory) -> Result<Category, ApiError> {
+        self.run(|conn| repository::create_category(conn, category))
+    }
+
+    fn replace(&self, id: i32, category: NewCategory) -> Result<Option<Category>, ApiError> {
+        self.run(|conn| repository::replace_category(conn, id, &category).optional())
+    }
+
+    fn update(&self, id: i32, changes: UpdateCategory) -> Result<Option<Category>, ApiError> {
+        self.run(|conn| repository::update_category(conn, id, changes).optional())
+    }
+
+    fn delete(&self, id: i32) -> Result<bool, ApiError> {
+        Ok(self.run(|conn| repository::delete_category(conn, id))? > 0)
+    }
+
+    fn delete_many(&self, ids: &[i32]) -> Result<usize, ApiError> {
+        self.run(|conn| repository::delete_categories(conn, ids))
+    }
+
+    fn ancestors(&self, id: i32) -> Result<Vec<i32>, ApiError> {
+        self.run(|conn| repository::get_category_ancestors(conn, id))
+    }
+
+    fn subtree(&self, id: i32) -> Result<Vec<i32>, ApiError> {
+        self.run(|conn| repository::get_category_subtree(conn, id))
+    }
+
+    fn move_children(&self, from: i32, to: i32) -> Result<Vec<i32>, ApiError> {
+        self.run(|conn| repository::move_subcategories(conn, from, to))
+    }
+}
+
+impl ExchangeRateStore for PgStore {
+    fn list(&self) -> Result<Vec<ExchangeRate>, ApiError> {
+        self.run(repository::get_all_exchange_rates)
+    }
+
+    fn get(&self, currency: &str) -> Result<Option<ExchangeRate>, ApiError> {
+        self.run(|conn| repository::get_exchange_rate(conn, currency).optional())
+    }
+
+    fn set(&self, currency: &str, rate: Rate) -> Result<ExchangeRate, ApiError> {
+        self.run(|conn| repository::set_exchange_rate(conn, currency, rate))
+    }
+
+    fn delete(&self, currency: &str) -> Result<bool, ApiError> {
+        Ok(self.run(|conn| repository::delete_exchange_rate(conn, currency))? > 0)
+    }
+}
+
+impl UserStore for PgStore {
+    fn get(&self, id: i32) -> Result<Option<User>, ApiError> {
+        self.run(|conn| repository::get_user(conn, id).optional())
+    }
+
+    fn find_by_username(&self, username: &str) -> Result<Option<User>, ApiError> {
+        self.run(|conn| repository::get_user_by_username(conn, username).optional())
+    }
+
+    fn create(&self, user: NewUser) -> Result<User, ApiError> {
+        self.run(|conn| repository::create_user(conn, user))
+    }
+}
+
+impl AuditStore for PgStore {
+    fn log(&self, entry: NewLog) -> Result<Log, ApiError> {
+        self.run(|conn| repository::create_log(conn, entry))
+    }
+
+    fn logs_since(&self, since: NaiveDateTime) -> Result<Vec<Log>, ApiError> {
+        self.run(|conn| repository::get_logs_since(conn, since))
+    }
+
+    fn count_since(&self, user_id: i32, since: NaiveDateTime) -> Result<i64, ApiError> {
+        self.run(|conn| repository::count_logs_since(conn, user_id, since))
+    }
+
+    fn monitored_users(&self) -> Result<Vec<MonitoredUser>, ApiError> {
+        self.run(repository::get_monitored_users)
+    }
+
+    fn add_monitored_user(&self, user_id: i32, username: &str) -> Result<(), ApiError> {
+        self.run(|conn| repository::add_monitored_user(conn, user_id, username))?;
+        Ok(())
+    }
+}
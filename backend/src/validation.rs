@@ -0,0 +1,301 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use serde_json::json;
+use validator::{ValidationError, ValidationErrors, ValidationErrorsKind};
+
+use crate::currency::Rate;
+use crate::db::models::{NewAttribute, VariantOption};
+use crate::errors::ApiError;
+use crate::money::Money;
+use crate::store::{AttributeStore, CategoryStore, ExchangeRateStore};
+
+pub const ATTRIBUTE_KINDS: [&str; 4] = ["string", "number", "enum", "bool"];
+
+const MAX_PRICE: Money = Money::from_cents(1_000_000_00);
+
+// Wide enough for any real currency, narrow enough that a converted MAX_PRICE fits in Money
+const MIN_RATE: Rate = Rate::from_micros(100);
+const MAX_RATE: Rate = Rate::from_micros(100_000_000_000);
+
+// Flattens validator's nested error tree into `{"field": ["message", ...]}`
+impl From<ValidationErrors> for ApiError {
+    fn from(errors: ValidationErrors) -> Self {
+        let mut fields: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        collect_errors(&errors, None, &mut fields);
+        ApiError::validation("Request validation failed", json!(fields))
+    }
+}
+
+fn collect_errors(errors: &ValidationErrors, prefix: Option<&str>, out: &mut BTreeMap<String, Vec<String>>) {
+    for (field, kind) in errors.errors() {
+        let path = match prefix {
+            Some(prefix) => format!("{}.{}", prefix, field),
+            None => field.to_string(),
+        };
+        match kind {
+            ValidationErrorsKind::Field(errors) => {
+                let messages = out.entry(path).or_default();
+                messages.extend(errors.iter().map(describe));
+            }
+            ValidationErrorsKind::Struct(nested) => collect_errors(nested, Some(&path), out),
+            ValidationErrorsKind::List(items) => {
+                for (index, nested) in items {
+                    collect_errors(nested, Some(&format!("{}[{}]", path, index)), out);
+                }
+            }
+        }
+    }
+}
+
+fn describe(error: &ValidationError) -> String {
+    if let Some(message) = &error.message {
+        return message.to_string();
+    }
+    let param = |name: &str| error.params.get(name).map(|value| value.to_string());
+    match error.code.as_ref() {
+        "length" => match (param("min"), param("max")) {
+            (Some(min), Some(max)) => format!("length must be between {} and {}", min, max),
+            (Some(min), None) => format!("length must be at least {}", min),
+            (None, Some(max)) => format!("length must be at most {}", max),
+            _ => "invalid length".to_string(),
+        },
+        "range" => match (param("min").or(param("exclusive_min")), param("max")) {
+            (Some(min), Some(max)) => format!("must be between {} and {}", min, max),
+            (Some(min), None) => format!("must be greater than {}", min),
+            (None, Some(max)) => format!("must be at most {}", max),
+            _ => "out of range".to_string(),
+        },
+        code => code.replace('_', " "),
+    }
+}
+
+fn error(code: &'static str, message: &'static str) -> ValidationError {
+    ValidationError::new(code).with_message(Cow::Borrowed(message))
+}
+
+pub fn not_blank(value: &str) -> Result<(), ValidationError> {
+    if value.trim().is_empty() {
+        return Err(error("blank", "must not be blank"));
+    }
+    Ok(())
+}
+
+// Media may be hosted elsewhere (absolute http/https URL) or served by us (root-relative path)
+pub fn media_url(value: &str) -> Result<(), ValidationError> {
+    if value.starts_with('/') && !value.starts_with("//") && !value.contains("..") {
+        return Ok(());
+    }
+    match url::Url::parse(value) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") && parsed.host().is_some() => Ok(()),
+        _ => Err(error("url", "must be an http(s) URL or a path starting with /")),
+    }
+}
+
+// Positive, and well inside what NUMERIC(12,2) can hold
+pub fn price_range(value: &Money) -> Result<(), ValidationError> {
+    if *value <= Money::from_cents(0) || *value > MAX_PRICE {
+        return Err(error("range", "must be greater than 0 and at most 1000000.00"));
+    }
+    Ok(())
+}
+
+// Three uppercase letters, as in ISO 4217
+pub fn currency_code(value: &str) -> Result<(), ValidationError> {
+    if value.len() != 3 || !value.bytes().all(|b| b.is_ascii_uppercase()) {
+        return Err(error("currency", "must be a three-letter ISO 4217 code such as EUR"));
+    }
+    Ok(())
+}
+
+pub fn rate_range(value: &Rate) -> Result<(), ValidationError> {
+    if *value < MIN_RATE || *value > MAX_RATE {
+        return Err(error("range", "must be between 0.0001 and 100000"));
+    }
+    Ok(())
+}
+
+// Lowercase letters and digits in words joined by single hyphens, e.g. "running-shoes"
+pub fn slug(value: &str) -> Result<(), ValidationError> {
+    let words_ok = value.split('-').all(|word| !word.is_empty() && word.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit()));
+    if !words_ok {
+        return Err(error("slug", "must be lowercase letters and digits separated by single hyphens"));
+    }
+    Ok(())
+}
+
+// The slug a name gets when none is given, matching the backfill in the
+// category_metadata migration: "Shoes & Boots" becomes "shoes-boots"
+pub fn slugify(name: &str) -> String {
+    let lowered = name.to_lowercase();
+    let words: Vec<&str> = lowered.split(|c: char| !c.is_ascii_alphanumeric()).filter(|word| !word.is_empty()).collect();
+    let slug: String = words.join("-").chars().take(100).collect();
+    match slug.trim_end_matches('-') {
+        "" => "category".to_string(),
+        slug => slug.to_string(),
+    }
+}
+
+// Letters, digits, '-', '_' and '.', as printed on labels
+pub fn sku(value: &str) -> Result<(), ValidationError> {
+    let allowed = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.');
+    if value.is_empty() || !value.chars().all(allowed) {
+        return Err(error("sku", "may only contain letters, digits, '-', '_' and '.'"));
+    }
+    Ok(())
+}
+
+// A variant takes one value per option
+pub fn distinct_option_names(options: &[VariantOption]) -> Result<(), ValidationError> {
+    for (index, option) in options.iter().enumerate() {
+        let name = option.name.trim().to_lowercase();
+        if options[..index].iter().any(|o| o.name.trim().to_lowercase() == name) {
+            return Err(error("options", "each option may only appear once"));
+        }
+    }
+    Ok(())
+}
+
+pub fn username_policy(value: &str) -> Result<(), ValidationError> {
+    let allowed = |c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-');
+    if !value.chars().all(allowed) {
+        return Err(error("username", "may only contain letters, digits, '_', '.' and '-'"));
+    }
+    if !value.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return Err(error("username", "must start with a letter"));
+    }
+    Ok(())
+}
+
+pub fn password_policy(value: &str) -> Result<(), ValidationError> {
+    let has_letter = value.chars().any(|c| c.is_alphabetic());
+    let has_digit = value.chars().any(|c| c.is_ascii_digit());
+    if !has_letter || !has_digit {
+        return Err(error("password", "must contain at least one letter and one digit"));
+    }
+    Ok(())
+}
+
+// Self-registration may only ask for the plain role; admins are not made by signing up
+pub fn registered_role(value: &str) -> Result<(), ValidationError> {
+    if value != "User" {
+        return Err(error("role", "must be User"));
+    }
+    Ok(())
+}
+
+// Lowercase letters, digits and '_', starting with a letter, so it reads well as attr.<name>
+pub fn attribute_name(value: &str) -> Result<(), ValidationError> {
+    let allowed = |b: u8| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_';
+    if !value.starts_with(|c: char| c.is_ascii_lowercase()) || !value.bytes().all(allowed) {
+        return Err(error("attribute_name", "must be lowercase letters, digits and '_', starting with a letter"));
+    }
+    Ok(())
+}
+
+pub fn attribute_kind(value: &str) -> Result<(), ValidationError> {
+    if !ATTRIBUTE_KINDS.contains(&value) {
+        return Err(error("kind", "must be one of string, number, enum, bool"));
+    }
+    Ok(())
+}
+
+// Trimmed, non-empty and free of commas, which separate tags in the tags= filter
+pub fn tag_name(value: &str) -> Result<(), ValidationError> {
+    if value.trim().is_empty() || value.trim() != value || value.contains(',') {
+        return Err(error("tag_name", "must be non-empty, without commas or surrounding whitespace"));
+    }
+    Ok(())
+}
+
+pub fn tag_names(values: &[String]) -> Result<(), ValidationError> {
+    for (index, value) in values.iter().enumerate() {
+        tag_name(value)?;
+        if value.chars().count() > 50 {
+            return Err(error("tag_name", "must be at most 50 characters"));
+        }
+        if values[..index].iter().any(|v| v.to_lowercase() == value.to_lowercase()) {
+            return Err(error("tags", "each tag may only appear once"));
+        }
+    }
+    Ok(())
+}
+
+// A SHA-256 digest as 64 lowercase hex digits
+pub fn sha256_hex(value: &str) -> Result<(), ValidationError> {
+    if value.len() != 64 || !value.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b)) {
+        return Err(error("sha256", "must be 64 lowercase hex digits"));
+    }
+    Ok(())
+}
+
+// Enum attributes list what they allow, distinct and not blank; other kinds list nothing
+pub fn ensure_allowed_values(attribute: &NewAttribute) -> Result<(), ApiError> {
+    let message = if attribute.kind != "enum" {
+        (!attribute.allowed_values.is_empty()).then_some("only enum attributes take allowed values")
+    } else if attribute.allowed_values.is_empty() {
+        Some("must not be empty for an enum attribute")
+    } else if attribute.allowed_values.iter().any(|v| v.trim().is_empty() || v.len() > 100) {
+        Some("values must not be blank or longer than 100 characters")
+    } else if attribute.allowed_values.iter().enumerate().any(|(i, v)| attribute.allowed_values[..i].contains(v)) {
+        Some("values must be distinct")
+    } else {
+        None
+    };
+    match message {
+        Some(message) => Err(ApiError::validation("Request validation failed", json!({ "allowed_values": [message] }))),
+        None => Ok(()),
+    }
+}
+
+// The values must fit the attributes of the category and its ancestors
+pub fn ensure_valid_attributes(
+    categories: &dyn CategoryStore,
+    attributes: &dyn AttributeStore,
+    category_id: i32,
+    values: &serde_json::Value,
+) -> Result<(), ApiError> {
+    let lineage = categories.ancestors(category_id)?;
+    let schema = crate::attributes::effective(attributes.list(&lineage)?, &lineage);
+    let errors = crate::attributes::check(&schema, values);
+    if !errors.is_empty() {
+        return Err(ApiError::validation("Request validation failed", json!(errors)));
+    }
+    Ok(())
+}
+
+// Category existence needs the store, so it runs after the declarative checks
+pub fn ensure_category_exists(categories: &dyn CategoryStore, category_id: i32) -> Result<(), ApiError> {
+    if categories.get(category_id)?.is_none() {
+        return Err(ApiError::validation(
+            "Request validation failed",
+            json!({ "category_id": [format!("category {} does not exist", category_id)] }),
+        ));
+    }
+    Ok(())
+}
+
+// The parent must exist and, when `id` is being moved, must not be `id` itself
+// or one of its subcategories
+pub fn ensure_valid_parent(categories: &dyn CategoryStore, id: Option<i32>, parent_id: i32) -> Result<(), ApiError> {
+    let ancestors = categories.ancestors(parent_id)?;
+    let message = if ancestors.is_empty() {
+        format!("category {} does not exist", parent_id)
+    } else if id.is_some_and(|id| ancestors.contains(&id)) {
+        "a category cannot be placed under itself or one of its subcategories".to_string()
+    } else {
+        return Ok(());
+    };
+    Err(ApiError::validation("Request validation failed", json!({ "parent_id": [message] })))
+}
+
+// Prices can only be given or converted in currencies that have a rate; returns that rate
+pub fn ensure_currency_known(rates: &dyn ExchangeRateStore, currency: &str) -> Result<Rate, ApiError> {
+    match rates.get(currency)? {
+        Some(exchange_rate) => Ok(exchange_rate.rate),
+        None => Err(ApiError::validation(
+            "Request validation failed",
+            json!({ "currency": [format!("no exchange rate for {}", currency)] }),
+        )),
+    }
+}
@@ -0,0 +1,196 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::audit::log_action_with_reason;
+use crate::db::models::{UpdateProduct, VideoUpload};
+use crate::errors::ApiError;
+use crate::media::{self, MediaFormat};
+use crate::AppState;
+
+// Larger chunks are refused; clients send big files in several
+pub const MAX_CHUNK_BYTES: usize = 16 * 1024 * 1024;
+
+const PARTS_DIR: &str = ".uploads";
+
+// Where the bytes received so far are kept, relative to MediaConfig::dir:
+// on this machine whatever the storage backend, since chunks are written into
+// the middle of the file. actix-files serves no hidden paths, so with the local
+// backend partial files never show up under the media URL prefix either.
+pub fn part_key(id: &str) -> String {
+    format!("{}/{}.part", PARTS_DIR, id)
+}
+
+// Missing files are fine, as with MediaStorage::remove
+fn remove_part(state: &AppState, id: &str) -> Result<(), ApiError> {
+    let key = part_key(id);
+    match fs::remove_file(state.media.dir.join(&key)) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(io_error(&key, err)),
+        _ => Ok(()),
+    }
+}
+
+pub fn not_found(id: &str) -> ApiError {
+    ApiError::NotFound(format!("Upload {} not found", id))
+}
+
+fn io_error(key: &str, err: io::Error) -> ApiError {
+    ApiError::Internal(format!("Writing {}: {}", key, err))
+}
+
+// Leaves exactly `offset` bytes before the chunk, dropping whatever a failed
+// earlier attempt at this chunk left behind
+fn write_at(path: &Path, offset: u64, chunk: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).truncate(false).write(true).open(path)?;
+    file.set_len(offset)?;
+    file.seek(SeekFrom::Start(offset))?;
+    file.write_all(chunk)?;
+    file.sync_data()
+}
+
+// The file's SHA-256 as lowercase hex, and its first bytes for sniffing
+fn hash_file(path: &Path) -> io::Result<(String, Vec<u8>)> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut head = Vec::new();
+    let mut buffer = vec![0; 1024 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        if head.is_empty() {
+            head = buffer[..read.min(16)].to_vec();
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok((format!("{:x}", hasher.finalize()), head))
+}
+
+// Writes one chunk, which has to start where the upload stands. Call inside a
+// transaction: the upload stays locked until it ends, so concurrent chunks for
+// one upload queue up and all but the first find the offset moved on.
+pub fn append(
+    state: &AppState,
+    product_id: i32,
+    id: &str,
+    offset: i64,
+    chunk: &[u8],
+    checksum: Option<&str>,
+) -> Result<VideoUpload, ApiError> {
+    let upload = state.video_uploads.lock(id)?.filter(|u| u.product_id == product_id).ok_or_else(|| not_found(id))?;
+    if upload.video.is_some() || offset != upload.received_bytes {
+        let message = if upload.video.is_some() { "Upload is complete" } else { "Upload-Offset is not where the upload stands" };
+        return Err(ApiError::Conflict {
+            message: message.to_string(),
+            details: json!({ "received_bytes": upload.received_bytes }),
+        });
+    }
+    let received_bytes = offset + chunk.len() as i64;
+    if received_bytes > upload.size_bytes {
+        return Err(ApiError::PayloadTooLarge(format!(
+            "The upload has {} bytes left",
+            upload.size_bytes - upload.received_bytes
+        )));
+    }
+    if checksum.is_some_and(|expected| format!("{:x}", Sha256::digest(chunk)) != expected) {
+        return Err(ApiError::validation(
+            "Chunk checksum mismatch",
+            json!({ "Upload-Checksum": ["does not match the chunk; send it again"] }),
+        ));
+    }
+    let key = part_key(id);
+    write_at(&state.media.dir.join(&key), offset as u64, chunk).map_err(|err| io_error(&key, err))?;
+    state.video_uploads.set_received(id, received_bytes)?.ok_or_else(|| not_found(id))
+}
+
+// The video format of a fully received upload, or why it is refused
+fn verify(media: &media::MediaConfig, upload: &VideoUpload) -> Result<Result<MediaFormat, ApiError>, ApiError> {
+    let key = part_key(&upload.id);
+    let (sha256, head) = hash_file(&media.dir.join(&key)).map_err(|err| io_error(&key, err))?;
+    let problem = if sha256 != upload.sha256 {
+        json!({ "sha256": ["does not match the uploaded bytes; start a new upload"] })
+    } else if let Some(format) = media::sniff_video(&head) {
+        return Ok(Ok(format));
+    } else {
+        json!({ "file": ["must be an MP4, QuickTime, WebM or Ogg video"] })
+    };
+    Ok(Err(ApiError::validation("Uploaded video rejected", problem)))
+}
+
+// Once every byte is in: checks the whole file against its SHA-256, moves it
+// into storage as videos/<sha256>.<ext> and makes it the product's video. A file that fails the
+// check is thrown away together with its upload; the client starts over.
+pub fn complete(state: &AppState, id: &str) -> Result<VideoUpload, ApiError> {
+    let outcome = state.in_transaction(|state| {
+        let upload = state.video_uploads.lock(id)?.ok_or_else(|| not_found(id))?;
+        // Finished meanwhile by a concurrent request
+        if upload.video.is_some() {
+            return Ok(Ok(upload));
+        }
+        let format = match verify(&state.media, &upload)? {
+            Ok(format) => format,
+            Err(problem) => {
+                state.video_uploads.delete(id)?;
+                return Ok(Err(problem));
+            }
+        };
+        let key = media::video_key(&upload.sha256, format);
+        let url = state.storage.url(&key);
+        let changes = UpdateProduct { video: Some(url.clone()), ..Default::default() };
+        let product = state.products.update(upload.product_id, changes)?.ok_or_else(|| ApiError::not_found("Product", upload.product_id))?;
+        log_action_with_reason(state, product.user_id, "UPDATE", "product", Some(product.id), "video uploaded")?;
+        let upload = state.video_uploads.complete(id, &url)?.ok_or_else(|| not_found(id))?;
+        // Last, so only a failed commit can leave the file moved without its row
+        let part = state.media.dir.join(part_key(id));
+        state.storage.put_file(&key, &part).map_err(|err| io_error(&key, err))?;
+        Ok(Ok(upload))
+    })?;
+    if outcome.is_err() {
+        remove_part(state, id)?;
+    }
+    outcome
+}
+
+// Drops the upload and whatever it received
+pub fn cancel(state: &AppState, product_id: i32, id: &str) -> Result<(), ApiError> {
+    state.video_uploads.get(id)?.filter(|u| u.product_id == product_id).ok_or_else(|| not_found(id))?;
+    state.video_uploads.delete(id)?;
+    remove_part(state, id)
+}
+
+// One pass of the cleanup job: forgets uploads nobody has touched for
+// MediaConfig::upload_expiry, finished or not, and deletes their partial files
+// along with any whose upload went with its product. Returns how many partial
+// files or uploads it removed.
+pub fn clean_up(state: &AppState, now: NaiveDateTime) -> Result<usize, ApiError> {
+    let cutoff = now - state.media.upload_expiry;
+    let mut removed = 0;
+    for upload in state.video_uploads.delete_stale(cutoff)? {
+        remove_part(state, &upload.id)?;
+        removed += 1;
+    }
+    let entries = match fs::read_dir(state.media.dir.join(PARTS_DIR)) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(removed),
+        Err(err) => return Err(io_error(PARTS_DIR, err)),
+    };
+    for entry in entries {
+        let entry = entry.map_err(|err| io_error(PARTS_DIR, err))?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let Some(id) = name.strip_suffix(".part") else { continue };
+        let modified = entry.metadata().and_then(|m| m.modified()).map_err(|err| io_error(&name, err))?;
+        if DateTime::<Utc>::from(modified).naive_utc() < cutoff && state.video_uploads.get(id)?.is_none() {
+            remove_part(state, id)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
@@ -0,0 +1,254 @@
+// Disposable PostgreSQL for the integration tests, and in `scenarios` the
+// tests both stores run.
+//
+// TEST_DATABASE_URL points the tests at an existing server. Without it a
+// throwaway cluster is created with `initdb` (from PATH, or `pg_config --bindir`)
+// and lives exactly as long as the test binary. Either way every TestDb gets
+// its own schema with the migrations applied, dropped again when it goes away.
+#![allow(dead_code)]
+
+pub mod scenarios;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use backend::db::connection::PgPooledConnection;
+use backend::db::models::{NewCategory, NewProduct, NewUser};
+use backend::db::{migrations, repository};
+use backend::{mock_data, AppState, DbPool};
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, Pool};
+
+static SERVER: OnceLock<Option<Server>> = OnceLock::new();
+static NEXT_SCHEMA: AtomicUsize = AtomicUsize::new(0);
+
+struct Server {
+    url: String,
+    // Holds the pipe the cluster's supervisor blocks on; see start_cluster
+    _supervisor: Option<Mutex<Child>>,
+}
+
+impl Server {
+    fn discover() -> Option<Server> {
+        if let Ok(url) = env::var("TEST_DATABASE_URL") {
+            return Some(Server { url, _supervisor: None });
+        }
+        match server_bindir() {
+            Some(bindir) => Some(start_cluster(&bindir)),
+            None => {
+                eprintln!("skipping database tests: set TEST_DATABASE_URL or install PostgreSQL (initdb)");
+                None
+            }
+        }
+    }
+}
+
+// Debian-style installs keep initdb off PATH, so fall back to pg_config
+fn server_bindir() -> Option<PathBuf> {
+    let on_path = env::var_os("PATH")
+        .and_then(|paths| env::split_paths(&paths).find(|dir| dir.join("initdb").is_file()));
+    on_path.or_else(|| {
+        let output = Command::new("pg_config").arg("--bindir").output().ok()?;
+        let dir = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+        (output.status.success() && dir.join("initdb").is_file()).then_some(dir)
+    })
+}
+
+fn start_cluster(bindir: &Path) -> Server {
+    let root = env::temp_dir().join(format!("backend-test-pg-{}", process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).expect("create cluster directory");
+    let data = root.join("data");
+
+    let initdb = Command::new(bindir.join("initdb"))
+        .arg("-D")
+        .arg(&data)
+        .args(["-U", "postgres", "-A", "trust", "-E", "UTF8", "--no-sync"])
+        .output()
+        .expect("run initdb");
+    if !initdb.status.success() {
+        let _ = fs::remove_dir_all(&root);
+        panic!(
+            "initdb failed (set TEST_DATABASE_URL to use an existing server instead):\n{}",
+            String::from_utf8_lossy(&initdb.stderr)
+        );
+    }
+
+    // The shell waits on our stdin. Statics are never dropped, but the pipe
+    // closes when the test binary exits however it exits, and the shell then
+    // stops the server and removes the cluster.
+    let supervisor = Command::new("sh")
+        .arg("-c")
+        .arg(
+            r#""$1" -D "$2" -k "$3" -c listen_addresses='' -c fsync=off -c synchronous_commit=off >"$3/server.log" 2>&1 &
+pid=$!
+read _
+kill -INT $pid
+wait $pid
+rm -rf "$3""#,
+        )
+        .arg("sh")
+        .arg(bindir.join("postgres"))
+        .arg(&data)
+        .arg(&root)
+        .stdin(Stdio::piped())
+        .spawn()
+        .expect("start postgres");
+
+    // Unix socket only, so parallel test binaries never fight over a port
+    let url = format!("postgres://postgres@/postgres?host={}", root.display());
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while PgConnection::establish(&url).is_err() {
+        if Instant::now() > deadline {
+            let log = fs::read_to_string(root.join("server.log")).unwrap_or_default();
+            panic!("postgres did not accept connections within 30s:\n{}", log);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    Server { url, _supervisor: Some(Mutex::new(supervisor)) }
+}
+
+fn with_search_path(url: &str, schema: &str) -> String {
+    let separator = if url.contains('?') { '&' } else { '?' };
+    format!("{}{}options=-csearch_path%3D{}", url, separator, schema)
+}
+
+// A multipart/form-data body with one part per (name, bytes); returns (content type, body)
+pub fn multipart(parts: &[(&str, &[u8])]) -> (String, Vec<u8>) {
+    let boundary = "test-boundary-7MA4YWxkTrZu0gW";
+    let mut body = Vec::new();
+    for (name, bytes) in parts {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"upload\"\r\nContent-Type: application/octet-stream\r\n\r\n",
+                boundary, name
+            )
+            .as_bytes(),
+        );
+        body.extend_from_slice(bytes);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+    (format!("multipart/form-data; boundary={}", boundary), body)
+}
+
+// A width x height gradient, encoded as PNG or, with an EXIF block saying
+// "rotate 90° clockwise to display", as JPEG
+pub fn sample_image(width: u32, height: u32, jpeg_with_exif: bool) -> Vec<u8> {
+    let pixels = image::RgbImage::from_fn(width, height, |x, y| image::Rgb([(x % 256) as u8, (y % 256) as u8, 128]));
+    let mut bytes = std::io::Cursor::new(Vec::new());
+    if !jpeg_with_exif {
+        pixels.write_to(&mut bytes, image::ImageFormat::Png).unwrap();
+        return bytes.into_inner();
+    }
+    pixels.write_to(&mut bytes, image::ImageFormat::Jpeg).unwrap();
+    let jpeg = bytes.into_inner();
+    // Little-endian TIFF holding one IFD entry: Orientation (0x0112) = 6
+    let tiff = b"II*\0\x08\0\0\0\x01\0\x12\x01\x03\0\x01\0\0\0\x06\0\0\0\0\0\0\0";
+    let mut app1 = b"Exif\0\0".to_vec();
+    app1.extend_from_slice(tiff);
+    let mut spliced = jpeg[..2].to_vec();
+    spliced.extend_from_slice(&[0xFF, 0xE1]);
+    spliced.extend_from_slice(&(app1.len() as u16 + 2).to_be_bytes());
+    spliced.extend_from_slice(&app1);
+    spliced.extend_from_slice(&jpeg[2..]);
+    spliced
+}
+
+pub fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+pub struct TestDb {
+    server_url: String,
+    schema: String,
+    pool: DbPool,
+}
+
+impl TestDb {
+    // None when no PostgreSQL is available; the caller returns early
+    pub fn new() -> Option<TestDb> {
+        let server = SERVER.get_or_init(Server::discover).as_ref()?;
+        let schema = format!("test_{}_{}", process::id(), NEXT_SCHEMA.fetch_add(1, Ordering::SeqCst));
+
+        let mut admin = PgConnection::establish(&server.url).expect("connect to test server");
+        diesel::sql_query(format!("CREATE SCHEMA {}", schema))
+            .execute(&mut admin)
+            .expect("create test schema");
+
+        let pool = Pool::builder()
+            .max_size(4)
+            .build(ConnectionManager::new(with_search_path(&server.url, &schema)))
+            .expect("build test pool");
+        migrations::run_pending(&mut pool.get().expect("test connection")).expect("apply migrations");
+
+        Some(TestDb { server_url: server.url.clone(), schema, pool })
+    }
+
+    pub fn pool(&self) -> DbPool {
+        self.pool.clone()
+    }
+
+    pub fn conn(&self) -> PgPooledConnection {
+        self.pool.get().expect("test connection")
+    }
+
+    pub fn app_state(&self) -> AppState {
+        AppState::postgres(self.pool())
+    }
+
+    // Same rows as the in-memory store; a fresh schema hands out ids 1, 2, ...
+    pub fn seed(&self) {
+        let conn = &mut self.conn();
+        for user in mock_data::init_mock_users() {
+            let new_user = NewUser { username: user.username, password: user.password, role: user.role };
+            repository::create_user(conn, new_user).expect("seed user");
+        }
+        for category in mock_data::init_mock_categories() {
+            let new_category = NewCategory {
+                name: category.name,
+                slug: category.slug,
+                description: category.description,
+                parent_id: category.parent_id,
+                image: category.image,
+                display_order: category.display_order,
+            };
+            repository::create_category(conn, new_category).expect("seed category");
+        }
+        for exchange_rate in mock_data::init_mock_exchange_rates() {
+            repository::set_exchange_rate(conn, &exchange_rate.currency, exchange_rate.rate).expect("seed exchange rate");
+        }
+        for product in mock_data::init_mock_data() {
+            let new_product = NewProduct {
+                sku: product.sku,
+                name: product.name,
+                price: product.price,
+                currency: product.currency,
+                description: product.description,
+                image: product.image,
+                video: product.video,
+                published: product.published,
+                category_id: product.category_id,
+                user_id: product.user_id,
+                low_stock_threshold: product.low_stock_threshold,
+                attributes: product.attributes,
+            };
+            let created = repository::create_product(conn, new_product).expect("seed product");
+            repository::adjust_stock(conn, created.id, product.stock).expect("seed stock");
+        }
+    }
+}
+
+impl Drop for TestDb {
+    fn drop(&mut self) {
+        if let Ok(mut admin) = PgConnection::establish(&self.server_url) {
+            let _ = diesel::sql_query(format!("DROP SCHEMA {} CASCADE", self.schema)).execute(&mut admin);
+        }
+    }
+}
@@ -0,0 +1,1085 @@
+// The HTTP scenarios both stores have to pass. tests/lib.rs runs each against
+// AppState::in_memory() and tests/postgres.rs against a seeded TestDb, adding
+// whatever only the database can show.
+
+use actix_web::{test, web, App};
+use backend::db::models::UpdateProduct;
+use backend::imports::{self, ImportFormat, ImportOptions};
+use backend::media::MediaConfig;
+use backend::money::Money;
+use backend::{renditions, routes, video_uploads, AppState};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use super::{contains, multipart, sample_image};
+
+pub async fn product_variants(state: AppState) {
+    let state = web::Data::new(state);
+    let app = test::init_service(
+        App::new()
+            .app_data(state.clone())
+            .configure(routes::configure_routes)
+    ).await;
+
+    for (sku, size, color) in [("RUN-42-BLK", "42", "black"), ("RUN-43-BLK", "43", "black"), ("RUN-42-WHT", "42", "white")] {
+        let req = test::TestRequest::post()
+            .uri("/api/v1/products/2/variants")
+            .set_json(json!({
+                "sku": sku,
+                "options": [{ "name": "Size", "value": size }, { "name": "Color", "value": color }]
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 201);
+    }
+
+    // Spelled and ordered like the existing variants; the override is in the product's currency
+    let req = test::TestRequest::post()
+        .uri("/api/v1/products/2/variants")
+        .set_json(json!({
+            "sku": "RUN-44-WHT",
+            "price": 159.99,
+            "options": [{ "name": "color", "value": "WHITE" }, { "name": "SIZE", "value": "44" }]
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let location = resp.headers().get("location").unwrap().to_str().unwrap().to_string();
+    let variant: Value = test::read_body_json(resp).await;
+    assert_eq!(location, format!("/api/v1/products/2/variants/{}", variant["id"]));
+    assert_eq!(variant["price"], json!(159.99));
+    assert_eq!(variant["stock"], json!(0));
+    assert_eq!(variant["options"], json!([{ "name": "Size", "value": "44" }, { "name": "Color", "value": "white" }]));
+
+    let req = test::TestRequest::get().uri("/api/v1/products/2/variants/options").to_request();
+    let options: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(options, json!([
+        { "name": "Size", "values": ["42", "43", "44"] },
+        { "name": "Color", "values": ["black", "white"] },
+    ]));
+
+    let rejected = [
+        // Same options as RUN-42-BLK
+        (json!({ "sku": "RUN-42-BLK-2", "options": [{ "name": "Size", "value": "42" }, { "name": "Color", "value": "Black" }] }), 409),
+        // SKU taken
+        (json!({ "sku": "RUN-42-BLK", "options": [{ "name": "Size", "value": "45" }, { "name": "Color", "value": "black" }] }), 409),
+        // The product's variants set Size and Color
+        (json!({ "sku": "RUN-45", "options": [{ "name": "Size", "value": "45" }] }), 422),
+        (json!({ "sku": "RUN 45", "options": [{ "name": "Size", "value": "45" }, { "name": "Color", "value": "red" }] }), 422),
+        (json!({ "sku": "RUN-45", "options": [{ "name": "Size", "value": "45" }, { "name": "size", "value": "46" }] }), 422),
+    ];
+    for (body, status) in rejected {
+        let req = test::TestRequest::post().uri("/api/v1/products/2/variants").set_json(&body).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), status, "{}", body);
+    }
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/products/2/variants/1/stock/increment")
+        .set_json(json!({ "quantity": 3, "reason": "delivery" }))
+        .to_request();
+    let variant: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(variant["stock"], json!(3));
+
+    // An increment that would overflow the stock column is refused, not a 500
+    let fill = i32::MAX - 500 - 3;
+    state.variants.adjust_stock(2, 1, fill).unwrap();
+    let req = test::TestRequest::post()
+        .uri("/api/v1/products/2/variants/1/stock/increment")
+        .set_json(json!({ "quantity": 1000, "reason": "delivery" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["details"], json!({ "available": i32::MAX - 500, "added": 1000, "maximum": i32::MAX }));
+    state.variants.adjust_stock(2, 1, -fill).unwrap();
+
+    // Size 42 in black is in stock, size 43 is not, and product 1 has no variants
+    let listed = |uri: &'static str| {
+        let req = test::TestRequest::get().uri(uri).to_request();
+        test::call_and_read_body_json::<_, _, Vec<Value>>(&app, req)
+    };
+    let ids = |products: Vec<Value>| products.iter().map(|p| p["id"].as_i64().unwrap()).collect::<Vec<_>>();
+    assert_eq!(ids(listed("/api/v1/products?options=size:42,color:BLACK").await), vec![2]);
+    assert_eq!(ids(listed("/api/v1/products?options=size:42&in_stock=true").await), vec![2]);
+    assert!(listed("/api/v1/products?options=size:43&in_stock=true").await.is_empty());
+    assert!(listed("/api/v1/products?options=size:41").await.is_empty());
+    let req = test::TestRequest::get().uri("/api/v1/products?options=size").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+
+    // PATCH keeps what it does not mention; PUT clears the price override
+    let req = test::TestRequest::patch()
+        .uri("/api/v1/products/2/variants/4")
+        .set_json(json!({ "options": [{ "name": "Size", "value": "45" }, { "name": "Color", "value": "white" }] }))
+        .to_request();
+    let variant: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!((variant["sku"].clone(), variant["price"].clone()), (json!("RUN-44-WHT"), json!(159.99)));
+    let req = test::TestRequest::put()
+        .uri("/api/v1/products/2/variants/4")
+        .set_json(json!({ "sku": "RUN-45-WHT", "options": [{ "name": "Size", "value": "45" }, { "name": "Color", "value": "white" }] }))
+        .to_request();
+    let variant: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!((variant["sku"].clone(), variant["price"].clone()), (json!("RUN-45-WHT"), Value::Null));
+
+    let req = test::TestRequest::delete().uri("/api/v1/products/2/variants/2").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 204);
+    let req = test::TestRequest::get().uri("/api/v1/products/2/variants/options").to_request();
+    let options: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(options[0], json!({ "name": "Size", "values": ["42", "45"] }));
+
+    // Variants are only reachable through their own product
+    let req = test::TestRequest::get().uri("/api/v1/products/1/variants/1").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+    let req = test::TestRequest::get().uri("/api/v1/products/999/variants").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+}
+
+pub async fn category_hierarchy(state: AppState) {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .configure(routes::configure_routes)
+    ).await;
+
+    // Shoes > Running > Trail
+    let mut parent_id = 2;
+    for name in ["Running", "Trail"] {
+        let req = test::TestRequest::post()
+            .uri("/api/v1/categories")
+            .set_json(json!({ "name": name, "description": name, "parent_id": parent_id }))
+            .to_request();
+        let category: Value = test::call_and_read_body_json(&app, req).await;
+        parent_id = category["id"].as_i64().unwrap();
+    }
+    let trail_id = parent_id;
+
+    let req = test::TestRequest::get().uri("/api/v1/categories/tree").to_request();
+    let tree: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(tree[1]["name"], "Shoes");
+    assert_eq!(tree[1]["children"][0]["name"], "Running");
+    assert_eq!(tree[1]["children"][0]["children"][0]["id"], json!(trail_id));
+    assert_eq!(tree[1]["children"][0]["children"][0]["children"], json!([]));
+
+    let req = test::TestRequest::patch()
+        .uri("/api/v1/products/2")
+        .set_json(json!({ "category_id": trail_id }))
+        .to_request();
+    test::call_service(&app, req).await;
+
+    let req = test::TestRequest::get().uri("/api/v1/products?category_id=2").to_request();
+    let products: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert!(products.is_empty());
+    let req = test::TestRequest::get().uri("/api/v1/products?category_id=2&include_subcategories=true").to_request();
+    let products: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(products.iter().map(|p| p["id"].as_i64().unwrap()).collect::<Vec<_>>(), vec![2]);
+
+    // Product 2 counts towards Trail and everything above it
+    let expected = json!([
+        { "category": "Running", "avg_price": 149.99 },
+        { "category": "Shoes", "avg_price": 149.99 },
+        { "category": "Trail", "avg_price": 149.99 },
+        { "category": "Clothes", "avg_price": 99.99 },
+    ]);
+    for uri in ["/api/stats/avg-price-per-category?user_id=1", "/api/stats/avg-price-per-category-inefficient?user_id=1"] {
+        let req = test::TestRequest::get().uri(uri).to_request();
+        let stats: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(stats, expected, "{}", uri);
+    }
+
+    // Under itself, under its own subcategory, under a missing category
+    for (id, parent_id) in [(2, 2), (2, trail_id), (1, 999)] {
+        let req = test::TestRequest::patch()
+            .uri(&format!("/api/v1/categories/{}", id))
+            .set_json(json!({ "parent_id": parent_id }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 422);
+        let body: Value = test::read_body_json(resp).await;
+        assert!(body["details"]["parent_id"].is_array());
+    }
+
+    // Running still has Trail below it
+    let req = test::TestRequest::delete().uri(&format!("/api/v1/categories/{}", trail_id - 1)).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+
+    // PUT without a parent moves Trail to the top level
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/v1/categories/{}", trail_id))
+        .set_json(json!({ "name": "Trail", "description": "Trail running" }))
+        .to_request();
+    let category: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(category["parent_id"], Value::Null);
+    let req = test::TestRequest::get().uri("/api/v1/categories/tree").to_request();
+    let tree: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(tree.iter().map(|c| c["name"].as_str().unwrap()).collect::<Vec<_>>(), vec!["Clothes", "Shoes", "Trail"]);
+}
+
+pub async fn delete_category_modes(state: AppState) {
+    let state = web::Data::new(state);
+    let since = chrono::Utc::now().naive_utc();
+    let app = test::init_service(
+        App::new()
+            .app_data(state.clone())
+            .configure(routes::configure_routes)
+    ).await;
+
+    // Shoes > Running, with product 2 in Shoes
+    let req = test::TestRequest::post()
+        .uri("/api/v1/categories")
+        .set_json(json!({ "name": "Running", "description": "Running", "parent_id": 2 }))
+        .to_request();
+    let running: Value = test::call_and_read_body_json(&app, req).await;
+    let running_id = running["id"].as_i64().unwrap();
+    let req = test::TestRequest::patch()
+        .uri("/api/v1/products/2")
+        .set_json(json!({ "category_id": 2 }))
+        .to_request();
+    test::call_service(&app, req).await;
+
+    let req = test::TestRequest::delete().uri("/api/v1/categories/2").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["details"], json!({ "products": 1, "subcategories": 1 }));
+
+    for uri in [
+        "/api/v1/categories/2?reassign_to=1&cascade=true".to_string(),
+        "/api/v1/categories/2?reassign_to=2".to_string(),
+        format!("/api/v1/categories/2?reassign_to={}", running_id),
+        "/api/v1/categories/2?reassign_to=999".to_string(),
+    ] {
+        let req = test::TestRequest::delete().uri(&uri).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 422, "{}", uri);
+    }
+
+    // Product 2 and Running move to Clothes
+    let req = test::TestRequest::delete().uri("/api/v1/categories/2?reassign_to=1").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 204);
+    let req = test::TestRequest::get().uri("/api/v1/products/2").to_request();
+    let product: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(product["category_id"], 1);
+    let req = test::TestRequest::get().uri(&format!("/api/v1/categories/{}", running_id)).to_request();
+    let category: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(category["parent_id"], 1);
+
+    // Clothes, Running and both products go together
+    let req = test::TestRequest::delete().uri("/api/v1/categories/1?cascade=true").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 204);
+    let req = test::TestRequest::get().uri("/api/v1/products").to_request();
+    let products: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert!(products.is_empty());
+    let req = test::TestRequest::get().uri("/api/v1/categories").to_request();
+    let categories: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert!(categories.is_empty());
+
+    let req = test::TestRequest::delete().uri("/api/v1/categories/1?cascade=true").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+
+    let logs = state.audit.logs_since(since).unwrap();
+    let running_id = running_id as i32;
+    let entries: Vec<_> = logs.iter().skip(2).map(|l| (l.action.as_str(), l.entity.as_str(), l.entity_id)).collect();
+    assert_eq!(
+        entries,
+        vec![
+            // Creating Running and moving product 2 come first
+            ("UPDATE", "product", Some(2)),
+            ("UPDATE", "category", Some(running_id)),
+            ("DELETE", "category", Some(2)),
+            ("DELETE", "product", Some(1)),
+            ("DELETE", "product", Some(2)),
+            ("DELETE", "category", Some(running_id)),
+            ("DELETE", "category", Some(1)),
+        ]
+    );
+    assert_eq!(logs[4].reason.as_deref(), Some("reassigned 1 products and 1 subcategories to category 1"));
+    assert_eq!(logs[8].reason.as_deref(), Some("deleted 2 products and 1 subcategories"));
+}
+
+pub async fn category_slugs_and_stats(state: AppState) {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .configure(routes::configure_routes)
+    ).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/categories")
+        .set_json(json!({ "name": "Running Shoes", "description": "Running", "parent_id": 2 }))
+        .to_request();
+    let running: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(running["slug"], "running-shoes");
+    assert_eq!(running["display_order"], 0);
+
+    let req = test::TestRequest::get().uri("/api/v1/categories/by-slug/running-shoes").to_request();
+    let category: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(category["id"], running["id"]);
+    let req = test::TestRequest::get().uri("/api/v1/categories/by-slug/hiking").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+
+    // Same name in other case, taken slug, malformed slug
+    for (body, status) in [
+        (json!({ "name": "running SHOES", "description": "", "slug": "other" }), 409),
+        (json!({ "name": "Trail", "description": "", "slug": "running-shoes" }), 409),
+        (json!({ "name": "Trail", "description": "", "slug": "Trail Shoes" }), 422),
+    ] {
+        let req = test::TestRequest::post().uri("/api/v1/categories").set_json(&body).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), status, "{}", body);
+    }
+
+    let req = test::TestRequest::patch()
+        .uri("/api/v1/products/2")
+        .set_json(json!({ "category_id": running["id"] }))
+        .to_request();
+    test::call_service(&app, req).await;
+    let req = test::TestRequest::patch()
+        .uri("/api/v1/categories/2")
+        .set_json(json!({ "display_order": -1, "image": "/assets/images/shoes.jpg" }))
+        .to_request();
+    test::call_service(&app, req).await;
+
+    // Shoes first now, counting the product in Running Shoes; prices in USD
+    let req = test::TestRequest::get().uri("/api/v1/categories?currency=USD").to_request();
+    let categories: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    let summary: Vec<_> = categories
+        .iter()
+        .map(|c| (c["slug"].as_str().unwrap(), c["product_count"].as_i64().unwrap(), c["min_price"].clone(), c["max_price"].clone()))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            ("shoes", 1, json!(161.99), json!(161.99)),
+            ("clothes", 1, json!(107.99), json!(107.99)),
+            ("running-shoes", 1, json!(161.99), json!(161.99)),
+        ]
+    );
+    assert_eq!(categories[0]["image"], "/assets/images/shoes.jpg");
+    assert_eq!(categories[0]["currency"], "USD");
+
+    let req = test::TestRequest::get().uri("/api/v1/categories?currency=XYZ").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 422);
+}
+
+pub async fn category_attributes(state: AppState) {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .configure(routes::configure_routes)
+    ).await;
+
+    for (body, status) in [
+        (json!({ "name": "size", "kind": "number", "required": true }), 201),
+        (json!({ "name": "material", "kind": "enum", "allowed_values": ["leather", "mesh"] }), 201),
+        (json!({ "name": "size", "kind": "string" }), 409),
+        (json!({ "name": "width", "kind": "enum" }), 422),
+        (json!({ "name": "Width", "kind": "string" }), 422),
+        (json!({ "name": "width", "kind": "date" }), 422),
+    ] {
+        let req = test::TestRequest::post().uri("/api/v1/categories/2/attributes").set_json(&body).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), status, "{}", body);
+    }
+
+    // Running inherits size and material from Shoes
+    let req = test::TestRequest::post()
+        .uri("/api/v1/categories")
+        .set_json(json!({ "name": "Running", "description": "Running", "parent_id": 2 }))
+        .to_request();
+    let running: Value = test::call_and_read_body_json(&app, req).await;
+    let running_id = running["id"].as_i64().unwrap();
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/v1/categories/{}/attributes", running_id))
+        .set_json(json!({ "name": "terrain", "kind": "string" }))
+        .to_request();
+    test::call_service(&app, req).await;
+    let req = test::TestRequest::get().uri(&format!("/api/v1/categories/{}/attributes", running_id)).to_request();
+    let schema: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(schema.iter().map(|a| a["name"].as_str().unwrap()).collect::<Vec<_>>(), vec!["material", "size", "terrain"]);
+
+    let product = |category_id: i64, attributes: Value| {
+        json!({
+            "name": "Runner",
+            "price": 89.99,
+            "description": "Shoe",
+            "image": "/assets/images/runner.jpg",
+            "category_id": category_id,
+            "user_id": 1,
+            "attributes": attributes
+        })
+    };
+    for (attributes, field) in [
+        (json!({ "material": "mesh" }), "attributes.size"),
+        (json!({ "size": "42" }), "attributes.size"),
+        (json!({ "size": 42, "material": "canvas" }), "attributes.material"),
+        (json!({ "size": 42, "colour": "red" }), "attributes.colour"),
+        (json!([42]), "attributes"),
+    ] {
+        let req = test::TestRequest::post().uri("/api/v1/products").set_json(product(running_id, attributes.clone())).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 422, "{}", attributes);
+        let body: Value = test::read_body_json(resp).await;
+        assert!(body["details"][field].is_array(), "{}", body);
+    }
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/products")
+        .set_json(product(running_id, json!({ "size": 42, "material": "leather", "terrain": "Trail" })))
+        .to_request();
+    let trail: Value = test::call_and_read_body_json(&app, req).await;
+    let req = test::TestRequest::post()
+        .uri("/api/v1/products")
+        .set_json(product(2, json!({ "size": 44, "material": "mesh" })))
+        .to_request();
+    let road: Value = test::call_and_read_body_json(&app, req).await;
+
+    for (filter, expected) in [
+        ("attr.size=42", vec![&trail]),
+        ("attr.terrain=trail", vec![&trail]),
+        ("attr.material=MESH", vec![&road]),
+        ("attr.size=44&attr.material=mesh", vec![&road]),
+        ("attr.size=42&attr.material=mesh", vec![]),
+    ] {
+        let req = test::TestRequest::get().uri(&format!("/api/v1/products?{}", filter)).to_request();
+        let products: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+        let ids: Vec<&Value> = products.iter().map(|p| &p["id"]).collect();
+        assert_eq!(ids, expected.iter().map(|p| &p["id"]).collect::<Vec<_>>(), "{}", filter);
+    }
+    let req = test::TestRequest::get().uri("/api/v1/products?attr.Size=42").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+
+    // PATCH merges; a null removes the value, which a required attribute refuses
+    let uri = format!("/api/v1/products/{}", trail["id"]);
+    let req = test::TestRequest::patch().uri(&uri).set_json(json!({ "attributes": { "terrain": null } })).to_request();
+    let patched: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(patched["attributes"], json!({ "size": 42, "material": "leather" }));
+    for changes in [json!({ "attributes": { "size": null } }), json!({ "category_id": 1 })] {
+        let req = test::TestRequest::patch().uri(&uri).set_json(&changes).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 422, "{}", changes);
+    }
+}
+
+pub async fn product_tags(state: AppState) {
+    let state = web::Data::new(state);
+    let since = chrono::Utc::now().naive_utc();
+    let app = test::init_service(
+        App::new()
+            .app_data(state.clone())
+            .configure(routes::configure_routes)
+    ).await;
+
+    let set_tags = |product_id: i32, tags: Value| {
+        test::TestRequest::put().uri(&format!("/api/v1/products/{}/tags", product_id)).set_json(json!({ "tags": tags })).to_request()
+    };
+    let names = |tags: &[Value]| tags.iter().map(|t| t["name"].as_str().unwrap().to_string()).collect::<Vec<_>>();
+
+    let tags: Vec<Value> = test::call_and_read_body_json(&app, set_tags(1, json!(["summer", "sale"]))).await;
+    assert_eq!(names(&tags), vec!["sale", "summer"]);
+    // "SALE" is the existing tag; "new" is created on the way
+    let tags: Vec<Value> = test::call_and_read_body_json(&app, set_tags(2, json!(["SALE", "new"]))).await;
+    assert_eq!(names(&tags), vec!["new", "sale"]);
+    for tags in [json!(["red", "Red"]), json!(["red,blue"]), json!([" red"]), json!([""])] {
+        let resp = test::call_service(&app, set_tags(1, tags.clone())).await;
+        assert_eq!(resp.status(), 422, "{}", tags);
+    }
+    let resp = test::call_service(&app, set_tags(99, json!(["sale"]))).await;
+    assert_eq!(resp.status(), 404);
+
+    let req = test::TestRequest::get().uri("/api/v1/tags").to_request();
+    let cloud: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    let counts: Vec<(&str, i64)> = cloud.iter().map(|t| (t["name"].as_str().unwrap(), t["product_count"].as_i64().unwrap())).collect();
+    assert_eq!(counts, vec![("sale", 2), ("new", 1), ("summer", 1)]);
+
+    for (filter, expected) in [
+        ("tags=sale", vec![1, 2]),
+        ("tags=summer,new", vec![1, 2]),
+        ("tags=summer,sale&tags_match=all", vec![1]),
+        ("tags=SUMMER,new&tags_match=all", vec![]),
+        ("tags=winter", vec![]),
+    ] {
+        let req = test::TestRequest::get().uri(&format!("/api/v1/products?{}", filter)).to_request();
+        let products: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+        let ids: Vec<i64> = products.iter().map(|p| p["id"].as_i64().unwrap()).collect();
+        assert_eq!(ids, expected, "{}", filter);
+    }
+    for filter in ["tags=sale&tags_match=some", "tags=sale,,new"] {
+        let req = test::TestRequest::get().uri(&format!("/api/v1/products?{}", filter)).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400, "{}", filter);
+    }
+
+    // Tag names are unique regardless of case
+    let req = test::TestRequest::post().uri("/api/v1/tags").set_json(json!({ "name": "Sale" })).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+    let req = test::TestRequest::post().uri("/api/v1/tags").set_json(json!({ "name": "winter" })).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let winter: Value = test::read_body_json(resp).await;
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/v1/tags/{}", winter["id"]))
+        .set_json(json!({ "name": "Summer" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+
+    // Deleting a tag takes it off its products
+    let summer = cloud.iter().find(|t| t["name"] == "summer").unwrap();
+    let req = test::TestRequest::delete().uri(&format!("/api/v1/tags/{}", summer["id"])).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 204);
+    let req = test::TestRequest::get().uri("/api/v1/products/1/tags").to_request();
+    let tags: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(names(&tags), vec!["sale"]);
+
+    // Tags created while tagging are logged as the product owner's
+    let logs = state.audit.logs_since(since).unwrap();
+    let entries: Vec<_> = logs.iter().take(3).map(|l| (l.user_id, l.action.as_str(), l.entity.as_str())).collect();
+    assert_eq!(entries, vec![(1, "CREATE", "tag"), (1, "CREATE", "tag"), (1, "UPDATE", "product")]);
+    assert_eq!(logs[2].reason.as_deref(), Some("tags set to sale, summer"));
+}
+
+pub async fn product_images(state: AppState) {
+    let media = MediaConfig {
+        dir: std::env::temp_dir().join(format!("media-{}", uuid::Uuid::new_v4())),
+        max_image_bytes: 64,
+        ..MediaConfig::default()
+    };
+    let state = web::Data::new(state.with_media(media.clone()));
+    let since = chrono::Utc::now().naive_utc();
+    let app = test::init_service(
+        App::new()
+            .app_data(state.clone())
+            .configure(routes::configure_routes)
+    ).await;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR-png";
+    const GIF: &[u8] = b"GIF89a\x01\0\x01\0-gif";
+    let upload = |product_id: i32, query: &str, parts: &[(&str, &[u8])]| {
+        let (content_type, body) = multipart(parts);
+        test::TestRequest::post()
+            .uri(&format!("/api/v1/products/{}/images{}", product_id, query))
+            .insert_header(("content-type", content_type))
+            .set_payload(body)
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, upload(1, "?primary=true", &[("file", PNG), ("note", b"ignored"), ("file", GIF)])).await;
+    assert_eq!(resp.status(), 201);
+    let images: Vec<Value> = test::read_body_json(resp).await;
+    assert_eq!(images.len(), 2);
+    assert_eq!(images[0]["content_type"], "image/png");
+    assert_eq!(images[1]["content_type"], "image/gif");
+    let png_key = images[0]["key"].as_str().unwrap().to_string();
+    assert!(png_key.starts_with("images/") && png_key.ends_with(".png"));
+    assert_eq!(images[0]["url"], format!("/media/{}", png_key));
+    assert_eq!(std::fs::read(media.dir.join(&png_key)).unwrap(), PNG);
+    let req = test::TestRequest::get().uri("/api/v1/products/1").to_request();
+    let product: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(product["image"], images[0]["url"]);
+
+    for (parts, status, product_id) in [
+        (vec![("file", &b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>"[..])], 422, 1),
+        (vec![("file", &[0xFF, 0xD8, 0xFF].repeat(40)[..])], 413, 1),
+        (vec![("note", &b"no file"[..])], 422, 1),
+        (vec![("file", PNG)], 409, 1),
+        (vec![("file", PNG)], 404, 99),
+    ] {
+        let resp = test::call_service(&app, upload(product_id, "", &parts)).await;
+        assert_eq!(resp.status(), status, "{:?}", parts);
+    }
+    // The new GIF was stored before the PNG turned out to be a duplicate; the rollback takes it along
+    let resp = test::call_service(&app, upload(1, "", &[("file", b"GIF89a\x02\0\x02\0-new"), ("file", PNG)])).await;
+    assert_eq!(resp.status(), 409);
+    assert_eq!(std::fs::read_dir(media.dir.join("images")).unwrap().count(), 2);
+
+    // Product 2 shares the stored PNG, so it stays until both images are gone
+    let resp = test::call_service(&app, upload(2, "", &[("file", PNG)])).await;
+    let shared: Vec<Value> = test::read_body_json(resp).await;
+    assert_eq!(shared[0]["key"], png_key.as_str());
+    let req = test::TestRequest::delete().uri(&format!("/api/v1/products/1/images/{}", images[0]["id"])).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 204);
+    assert!(media.dir.join(&png_key).exists());
+    let req = test::TestRequest::get().uri("/api/v1/products/1").to_request();
+    let product: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(product["image"], images[1]["url"]);
+    let req = test::TestRequest::delete().uri(&format!("/api/v1/products/2/images/{}", shared[0]["id"])).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 204);
+    assert!(!media.dir.join(&png_key).exists());
+
+    // The GIF is product 1's main image now, and its last
+    let req = test::TestRequest::delete().uri(&format!("/api/v1/products/1/images/{}", images[1]["id"])).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 409);
+    let req = test::TestRequest::get().uri("/api/v1/products/1/images").to_request();
+    let remaining: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(remaining.len(), 1);
+    std::fs::remove_dir_all(&media.dir).unwrap();
+
+    let logs = state.audit.logs_since(since).unwrap();
+    let entries: Vec<_> = logs.iter().take(3).map(|l| (l.action.as_str(), l.entity.as_str())).collect();
+    assert_eq!(entries, vec![("CREATE", "product_image"), ("CREATE", "product_image"), ("UPDATE", "product")]);
+}
+
+pub async fn image_renditions(state: AppState) {
+    let media = MediaConfig {
+        dir: std::env::temp_dir().join(format!("media-{}", uuid::Uuid::new_v4())),
+        ..MediaConfig::default()
+    };
+    let state = web::Data::new(state.with_media(media.clone()));
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes::configure_routes)).await;
+    let upload = |product_id: i32, query: &str, file: &[u8]| {
+        let (content_type, body) = multipart(&[("file", file)]);
+        test::TestRequest::post()
+            .uri(&format!("/api/v1/products/{}/images{}", product_id, query))
+            .insert_header(("content-type", content_type))
+            .set_payload(body)
+            .to_request()
+    };
+
+    let png = sample_image(800, 400, false);
+    let resp = test::call_service(&app, upload(1, "?primary=true", &png)).await;
+    assert_eq!(resp.status(), 201);
+    let images: Vec<Value> = test::read_body_json(resp).await;
+    assert_eq!(images[0]["renditions_status"], "pending");
+    assert_eq!(images[0]["srcset"], Value::Null);
+    let req = test::TestRequest::get().uri("/api/v1/products/1").to_request();
+    let product: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(product["image_srcset"], Value::Null);
+
+    let jpeg = sample_image(800, 400, true);
+    let resp = test::call_service(&app, upload(2, "", &jpeg)).await;
+    assert_eq!(resp.status(), 201);
+    // Sniffs as PNG but does not decode
+    let resp = test::call_service(&app, upload(2, "", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR-broken")).await;
+    assert_eq!(resp.status(), 201);
+
+    assert_eq!(renditions::process_pending(&state, 10).unwrap(), 3);
+    assert_eq!(renditions::process_pending(&state, 10).unwrap(), 0);
+
+    let req = test::TestRequest::get().uri("/api/v1/products/1/images").to_request();
+    let images: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    let image = &images[0];
+    assert_eq!(image["renditions_status"], "ready");
+    assert_eq!((image["width"].as_i64(), image["height"].as_i64()), (Some(800), Some(400)));
+    let stem = image["url"].as_str().unwrap().trim_end_matches(".png").to_string();
+    assert_eq!(
+        image["srcset"]["webp"],
+        format!("{0}/thumbnail.webp 160w, {0}/medium.webp 640w, {0}/large.webp 800w", stem)
+    );
+    assert_eq!(
+        image["srcset"]["jpeg"],
+        format!("{0}/thumbnail.jpg 160w, {0}/medium.jpg 640w, {0}/large.jpg 800w", stem)
+    );
+    let key = image["key"].as_str().unwrap().trim_end_matches(".png").to_string();
+    for file in ["thumbnail.webp", "medium.jpg", "large.webp"] {
+        assert!(media.dir.join(&key).join(file).exists(), "{}", file);
+    }
+    let req = test::TestRequest::get().uri("/api/v1/products/1").to_request();
+    let product: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(product["image_srcset"], image["srcset"]);
+    let req = test::TestRequest::get().uri("/api/v1/products").to_request();
+    let listed: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    let listed = listed.iter().find(|p| p["id"] == 1).unwrap();
+    assert_eq!(listed["image_srcset"], image["srcset"]);
+
+    // Turned upright and stripped of its EXIF block; the original is kept as uploaded
+    let req = test::TestRequest::get().uri("/api/v1/products/2/images").to_request();
+    let images: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!((images[0]["width"].as_i64(), images[0]["height"].as_i64()), (Some(400), Some(800)));
+    let key = images[0]["key"].as_str().unwrap().trim_end_matches(".jpg").to_string();
+    assert!(contains(&std::fs::read(media.dir.join(images[0]["key"].as_str().unwrap())).unwrap(), b"Exif"));
+    for file in ["thumbnail.jpg", "large.jpg", "large.webp"] {
+        assert!(!contains(&std::fs::read(media.dir.join(&key).join(file)).unwrap(), b"Exif"), "{}", file);
+    }
+    assert_eq!(images[1]["renditions_status"], "failed");
+    assert_eq!(images[1]["srcset"], Value::Null);
+
+    // Pointing a product at a rendered image brings its srcset along
+    let req = test::TestRequest::patch()
+        .uri("/api/v1/products/2")
+        .set_json(json!({ "image": images[0]["url"] }))
+        .to_request();
+    let product: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(product["image_srcset"], images[0]["srcset"]);
+
+    // Deleting the image removes its renditions along with the original
+    let req = test::TestRequest::delete().uri(&format!("/api/v1/products/2/images/{}", images[0]["id"])).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 204);
+    assert!(!media.dir.join(images[0]["key"].as_str().unwrap()).exists());
+    for size in ["thumbnail", "medium", "large"] {
+        for extension in ["webp", "jpg"] {
+            let file = format!("{}.{}", size, extension);
+            assert!(!media.dir.join(&key).join(&file).exists(), "{}", file);
+        }
+    }
+    std::fs::remove_dir_all(&media.dir).unwrap();
+}
+
+pub async fn video_uploads(state: AppState) {
+    let media = MediaConfig {
+        dir: std::env::temp_dir().join(format!("media-{}", uuid::Uuid::new_v4())),
+        max_video_bytes: 4096,
+        ..MediaConfig::default()
+    };
+    let state = web::Data::new(state.with_media(media.clone()));
+    let since = chrono::Utc::now().naive_utc();
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes::configure_routes)).await;
+    let sha256 = |bytes: &[u8]| format!("{:x}", Sha256::digest(bytes));
+    let chunk = |location: &str, offset: i64, bytes: &[u8], checksum: Option<String>| {
+        let mut req = test::TestRequest::patch()
+            .uri(location)
+            .insert_header(("Upload-Offset", offset.to_string()))
+            .insert_header(("content-type", "application/offset+octet-stream"))
+            .set_payload(bytes.to_vec());
+        if let Some(checksum) = checksum {
+            req = req.insert_header(("Upload-Checksum", format!("sha256 {}", checksum)));
+        }
+        req.to_request()
+    };
+
+    let mut video = b"\0\0\0\x20ftypisom\0\0\x02\0".to_vec();
+    video.extend((0..3000u32).map(|i| (i % 251) as u8));
+    let digest = sha256(&video);
+    for (product_id, body, status) in [
+        (1, json!({ "size_bytes": video.len(), "sha256": digest.to_uppercase() }), 422),
+        (1, json!({ "size_bytes": 0, "sha256": digest }), 422),
+        (1, json!({ "size_bytes": 5000, "sha256": digest }), 413),
+        (99, json!({ "size_bytes": video.len(), "sha256": digest }), 404),
+    ] {
+        let req = test::TestRequest::post().uri(&format!("/api/v1/products/{}/video/uploads", product_id)).set_json(&body).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), status, "{}", body);
+    }
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/products/1/video/uploads")
+        .set_json(json!({ "size_bytes": video.len(), "sha256": digest }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    assert_eq!(resp.headers().get("Upload-Offset").unwrap(), "0");
+    let location = resp.headers().get("location").unwrap().to_str().unwrap().to_string();
+    let upload: Value = test::read_body_json(resp).await;
+    assert_eq!(location, format!("/api/v1/products/1/video/uploads/{}", upload["id"].as_str().unwrap()));
+    assert_eq!(upload["video"], Value::Null);
+
+    let req = test::TestRequest::patch().uri(&location).set_payload(video[..1000].to_vec()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+    let resp = test::call_service(&app, chunk(&location, 0, &video[..1000], Some(sha256(&video[..1000])))).await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers().get("Upload-Offset").unwrap(), "1000");
+
+    // A repeated chunk, a corrupted one and one running past the end change nothing
+    let resp = test::call_service(&app, chunk(&location, 0, &video[..1000], None)).await;
+    assert_eq!(resp.status(), 409);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["details"]["received_bytes"], 1000);
+    let resp = test::call_service(&app, chunk(&location, 1000, &video[1000..2000], Some(sha256(b"other")))).await;
+    assert_eq!(resp.status(), 422);
+    let mut too_long = video[1000..].to_vec();
+    too_long.push(0);
+    assert_eq!(test::call_service(&app, chunk(&location, 1000, &too_long, None)).await.status(), 413);
+    let req = test::TestRequest::get().uri(&location).to_request();
+    let upload: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(upload["received_bytes"], 1000);
+
+    let resp = test::call_service(&app, chunk(&location, 1000, &video[1000..], None)).await;
+    assert_eq!(resp.status(), 200);
+    let upload: Value = test::read_body_json(resp).await;
+    let url = format!("/media/videos/{}.mp4", digest);
+    assert_eq!(upload["video"], url.as_str());
+    assert_eq!(std::fs::read(media.dir.join(format!("videos/{}.mp4", digest))).unwrap(), video);
+    assert!(!media.dir.join(video_uploads::part_key(upload["id"].as_str().unwrap())).exists());
+    let req = test::TestRequest::get().uri("/api/v1/products/1").to_request();
+    let product: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(product["video"], url.as_str());
+    let logs = state.audit.logs_since(since).unwrap();
+    assert!(logs.iter().any(|l| l.entity == "product" && l.entity_id == Some(1) && l.reason.as_deref() == Some("video uploaded")));
+    assert_eq!(test::call_service(&app, chunk(&location, video.len() as i64, b"", None)).await.status(), 409);
+
+    // Bytes that do not hash to the declared digest are thrown away with their upload
+    let req = test::TestRequest::post()
+        .uri("/api/v1/products/2/video/uploads")
+        .set_json(json!({ "size_bytes": video.len(), "sha256": sha256(b"other") }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let location = resp.headers().get("location").unwrap().to_str().unwrap().to_string();
+    let rejected: Value = test::read_body_json(resp).await;
+    let resp = test::call_service(&app, chunk(&location, 0, &video, None)).await;
+    assert_eq!(resp.status(), 422);
+    let body: Value = test::read_body_json(resp).await;
+    assert!(body["details"]["sha256"].is_array());
+    assert_eq!(test::call_service(&app, test::TestRequest::get().uri(&location).to_request()).await.status(), 404);
+    assert!(!media.dir.join(video_uploads::part_key(rejected["id"].as_str().unwrap())).exists());
+
+    // An abandoned upload, and a partial file whose upload went with its product
+    let req = test::TestRequest::post()
+        .uri("/api/v1/products/2/video/uploads")
+        .set_json(json!({ "size_bytes": video.len(), "sha256": digest }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let location = resp.headers().get("location").unwrap().to_str().unwrap().to_string();
+    let abandoned: Value = test::read_body_json(resp).await;
+    assert_eq!(test::call_service(&app, chunk(&location, 0, &video[..10], None)).await.status(), 200);
+    std::fs::write(media.dir.join(video_uploads::part_key("orphan")), b"left behind").unwrap();
+    assert_eq!(video_uploads::clean_up(&state, chrono::Utc::now().naive_utc()).unwrap(), 0);
+    let later = chrono::Utc::now().naive_utc() + media.upload_expiry + chrono::Duration::minutes(1);
+    // The finished upload is forgotten too; its video stays
+    assert_eq!(video_uploads::clean_up(&state, later).unwrap(), 3);
+    assert_eq!(test::call_service(&app, test::TestRequest::get().uri(&location).to_request()).await.status(), 404);
+    assert!(!media.dir.join(video_uploads::part_key(abandoned["id"].as_str().unwrap())).exists());
+    assert!(!media.dir.join(video_uploads::part_key("orphan")).exists());
+    assert!(media.dir.join(format!("videos/{}.mp4", digest)).exists());
+    std::fs::remove_dir_all(&media.dir).unwrap();
+}
+
+pub async fn video_streaming(state: AppState) {
+    let dir = std::env::temp_dir().join(format!("media-{}", uuid::Uuid::new_v4()));
+    let media = MediaConfig {
+        legacy_videos_dir: dir.join("legacy"),
+        dir,
+        ..MediaConfig::default()
+    };
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.with_media(media.clone())))
+            .service(media.files().unwrap())
+            .configure(routes::configure_routes)
+    ).await;
+    let video: Vec<u8> = b"\0\0\0\x20ftypisom".iter().copied().chain((0..500u32).map(|i| (i % 251) as u8)).collect();
+    let key = format!("videos/{:x}.mp4", Sha256::digest(&video));
+    std::fs::create_dir_all(media.dir.join("videos")).unwrap();
+    std::fs::write(media.dir.join(&key), &video).unwrap();
+    std::fs::create_dir_all(media.dir.join("images")).unwrap();
+    std::fs::write(media.dir.join("images/a.png"), b"png").unwrap();
+    let patch = |product_id: i32, changes: Value| {
+        test::TestRequest::patch().uri(&format!("/api/v1/products/{}", product_id)).set_json(changes).to_request()
+    };
+    let get = |range: Option<&str>| {
+        let mut req = test::TestRequest::get().uri("/api/v1/products/1/video");
+        if let Some(range) = range {
+            req = req.insert_header(("Range", range));
+        }
+        req
+    };
+
+    // Only through the product route, and no directory listings anywhere
+    let req = test::TestRequest::get().uri(&format!("/media/{}", key)).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+    let req = test::TestRequest::get().uri("/media/videos/").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+    let req = test::TestRequest::get().uri("/media/images/a.png").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    assert_eq!(test::call_service(&app, get(None).to_request()).await.status(), 404);
+
+    assert_eq!(test::call_service(&app, patch(1, json!({ "video": format!("/media/{}", key) }))).await.status(), 200);
+    let resp = test::call_service(&app, get(None).to_request()).await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers().get("content-type").unwrap(), "video/mp4");
+    assert_eq!(resp.headers().get("accept-ranges").unwrap(), "bytes");
+    assert_eq!(resp.headers().get("cache-control").unwrap(), "public, no-cache");
+    let etag = resp.headers().get("etag").unwrap().clone();
+    assert_eq!(test::read_body(resp).await.to_vec(), video);
+
+    let resp = test::call_service(&app, get(Some("bytes=10-19")).to_request()).await;
+    assert_eq!(resp.status(), 206);
+    assert_eq!(resp.headers().get("content-range").unwrap().to_str().unwrap(), format!("bytes 10-19/{}", video.len()));
+    assert_eq!(test::read_body(resp).await.to_vec(), video[10..20].to_vec());
+    let resp = test::call_service(&app, get(Some("bytes=9000-")).to_request()).await;
+    assert_eq!(resp.status(), 416);
+    let resp = test::call_service(&app, get(None).insert_header(("If-None-Match", etag)).to_request()).await;
+    assert_eq!(resp.status(), 304);
+
+    // Videos from before media storage keep their /videos/<file> URL and stream the same way
+    std::fs::create_dir_all(&media.legacy_videos_dir).unwrap();
+    std::fs::write(media.legacy_videos_dir.join("demo.mp4"), &video).unwrap();
+    let legacy = |range: &str| test::TestRequest::get().uri("/api/v1/products/2/video").insert_header(("Range", range.to_string()));
+    assert_eq!(test::call_service(&app, patch(2, json!({ "video": "/videos/demo.mp4" }))).await.status(), 200);
+    let resp = test::call_service(&app, legacy("bytes=10-19").to_request()).await;
+    assert_eq!(resp.status(), 206);
+    assert_eq!(resp.headers().get("content-type").unwrap(), "video/mp4");
+    assert!(resp.headers().contains_key("etag"));
+    assert_eq!(test::read_body(resp).await.to_vec(), video[10..20].to_vec());
+    assert_eq!(test::call_service(&app, patch(2, json!({ "published": false }))).await.status(), 200);
+    assert_eq!(test::call_service(&app, legacy("bytes=0-3").to_request()).await.status(), 401);
+    assert_eq!(test::call_service(&app, patch(2, json!({ "published": true, "video": "/videos/gone.mp4" }))).await.status(), 200);
+    assert_eq!(test::call_service(&app, legacy("bytes=0-3").to_request()).await.status(), 404);
+
+    // Videos hosted elsewhere are a redirect away
+    assert_eq!(test::call_service(&app, patch(2, json!({ "video": "https://cdn.example.com/v.mp4" }))).await.status(), 200);
+    let req = test::TestRequest::get().uri("/api/v1/products/2/video").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 302);
+    assert_eq!(resp.headers().get("location").unwrap(), "https://cdn.example.com/v.mp4");
+
+    // Unpublished: the owner (user 1, also an admin) only; user 2 is neither
+    let resp = test::call_service(&app, patch(1, json!({ "published": false }))).await;
+    let product: Value = test::read_body_json(resp).await;
+    assert_eq!(product["published"], false);
+    assert_eq!(test::call_service(&app, get(None).to_request()).await.status(), 401);
+    let resp = test::call_service(&app, get(None).insert_header(("Authorization", "Bearer 2")).to_request()).await;
+    assert_eq!(resp.status(), 403);
+    let resp = test::call_service(&app, get(Some("bytes=0-3")).insert_header(("Authorization", "Bearer 1")).to_request()).await;
+    assert_eq!(resp.status(), 206);
+    assert_eq!(resp.headers().get("cache-control").unwrap(), "private, no-cache");
+    std::fs::remove_dir_all(&media.dir).unwrap();
+}
+
+fn import_request(query: &str, content_type: &str, body: &str) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri(&format!("/api/v1/products/import{}", query))
+        .insert_header(("content-type", content_type))
+        .insert_header(("authorization", "Bearer 1"))
+        .set_payload(body.to_string())
+}
+
+pub async fn product_import(state: AppState) {
+    let state = web::Data::new(state);
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes::configure_routes)).await;
+    let csv = "\u{feff}sku,name,price,description,image,category,published\n\
+               SKU-1,Shirt,19.99,Cotton,/assets/images/shirt.jpg,clothes,false\n\
+               SKU-2,Boot,0,Leather,/assets/images/boot.jpg,Shoes,true\n\
+               SKU-3,Hat,9.99,Wool,/assets/images/hat.jpg,Hats,true\n\
+               SKU-4,Sock\n";
+
+    let req = test::TestRequest::post().uri("/api/v1/products/import").insert_header(("content-type", "text/csv")).set_payload(csv).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+    let resp = test::call_service(&app, import_request("", "text/plain", csv).to_request()).await;
+    assert_eq!(resp.status(), 400);
+    let resp = test::call_service(&app, import_request("?format=csv", "text/plain", "sku,colour\nSKU-5,red\n").to_request()).await;
+    assert_eq!(resp.status(), 422);
+    let body: Value = test::read_body_json(resp).await;
+    assert!(body["details"]["file"][0].as_str().unwrap().contains("colour"), "{}", body);
+
+    // A dry run counts what would happen, categories included, and changes nothing
+    let resp = test::call_service(&app, import_request("?dry_run=true&create_categories=true", "text/csv", csv).to_request()).await;
+    assert_eq!(resp.status(), 200);
+    let job: Value = test::read_body_json(resp).await;
+    assert_eq!((job["status"].as_str(), job["created_rows"].as_i64(), job["failed_rows"].as_i64()), (Some("finished"), Some(2), Some(2)));
+    let req = test::TestRequest::get().uri("/api/v1/products").to_request();
+    let products: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(products.len(), 2);
+    assert!(state.categories.find_by_name("Hats").unwrap().is_none());
+
+    let resp = test::call_service(&app, import_request("", "text/csv; charset=utf-8", csv).to_request()).await;
+    assert_eq!(resp.status(), 200);
+    let job: Value = test::read_body_json(resp).await;
+    assert_eq!(job["total_rows"], 4);
+    assert_eq!((job["processed_rows"].as_i64(), job["created_rows"].as_i64(), job["failed_rows"].as_i64()), (Some(4), Some(1), Some(3)));
+    let errors = job["row_errors"].as_array().unwrap();
+    assert_eq!(errors.iter().map(|e| e["row"].as_i64().unwrap()).collect::<Vec<_>>(), vec![3, 4, 5]);
+    assert_eq!(errors[0]["sku"], "SKU-2");
+    assert!(errors[0]["details"]["price"].is_array(), "{}", errors[0]);
+    assert!(errors[1]["details"]["category"][0].as_str().unwrap().contains("create_categories"), "{}", errors[1]);
+    assert!(errors[2]["details"]["row"].is_array(), "{}", errors[2]);
+    let shirt = state.products.find_by_sku("SKU-1").unwrap().unwrap();
+    assert_eq!((shirt.category_id, shirt.user_id, shirt.published), (1, 1, false));
+
+    // A taken SKU fails unless the import upserts; then only the given fields change
+    let ndjson = "{\"sku\": \"SKU-1\", \"price\": \"24.99\"}\n\n{\"sku\": \"SKU-6\", \"name\": \"Cap\", \"price\": 5, \"description\": \"Cap\", \"image\": \"/assets/images/cap.jpg\", \"category\": \"Hats\"}\n[1]\n";
+    let job: Value = test::call_and_read_body_json(&app, import_request("", "application/x-ndjson", ndjson).to_request()).await;
+    assert_eq!((job["updated_rows"].as_i64(), job["failed_rows"].as_i64()), (Some(0), Some(3)));
+    assert!(job["row_errors"][0]["details"]["sku"][0].as_str().unwrap().contains("upsert"));
+    let job: Value = test::call_and_read_body_json(&app, import_request("?format=ndjson&upsert=true&create_categories=true", "text/plain", ndjson).to_request()).await;
+    assert_eq!((job["created_rows"].as_i64(), job["updated_rows"].as_i64(), job["failed_rows"].as_i64()), (Some(1), Some(1), Some(1)));
+    assert_eq!(job["row_errors"][0]["row"], 4);
+    let req = test::TestRequest::get().uri(&format!("/api/v1/products/{}", shirt.id)).to_request();
+    let updated: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!((&updated["price"], &updated["name"]), (&json!(24.99), &json!("Shirt")));
+    let hats = state.categories.find_by_name("hats").unwrap().unwrap();
+    assert_eq!(state.products.find_by_sku("SKU-6").unwrap().unwrap().category_id, hats.id);
+}
+
+pub async fn background_product_import(state: AppState) {
+    let state = web::Data::new(state);
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes::configure_routes)).await;
+    let options = ImportOptions { format: ImportFormat::Csv, dry_run: false, upsert: false, create_categories: false };
+    let csv = "name,price,description,image,category_id\nScarf,12.50,Wool,/assets/images/scarf.jpg,1\nGlove,8,Wool,/assets/images/glove.jpg,9\n";
+    let job = imports::create(&state, 1, options, csv.to_string(), 1).unwrap();
+    assert_eq!(job.status, "pending");
+
+    let get = |user: &str| {
+        test::TestRequest::get()
+            .uri(&format!("/api/v1/products/import/{}", job.id))
+            .insert_header(("authorization", format!("Bearer {}", user)))
+            .to_request()
+    };
+    let pending: Value = test::call_and_read_body_json(&app, get("1")).await;
+    assert_eq!((pending["status"].as_str(), pending["processed_rows"].as_i64()), (Some("pending"), Some(0)));
+    assert_eq!(test::call_service(&app, get("2")).await.status(), 403);
+
+    assert_eq!(imports::process_pending(&state).unwrap(), 1);
+    assert_eq!(imports::process_pending(&state).unwrap(), 0);
+    let finished: Value = test::call_and_read_body_json(&app, get("1")).await;
+    assert_eq!(finished["status"], "finished");
+    assert_eq!((finished["created_rows"].as_i64(), finished["failed_rows"].as_i64()), (Some(1), Some(1)));
+    assert!(finished["row_errors"][0]["details"]["category_id"].is_array(), "{}", finished);
+
+    // Over the inline limit the endpoint queues the file and says where to look
+    let rows: String = (0..=imports::INLINE_ROWS).map(|i| format!("Item {},1,Item,/assets/images/item.jpg,1\n", i)).collect();
+    let resp = test::call_service(&app, import_request("", "text/csv", &format!("name,price,description,image,category_id\n{}", rows)).to_request()).await;
+    assert_eq!(resp.status(), 202);
+    let location = resp.headers().get("location").unwrap().to_str().unwrap().to_string();
+    let job: Value = test::read_body_json(resp).await;
+    assert_eq!(location, format!("/api/v1/products/import/{}", job["id"].as_str().unwrap()));
+    assert_eq!(imports::process_pending(&state).unwrap(), 1);
+    let job = state.import_jobs.get(job["id"].as_str().unwrap()).unwrap().unwrap();
+    assert_eq!((job.status.as_str(), job.created_rows as usize), ("finished", imports::INLINE_ROWS + 1));
+}
+
+pub async fn product_import_transactions(state: AppState) {
+    let state = web::Data::new(state);
+    let since = chrono::Utc::now().naive_utc();
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes::configure_routes)).await;
+    let csv = "sku,name,price,description,image,category\n\
+               SKU-1,Shirt,19.99,Cotton,/assets/images/shirt.jpg,Clothes\n\
+               SKU-1,Shirt again,21.99,Cotton,/assets/images/shirt.jpg,Clothes\n\
+               SKU-2,Hat,0,Wool,/assets/images/hat.jpg,Hats\n\
+               SKU-3,Cap,5,Cotton,/assets/images/cap.jpg,hats\n";
+
+    // Checked in one rolled back transaction: the second SKU-1 sees the first
+    let job: Value = test::call_and_read_body_json(&app, import_request("?dry_run=true&create_categories=true", "text/csv", csv).to_request()).await;
+    assert_eq!((job["created_rows"].as_i64(), job["failed_rows"].as_i64()), (Some(2), Some(2)));
+    assert_eq!(job["row_errors"].as_array().unwrap().iter().map(|e| e["row"].as_i64().unwrap()).collect::<Vec<_>>(), vec![3, 4]);
+    assert!(state.products.find_by_sku("SKU-1").unwrap().is_none());
+    assert!(state.categories.find_by_name("Hats").unwrap().is_none());
+
+    // The failed Hat row takes the category it created with it; Cap creates it again
+    let job: Value = test::call_and_read_body_json(&app, import_request("?create_categories=true", "text/csv", csv).to_request()).await;
+    assert_eq!((job["status"].as_str(), job["created_rows"].as_i64(), job["failed_rows"].as_i64()), (Some("finished"), Some(2), Some(2)));
+    let hats = state.categories.find_by_name("HATS").unwrap().unwrap();
+    assert_eq!(state.products.find_by_sku("SKU-3").unwrap().unwrap().category_id, hats.id);
+    let logs = state.audit.logs_since(since).unwrap();
+    let reasons: Vec<_> = logs.iter().filter(|l| l.entity == "category").map(|l| l.reason.clone()).collect();
+    assert_eq!(reasons, vec![Some(format!("import {}", job["id"].as_str().unwrap()))]);
+
+    // Large files go to the import job, which resumes from saved progress
+    let options = ImportOptions { format: ImportFormat::Csv, dry_run: false, upsert: true, create_categories: false };
+    let upsert = "sku,price\nSKU-1,17.50\nSKU-3,abc\n";
+    let job = imports::create(&state, 1, options, upsert.to_string(), 0).unwrap();
+    assert_eq!(job.status, "pending");
+    assert_eq!(imports::process_pending(&state).unwrap(), 1);
+    let job = state.import_jobs.get(&job.id).unwrap().unwrap();
+    assert_eq!((job.status.as_str(), job.updated_rows, job.failed_rows), ("finished", 1, 1));
+    assert!(job.row_errors[0]["details"]["price"].is_array(), "{:?}", job.row_errors);
+    assert_eq!(state.products.find_by_sku("SKU-1").unwrap().unwrap().price, Money::from_cents(1750));
+    assert!(state.import_jobs.source(&job.id).unwrap().is_none());
+    assert_eq!(imports::process_pending(&state).unwrap(), 0);
+
+    // Two products never share a SKU
+    let product = state.products.find_by_sku("SKU-3").unwrap().unwrap();
+    let changes = UpdateProduct { sku: Some("SKU-1".to_string()), ..UpdateProduct::default() };
+    let err = state.products.update(product.id, changes).unwrap_err();
+    assert_eq!(err.details()["constraint"], "products_sku_key");
+}
@@ -1,279 +1,230 @@
+mod common;
+
 use actix_web::{test, web, App};
-use super::*;
+use backend::db::models::Product;
+use backend::media::MediaConfig;
+use backend::money::Money;
+use backend::storage::{self, CopyReport, LocalStorage, MediaStorage};
+use backend::{routes, video_uploads, AppState};
+use common::scenarios;
+use serde_json::{json, Value};
+
+fn app_state() -> web::Data<AppState> {
+    web::Data::new(AppState::in_memory())
+}
+
+fn sample_product() -> Value {
+    json!({
+        "name": "Test Product",
+        "price": 99.99,
+        "description": "Test Description",
+        "image": "/assets/images/test.jpg",
+        "category_id": 1,
+        "user_id": 1
+    })
+}
 
 #[actix_web::test]
 async fn test_get_products() {
-    let app_state = web::Data::new(AppState {
-        products: Mutex::new(mock_data::init_mock_data()),
-    });
-
     let app = test::init_service(
         App::new()
-            .app_data(app_state.clone())
-            .route("/api/get/products", web::get().to(get_products))
+            .app_data(app_state())
+            .configure(routes::configure_routes)
     ).await;
 
-    let req = test::TestRequest::get().uri("/api/get/products").to_request();
+    let req = test::TestRequest::get().uri("/api/v1/products").to_request();
     let resp = test::call_service(&app, req).await;
     assert!(resp.status().is_success());
+    let products: Vec<Value> = test::read_body_json(resp).await;
+    assert_eq!(products.len(), 2);
 }
 
 #[actix_web::test]
 async fn test_create_product() {
-    let app_state = web::Data::new(AppState {
-        products: Mutex::new(Vec::new()),
-    });
-
     let app = test::init_service(
         App::new()
-            .app_data(app_state.clone())
-            .route("/api/post/products", web::post().to(create_product))
+            .app_data(app_state())
+            .configure(routes::configure_routes)
     ).await;
 
-    let new_product = CreateProductRequest {
-        name: "Test Product".to_string(),
-        price: 99.99,
-        image: "test.jpg".to_string(),
-        description: "Test Description".to_string(),
-        category: "Test Category".to_string(),
-    };
-
     let req = test::TestRequest::post()
-        .uri("/api/post/products")
-        .set_json(&new_product)
+        .uri("/api/v1/products")
+        .set_json(sample_product())
         .to_request();
-    
+
     let resp = test::call_service(&app, req).await;
-    assert!(resp.status().is_success());
+    assert_eq!(resp.status(), 201);
+    let location = resp.headers().get("location").unwrap().to_str().unwrap().to_string();
+    let product: Product = test::read_body_json(resp).await;
+    assert_eq!(location, format!("/api/v1/products/{}", product.id));
+    assert_eq!(product.name, "Test Product");
 }
 
 #[actix_web::test]
 async fn test_create_product_validation() {
-    let app_state = web::Data::new(AppState {
-        products: Mutex::new(Vec::new()),
-    });
-
     let app = test::init_service(
         App::new()
-            .app_data(app_state.clone())
-            .route("/api/post/products", web::post().to(create_product))
+            .app_data(app_state())
+            .configure(routes::configure_routes)
     ).await;
 
     // Test with empty name
-    let invalid_product = CreateProductRequest {
-        name: "".to_string(),
-        price: 99.99,
-        image: "test.jpg".to_string(),
-        description: "Test Description".to_string(),
-        category: "Test Category".to_string(),
-    };
+    let mut invalid_product = sample_product();
+    invalid_product["name"] = json!("");
 
     let req = test::TestRequest::post()
-        .uri("/api/post/products")
+        .uri("/api/v1/products")
         .set_json(&invalid_product)
         .to_request();
-    
+
     let resp = test::call_service(&app, req).await;
-    assert!(resp.status().is_client_error());
+    assert_eq!(resp.status(), 422);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["code"], "validation_failed");
+    assert!(body["details"]["name"].is_array());
 }
 
 #[actix_web::test]
-async fn test_update_product() {
-    let mut mock_products = Vec::new();
-    mock_products.push(Product {
-        id: 1,
-        name: "Original Product".to_string(),
-        price: 50.0,
-        image: "original.jpg".to_string(),
-        description: "Original Description".to_string(),
-        category: "Original Category".to_string(),
-    });
+async fn test_create_product_unknown_category() {
+    let app = test::init_service(
+        App::new()
+            .app_data(app_state())
+            .configure(routes::configure_routes)
+    ).await;
 
-    let app_state = web::Data::new(AppState {
-        products: Mutex::new(mock_products),
-    });
+    let mut product = sample_product();
+    product["category_id"] = json!(999);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/products")
+        .set_json(&product)
+        .to_request();
 
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 422);
+    let body: Value = test::read_body_json(resp).await;
+    assert!(body["details"]["category_id"].is_array());
+}
+
+#[actix_web::test]
+async fn test_update_product() {
     let app = test::init_service(
         App::new()
-            .app_data(app_state.clone())
-            .route("/api/products/{id}", web::put().to(update_product))
+            .app_data(app_state())
+            .configure(routes::configure_routes)
     ).await;
 
-    let updated_product = Product {
-        id: 1,
-        name: "Updated Product".to_string(),
-        price: 75.0,
-        image: "updated.jpg".to_string(),
-        description: "Updated Description".to_string(),
-        category: "Updated Category".to_string(),
-    };
+    let mut updated_product = sample_product();
+    updated_product["name"] = json!("Updated Product");
+    updated_product["price"] = json!(75.0);
 
     let req = test::TestRequest::put()
-        .uri("/api/products/1")
+        .uri("/api/v1/products/1")
         .set_json(&updated_product)
         .to_request();
-    
+
     let resp = test::call_service(&app, req).await;
     assert!(resp.status().is_success());
+    let product: Product = test::read_body_json(resp).await;
+    assert_eq!(product.name, "Updated Product");
+    assert_eq!(product.price, Money::from_cents(75_00));
 }
 
 #[actix_web::test]
 async fn test_delete_product() {
-    let mut mock_products = Vec::new();
-    mock_products.push(Product {
-        id: 1,
-        name: "Test Product".to_string(),
-        price: 50.0,
-        image: "test.jpg".to_string(),
-        description: "Test Description".to_string(),
-        category: "Test Category".to_string(),
-    });
-
-    let app_state = web::Data::new(AppState {
-        products: Mutex::new(mock_products),
-    });
-
     let app = test::init_service(
         App::new()
-            .app_data(app_state.clone())
-            .route("/api/products/{id}", web::delete().to(delete_product))
+            .app_data(app_state())
+            .configure(routes::configure_routes)
     ).await;
 
-    let req = test::TestRequest::delete().uri("/api/products/1").to_request();
+    let req = test::TestRequest::delete().uri("/api/v1/products/1").to_request();
     let resp = test::call_service(&app, req).await;
-    assert!(resp.status().is_success());
+    assert_eq!(resp.status(), 204);
+
+    let req = test::TestRequest::get().uri("/api/v1/products/1").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
 }
 
 #[actix_web::test]
 async fn test_filter_and_sort_products() {
-    let mut mock_products = Vec::new();
-    mock_products.push(Product {
-        id: 1,
-        name: "Product A".to_string(),
-        price: 100.0,
-        image: "a.jpg".to_string(),
-        description: "Description A".to_string(),
-        category: "Category A".to_string(),
-    });
-    mock_products.push(Product {
-        id: 2,
-        name: "Product B".to_string(),
-        price: 50.0,
-        image: "b.jpg".to_string(),
-        description: "Description B".to_string(),
-        category: "Category B".to_string(),
-    });
-
-    let app_state = web::Data::new(AppState {
-        products: Mutex::new(mock_products),
-    });
-
     let app = test::init_service(
         App::new()
-            .app_data(app_state.clone())
-            .route("/api/products", web::get().to(get_products))
+            .app_data(app_state())
+            .configure(routes::configure_routes)
     ).await;
 
     // Test filtering by category
     let req = test::TestRequest::get()
-        .uri("/api/products?category=Category%20A")
+        .uri("/api/v1/products?category_id=2")
         .to_request();
     let resp = test::call_service(&app, req).await;
     assert!(resp.status().is_success());
+    let products: Vec<Value> = test::read_body_json(resp).await;
+    assert!(products.is_empty());
 
     // Test sorting by price
     let req = test::TestRequest::get()
-        .uri("/api/products?sort_by=price&sort_order=asc")
+        .uri("/api/v1/products?sort_by=price&sort_order=desc")
         .to_request();
     let resp = test::call_service(&app, req).await;
     assert!(resp.status().is_success());
+    let products: Vec<Value> = test::read_body_json(resp).await;
+    let prices: Vec<f64> = products.iter().map(|p| p["price"].as_f64().unwrap()).collect();
+    assert_eq!(prices, vec![149.99, 99.99]);
 }
 
 #[actix_web::test]
 async fn test_get_nonexistent_product() {
-    let app_state = web::Data::new(AppState {
-        products: Mutex::new(Vec::new()),
-    });
-
     let app = test::init_service(
         App::new()
-            .app_data(app_state.clone())
-            .route("/api/products/{id}", web::get().to(get_product))
+            .app_data(app_state())
+            .configure(routes::configure_routes)
     ).await;
 
-    let req = test::TestRequest::get().uri("/api/products/999").to_request();
+    let req = test::TestRequest::get().uri("/api/v1/products/999").to_request();
     let resp = test::call_service(&app, req).await;
-    assert!(resp.status().is_client_error());
+    assert_eq!(resp.status(), 404);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["code"], "not_found");
 }
 
 #[actix_web::test]
 async fn test_patch_product_validation() {
-    let mut mock_products = Vec::new();
-    mock_products.push(Product {
-        id: 1,
-        name: "Original Product".to_string(),
-        price: 50.0,
-        image: "original.jpg".to_string(),
-        description: "Original Description".to_string(),
-        category: "Original Category".to_string(),
-    });
-
-    let app_state = web::Data::new(AppState {
-        products: Mutex::new(mock_products),
-    });
-
     let app = test::init_service(
         App::new()
-            .app_data(app_state.clone())
-            .route("/api/patch/products/{id}", web::patch().to(patch_product))
+            .app_data(app_state())
+            .configure(routes::configure_routes)
     ).await;
 
     // Test with invalid data
-    let invalid_update = CreateProductRequest {
-        name: "".to_string(), // Empty name
-        price: -1.0, // Negative price
-        image: "".to_string(), // Empty image
-        description: "".to_string(), // Empty description
-        category: "".to_string(), // Empty category
-    };
+    let invalid_update = json!({
+        "name": "",         // Empty name
+        "price": -1.0,      // Negative price
+        "description": ""   // Empty description
+    });
 
     let req = test::TestRequest::patch()
         .uri("/api/patch/products/1")
         .set_json(&invalid_update)
         .to_request();
-    
+
     let resp = test::call_service(&app, req).await;
-    assert!(resp.status().is_client_error());
+    assert_eq!(resp.status(), 422);
+    assert!(resp.headers().contains_key("deprecation"));
+    let body: Value = test::read_body_json(resp).await;
+    for field in ["name", "price", "description"] {
+        assert!(body["details"][field].is_array(), "missing error for {}", field);
+    }
 }
 
 #[actix_web::test]
 async fn test_filter_products_edge_cases() {
-    let mut mock_products = Vec::new();
-    mock_products.push(Product {
-        id: 1,
-        name: "Product A".to_string(),
-        price: 100.0,
-        image: "a.jpg".to_string(),
-        description: "Description A".to_string(),
-        category: "Category A".to_string(),
-    });
-    mock_products.push(Product {
-        id: 2,
-        name: "Product B".to_string(),
-        price: 50.0,
-        image: "b.jpg".to_string(),
-        description: "Description B".to_string(),
-        category: "Category B".to_string(),
-    });
-
-    let app_state = web::Data::new(AppState {
-        products: Mutex::new(mock_products),
-    });
-
     let app = test::init_service(
         App::new()
-            .app_data(app_state.clone())
-            .route("/api/get/products", web::get().to(get_products))
+            .app_data(app_state())
+            .configure(routes::configure_routes)
     ).await;
 
     // Test with invalid sort field
@@ -296,24 +247,441 @@ async fn test_filter_products_edge_cases() {
         .to_request();
     let resp = test::call_service(&app, req).await;
     assert!(resp.status().is_success());
-    let body = test::read_body(resp).await;
-    let products: Vec<Product> = serde_json::from_slice(&body).unwrap();
+    let products: Vec<Value> = test::read_body_json(resp).await;
     assert!(products.is_empty());
 }
 
 #[actix_web::test]
 async fn test_delete_nonexistent_product() {
-    let app_state = web::Data::new(AppState {
-        products: Mutex::new(Vec::new()),
-    });
-
     let app = test::init_service(
         App::new()
-            .app_data(app_state.clone())
-            .route("/api/delete/products/{id}", web::delete().to(delete_product))
+            .app_data(app_state())
+            .configure(routes::configure_routes)
     ).await;
 
     let req = test::TestRequest::delete().uri("/api/delete/products/999").to_request();
     let resp = test::call_service(&app, req).await;
-    assert!(resp.status().is_client_error());
-} 
\ No newline at end of file
+    assert_eq!(resp.status(), 404);
+}
+
+#[actix_web::test]
+async fn test_prices_in_requested_currency() {
+    let app = test::init_service(
+        App::new()
+            .app_data(app_state())
+            .configure(routes::configure_routes)
+    ).await;
+
+    // 108.00 USD is 100.00 EUR at the seeded rate of 1.08
+    let mut imported = sample_product();
+    imported["name"] = json!("Imported");
+    imported["price"] = json!(108.0);
+    imported["currency"] = json!("USD");
+    let req = test::TestRequest::post().uri("/api/v1/products").set_json(&imported).to_request();
+    let product: Product = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(product.currency, "USD");
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/products?currency=EUR&min_price=100&max_price=100")
+        .to_request();
+    let products: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(products.len(), 1);
+    assert_eq!(products[0]["name"], "Imported");
+    assert_eq!(products[0]["price"], json!(100.0));
+    assert_eq!(products[0]["currency"], "EUR");
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/products?currency=USD&sort_by=price")
+        .to_request();
+    let products: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    let prices: Vec<f64> = products.iter().map(|p| p["price"].as_f64().unwrap()).collect();
+    assert_eq!(prices, vec![107.99, 108.0, 161.99]);
+    assert!(products.iter().all(|p| p["currency"] == "USD"));
+
+    // Without a currency every product keeps its own
+    let req = test::TestRequest::get().uri("/api/v1/products?search_term=imported").to_request();
+    let products: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!((products[0]["price"].as_f64(), products[0]["currency"].as_str()), (Some(108.0), Some("USD")));
+
+    let req = test::TestRequest::get().uri("/api/v1/products?currency=XYZ").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 422);
+    let body: Value = test::read_body_json(resp).await;
+    assert!(body["details"]["currency"].is_array());
+
+    // Stats default to EUR: (99.99 + 149.99 + 100.00) / 3
+    let req = test::TestRequest::get().uri("/api/stats/avg-price-per-category?user_id=1").to_request();
+    let stats: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(stats[0]["avg_price"], json!(116.66));
+
+    // (107.99 + 161.99 + 108.00) / 3, the prices as listed in USD
+    let req = test::TestRequest::get().uri("/api/stats/avg-price-per-category?user_id=1&currency=USD").to_request();
+    let stats: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(stats[0]["avg_price"], json!(125.99));
+}
+
+#[actix_web::test]
+async fn test_register_cannot_claim_admin() {
+    let app = test::init_service(
+        App::new()
+            .app_data(app_state())
+            .configure(routes::configure_routes)
+    ).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/register")
+        .set_json(json!({ "username": "mallory", "password": "secret123", "role": "Admin" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 422);
+
+    let req = test::TestRequest::post()
+        .uri("/api/register")
+        .set_json(json!({ "username": "mallory", "password": "secret123" }))
+        .to_request();
+    let user: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(user["role"], "User");
+
+    // The new account is no admin, so it may not change rates
+    let req = test::TestRequest::put()
+        .uri("/api/v1/exchange-rates/GBP")
+        .insert_header(("authorization", format!("Bearer {}", user["id"])))
+        .set_json(json!({ "rate": 0.85 }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 403);
+}
+
+#[actix_web::test]
+async fn test_manage_exchange_rates() {
+    let state = app_state();
+    let app = test::init_service(
+        App::new()
+            .app_data(state.clone())
+            .configure(routes::configure_routes)
+    ).await;
+
+    // Only admins change rates
+    for (authorization, status) in [(None, 401), (Some("Bearer 2"), 403)] {
+        let mut req = test::TestRequest::put().uri("/api/v1/exchange-rates/GBP").set_json(json!({ "rate": 0.85 }));
+        if let Some(authorization) = authorization {
+            req = req.insert_header(("authorization", authorization));
+        }
+        let resp = test::call_service(&app, req.to_request()).await;
+        assert_eq!(resp.status(), status);
+    }
+    let req = test::TestRequest::put()
+        .uri("/api/v1/exchange-rates/GBP")
+        .insert_header(("authorization", "Bearer 1"))
+        .set_json(json!({ "rate": 0.85 }))
+        .to_request();
+    let rate: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(rate["rate"], json!(0.85));
+
+    let req = test::TestRequest::get().uri("/api/v1/exchange-rates").to_request();
+    let rates: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    let currencies: Vec<&str> = rates.iter().map(|r| r["currency"].as_str().unwrap()).collect();
+    assert_eq!(currencies, vec!["EUR", "GBP", "USD"]);
+
+    for (uri, body) in [
+        ("/api/v1/exchange-rates/EUR", json!({ "rate": 2 })),
+        ("/api/v1/exchange-rates/gbp", json!({ "rate": 0.85 })),
+        ("/api/v1/exchange-rates/GBP", json!({ "rate": 0 })),
+    ] {
+        let req = test::TestRequest::put().uri(uri).insert_header(("authorization", "Bearer 1")).set_json(body).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 422, "{}", uri);
+    }
+
+    // A product priced in GBP keeps its rate alive
+    let mut product = sample_product();
+    product["currency"] = json!("GBP");
+    let req = test::TestRequest::post().uri("/api/v1/products").set_json(&product).to_request();
+    let created: Product = test::call_and_read_body_json(&app, req).await;
+    let req = test::TestRequest::delete().uri("/api/v1/exchange-rates/GBP").insert_header(("authorization", "Bearer 1")).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+
+    let req = test::TestRequest::delete().uri(&format!("/api/v1/products/{}", created.id)).to_request();
+    test::call_service(&app, req).await;
+    let req = test::TestRequest::delete().uri("/api/v1/exchange-rates/GBP").insert_header(("authorization", "Bearer 1")).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 204);
+    let since = chrono::Utc::now().naive_utc() - chrono::Duration::minutes(1);
+    let logs = state.audit.logs_since(since).unwrap();
+    let changes: Vec<_> = logs.iter().filter(|l| l.entity == "exchange_rate").map(|l| (l.action.as_str(), l.user_id)).collect();
+    assert_eq!(changes, vec![("UPDATE", 1), ("DELETE", 1)]);
+
+    // Prices can only be given in currencies that have a rate
+    let req = test::TestRequest::post().uri("/api/v1/products").set_json(&product).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 422);
+}
+
+#[actix_web::test]
+async fn test_price_history_and_drops() {
+    let app = test::init_service(
+        App::new()
+            .app_data(app_state())
+            .configure(routes::configure_routes)
+    ).await;
+
+    for (id, price) in [(1, 89.99), (2, 159.99)] {
+        let req = test::TestRequest::patch()
+            .uri(&format!("/api/v1/products/{}", id))
+            .set_json(json!({ "price": price }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+    }
+    // Not a price change, so no entry
+    let req = test::TestRequest::patch()
+        .uri("/api/v1/products/1")
+        .set_json(json!({ "name": "Renamed" }))
+        .to_request();
+    test::call_service(&app, req).await;
+
+    let req = test::TestRequest::get().uri("/api/v1/products/1/price-history").to_request();
+    let history: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    let prices: Vec<(Value, Value)> = history.iter().map(|c| (c["old_price"].clone(), c["new_price"].clone())).collect();
+    assert_eq!(prices, vec![(Value::Null, json!(99.99)), (json!(99.99), json!(89.99))]);
+
+    let req = test::TestRequest::get().uri("/api/v1/products?price_dropped_within_days=7").to_request();
+    let products: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(products.iter().map(|p| p["id"].as_i64().unwrap()).collect::<Vec<_>>(), vec![1]);
+    for days in ["0", "3651", "100000000"] {
+        let req = test::TestRequest::get().uri(&format!("/api/v1/users/1/products?price_dropped_within_days={}", days)).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 422, "{}", days);
+    }
+
+    let req = test::TestRequest::get().uri("/api/v1/products/999/price-history").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+}
+
+#[actix_web::test]
+async fn test_stock_movements() {
+    let state = app_state();
+    let app = test::init_service(
+        App::new()
+            .app_data(state.clone())
+            .configure(routes::configure_routes)
+    ).await;
+
+    // Seeded with 10 in stock and a low-stock threshold of 3
+    let req = test::TestRequest::post()
+        .uri("/api/v1/products/1/stock/decrement")
+        .set_json(json!({ "quantity": 8, "reason": "sold" }))
+        .to_request();
+    let product: Product = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(product.stock, 2);
+
+    let req = test::TestRequest::get().uri("/api/v1/stock-alerts").to_request();
+    let alerts: Vec<Product> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(alerts.iter().map(|p| p.id).collect::<Vec<_>>(), vec![1]);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/products/1/stock/decrement")
+        .set_json(json!({ "quantity": 5, "reason": "sold" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["details"], json!({ "available": 2, "requested": 5 }));
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/products/2/stock/increment")
+        .set_json(json!({ "quantity": 4, "reason": "delivery" }))
+        .to_request();
+    let product: Product = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(product.stock, 4);
+
+    let req = test::TestRequest::get().uri("/api/v1/products?in_stock=false").to_request();
+    let products: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert!(products.is_empty());
+
+    for body in [json!({ "quantity": 0, "reason": "sold" }), json!({ "quantity": 1, "reason": " " })] {
+        let req = test::TestRequest::post()
+            .uri("/api/v1/products/1/stock/increment")
+            .set_json(body)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 422);
+    }
+
+    // Fill product 2 up to a million short of the column's maximum
+    state.products.adjust_stock(2, i32::MAX - 1_000_000 - 4).unwrap();
+    let req = test::TestRequest::post()
+        .uri("/api/v1/products/2/stock/increment")
+        .set_json(json!({ "quantity": 1_000_000, "reason": "delivery" }))
+        .to_request();
+    let product: Product = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(product.stock, i32::MAX);
+    let req = test::TestRequest::post()
+        .uri("/api/v1/products/2/stock/increment")
+        .set_json(json!({ "quantity": 1, "reason": "delivery" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["details"], json!({ "available": i32::MAX, "added": 1, "maximum": i32::MAX }));
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/products/999/stock/increment")
+        .set_json(json!({ "quantity": 1, "reason": "delivery" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+}
+
+#[actix_web::test]
+async fn test_product_variants() {
+    scenarios::product_variants(AppState::in_memory()).await;
+}
+
+#[actix_web::test]
+async fn test_category_hierarchy() {
+    scenarios::category_hierarchy(AppState::in_memory()).await;
+}
+
+#[actix_web::test]
+async fn test_delete_category_modes() {
+    scenarios::delete_category_modes(AppState::in_memory()).await;
+}
+
+#[actix_web::test]
+async fn test_category_slugs_and_stats() {
+    scenarios::category_slugs_and_stats(AppState::in_memory()).await;
+}
+
+#[actix_web::test]
+async fn test_category_attributes() {
+    scenarios::category_attributes(AppState::in_memory()).await;
+}
+
+#[actix_web::test]
+async fn test_product_tags() {
+    scenarios::product_tags(AppState::in_memory()).await;
+}
+
+#[actix_web::test]
+async fn test_product_images() {
+    scenarios::product_images(AppState::in_memory()).await;
+}
+
+#[actix_web::test]
+async fn test_image_renditions() {
+    scenarios::image_renditions(AppState::in_memory()).await;
+}
+
+#[actix_web::test]
+async fn test_video_uploads() {
+    scenarios::video_uploads(AppState::in_memory()).await;
+}
+
+#[actix_web::test]
+async fn test_video_streaming() {
+    scenarios::video_streaming(AppState::in_memory()).await;
+}
+
+#[actix_web::test]
+async fn test_direct_image_uploads() {
+    let media = MediaConfig {
+        dir: std::env::temp_dir().join(format!("media-{}", uuid::Uuid::new_v4())),
+        max_image_bytes: 64,
+        ..MediaConfig::default()
+    };
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState::in_memory().with_media(media.clone())))
+            .configure(routes::configure_routes)
+    ).await;
+    let complete = |upload_id: &str| {
+        test::TestRequest::post().uri(&format!("/api/v1/products/1/images/direct-uploads/{}?primary=true", upload_id)).to_request()
+    };
+
+    // Local storage hands out no URLs to upload to
+    let req = test::TestRequest::post().uri("/api/v1/products/1/images/direct-uploads").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["details"]["storage"], "local");
+    let req = test::TestRequest::post().uri("/api/v1/products/99/images/direct-uploads").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+    // Completing works the same whichever way the file arrived
+    const GIF: &[u8] = b"GIF89a\x01\0\x01\0-gif";
+    let put = |upload_id: &str, bytes: &[u8]| {
+        std::fs::create_dir_all(media.dir.join("incoming")).unwrap();
+        std::fs::write(media.dir.join("incoming").join(upload_id), bytes).unwrap();
+    };
+    let upload_id = uuid::Uuid::new_v4().to_string();
+    assert_eq!(test::call_service(&app, complete(&upload_id)).await.status(), 404);
+    assert_eq!(test::call_service(&app, complete("not-an-upload")).await.status(), 404);
+    put(&upload_id, GIF);
+    let resp = test::call_service(&app, complete(&upload_id)).await;
+    assert_eq!(resp.status(), 201);
+    let images: Vec<Value> = test::read_body_json(resp).await;
+    let key = images[0]["key"].as_str().unwrap();
+    assert!(key.starts_with("images/") && key.ends_with(".gif"));
+    assert_eq!(std::fs::read(media.dir.join(key)).unwrap(), GIF);
+    assert!(!media.dir.join("incoming").join(&upload_id).exists());
+    let req = test::TestRequest::get().uri("/api/v1/products/1").to_request();
+    let product: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(product["image"], images[0]["url"]);
+
+    // Rejected files are thrown away too
+    for (bytes, status) in [(&b"<svg/>"[..], 422), (&[0x47; 65][..], 413), (GIF, 409)] {
+        let upload_id = uuid::Uuid::new_v4().to_string();
+        put(&upload_id, bytes);
+        assert_eq!(test::call_service(&app, complete(&upload_id)).await.status(), status);
+        assert!(!media.dir.join("incoming").join(&upload_id).exists());
+    }
+    std::fs::remove_dir_all(&media.dir).unwrap();
+}
+
+#[actix_web::test]
+async fn test_copy_media_between_storages() {
+    let dirs = [0, 1].map(|_| std::env::temp_dir().join(format!("media-{}", uuid::Uuid::new_v4())));
+    let from = LocalStorage::new(&dirs[0], "/media");
+    let to = LocalStorage::new(&dirs[1], "/media");
+    from.put("images/a.png", b"png").unwrap();
+    from.put("images/a/thumbnail.webp", b"webp").unwrap();
+    from.put("videos/b.mp4", b"mp4").unwrap();
+    to.put("videos/b.mp4", b"mp4").unwrap();
+    // Partial uploads are not media yet
+    std::fs::create_dir_all(dirs[0].join(".uploads")).unwrap();
+    std::fs::write(dirs[0].join(video_uploads::part_key("x")), b"part").unwrap();
+
+    let mut keys = from.list().unwrap();
+    keys.sort();
+    assert_eq!(keys, vec!["images/a.png", "images/a/thumbnail.webp", "videos/b.mp4"]);
+    assert_eq!(storage::copy_all(&from, &to).unwrap(), CopyReport { copied: 2, skipped: 1 });
+    assert_eq!(to.get("images/a/thumbnail.webp").unwrap(), b"webp");
+    assert_eq!(storage::copy_all(&from, &to).unwrap(), CopyReport { copied: 0, skipped: 3 });
+    assert!(!to.exists(&video_uploads::part_key("x")).unwrap());
+
+    to.remove("images/a.png").unwrap();
+    to.remove("images/a.png").unwrap();
+    assert_eq!(to.get("images/a.png").unwrap_err().kind(), std::io::ErrorKind::NotFound);
+    assert_eq!(to.key(&to.url("videos/b.mp4")).as_deref(), Some("videos/b.mp4"));
+    for dir in dirs {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+#[actix_web::test]
+async fn test_product_import() {
+    scenarios::product_import(AppState::in_memory()).await;
+}
+
+#[actix_web::test]
+async fn test_background_product_import() {
+    scenarios::background_product_import(AppState::in_memory()).await;
+}
+
+#[actix_web::test]
+async fn test_product_import_transactions() {
+    scenarios::product_import_transactions(AppState::in_memory()).await;
+}
@@ -0,0 +1,78 @@
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use actix_web::{test, web, App};
+use backend::{db, routes, AppState};
+use diesel::connection::SimpleConnection;
+use futures::future::join_all;
+use tokio::time::sleep;
+
+const STALL: Duration = Duration::from_secs(2);
+
+// Holds an exclusive lock on tags for STALL from another connection, so the
+// app's own tag query waits on it
+fn lock_tags(pool: &backend::DbPool) -> thread::JoinHandle<()> {
+    let mut conn = pool.get().expect("a connection for the lock");
+    let (locked, is_locked) = mpsc::channel();
+    let holder = thread::spawn(move || {
+        conn.batch_execute("BEGIN; LOCK TABLE tags IN ACCESS EXCLUSIVE MODE").unwrap();
+        locked.send(()).unwrap();
+        thread::sleep(STALL);
+        conn.batch_execute("COMMIT").unwrap();
+    });
+    is_locked.recv().unwrap();
+    holder
+}
+
+// Needs a live, migrated database: DATABASE_URL=postgres://... cargo test -- --ignored
+// The test runtime is single threaded, so a handler that ran its query on it
+// would hold every other request back until the lock was released
+#[actix_web::test]
+#[ignore]
+async fn test_slow_query_does_not_stall_other_requests() {
+    let pool = db::connection::init_pool();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState::postgres(pool.clone())))
+            .configure(routes::configure_routes),
+    )
+    .await;
+    let fast_requests = || async {
+        let started = Instant::now();
+        let requests = (0..50).map(|_| {
+            test::call_service(&app, test::TestRequest::get().uri("/api/v1/categories").to_request())
+        });
+        for resp in join_all(requests).await {
+            assert!(resp.status().is_success());
+        }
+        started.elapsed()
+    };
+
+    let unloaded = fast_requests().await;
+
+    let holder = lock_tags(&pool);
+    let started = Instant::now();
+    let slow = async {
+        let req = test::TestRequest::get().uri("/api/v1/tags").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        started.elapsed()
+    };
+    let fast = async {
+        // Let the slow request reach the lock first
+        sleep(Duration::from_millis(100)).await;
+        fast_requests().await;
+        started.elapsed()
+    };
+    let (slow_done, loaded) = tokio::join!(slow, fast);
+    holder.join().unwrap();
+
+    assert!(slow_done >= STALL - Duration::from_millis(100), "the tag request did not wait for the lock");
+    assert!(
+        loaded < unloaded * 2 + Duration::from_millis(350),
+        "50 requests were done {:?} after a tag query started waiting on a lock; they take {:?} without",
+        loaded,
+        unloaded
+    );
+}
@@ -0,0 +1,660 @@
+// The HTTP tests again, this time through the Diesel queries in db::repository
+// against a real PostgreSQL (see tests/common for the fixture). Scenarios both
+// stores share live in tests/common/scenarios.rs; what is left here is what only
+// the database can show.
+#![allow(clippy::inconsistent_digit_grouping)]
+
+mod common;
+
+use actix_web::{test, web, App};
+use backend::audit::AuditPolicy;
+use backend::db::models::{NewImage, UpdateCategory, UpdateProduct};
+use backend::db::repository;
+use backend::db::schema::product_option_values;
+use backend::models::ProductQuery;
+use backend::money::Money;
+use backend::routes;
+use common::{scenarios, TestDb};
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use diesel::{QueryDsl, RunQueryDsl};
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn test_filter_products_edge_cases() {
+    let Some(db) = TestDb::new() else { return };
+    db.seed();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(db.app_state()))
+            .configure(routes::configure_routes)
+    ).await;
+
+    // Test with invalid sort field
+    let req = test::TestRequest::get()
+        .uri("/api/v1/products?sort_by=invalid_field&sort_order=asc")
+        .to_request();
+    let products: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(products.len(), 2);
+
+    // Test with invalid sort order
+    let req = test::TestRequest::get()
+        .uri("/api/v1/products?sort_by=price&sort_order=invalid_order")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    // Test with min_price > max_price
+    let req = test::TestRequest::get()
+        .uri("/api/v1/products?min_price=200&max_price=100")
+        .to_request();
+    let products: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert!(products.is_empty());
+
+    // Bounds are inclusive at cent precision
+    let req = test::TestRequest::get()
+        .uri("/api/v1/products?min_price=99.99&max_price=99.99")
+        .to_request();
+    let products: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(products.len(), 1);
+    assert_eq!(products[0]["category_name"], "Clothes");
+
+    // Test filtering by a category without products
+    let req = test::TestRequest::get()
+        .uri("/api/v1/products?category_id=2")
+        .to_request();
+    let products: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert!(products.is_empty());
+}
+
+#[actix_web::test]
+async fn test_list_products_filters_in_sql() {
+    let Some(db) = TestDb::new() else { return };
+    db.seed();
+    let conn = &mut db.conn();
+
+    let query = ProductQuery { search_term: Some("ANOTHER".to_string()), ..Default::default() };
+    let products = repository::list_products(conn, None, &query).unwrap();
+    assert_eq!(products.iter().map(|p| p.id).collect::<Vec<_>>(), vec![2]);
+    // Searched for as text, not as LIKE wildcards
+    for term in ["%", "_", "\\", "Sample_Product"] {
+        let query = ProductQuery { search_term: Some(term.to_string()), ..Default::default() };
+        assert!(repository::list_products(conn, None, &query).unwrap().is_empty(), "{}", term);
+    }
+
+    let query = ProductQuery {
+        sort_by: Some("created_at".to_string()),
+        sort_order: Some("desc".to_string()),
+        ..Default::default()
+    };
+    let products = repository::list_products(conn, Some(1), &query).unwrap();
+    assert_eq!(products.iter().map(|p| p.id).collect::<Vec<_>>(), vec![2, 1]);
+
+    assert!(repository::list_products(conn, Some(2), &ProductQuery::default()).unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn test_get_nonexistent_product() {
+    let Some(db) = TestDb::new() else { return };
+    db.seed();
+
+    let conn = &mut db.conn();
+    assert!(matches!(repository::get_product(conn, 999), Err(DieselError::NotFound)));
+    assert!(matches!(
+        repository::update_product(conn, 999, UpdateProduct { name: Some("Ghost".to_string()), ..Default::default() }),
+        Err(DieselError::NotFound)
+    ));
+    assert_eq!(repository::delete_product(conn, 999).unwrap(), 0);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(db.app_state()))
+            .configure(routes::configure_routes)
+    ).await;
+
+    let req = test::TestRequest::get().uri("/api/v1/products/999").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+
+    let req = test::TestRequest::delete().uri("/api/v1/products/999").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+}
+
+#[actix_web::test]
+async fn test_patch_product_validation() {
+    let Some(db) = TestDb::new() else { return };
+    db.seed();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(db.app_state()))
+            .configure(routes::configure_routes)
+    ).await;
+
+    // Test with invalid data
+    let req = test::TestRequest::patch()
+        .uri("/api/v1/products/1")
+        .set_json(json!({ "name": "", "price": -1.0, "category_id": 999 }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 422);
+
+    // A rejected patch must not touch the row
+    let product = repository::get_product(&mut db.conn(), 1).unwrap();
+    assert_eq!(product.name, "Sample Product 1");
+    assert_eq!(product.price, Money::from_cents(99_99));
+
+    let req = test::TestRequest::patch()
+        .uri("/api/v1/products/1")
+        .set_json(json!({ "price": 79.5 }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let product = repository::get_product(&mut db.conn(), 1).unwrap();
+    assert_eq!(product.price, Money::from_cents(79_50));
+    assert_eq!(product.name, "Sample Product 1");
+}
+
+#[actix_web::test]
+async fn test_foreign_key_conflicts() {
+    let Some(db) = TestDb::new() else { return };
+    db.seed();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(db.app_state()))
+            .configure(routes::configure_routes)
+    ).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/products")
+        .set_json(json!({
+            "name": "Orphan",
+            "price": 10.0,
+            "description": "No such owner",
+            "image": "/assets/images/placeholder.jpg",
+            "category_id": 1,
+            "user_id": 999
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["details"]["constraint"], "products_user_id_fkey");
+
+    let req = test::TestRequest::delete().uri("/api/v1/categories/1").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+}
+
+#[actix_web::test]
+async fn test_prices_are_exact() {
+    let Some(db) = TestDb::new() else { return };
+    db.seed();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(db.app_state()))
+            .configure(routes::configure_routes)
+    ).await;
+
+    for price in [json!(0.1), json!("0.20")] {
+        let req = test::TestRequest::post()
+            .uri("/api/v1/products")
+            .set_json(json!({
+                "name": "Cheap",
+                "price": price,
+                "description": "Sums that floats get wrong",
+                "image": "/assets/images/placeholder.jpg",
+                "category_id": 2,
+                "user_id": 2
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 201);
+    }
+
+    // Sub-cent amounts are rejected instead of rounded
+    let req = test::TestRequest::patch()
+        .uri("/api/v1/products/3")
+        .set_json(json!({ "price": 0.105 }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_client_error());
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/products?min_price=0.1&max_price=0.10")
+        .to_request();
+    let products: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(products.len(), 1);
+
+    let stored = repository::get_product(&mut db.conn(), 4).unwrap();
+    assert_eq!(stored.price, Money::from_cents(20));
+
+    // (0.10 + 0.20) / 2 = 0.15 exactly, rounded in SQL like in the in-memory path
+    let req = test::TestRequest::get()
+        .uri("/api/stats/avg-price-per-category?user_id=2")
+        .to_request();
+    let body = test::call_and_read_body(&app, req).await;
+    assert_eq!(body, r#"[{"avg_price":0.15,"category":"Shoes"}]"#);
+
+    let req = test::TestRequest::get()
+        .uri("/api/stats/avg-price-per-category-inefficient?user_id=2")
+        .to_request();
+    let body = test::call_and_read_body(&app, req).await;
+    assert_eq!(body, r#"[{"avg_price":0.15,"category":"Shoes"}]"#);
+}
+
+#[actix_web::test]
+async fn test_prices_in_requested_currency() {
+    let Some(db) = TestDb::new() else { return };
+    db.seed();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(db.app_state()))
+            .configure(routes::configure_routes)
+    ).await;
+
+    // 108.00 USD is 100.00 EUR at the seeded rate of 1.08
+    let req = test::TestRequest::post()
+        .uri("/api/v1/products")
+        .set_json(json!({
+            "name": "Imported",
+            "price": 108.0,
+            "currency": "USD",
+            "description": "Priced in dollars",
+            "image": "/assets/images/placeholder.jpg",
+            "category_id": 1,
+            "user_id": 1
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+
+    // Conversion, filters and ordering all happen in SQL
+    let query = ProductQuery {
+        currency: Some("EUR".to_string()),
+        min_price: Some(Money::from_cents(100_00)),
+        sort_by: Some("price".to_string()),
+        ..Default::default()
+    };
+    let products = repository::list_products(&mut db.conn(), None, &query).unwrap();
+    let listed: Vec<_> = products.iter().map(|p| (p.id, p.price, p.currency.as_str())).collect();
+    assert_eq!(listed, vec![(3, Money::from_cents(100_00), "EUR"), (2, Money::from_cents(149_99), "EUR")]);
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/products?currency=USD&sort_by=price&sort_order=desc")
+        .to_request();
+    let products: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    let prices: Vec<f64> = products.iter().map(|p| p["price"].as_f64().unwrap()).collect();
+    assert_eq!(prices, vec![161.99, 108.0, 107.99]);
+
+    // SQL averages the same converted prices the in-memory path does
+    for path in ["avg-price-per-category", "avg-price-per-category-inefficient"] {
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/stats/{}?user_id=1&currency=USD", path))
+            .to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        assert_eq!(body, r#"[{"avg_price":125.99,"category":"Clothes"}]"#, "{}", path);
+    }
+
+    let req = test::TestRequest::delete().uri("/api/v1/exchange-rates/USD").insert_header(("authorization", "Bearer 1")).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["details"]["constraint"], "products_currency_fkey");
+}
+
+#[actix_web::test]
+async fn test_price_history_and_drops() {
+    let Some(db) = TestDb::new() else { return };
+    db.seed();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(db.app_state()))
+            .configure(routes::configure_routes)
+    ).await;
+
+    // 1 drops and stays down; 2 drops and comes back up, so it is no deal
+    for (id, body) in [
+        (1, json!({ "price": 89.99 })),
+        (2, json!({ "price": 139.99 })),
+        (2, json!({ "price": 149.99 })),
+        (2, json!({ "name": "Unchanged price" })),
+    ] {
+        let req = test::TestRequest::patch()
+            .uri(&format!("/api/v1/products/{}", id))
+            .set_json(body)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+    }
+
+    let history = repository::get_price_history(&mut db.conn(), 2).unwrap();
+    let prices: Vec<_> = history.iter().map(|c| (c.old_price, c.new_price)).collect();
+    assert_eq!(prices, vec![
+        (None, Money::from_cents(149_99)),
+        (Some(Money::from_cents(149_99)), Money::from_cents(139_99)),
+        (Some(Money::from_cents(139_99)), Money::from_cents(149_99)),
+    ]);
+
+    let query = ProductQuery { price_dropped_within_days: Some(7), ..Default::default() };
+    let products = repository::list_products(&mut db.conn(), None, &query).unwrap();
+    assert_eq!(products.iter().map(|p| p.id).collect::<Vec<_>>(), vec![1]);
+
+    // A PUT that keeps the price leaves the history alone
+    let req = test::TestRequest::put()
+        .uri("/api/v1/products/1")
+        .set_json(json!({
+            "name": "Replaced",
+            "price": 89.99,
+            "description": "Same price",
+            "image": "/assets/images/placeholder.jpg",
+            "category_id": 1,
+            "user_id": 1
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let req = test::TestRequest::get().uri("/api/v1/products/1/price-history").to_request();
+    let history: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(history.len(), 2);
+
+    let req = test::TestRequest::delete().uri("/api/v1/products/1").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 204);
+    assert!(repository::get_price_history(&mut db.conn(), 1).unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn test_concurrent_stock_decrements_never_oversell() {
+    let Some(db) = TestDb::new() else { return };
+    db.seed();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(db.app_state()))
+            .configure(routes::configure_routes)
+    ).await;
+
+    // Product 1 starts with 10 in stock and a threshold of 3
+    let requests = (0..15).map(|_| {
+        let req = test::TestRequest::post()
+            .uri("/api/v1/products/1/stock/decrement")
+            .set_json(json!({ "quantity": 1, "reason": "checkout" }))
+            .to_request();
+        test::call_service(&app, req)
+    });
+    let statuses: Vec<u16> = futures::future::join_all(requests).await.iter().map(|r| r.status().as_u16()).collect();
+    assert_eq!(statuses.iter().filter(|s| **s == 200).count(), 10, "{:?}", statuses);
+    assert_eq!(statuses.iter().filter(|s| **s == 409).count(), 5, "{:?}", statuses);
+    assert_eq!(repository::get_product(&mut db.conn(), 1).unwrap().stock, 0);
+
+    diesel::sql_query("UPDATE products SET stock = 2147483000 WHERE id = 1").execute(&mut db.conn()).unwrap();
+    let req = test::TestRequest::post()
+        .uri("/api/v1/products/1/stock/increment")
+        .set_json(json!({ "quantity": 1000, "reason": "delivery" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+    assert_eq!(repository::get_product(&mut db.conn(), 1).unwrap().stock, 2147483000);
+    diesel::sql_query("UPDATE products SET stock = 0 WHERE id = 1").execute(&mut db.conn()).unwrap();
+
+    let req = test::TestRequest::get().uri("/api/v1/products?in_stock=true").to_request();
+    let products: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert!(products.is_empty());
+
+    // Every movement is audited with its reason, and the threshold crossing once
+    let since = chrono::Utc::now().naive_utc() - chrono::Duration::minutes(1);
+    let logs = repository::get_logs_since(&mut db.conn(), since).unwrap();
+    let stock_out: Vec<_> = logs.iter().filter(|l| l.action == "STOCK_OUT").collect();
+    assert_eq!(stock_out.len(), 10);
+    assert!(stock_out.iter().all(|l| l.reason.as_deref().is_some_and(|r| r.starts_with("checkout (-1, stock now "))));
+    assert_eq!(logs.iter().filter(|l| l.action == "LOW_STOCK").count(), 1);
+
+    let alerts = repository::get_low_stock_products(&mut db.conn()).unwrap();
+    assert_eq!(alerts.iter().map(|p| p.id).collect::<Vec<_>>(), vec![1]);
+}
+
+#[actix_web::test]
+async fn test_product_variants() {
+    let Some(db) = TestDb::new() else { return };
+    db.seed();
+    scenarios::product_variants(db.app_state()).await;
+
+    // Values no variant uses any more are gone from the option tables too
+    let values: Vec<String> = product_option_values::table
+        .select(product_option_values::value)
+        .order(product_option_values::value)
+        .load(&mut db.conn())
+        .unwrap();
+    assert_eq!(values, vec!["42", "45", "black", "white"]);
+}
+
+#[actix_web::test]
+async fn test_category_hierarchy() {
+    let Some(db) = TestDb::new() else { return };
+    db.seed();
+    scenarios::category_hierarchy(db.app_state()).await;
+
+    // The trigger holds even for writes that skip the handlers' checks
+    let running = db.app_state().categories.find_by_name("Running").unwrap().unwrap();
+    let moved = repository::update_category(
+        &mut db.conn(),
+        2,
+        UpdateCategory {
+            name: None,
+            description: None,
+            parent_id: Some(running.id),
+            slug: None,
+            image: None,
+            display_order: None,
+        },
+    );
+    assert!(matches!(moved, Err(DieselError::DatabaseError(DatabaseErrorKind::CheckViolation, _))), "{:?}", moved);
+}
+
+fn new_product() -> Value {
+    json!({
+        "name": "Audited",
+        "price": 10.0,
+        "description": "Written together with its log entry",
+        "image": "/assets/images/placeholder.jpg",
+        "category_id": 1,
+        "user_id": 1
+    })
+}
+
+#[actix_web::test]
+async fn test_write_and_audit_entry_commit_together() {
+    let Some(db) = TestDb::new() else { return };
+    db.seed();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(db.app_state()))
+            .configure(routes::configure_routes)
+    ).await;
+
+    let req = test::TestRequest::post().uri("/api/v1/products").set_json(new_product()).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+
+    let req = test::TestRequest::delete().uri("/api/v1/categories/2").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 204);
+
+    let since = chrono::Utc::now().naive_utc() - chrono::Duration::minutes(1);
+    let logs = repository::get_logs_since(&mut db.conn(), since).unwrap();
+    let entries: Vec<_> = logs.iter().map(|l| (l.action.as_str(), l.entity.as_str())).collect();
+    assert_eq!(entries, vec![("CREATE", "product"), ("DELETE", "category")]);
+}
+
+#[actix_web::test]
+async fn test_failed_audit_rolls_back_write() {
+    let Some(db) = TestDb::new() else { return };
+    db.seed();
+    diesel::sql_query("DROP TABLE logs").execute(&mut db.conn()).unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(db.app_state()))
+            .configure(routes::configure_routes)
+    ).await;
+
+    let req = test::TestRequest::post().uri("/api/v1/products").set_json(new_product()).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 500);
+
+    let req = test::TestRequest::delete().uri("/api/v1/products/1").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 500);
+
+    let products = repository::list_products(&mut db.conn(), None, &ProductQuery::default()).unwrap();
+    assert_eq!(products.iter().map(|p| p.id).collect::<Vec<_>>(), vec![1, 2]);
+}
+
+#[actix_web::test]
+async fn test_best_effort_audit_keeps_write() {
+    let Some(db) = TestDb::new() else { return };
+    db.seed();
+    diesel::sql_query("DROP TABLE logs").execute(&mut db.conn()).unwrap();
+    let state = db.app_state().with_audit_policy(AuditPolicy::BestEffort);
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .configure(routes::configure_routes)
+    ).await;
+
+    let req = test::TestRequest::post().uri("/api/v1/products").set_json(new_product()).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+
+    let product = repository::get_product(&mut db.conn(), 3).unwrap();
+    assert_eq!(product.name, "Audited");
+}
+
+#[actix_web::test]
+async fn test_schemas_are_isolated() {
+    let Some(first) = TestDb::new() else { return };
+    let Some(second) = TestDb::new() else { return };
+    first.seed();
+
+    assert_eq!(repository::get_categories(&mut first.conn()).unwrap().len(), 2);
+    assert!(repository::get_categories(&mut second.conn()).unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn test_delete_category_modes() {
+    let Some(db) = TestDb::new() else { return };
+    db.seed();
+    scenarios::delete_category_modes(db.app_state()).await;
+}
+
+#[actix_web::test]
+async fn test_category_slugs_and_stats() {
+    let Some(db) = TestDb::new() else { return };
+    db.seed();
+    scenarios::category_slugs_and_stats(db.app_state()).await;
+}
+
+#[actix_web::test]
+async fn test_category_attributes() {
+    let Some(db) = TestDb::new() else { return };
+    db.seed();
+    scenarios::category_attributes(db.app_state()).await;
+}
+
+#[actix_web::test]
+async fn test_product_tags() {
+    let Some(db) = TestDb::new() else { return };
+    db.seed();
+    scenarios::product_tags(db.app_state()).await;
+}
+
+#[actix_web::test]
+async fn test_product_images() {
+    let Some(db) = TestDb::new() else { return };
+    db.seed();
+    scenarios::product_images(db.app_state()).await;
+}
+
+#[actix_web::test]
+async fn test_image_renditions() {
+    let Some(db) = TestDb::new() else { return };
+    db.seed();
+    scenarios::image_renditions(db.app_state()).await;
+}
+
+#[actix_web::test]
+async fn test_video_uploads() {
+    let Some(db) = TestDb::new() else { return };
+    db.seed();
+    scenarios::video_uploads(db.app_state()).await;
+}
+
+#[actix_web::test]
+async fn test_video_streaming() {
+    let Some(db) = TestDb::new() else { return };
+    db.seed();
+    scenarios::video_streaming(db.app_state()).await;
+}
+
+#[actix_web::test]
+async fn test_rewrite_media_urls() {
+    let Some(db) = TestDb::new() else { return };
+    db.seed();
+    let conn = &mut db.conn();
+    let (from, to) = ("/media/", "http://localhost:9000/media/");
+    let image = NewImage {
+        key: "images/a.png".to_string(),
+        url: "/media/images/a.png".to_string(),
+        content_type: "image/png".to_string(),
+        size_bytes: 3,
+    };
+    let image = repository::create_product_image(conn, 1, &image).unwrap();
+    let srcset = json!({ "webp": "/media/images/a/thumbnail.webp 160w, /media/images/a/medium.webp 640w", "jpeg": "/media/images/a/thumbnail.jpg 160w" });
+    repository::finish_renditions(conn, image.id, 800, 600, &srcset).unwrap();
+    let changes = UpdateProduct {
+        image: Some(image.url.clone()),
+        video: Some("/media/videos/v.mp4".to_string()),
+        ..Default::default()
+    };
+    repository::update_product(conn, 1, changes).unwrap();
+    let changes = UpdateProduct { image: Some("https://cdn.example.com/media/b.png".to_string()), ..Default::default() };
+    repository::update_product(conn, 2, changes).unwrap();
+
+    // The image row, and product 1's image and video
+    assert_eq!(repository::rewrite_media_urls(conn, from, to).unwrap(), 3);
+    let image = repository::get_product_image(conn, 1, image.id).unwrap();
+    assert_eq!(image.url, "http://localhost:9000/media/images/a.png");
+    let rebased = json!({
+        "webp": "http://localhost:9000/media/images/a/thumbnail.webp 160w, http://localhost:9000/media/images/a/medium.webp 640w",
+        "jpeg": "http://localhost:9000/media/images/a/thumbnail.jpg 160w",
+    });
+    assert_eq!(image.srcset, Some(rebased.clone()));
+    let product = repository::get_product(conn, 1).unwrap();
+    assert_eq!(product.image, image.url);
+    assert_eq!(product.image_srcset, Some(rebased));
+    assert_eq!(product.video.as_deref(), Some("http://localhost:9000/media/videos/v.mp4"));
+    assert_eq!(repository::get_product(conn, 2).unwrap().image, "https://cdn.example.com/media/b.png");
+
+    assert_eq!(repository::rewrite_media_urls(conn, from, to).unwrap(), 0);
+    assert_eq!(repository::rewrite_media_urls(conn, to, from).unwrap(), 3);
+    assert_eq!(repository::get_product(conn, 1).unwrap().image, "/media/images/a.png");
+}
+
+#[actix_web::test]
+async fn test_product_import() {
+    let Some(db) = TestDb::new() else { return };
+    db.seed();
+    scenarios::product_import(db.app_state()).await;
+}
+
+#[actix_web::test]
+async fn test_background_product_import() {
+    let Some(db) = TestDb::new() else { return };
+    db.seed();
+    scenarios::background_product_import(db.app_state()).await;
+}
+
+#[actix_web::test]
+async fn test_product_import_transactions() {
+    let Some(db) = TestDb::new() else { return };
+    db.seed();
+    scenarios::product_import_transactions(db.app_state()).await;
+}
@@ -0,0 +1,162 @@
+// The S3 storage backend against a real S3-compatible server, e.g. MinIO:
+//
+//     docker run -p 9000:9000 minio/minio server /data
+//     mc alias set local http://localhost:9000 minioadmin minioadmin && mc mb local/test-media
+//     TEST_S3_ENDPOINT=http://localhost:9000 cargo test --test s3
+//
+// TEST_S3_BUCKET names the bucket (default test-media), TEST_S3_ACCESS_KEY_ID
+// and TEST_S3_SECRET_ACCESS_KEY the credentials (default minioadmin). Without
+// TEST_S3_ENDPOINT every test here is skipped.
+
+use std::env;
+use std::io::Read;
+
+use actix_web::{test, web, App};
+use backend::media::MediaConfig;
+use backend::storage::{self, s3, Backend, CopyReport, LocalStorage, MediaStorage, S3Config, S3Storage};
+use backend::{routes, AppState};
+use chrono::Duration;
+use serde_json::{json, Value};
+
+fn config() -> Option<S3Config> {
+    let Ok(endpoint) = env::var("TEST_S3_ENDPOINT") else {
+        eprintln!("skipping S3 tests: set TEST_S3_ENDPOINT, e.g. to a local MinIO");
+        return None;
+    };
+    let var = |name: &str, default: &str| env::var(name).unwrap_or_else(|_| default.to_string());
+    Some(S3Config {
+        endpoint,
+        bucket: var("TEST_S3_BUCKET", "test-media"),
+        region: "us-east-1".to_string(),
+        access_key_id: var("TEST_S3_ACCESS_KEY_ID", "minioadmin"),
+        secret_access_key: var("TEST_S3_SECRET_ACCESS_KEY", "minioadmin"),
+        path_style: true,
+        public_url: None,
+    })
+}
+
+// A key of its own for each run, so runs never see each other's files
+fn unique(key: &str) -> String {
+    let (stem, extension) = key.rsplit_once('.').unwrap();
+    format!("{}-{}.{}", stem, uuid::Uuid::new_v4(), extension)
+}
+
+#[actix_web::test]
+async fn test_s3_storage() {
+    let Some(config) = config() else { return };
+    let s3 = S3Storage::new(config);
+    let key = unique("images/test.png");
+
+    assert!(!s3.exists(&key).unwrap());
+    assert_eq!(s3.get(&key).unwrap_err().kind(), std::io::ErrorKind::NotFound);
+    s3.put(&key, b"\x89PNG bytes").unwrap();
+    assert!(s3.exists(&key).unwrap());
+    assert_eq!(s3.get(&key).unwrap(), b"\x89PNG bytes");
+    assert!(s3.list().unwrap().contains(&key));
+    assert_eq!(s3.key(&s3.url(&key)).as_deref(), Some(key.as_str()));
+    s3.remove(&key).unwrap();
+    s3.remove(&key).unwrap();
+    assert!(!s3.exists(&key).unwrap());
+
+    // Local files are uploaded and then removed
+    let path = env::temp_dir().join(format!("upload-{}", uuid::Uuid::new_v4()));
+    std::fs::write(&path, vec![7u8; 100_000]).unwrap();
+    let key = unique("videos/test.mp4");
+    s3.put_file(&key, &path).unwrap();
+    assert!(!path.exists());
+    let (mut body, len) = s3.open(&key).unwrap();
+    assert_eq!(len, 100_000);
+    let mut bytes = Vec::new();
+    body.read_to_end(&mut bytes).unwrap();
+    assert_eq!(bytes, vec![7u8; 100_000]);
+    s3.remove(&key).unwrap();
+
+    // Bodies over the threshold go up in parts; one that falls short is aborted
+    let len = s3::MULTIPART_THRESHOLD as usize + 1_000_001;
+    let video: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+    let key = unique("videos/large.mp4");
+    s3.put(&key, &video).unwrap();
+    assert_eq!(s3.get(&key).unwrap(), video);
+    s3.remove(&key).unwrap();
+    let err = s3.write(&key, &mut &video[..], len as u64 + 1).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    assert!(!s3.exists(&key).unwrap());
+
+    // Pre-signed URLs work without credentials
+    let key = unique("incoming/test.gif");
+    let url = s3.presigned_url("PUT", &key, Duration::minutes(5)).unwrap();
+    ureq::put(&url).send_bytes(b"GIF89a").unwrap();
+    let url = s3.presigned_url("GET", &key, Duration::minutes(5)).unwrap();
+    assert_eq!(ureq::get(&url).call().unwrap().into_string().unwrap(), "GIF89a");
+    s3.remove(&key).unwrap();
+}
+
+#[actix_web::test]
+async fn test_copy_media_to_s3() {
+    let Some(config) = config() else { return };
+    let s3 = S3Storage::new(config);
+    let dir = env::temp_dir().join(format!("media-{}", uuid::Uuid::new_v4()));
+    let local = LocalStorage::new(&dir, "/media");
+    let keys = [unique("images/a.png"), unique("videos/b.mp4")];
+    for key in &keys {
+        local.put(key, key.as_bytes()).unwrap();
+    }
+
+    assert_eq!(storage::copy_all(&local, &s3).unwrap(), CopyReport { copied: 2, skipped: 0 });
+    assert_eq!(storage::copy_all(&local, &s3).unwrap(), CopyReport { copied: 0, skipped: 2 });
+    for key in &keys {
+        assert_eq!(s3.get(key).unwrap(), key.as_bytes());
+        s3.remove(key).unwrap();
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[actix_web::test]
+async fn test_s3_media_through_the_api() {
+    let Some(config) = config() else { return };
+    let media = MediaConfig {
+        dir: env::temp_dir().join(format!("media-{}", uuid::Uuid::new_v4())),
+        backend: Backend::S3(config),
+        ..MediaConfig::default()
+    };
+    let state = web::Data::new(AppState::in_memory().with_media(media.clone()));
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes::configure_routes)).await;
+    assert!(media.files().is_none());
+
+    // Straight to the bucket, then checked and stored by content hash
+    let req = test::TestRequest::post().uri("/api/v1/products/1/images/direct-uploads").to_request();
+    let upload: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(upload["method"], "PUT");
+    let gif = format!("GIF89a\x01\0\x01\0{}", uuid::Uuid::new_v4()).into_bytes();
+    ureq::put(upload["url"].as_str().unwrap()).send_bytes(&gif).unwrap();
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/v1/products/1/images/direct-uploads/{}", upload["id"].as_str().unwrap()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let images: Vec<Value> = test::read_body_json(resp).await;
+    let key = images[0]["key"].as_str().unwrap();
+    assert_eq!(images[0]["url"], state.storage.url(key));
+    assert_eq!(state.storage.get(key).unwrap(), gif);
+    assert!(!state.storage.exists(&format!("incoming/{}", upload["id"].as_str().unwrap())).unwrap());
+
+    // Videos are a redirect to a pre-signed URL that serves ranges
+    let video_key = unique("videos/test.mp4");
+    state.storage.put(&video_key, b"\0\0\0\x20ftypisom0123456789").unwrap();
+    let req = test::TestRequest::patch()
+        .uri("/api/v1/products/1")
+        .set_json(json!({ "video": state.storage.url(&video_key) }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    let req = test::TestRequest::get().uri("/api/v1/products/1/video").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 302);
+    assert_eq!(resp.headers().get("cache-control").unwrap(), "private, no-store");
+    let location = resp.headers().get("location").unwrap().to_str().unwrap();
+    let ranged = ureq::get(location).set("Range", "bytes=12-15").call().unwrap();
+    assert_eq!(ranged.status(), 206);
+    assert_eq!(ranged.into_string().unwrap(), "0123");
+
+    state.storage.remove(key).unwrap();
+    state.storage.remove(&video_key).unwrap();
+}